@@ -1,3 +1,4 @@
+use std::path::PathBuf;
 use std::process::Stdio;
 
 use nix::unistd::User;
@@ -5,13 +6,17 @@ use target_lexicon::OperatingSystem;
 use tokio::process::Command;
 use tracing::{span, Span};
 
-use crate::action::{ActionError, ActionErrorKind};
+use crate::action::{ActionError, ActionErrorKind, ActionResource};
 use crate::execute_command;
+use crate::os::linux::UserDatabase;
+use crate::settings::{default_root, rebase};
 
 use crate::action::{Action, ActionDescription, StatefulAction};
 
 /**
 Create an operating system level user in the given group
+
+On Linux, when `root` is not `/` the membership is added to the `/etc/group` under it.
 */
 #[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
 pub struct AddUserToGroup {
@@ -19,6 +24,8 @@ pub struct AddUserToGroup {
     uid: u32,
     groupname: String,
     gid: u32,
+    #[serde(default = "default_root")]
+    root: PathBuf,
 }
 
 impl AddUserToGroup {
@@ -28,24 +35,40 @@ impl AddUserToGroup {
         uid: u32,
         groupname: String,
         gid: u32,
+        root: PathBuf,
     ) -> Result<StatefulAction<Self>, ActionError> {
         let this = Self {
             name: name.clone(),
             uid,
             groupname,
             gid,
+            root,
         };
 
+        let database = UserDatabase::new(this.root.clone());
+        if !database.is_host() {
+            let user_in_group = database
+                .user_in_group(&this.name, &this.groupname)
+                .await
+                .map_err(Self::error)?;
+            if user_in_group {
+                tracing::debug!(
+                    "Adding user `{}` to group `{}` already complete",
+                    this.name,
+                    this.groupname
+                );
+                return Ok(StatefulAction::completed(this));
+            }
+            return Ok(StatefulAction::uncompleted(this));
+        }
+
         match OperatingSystem::host() {
             OperatingSystem::MacOSX { .. } | OperatingSystem::Darwin => (),
             _ => {
                 if !(which::which("addgroup").is_ok() || which::which("gpasswd").is_ok()) {
-                    return Err(Self::error(ActionErrorKind::MissingAddUserToGroupCommand));
-                }
-                if !(which::which("delgroup").is_ok() || which::which("gpasswd").is_ok()) {
-                    return Err(Self::error(
-                        ActionErrorKind::MissingRemoveUserFromGroupCommand,
-                    ));
+                    tracing::debug!(
+                        "Neither `gpasswd` nor `addgroup` found, `/etc/group` will be edited directly"
+                    );
                 }
             },
         }
@@ -120,6 +143,21 @@ impl AddUserToGroup {
                         },
                     };
                 },
+                _ if which::which("groups").is_err() => {
+                    let user_in_group = database
+                        .user_in_group(&this.name, &this.groupname)
+                        .await
+                        .map_err(Self::error)?;
+
+                    if user_in_group {
+                        tracing::debug!(
+                            "Adding user `{}` to group `{}` already complete",
+                            this.name,
+                            this.groupname
+                        );
+                        return Ok(StatefulAction::completed(this));
+                    }
+                },
                 _ => {
                     let output = execute_command(
                         Command::new("groups")
@@ -172,6 +210,15 @@ impl Action for AddUserToGroup {
         )
     }
 
+    fn resources(&self) -> Vec<ActionResource> {
+        vec![
+            ActionResource::User(self.name.clone()),
+            ActionResource::Group(self.groupname.clone()),
+            ActionResource::path(rebase(&self.root, "/etc/group")),
+            ActionResource::path(rebase(&self.root, "/etc/gshadow")),
+        ]
+    }
+
     fn execute_description(&self) -> Vec<ActionDescription> {
         vec![ActionDescription::new(
             self.tracing_synopsis(),
@@ -188,6 +235,7 @@ impl Action for AddUserToGroup {
             uid: _,
             groupname,
             gid: _,
+            root,
         } = self;
 
         use target_lexicon::OperatingSystem;
@@ -227,7 +275,8 @@ impl Action for AddUserToGroup {
                 .map_err(Self::error)?;
             },
             _ => {
-                if which::which("gpasswd").is_ok() {
+                let database = UserDatabase::new(root.clone());
+                if database.is_host() && which::which("gpasswd").is_ok() {
                     execute_command(
                         Command::new("gpasswd")
                             .process_group(0)
@@ -237,7 +286,7 @@ impl Action for AddUserToGroup {
                     )
                     .await
                     .map_err(Self::error)?;
-                } else if which::which("addgroup").is_ok() {
+                } else if database.is_host() && which::which("addgroup").is_ok() {
                     execute_command(
                         Command::new("addgroup")
                             .process_group(0)
@@ -247,9 +296,10 @@ impl Action for AddUserToGroup {
                     .await
                     .map_err(Self::error)?;
                 } else {
-                    return Err(Self::error(Self::error(
-                        ActionErrorKind::MissingAddUserToGroupCommand,
-                    )));
+                    database
+                        .add_user_to_group(name, groupname)
+                        .await
+                        .map_err(Self::error)?;
                 }
             },
         }
@@ -276,6 +326,7 @@ impl Action for AddUserToGroup {
             uid: _,
             groupname,
             gid: _,
+            root,
         } = self;
 
         use target_lexicon::OperatingSystem;
@@ -297,7 +348,8 @@ impl Action for AddUserToGroup {
                 .map_err(Self::error)?;
             },
             _ => {
-                if which::which("gpasswd").is_ok() {
+                let database = UserDatabase::new(root.clone());
+                if database.is_host() && which::which("gpasswd").is_ok() {
                     execute_command(
                         Command::new("gpasswd")
                             .process_group(0)
@@ -307,7 +359,7 @@ impl Action for AddUserToGroup {
                     )
                     .await
                     .map_err(Self::error)?;
-                } else if which::which("delgroup").is_ok() {
+                } else if database.is_host() && which::which("delgroup").is_ok() {
                     execute_command(
                         Command::new("delgroup")
                             .process_group(0)
@@ -317,9 +369,10 @@ impl Action for AddUserToGroup {
                     .await
                     .map_err(Self::error)?;
                 } else {
-                    return Err(Self::error(
-                        ActionErrorKind::MissingRemoveUserFromGroupCommand,
-                    ));
+                    database
+                        .remove_user_from_group(name, groupname)
+                        .await
+                        .map_err(Self::error)?;
                 }
             },
         };
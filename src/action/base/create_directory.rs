@@ -7,7 +7,7 @@ use tokio::fs::{create_dir, remove_dir_all, remove_file};
 use tokio::process::Command;
 use tracing::{span, Span};
 
-use crate::action::{Action, ActionDescription, ActionErrorKind, ActionState};
+use crate::action::{Action, ActionDescription, ActionErrorKind, ActionResource, ActionState};
 use crate::action::{ActionError, StatefulAction};
 use crate::execute_command;
 
@@ -137,6 +137,17 @@ impl Action for CreateDirectory {
         )
     }
 
+    fn resources(&self) -> Vec<ActionResource> {
+        let mut resources = vec![ActionResource::Path(self.path.clone())];
+        if let Some(user) = &self.user {
+            resources.push(ActionResource::User(user.clone()));
+        }
+        if let Some(group) = &self.group {
+            resources.push(ActionResource::Group(group.clone()));
+        }
+        resources
+    }
+
     fn execute_description(&self) -> Vec<ActionDescription> {
         vec![ActionDescription::new(self.tracing_synopsis(), vec![])]
     }
@@ -11,7 +11,8 @@ use tokio::{
 };
 
 use crate::action::{
-    Action, ActionDescription, ActionError, ActionErrorKind, ActionTag, StatefulAction,
+    Action, ActionDescription, ActionError, ActionErrorKind, ActionResource, ActionTag,
+    StatefulAction,
 };
 
 /** Create a file at the given location with the provided `buf`,
@@ -171,6 +172,17 @@ impl Action for CreateFile {
         span
     }
 
+    fn resources(&self) -> Vec<ActionResource> {
+        let mut resources = vec![ActionResource::Path(self.path.clone())];
+        if let Some(user) = &self.user {
+            resources.push(ActionResource::User(user.clone()));
+        }
+        if let Some(group) = &self.group {
+            resources.push(ActionResource::Group(group.clone()));
+        }
+        resources
+    }
+
     fn execute_description(&self) -> Vec<ActionDescription> {
         vec![ActionDescription::new(self.tracing_synopsis(), vec![])]
     }
@@ -1,38 +1,64 @@
+use std::path::PathBuf;
+
 use nix::unistd::Group;
 use target_lexicon::OperatingSystem;
 use tokio::process::Command;
 use tracing::{span, Span};
 
-use crate::action::{ActionError, ActionErrorKind, ActionTag};
+use crate::action::{ActionError, ActionErrorKind, ActionResource, ActionTag};
 use crate::execute_command;
+use crate::os::linux::UserDatabase;
+use crate::settings::{default_root, rebase};
 
 use crate::action::{Action, ActionDescription, StatefulAction};
 
 /**
 Create an operating system level user group
+
+On Linux, when `root` is not `/` the group is added to the `/etc/group` under it.
 */
 #[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
 pub struct CreateGroup {
     name: String,
     gid: u32,
+    #[serde(default = "default_root")]
+    root: PathBuf,
 }
 
 impl CreateGroup {
     #[tracing::instrument(level = "debug", skip_all)]
-    pub fn plan(name: String, gid: u32) -> Result<StatefulAction<Self>, ActionError> {
+    pub async fn plan(
+        name: String,
+        gid: u32,
+        root: PathBuf,
+    ) -> Result<StatefulAction<Self>, ActionError> {
         let this = Self {
             name: name.clone(),
             gid,
+            root,
         };
 
+        let database = UserDatabase::new(this.root.clone());
+        if !database.is_host() {
+            return match database.group(&name).await.map_err(Self::error)? {
+                Some(existing_gid) if existing_gid != gid => Err(Self::error(
+                    ActionErrorKind::GroupGidMismatch(name, existing_gid, gid),
+                )),
+                Some(_) => {
+                    tracing::debug!("Creating group `{}` already complete", this.name);
+                    Ok(StatefulAction::completed(this))
+                },
+                None => Ok(StatefulAction::uncompleted(this)),
+            };
+        }
+
         match OperatingSystem::host() {
             OperatingSystem::MacOSX { .. } | OperatingSystem::Darwin => (),
             _ => {
                 if !(which::which("groupadd").is_ok() || which::which("addgroup").is_ok()) {
-                    return Err(Self::error(ActionErrorKind::MissingGroupCreationCommand));
-                }
-                if !(which::which("groupdel").is_ok() || which::which("delgroup").is_ok()) {
-                    return Err(Self::error(ActionErrorKind::MissingGroupDeletionCommand));
+                    tracing::debug!(
+                        "Neither `groupadd` nor `addgroup` found, `/etc/group` will be edited directly"
+                    );
                 }
             },
         }
@@ -67,7 +93,11 @@ impl Action for CreateGroup {
         format!("Create group `{}` (GID {})", self.name, self.gid)
     }
     fn execute_description(&self) -> Vec<ActionDescription> {
-        let Self { name: _, gid: _ } = &self;
+        let Self {
+            name: _,
+            gid: _,
+            root: _,
+        } = &self;
         vec![ActionDescription::new(
             self.tracing_synopsis(),
             vec![format!(
@@ -85,9 +115,17 @@ impl Action for CreateGroup {
         )
     }
 
+    fn resources(&self) -> Vec<ActionResource> {
+        vec![
+            ActionResource::Group(self.name.clone()),
+            ActionResource::path(rebase(&self.root, "/etc/group")),
+            ActionResource::path(rebase(&self.root, "/etc/gshadow")),
+        ]
+    }
+
     #[tracing::instrument(level = "debug", skip_all)]
     async fn execute(&mut self) -> Result<(), ActionError> {
-        let Self { name, gid } = self;
+        let Self { name, gid, root } = self;
 
         use OperatingSystem;
         match OperatingSystem::host() {
@@ -115,7 +153,8 @@ impl Action for CreateGroup {
                 .map_err(Self::error)?;
             },
             _ => {
-                if which::which("groupadd").is_ok() {
+                let database = UserDatabase::new(root.clone());
+                if database.is_host() && which::which("groupadd").is_ok() {
                     execute_command(
                         Command::new("groupadd")
                             .process_group(0)
@@ -124,7 +163,7 @@ impl Action for CreateGroup {
                     )
                     .await
                     .map_err(Self::error)?;
-                } else if which::which("addgroup").is_ok() {
+                } else if database.is_host() && which::which("addgroup").is_ok() {
                     execute_command(
                         Command::new("addgroup")
                             .process_group(0)
@@ -134,7 +173,7 @@ impl Action for CreateGroup {
                     .await
                     .map_err(Self::error)?;
                 } else {
-                    return Err(Self::error(ActionErrorKind::MissingGroupCreationCommand));
+                    database.add_group(name, *gid).await.map_err(Self::error)?;
                 }
             },
         };
@@ -143,7 +182,7 @@ impl Action for CreateGroup {
     }
 
     fn revert_description(&self) -> Vec<ActionDescription> {
-        let Self { name, gid } = &self;
+        let Self { name, gid, .. } = &self;
         vec![ActionDescription::new(
             format!("Delete group `{name}` (GID {gid})"),
             vec![format!(
@@ -154,7 +193,7 @@ impl Action for CreateGroup {
 
     #[tracing::instrument(level = "debug", skip_all)]
     async fn revert(&mut self) -> Result<(), ActionError> {
-        let Self { name, gid: _ } = self;
+        let Self { name, gid: _, root } = self;
 
         use OperatingSystem;
         match OperatingSystem::host() {
@@ -173,7 +212,8 @@ impl Action for CreateGroup {
                 .map_err(Self::error)?;
             },
             _ => {
-                if which::which("groupdel").is_ok() {
+                let database = UserDatabase::new(root.clone());
+                if database.is_host() && which::which("groupdel").is_ok() {
                     execute_command(
                         Command::new("groupdel")
                             .process_group(0)
@@ -182,7 +222,7 @@ impl Action for CreateGroup {
                     )
                     .await
                     .map_err(Self::error)?;
-                } else if which::which("delgroup").is_ok() {
+                } else if database.is_host() && which::which("delgroup").is_ok() {
                     execute_command(
                         Command::new("delgroup")
                             .process_group(0)
@@ -192,7 +232,7 @@ impl Action for CreateGroup {
                     .await
                     .map_err(Self::error)?;
                 } else {
-                    return Err(Self::error(ActionErrorKind::MissingGroupDeletionCommand));
+                    database.delete_group(name).await.map_err(Self::error)?;
                 }
             },
         };
@@ -1,7 +1,8 @@
 use nix::unistd::{chown, Group, User};
 
 use crate::action::{
-    Action, ActionDescription, ActionError, ActionErrorKind, ActionTag, StatefulAction,
+    Action, ActionDescription, ActionError, ActionErrorKind, ActionResource, ActionTag,
+    StatefulAction,
 };
 use rand::Rng;
 use std::{
@@ -176,6 +177,17 @@ impl Action for CreateOrInsertIntoFile {
         span
     }
 
+    fn resources(&self) -> Vec<ActionResource> {
+        let mut resources = vec![ActionResource::Path(self.path.clone())];
+        if let Some(user) = &self.user {
+            resources.push(ActionResource::User(user.clone()));
+        }
+        if let Some(group) = &self.group {
+            resources.push(ActionResource::Group(group.clone()));
+        }
+        resources
+    }
+
     fn execute_description(&self) -> Vec<ActionDescription> {
         vec![ActionDescription::new(self.tracing_synopsis(), vec![])]
     }
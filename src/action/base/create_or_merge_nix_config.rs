@@ -12,7 +12,8 @@ use tokio::{
 use tracing::{span, Span};
 
 use crate::action::{
-    Action, ActionDescription, ActionError, ActionErrorKind, ActionTag, StatefulAction,
+    Action, ActionDescription, ActionError, ActionErrorKind, ActionResource, ActionTag,
+    StatefulAction,
 };
 
 /// The `nix.conf` configuration names that are safe to merge.
@@ -211,6 +212,10 @@ impl Action for CreateOrMergeNixConfig {
         span
     }
 
+    fn resources(&self) -> Vec<ActionResource> {
+        vec![ActionResource::Path(self.path.clone())]
+    }
+
     fn execute_description(&self) -> Vec<ActionDescription> {
         vec![ActionDescription::new(
             self.tracing_synopsis(),
@@ -0,0 +1,484 @@
+use std::{
+    ops::Range,
+    os::unix::fs::{MetadataExt, PermissionsExt},
+    path::{Path, PathBuf},
+};
+
+use nix::unistd::{chown, Gid, Group, Uid, User};
+use rand::Rng;
+use tokio::fs::OpenOptions;
+use tokio::io::AsyncWriteExt;
+use tracing::{span, Span};
+
+use crate::action::{
+    base::create_or_insert_into_file::Position, Action, ActionDescription, ActionError,
+    ActionErrorKind, ActionResource, ActionTag, StatefulAction,
+};
+
+/** Maintain a block of lines between a `begin` and an `end` marker line in a file, creating the file if needed
+
+The block is found by its markers rather than its content, so a block written by an older version (or edited since)
+is updated in place, and reverting removes the whole block whatever it contains by then. If the file holds more than
+one block, they are collapsed into one. An existing file keeps its mode and owner, `mode`, `user` and `group` only
+apply to a created one.
+ */
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
+pub struct CreateOrUpdateManagedBlock {
+    path: PathBuf,
+    #[serde(default)]
+    user: Option<String>,
+    #[serde(default)]
+    group: Option<String>,
+    mode: Option<u32>,
+    begin: String,
+    end: String,
+    body: String,
+    position: Position,
+}
+
+impl CreateOrUpdateManagedBlock {
+    #[allow(clippy::too_many_arguments)]
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub async fn plan(
+        path: impl AsRef<Path>,
+        user: impl Into<Option<String>>,
+        group: impl Into<Option<String>>,
+        mode: impl Into<Option<u32>>,
+        begin: impl Into<String>,
+        end: impl Into<String>,
+        body: String,
+        position: Position,
+    ) -> Result<StatefulAction<Self>, ActionError> {
+        let this = Self {
+            path: path.as_ref().to_path_buf(),
+            user: user.into(),
+            group: group.into(),
+            mode: mode.into(),
+            begin: begin.into(),
+            end: end.into(),
+            body,
+            position,
+        };
+
+        if this.path.exists() {
+            if !this.path.is_file() {
+                return Err(Self::error(ActionErrorKind::PathWasNotFile(this.path)));
+            }
+            let content = tokio::fs::read_to_string(&this.path)
+                .await
+                .map_err(|e| ActionErrorKind::Read(this.path.clone(), e))
+                .map_err(Self::error)?;
+            let blocks = find_blocks(&content, &this.begin, &this.end);
+            if blocks.len() > 1 {
+                tracing::warn!(
+                    "Found {} `{}` blocks in `{}`, they will be collapsed into one",
+                    blocks.len(),
+                    this.begin,
+                    this.path.display()
+                );
+            }
+            if upsert_block(&content, &this.begin, &this.end, &this.body, &this.position) == content
+            {
+                tracing::debug!(
+                    "Updating the `{}` block in `{}` already complete",
+                    this.begin,
+                    this.path.display()
+                );
+                return Ok(StatefulAction::completed(this));
+            }
+        }
+
+        Ok(StatefulAction::uncompleted(this))
+    }
+}
+
+#[async_trait::async_trait]
+#[typetag::serde(name = "create_or_update_managed_block")]
+impl Action for CreateOrUpdateManagedBlock {
+    fn action_tag() -> ActionTag {
+        ActionTag("create_or_update_managed_block")
+    }
+    fn tracing_synopsis(&self) -> String {
+        format!(
+            "Create or update the `{}` block in `{}`",
+            self.begin,
+            self.path.display()
+        )
+    }
+
+    fn tracing_span(&self) -> Span {
+        let span = span!(
+            tracing::Level::DEBUG,
+            "create_or_update_managed_block",
+            path = tracing::field::display(self.path.display()),
+            begin = self.begin,
+            body = tracing::field::Empty,
+        );
+
+        if tracing::enabled!(tracing::Level::TRACE) {
+            span.record("body", &self.body);
+        }
+        span
+    }
+
+    fn resources(&self) -> Vec<ActionResource> {
+        let mut resources = vec![ActionResource::Path(self.path.clone())];
+        if let Some(user) = &self.user {
+            resources.push(ActionResource::User(user.clone()));
+        }
+        if let Some(group) = &self.group {
+            resources.push(ActionResource::Group(group.clone()));
+        }
+        resources
+    }
+
+    fn execute_description(&self) -> Vec<ActionDescription> {
+        vec![ActionDescription::new(self.tracing_synopsis(), vec![])]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn execute(&mut self) -> Result<(), ActionError> {
+        let (content, metadata) = match tokio::fs::read_to_string(&self.path).await {
+            Ok(content) => {
+                let metadata = tokio::fs::metadata(&self.path)
+                    .await
+                    .map_err(|e| ActionErrorKind::GettingMetadata(self.path.clone(), e))
+                    .map_err(Self::error)?;
+                (content, Some(metadata))
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (String::new(), None),
+            Err(e) => return Err(Self::error(ActionErrorKind::Read(self.path.clone(), e))),
+        };
+
+        let updated = upsert_block(&content, &self.begin, &self.end, &self.body, &self.position);
+        let mode = match &metadata {
+            Some(metadata) => metadata.permissions().mode(),
+            None => self.mode.unwrap_or(0o644),
+        };
+        let owner = match &metadata {
+            Some(metadata) => Some((Uid::from_raw(metadata.uid()), Gid::from_raw(metadata.gid()))),
+            None if self.user.is_some() || self.group.is_some() => {
+                let uid = match &self.user {
+                    Some(user) => {
+                        User::from_name(user.as_str())
+                            .map_err(|e| ActionErrorKind::GettingUserId(user.clone(), e))
+                            .map_err(Self::error)?
+                            .ok_or(ActionErrorKind::NoUser(user.clone()))
+                            .map_err(Self::error)?
+                            .uid
+                    },
+                    None => Uid::effective(),
+                };
+                let gid = match &self.group {
+                    Some(group) => {
+                        Group::from_name(group.as_str())
+                            .map_err(|e| ActionErrorKind::GettingGroupId(group.clone(), e))
+                            .map_err(Self::error)?
+                            .ok_or(ActionErrorKind::NoGroup(group.clone()))
+                            .map_err(Self::error)?
+                            .gid
+                    },
+                    None => Gid::effective(),
+                };
+                Some((uid, gid))
+            },
+            None => None,
+        };
+        write_atomically(&self.path, &updated, mode, owner)
+            .await
+            .map_err(Self::error)?;
+
+        Ok(())
+    }
+
+    fn revert_description(&self) -> Vec<ActionDescription> {
+        vec![ActionDescription::new(
+            format!(
+                "Remove the `{}` block from `{}`",
+                self.begin,
+                self.path.display()
+            ),
+            vec![format!(
+                "Everything from a `{}` line to the next `{}` line is removed, even if it was edited",
+                self.begin, self.end
+            )],
+        )]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn revert(&mut self) -> Result<(), ActionError> {
+        let content = match tokio::fs::read_to_string(&self.path).await {
+            Ok(content) => content,
+            // The user already deleted it
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(Self::error(ActionErrorKind::Read(self.path.clone(), e))),
+        };
+
+        let updated = remove_blocks(&content, &self.begin, &self.end);
+        if updated == content {
+            return Ok(());
+        }
+
+        if updated.trim().is_empty() {
+            tokio::fs::remove_file(&self.path)
+                .await
+                .map_err(|e| ActionErrorKind::Remove(self.path.clone(), e))
+                .map_err(Self::error)?;
+        } else {
+            let metadata = tokio::fs::metadata(&self.path)
+                .await
+                .map_err(|e| ActionErrorKind::GettingMetadata(self.path.clone(), e))
+                .map_err(Self::error)?;
+            write_atomically(
+                &self.path,
+                &updated,
+                metadata.permissions().mode(),
+                Some((Uid::from_raw(metadata.uid()), Gid::from_raw(metadata.gid()))),
+            )
+            .await
+            .map_err(Self::error)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Replace `path` with `content` through a temporary file in the same directory, so it is never half written
+async fn write_atomically(
+    path: &Path,
+    content: &str,
+    mode: u32,
+    owner: Option<(Uid, Gid)>,
+) -> Result<(), ActionErrorKind> {
+    let parent_dir = path.parent().expect("File must be in a directory");
+    let temp_file_path = parent_dir.join(format!(
+        "nix-installer-tmp.{}",
+        rand::thread_rng().gen::<u32>()
+    ));
+    let mut temp_file = OpenOptions::new()
+        .create_new(true)
+        .write(true)
+        .mode(0o600)
+        .open(&temp_file_path)
+        .await
+        .map_err(|e| ActionErrorKind::Open(temp_file_path.clone(), e))?;
+    temp_file
+        .write_all(content.as_bytes())
+        .await
+        .map_err(|e| ActionErrorKind::Write(temp_file_path.clone(), e))?;
+    temp_file
+        .flush()
+        .await
+        .map_err(|e| ActionErrorKind::Flush(temp_file_path.clone(), e))?;
+    drop(temp_file);
+
+    if let Some((uid, gid)) = owner {
+        chown(&temp_file_path, Some(uid), Some(gid))
+            .map_err(|e| ActionErrorKind::Chown(path.to_path_buf(), e))?;
+    }
+    tokio::fs::set_permissions(&temp_file_path, PermissionsExt::from_mode(mode))
+        .await
+        .map_err(|e| ActionErrorKind::SetPermissions(mode, path.to_path_buf(), e))?;
+    tokio::fs::rename(&temp_file_path, path)
+        .await
+        .map_err(|e| ActionErrorKind::Rename(path.to_path_buf(), temp_file_path.clone(), e))?;
+
+    Ok(())
+}
+
+/// The line ranges of the blocks in `content`, from a `begin` marker line to the following `end` marker line
+///
+/// A `begin` marker without an `end` is not a block, it's left alone rather than guessing where it stops.
+fn find_blocks(content: &str, begin: &str, end: &str) -> Vec<Range<usize>> {
+    let lines: Vec<&str> = content.lines().collect();
+    let mut blocks = vec![];
+    let mut index = 0;
+    while index < lines.len() {
+        if lines[index].trim() == begin {
+            if let Some(offset) = lines[index + 1..]
+                .iter()
+                .position(|line| line.trim() == end)
+            {
+                let block_end = index + 1 + offset + 1;
+                blocks.push(index..block_end);
+                index = block_end;
+                continue;
+            }
+        }
+        index += 1;
+    }
+    blocks
+}
+
+/// The lines of the block, markers included
+fn block_lines<'a>(begin: &'a str, end: &'a str, body: &'a str) -> Vec<&'a str> {
+    let mut lines = vec![begin];
+    lines.extend(body.lines());
+    lines.push(end);
+    lines
+}
+
+/// `content` with the first block replaced by one holding `body`, or with one inserted at `position` if there is
+/// none, and any others removed
+fn upsert_block(content: &str, begin: &str, end: &str, body: &str, position: &Position) -> String {
+    let blocks = find_blocks(content, begin, end);
+    let lines: Vec<&str> = content.lines().collect();
+    let block = block_lines(begin, end, body);
+
+    let mut updated: Vec<&str> = Vec::with_capacity(lines.len() + block.len() + 1);
+    match blocks.first() {
+        Some(first) => {
+            let mut index = 0;
+            for range in &blocks {
+                updated.extend(&lines[index..range.start]);
+                if range == first {
+                    updated.extend(&block);
+                }
+                index = range.end;
+            }
+            updated.extend(&lines[index..]);
+        },
+        None => match position {
+            Position::Beginning => {
+                updated.extend(&block);
+                if !lines.is_empty() {
+                    updated.push("");
+                }
+                updated.extend(&lines);
+            },
+            Position::End => {
+                updated.extend(&lines);
+                if lines.last().is_some_and(|line| !line.trim().is_empty()) {
+                    updated.push("");
+                }
+                updated.extend(&block);
+            },
+        },
+    }
+
+    join_lines(&updated)
+}
+
+/// `content` without any of the blocks, along with the blank line which separated each from the rest of the file
+fn remove_blocks(content: &str, begin: &str, end: &str) -> String {
+    let blocks = find_blocks(content, begin, end);
+    if blocks.is_empty() {
+        return content.to_string();
+    }
+    let lines: Vec<&str> = content.lines().collect();
+
+    let mut updated: Vec<&str> = Vec::with_capacity(lines.len());
+    let mut index = 0;
+    for range in &blocks {
+        updated.extend(&lines[index..range.start]);
+        index = range.end;
+        let followed_by_blank = lines.get(index).is_some_and(|line| line.trim().is_empty());
+        if range.start == 0 && followed_by_blank {
+            index += 1;
+        } else if updated.last().is_some_and(|line| line.trim().is_empty()) {
+            updated.pop();
+        }
+    }
+    updated.extend(&lines[index..]);
+
+    join_lines(&updated)
+}
+
+fn join_lines(lines: &[&str]) -> String {
+    if lines.is_empty() {
+        return String::new();
+    }
+    let mut joined = lines.join("\n");
+    joined.push('\n');
+    joined
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const BODY: &str = "if [ -e '/nix/nix-daemon.sh' ]; then\n    . '/nix/nix-daemon.sh'\nfi\n";
+
+    #[test]
+    fn inserts_updates_and_removes_blocks() {
+        let original = "export EDITOR=vi\n";
+        let inserted = upsert_block(original, "# Nix", "# End Nix", BODY, &Position::Beginning);
+        assert_eq!(
+            inserted,
+            format!("# Nix\n{BODY}# End Nix\n\nexport EDITOR=vi\n")
+        );
+        assert_eq!(
+            upsert_block(original, "# Nix", "# End Nix", BODY, &Position::End),
+            format!("export EDITOR=vi\n\n# Nix\n{BODY}# End Nix\n")
+        );
+
+        // A block which was reformatted (or written by an older version) is replaced in place
+        let edited = "umask 022\n\n# Nix\nif [ -e /old ]; then . /old; fi\n  # End Nix  \nalias ll='ls -l'\n";
+        assert_eq!(
+            upsert_block(edited, "# Nix", "# End Nix", BODY, &Position::Beginning),
+            format!("umask 022\n\n# Nix\n{BODY}# End Nix\nalias ll='ls -l'\n")
+        );
+
+        // Duplicates are collapsed into the first
+        let duplicated = "# Nix\nold\n# End Nix\nexport A=1\n# Nix\nolder\n# End Nix\n";
+        assert_eq!(
+            upsert_block(duplicated, "# Nix", "# End Nix", BODY, &Position::Beginning),
+            format!("# Nix\n{BODY}# End Nix\nexport A=1\n")
+        );
+
+        // Reverting removes the block whatever it contains, with its separating blank line
+        assert_eq!(remove_blocks(&inserted, "# Nix", "# End Nix"), original);
+        assert_eq!(
+            remove_blocks(
+                &upsert_block(original, "# Nix", "# End Nix", BODY, &Position::End),
+                "# Nix",
+                "# End Nix"
+            ),
+            original
+        );
+        assert_eq!(
+            remove_blocks(edited, "# Nix", "# End Nix"),
+            "umask 022\nalias ll='ls -l'\n"
+        );
+
+        // An unterminated block is left alone
+        let unterminated = "# Nix\nexport A=1\n";
+        assert!(find_blocks(unterminated, "# Nix", "# End Nix").is_empty());
+        assert_eq!(
+            remove_blocks(unterminated, "# Nix", "# End Nix"),
+            unterminated
+        );
+    }
+
+    #[tokio::test]
+    async fn reverts_edited_block() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let test_file = temp_dir.path().join("bashrc");
+        tokio::fs::write(&test_file, "export EDITOR=vi\n").await?;
+
+        let mut action = CreateOrUpdateManagedBlock::plan(
+            &test_file,
+            None,
+            None,
+            0o644,
+            "# Nix",
+            "# End Nix",
+            BODY.to_string(),
+            Position::Beginning,
+        )
+        .await?;
+        action.try_execute().await?;
+
+        // Someone reformats the block after install
+        let content = tokio::fs::read_to_string(&test_file).await?;
+        tokio::fs::write(&test_file, content.replace("    . ", "\t. ")).await?;
+
+        action.try_revert().await?;
+        assert_eq!(
+            tokio::fs::read_to_string(&test_file).await?,
+            "export EDITOR=vi\n"
+        );
+
+        Ok(())
+    }
+}
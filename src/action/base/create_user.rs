@@ -1,15 +1,21 @@
+use std::path::PathBuf;
+
 use nix::unistd::User;
 use target_lexicon::OperatingSystem;
 use tokio::process::Command;
 use tracing::{span, Span};
 
-use crate::action::{ActionError, ActionErrorKind, ActionTag};
+use crate::action::{ActionError, ActionErrorKind, ActionResource, ActionTag};
 use crate::execute_command;
+use crate::os::linux::UserDatabase;
+use crate::settings::{default_root, rebase};
 
 use crate::action::{Action, ActionDescription, StatefulAction};
 
 /**
 Create an operating system level user in the given group
+
+On Linux, when `root` is not `/` the user is added to the `/etc/passwd` under it.
 */
 #[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
 pub struct CreateUser {
@@ -18,6 +24,8 @@ pub struct CreateUser {
     groupname: String,
     gid: u32,
     comment: String,
+    #[serde(default = "default_root")]
+    root: PathBuf,
 }
 
 impl CreateUser {
@@ -28,6 +36,7 @@ impl CreateUser {
         groupname: String,
         gid: u32,
         comment: String,
+        root: PathBuf,
     ) -> Result<StatefulAction<Self>, ActionError> {
         let this = Self {
             name: name.clone(),
@@ -35,16 +44,33 @@ impl CreateUser {
             groupname,
             gid,
             comment,
+            root,
         };
 
+        let database = UserDatabase::new(this.root.clone());
+        if !database.is_host() {
+            return match database.user(&name).await.map_err(Self::error)? {
+                Some((existing_uid, _)) if existing_uid != uid => Err(Self::error(
+                    ActionErrorKind::UserUidMismatch(name, existing_uid, uid),
+                )),
+                Some((_, existing_gid)) if existing_gid != gid => Err(Self::error(
+                    ActionErrorKind::UserGidMismatch(name, existing_gid, gid),
+                )),
+                Some(_) => {
+                    tracing::debug!("Creating user `{}` already complete", this.name);
+                    Ok(StatefulAction::completed(this))
+                },
+                None => Ok(StatefulAction::uncompleted(this)),
+            };
+        }
+
         match OperatingSystem::host() {
             OperatingSystem::MacOSX { .. } | OperatingSystem::Darwin => (),
             _ => {
                 if !(which::which("useradd").is_ok() || which::which("adduser").is_ok()) {
-                    return Err(Self::error(ActionErrorKind::MissingUserCreationCommand));
-                }
-                if !(which::which("userdel").is_ok() || which::which("deluser").is_ok()) {
-                    return Err(Self::error(ActionErrorKind::MissingUserDeletionCommand));
+                    tracing::debug!(
+                        "Neither `useradd` nor `adduser` found, `/etc/passwd` will be edited directly"
+                    );
                 }
             },
         }
@@ -102,6 +128,17 @@ impl Action for CreateUser {
         )
     }
 
+    fn resources(&self) -> Vec<ActionResource> {
+        vec![
+            ActionResource::User(self.name.clone()),
+            ActionResource::Group(self.groupname.clone()),
+            ActionResource::path(rebase(&self.root, "/etc/passwd")),
+            ActionResource::path(rebase(&self.root, "/etc/shadow")),
+            ActionResource::path(rebase(&self.root, "/etc/group")),
+            ActionResource::path(rebase(&self.root, "/etc/gshadow")),
+        ]
+    }
+
     fn execute_description(&self) -> Vec<ActionDescription> {
         vec![ActionDescription::new(
             self.tracing_synopsis(),
@@ -119,6 +156,7 @@ impl Action for CreateUser {
             groupname,
             gid,
             comment,
+            root,
         } = self;
 
         use OperatingSystem;
@@ -203,7 +241,8 @@ impl Action for CreateUser {
                 .map_err(Self::error)?;
             },
             _ => {
-                if which::which("useradd").is_ok() {
+                let database = UserDatabase::new(root.clone());
+                if database.is_host() && which::which("useradd").is_ok() {
                     execute_command(
                         Command::new("useradd")
                             .process_group(0)
@@ -230,7 +269,7 @@ impl Action for CreateUser {
                     )
                     .await
                     .map_err(Self::error)?;
-                } else if which::which("adduser").is_ok() {
+                } else if database.is_host() && which::which("adduser").is_ok() {
                     execute_command(
                         Command::new("adduser")
                             .process_group(0)
@@ -255,7 +294,10 @@ impl Action for CreateUser {
                     .await
                     .map_err(Self::error)?;
                 } else {
-                    return Err(Self::error(ActionErrorKind::MissingUserCreationCommand));
+                    database
+                        .add_user(name, *uid, *gid, comment, "/var/empty", "/sbin/nologin")
+                        .await
+                        .map_err(Self::error)?;
                 }
             },
         }
@@ -316,7 +358,8 @@ impl Action for CreateUser {
                 }
             },
             _ => {
-                if which::which("userdel").is_ok() {
+                let database = UserDatabase::new(self.root.clone());
+                if database.is_host() && which::which("userdel").is_ok() {
                     execute_command(
                         Command::new("userdel")
                             .process_group(0)
@@ -325,7 +368,7 @@ impl Action for CreateUser {
                     )
                     .await
                     .map_err(Self::error)?;
-                } else if which::which("deluser").is_ok() {
+                } else if database.is_host() && which::which("deluser").is_ok() {
                     execute_command(
                         Command::new("deluser")
                             .process_group(0)
@@ -335,7 +378,10 @@ impl Action for CreateUser {
                     .await
                     .map_err(Self::error)?;
                 } else {
-                    return Err(Self::error(ActionErrorKind::MissingUserDeletionCommand));
+                    database
+                        .delete_user(&self.name)
+                        .await
+                        .map_err(Self::error)?;
                 }
             },
         };
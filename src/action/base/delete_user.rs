@@ -3,8 +3,9 @@ use target_lexicon::OperatingSystem;
 use tokio::process::Command;
 use tracing::{span, Span};
 
-use crate::action::{ActionError, ActionErrorKind, ActionTag};
+use crate::action::{ActionError, ActionErrorKind, ActionResource, ActionTag};
 use crate::execute_command;
+use crate::os::linux::UserDatabase;
 
 use crate::action::{Action, ActionDescription, StatefulAction};
 
@@ -21,15 +22,6 @@ impl DeleteUser {
     pub async fn plan(name: String) -> Result<StatefulAction<Self>, ActionError> {
         let this = Self { name: name.clone() };
 
-        match OperatingSystem::host() {
-            OperatingSystem::MacOSX { .. } | OperatingSystem::Darwin => (),
-            _ => {
-                if !(which::which("userdel").is_ok() || which::which("deluser").is_ok()) {
-                    return Err(Self::error(ActionErrorKind::MissingUserDeletionCommand));
-                }
-            },
-        }
-
         // Ensure user exists
         let _ = User::from_name(name.as_str())
             .map_err(|e| ActionErrorKind::GettingUserId(name.clone(), e))
@@ -61,6 +53,16 @@ impl Action for DeleteUser {
         span!(tracing::Level::DEBUG, "delete_user", user = self.name,)
     }
 
+    fn resources(&self) -> Vec<ActionResource> {
+        vec![
+            ActionResource::User(self.name.clone()),
+            ActionResource::path("/etc/passwd"),
+            ActionResource::path("/etc/shadow"),
+            ActionResource::path("/etc/group"),
+            ActionResource::path("/etc/gshadow"),
+        ]
+    }
+
     fn execute_description(&self) -> Vec<ActionDescription> {
         vec![ActionDescription::new(
             self.tracing_synopsis(),
@@ -130,7 +132,10 @@ impl Action for DeleteUser {
                     .await
                     .map_err(Self::error)?;
                 } else {
-                    return Err(Self::error(ActionErrorKind::MissingUserDeletionCommand));
+                    UserDatabase::host()
+                        .delete_user(&self.name)
+                        .await
+                        .map_err(Self::error)?;
                 }
             },
         };
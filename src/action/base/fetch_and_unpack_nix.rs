@@ -5,7 +5,10 @@ use reqwest::Url;
 use tracing::{span, Span};
 
 use crate::{
-    action::{Action, ActionDescription, ActionError, ActionErrorKind, ActionTag, StatefulAction},
+    action::{
+        Action, ActionDescription, ActionError, ActionErrorKind, ActionResource, ActionTag,
+        StatefulAction,
+    },
     parse_ssl_cert,
     settings::UrlOrPath,
 };
@@ -91,6 +94,10 @@ impl Action for FetchAndUnpackNix {
         span
     }
 
+    fn resources(&self) -> Vec<ActionResource> {
+        vec![ActionResource::Path(self.dest.clone())]
+    }
+
     fn execute_description(&self) -> Vec<ActionDescription> {
         vec![ActionDescription::new(self.tracing_synopsis(), vec![])]
     }
@@ -1,33 +1,44 @@
-use std::{
-    os::unix::prelude::PermissionsExt,
-    path::{Path, PathBuf},
-};
+use std::{os::unix::prelude::PermissionsExt, path::PathBuf};
 
 use tracing::{span, Span};
 use walkdir::WalkDir;
 
 use crate::action::{
-    Action, ActionDescription, ActionError, ActionErrorKind, ActionTag, StatefulAction,
+    Action, ActionDescription, ActionError, ActionErrorKind, ActionResource, ActionTag,
+    StatefulAction,
 };
 
 pub(crate) const DEST: &str = "/nix/";
 
 /**
-Move an unpacked Nix at `src` to `/nix`
+Move an unpacked Nix at `src` to `dest` (usually `/nix`)
 */
 #[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
 pub struct MoveUnpackedNix {
     unpacked_path: PathBuf,
+    #[serde(default = "default_dest")]
+    dest: PathBuf,
 }
 
 impl MoveUnpackedNix {
     #[tracing::instrument(level = "debug", skip_all)]
-    pub async fn plan(unpacked_path: PathBuf) -> Result<StatefulAction<Self>, ActionError> {
+    pub async fn plan(
+        unpacked_path: PathBuf,
+        dest: PathBuf,
+    ) -> Result<StatefulAction<Self>, ActionError> {
         // Note: Do NOT try to check for the src/dest since the installer creates those
-        Ok(Self { unpacked_path }.into())
+        Ok(Self {
+            unpacked_path,
+            dest,
+        }
+        .into())
     }
 }
 
+fn default_dest() -> PathBuf {
+    PathBuf::from(DEST)
+}
+
 #[async_trait::async_trait]
 #[typetag::serde(name = "mount_unpacked_nix")]
 impl Action for MoveUnpackedNix {
@@ -35,7 +46,7 @@ impl Action for MoveUnpackedNix {
         ActionTag("move_unpacked_nix")
     }
     fn tracing_synopsis(&self) -> String {
-        "Move the downloaded Nix into `/nix`".to_string()
+        format!("Move the downloaded Nix into `{}`", self.dest.display())
     }
 
     fn tracing_span(&self) -> Span {
@@ -43,23 +54,34 @@ impl Action for MoveUnpackedNix {
             tracing::Level::DEBUG,
             "mount_unpacked_nix",
             src = tracing::field::display(self.unpacked_path.display()),
-            dest = DEST,
+            dest = tracing::field::display(self.dest.display()),
         )
     }
 
+    fn resources(&self) -> Vec<ActionResource> {
+        vec![
+            ActionResource::Path(self.unpacked_path.clone()),
+            ActionResource::path(self.dest.join("store")),
+        ]
+    }
+
     fn execute_description(&self) -> Vec<ActionDescription> {
         vec![ActionDescription::new(
-            "Move the downloaded Nix into `/nix`".to_string(),
+            self.tracing_synopsis(),
             vec![format!(
-                "Nix is being downloaded to `{}` and should be in `/nix`",
+                "Nix is being downloaded to `{}` and should be in `{}`",
                 self.unpacked_path.display(),
+                self.dest.display(),
             )],
         )]
     }
 
     #[tracing::instrument(level = "debug", skip_all)]
     async fn execute(&mut self) -> Result<(), ActionError> {
-        let Self { unpacked_path } = self;
+        let Self {
+            unpacked_path,
+            dest,
+        } = self;
 
         // This is the `nix-$VERSION` folder which unpacks from the tarball, not a nix derivation
         let found_nix_paths = glob::glob(&format!("{}/nix-*", unpacked_path.display()))
@@ -75,7 +97,7 @@ impl Action for MoveUnpackedNix {
             .await
             .map_err(|e| ActionErrorKind::ReadDir(src_store.clone(), e))
             .map_err(Self::error)?;
-        let dest_store = Path::new(DEST).join("store");
+        let dest_store = dest.join("store");
         if dest_store.exists() {
             if !dest_store.is_dir() {
                 return Err(Self::error(ActionErrorKind::PathWasNotDirectory(
@@ -3,7 +3,7 @@ use std::path::{Path, PathBuf};
 use tokio::fs::remove_dir_all;
 use tracing::{span, Span};
 
-use crate::action::{Action, ActionDescription, ActionErrorKind, ActionState};
+use crate::action::{Action, ActionDescription, ActionErrorKind, ActionResource, ActionState};
 use crate::action::{ActionError, StatefulAction};
 
 /** Remove a directory, does nothing on revert.
@@ -43,6 +43,10 @@ impl Action for RemoveDirectory {
         )
     }
 
+    fn resources(&self) -> Vec<ActionResource> {
+        vec![ActionResource::Path(self.path.clone())]
+    }
+
     fn execute_description(&self) -> Vec<ActionDescription> {
         vec![ActionDescription::new(self.tracing_synopsis(), vec![])]
     }
@@ -0,0 +1,172 @@
+use std::path::{Path, PathBuf};
+
+use tokio::process::Command;
+use tracing::{span, Span};
+
+use crate::action::{
+    Action, ActionDescription, ActionError, ActionErrorKind, ActionTag, StatefulAction,
+};
+use crate::execute_command;
+
+#[cfg(target_os = "linux")]
+const UNITS: &[&str] = &["nix-daemon.socket", "nix-daemon.service"];
+/// Where the upstream Nix install scripts link, enable or place the Nix daemon
+#[cfg(target_os = "linux")]
+const DAEMON_FILES: &[&str] = &[
+    "/etc/systemd/system/sockets.target.wants/nix-daemon.socket",
+    "/etc/systemd/system/multi-user.target.wants/nix-daemon.service",
+    "/etc/systemd/system/nix-daemon.socket",
+    "/etc/systemd/system/nix-daemon.service",
+    "/etc/tmpfiles.d/nix-daemon.conf",
+];
+#[cfg(target_os = "macos")]
+const DAEMON_FILES: &[&str] = &["/Library/LaunchDaemons/org.nixos.nix-daemon.plist"];
+
+/**
+Stop the Nix daemon set up by another installer and remove its units, does nothing on execute
+
+Unlike [`ConfigureInitService`](crate::action::common::ConfigureInitService), this copes with units which no longer
+resolve because the store they link into was removed.
+*/
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
+pub struct RevertForeignNixDaemon {
+    files: Vec<PathBuf>,
+}
+
+impl RevertForeignNixDaemon {
+    /// Plan the revert, if any of the files of the Nix daemon exist
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub async fn plan() -> Result<Option<StatefulAction<Self>>, ActionError> {
+        let files: Vec<PathBuf> = DAEMON_FILES
+            .iter()
+            .map(PathBuf::from)
+            // `exists` follows links, which may dangle
+            .filter(|v| v.is_symlink() || v.exists())
+            .collect();
+        if files.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(StatefulAction::completed(Self { files })))
+    }
+}
+
+#[async_trait::async_trait]
+#[typetag::serde(name = "revert_foreign_nix_daemon")]
+impl Action for RevertForeignNixDaemon {
+    fn action_tag() -> ActionTag {
+        ActionTag("revert_foreign_nix_daemon")
+    }
+    fn tracing_synopsis(&self) -> String {
+        "Stop and remove the Nix daemon set up by another installer".to_string()
+    }
+
+    fn tracing_span(&self) -> Span {
+        span!(tracing::Level::DEBUG, "revert_foreign_nix_daemon",)
+    }
+
+    fn execute_description(&self) -> Vec<ActionDescription> {
+        vec![]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn execute(&mut self) -> Result<(), ActionError> {
+        // noop
+
+        Ok(())
+    }
+
+    fn revert_description(&self) -> Vec<ActionDescription> {
+        vec![ActionDescription::new(
+            self.tracing_synopsis(),
+            self.files
+                .iter()
+                .map(|v| format!("Remove `{}`", v.display()))
+                .collect(),
+        )]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn revert(&mut self) -> Result<(), ActionError> {
+        let mut errors = vec![];
+
+        #[cfg(target_os = "linux")]
+        let systemd_is_running = Path::new("/run/systemd/system").exists();
+        #[cfg(target_os = "linux")]
+        if systemd_is_running {
+            for unit in UNITS {
+                let is_active = Command::new("systemctl")
+                    .args(["is-active", "--quiet", unit])
+                    .stdin(std::process::Stdio::null())
+                    .status()
+                    .await
+                    .is_ok_and(|status| status.success());
+                if !is_active {
+                    continue;
+                }
+                if let Err(err) = execute_command(
+                    Command::new("systemctl")
+                        .process_group(0)
+                        .args(["stop", unit])
+                        .stdin(std::process::Stdio::null()),
+                )
+                .await
+                {
+                    errors.push(Self::error(err));
+                }
+            }
+        }
+        #[cfg(target_os = "macos")]
+        for plist in self.files.iter().filter(|v| v.exists()) {
+            if let Err(err) = execute_command(
+                Command::new("launchctl")
+                    .process_group(0)
+                    .arg("unload")
+                    .arg(plist)
+                    .stdin(std::process::Stdio::null()),
+            )
+            .await
+            {
+                errors.push(Self::error(err));
+            }
+        }
+
+        for file in &self.files {
+            if let Err(err) = remove_if_present(file).await {
+                errors.push(Self::error(err));
+            }
+        }
+
+        #[cfg(target_os = "linux")]
+        if systemd_is_running {
+            if let Err(err) = execute_command(
+                Command::new("systemctl")
+                    .process_group(0)
+                    .arg("daemon-reload")
+                    .stdin(std::process::Stdio::null()),
+            )
+            .await
+            {
+                errors.push(Self::error(err));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else if errors.len() == 1 {
+            Err(errors
+                .into_iter()
+                .next()
+                .expect("Expected 1 len Vec to have at least 1 item"))
+        } else {
+            Err(Self::error(ActionErrorKind::MultipleChildren(errors)))
+        }
+    }
+}
+
+async fn remove_if_present(path: &Path) -> Result<(), ActionErrorKind> {
+    match tokio::fs::remove_file(path).await {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(ActionErrorKind::Remove(path.to_path_buf(), e)),
+    }
+}
@@ -0,0 +1,264 @@
+use std::path::{Path, PathBuf};
+
+use tracing::{span, Span};
+
+use crate::action::{
+    Action, ActionDescription, ActionError, ActionErrorKind, ActionResource, ActionTag,
+    StatefulAction,
+};
+
+/// The suffix the upstream Nix install scripts give the copy of a shell profile they make before editing it
+pub const BACKUP_SUFFIX: &str = ".backup-before-nix";
+const BLOCK_BEGIN: &str = "# Nix";
+const BLOCK_END: &str = "# End Nix";
+
+/**
+Undo the edit the upstream Nix install scripts made to a shell profile, does nothing on execute
+
+The scripts copy a profile to `<profile>.backup-before-nix` before appending a block between `# Nix` and `# End Nix`
+to it, or create the profile if it didn't exist. On [`revert`](RevertForeignShellProfile::revert) the block is removed,
+along with the profile if nothing else is left in it. Moving the backup back would lose any edit made to the profile
+since, so the backup is only removed if the profile is the same as it again, otherwise it's kept and reported.
+*/
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
+pub struct RevertForeignShellProfile {
+    path: PathBuf,
+    backup: Option<PathBuf>,
+}
+
+impl RevertForeignShellProfile {
+    /// Plan the revert of `path`, if the upstream scripts left a backup of it or their block in it
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub async fn plan(path: impl AsRef<Path>) -> Result<Option<StatefulAction<Self>>, ActionError> {
+        let path = path.as_ref().to_path_buf();
+
+        let backup = PathBuf::from(format!("{}{BACKUP_SUFFIX}", path.display()));
+        if backup.exists() {
+            return Ok(Some(StatefulAction::completed(Self {
+                path,
+                backup: Some(backup),
+            })));
+        }
+
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let content = tokio::fs::read_to_string(&path)
+            .await
+            .map_err(|e| ActionErrorKind::Read(path.clone(), e))
+            .map_err(Self::error)?;
+        if remove_block(&content).is_none() {
+            return Ok(None);
+        }
+        Ok(Some(StatefulAction::completed(Self { path, backup: None })))
+    }
+}
+
+#[async_trait::async_trait]
+#[typetag::serde(name = "revert_foreign_shell_profile")]
+impl Action for RevertForeignShellProfile {
+    fn action_tag() -> ActionTag {
+        ActionTag("revert_foreign_shell_profile")
+    }
+    fn tracing_synopsis(&self) -> String {
+        format!(
+            "Remove the Nix setup from `{}` added by another installer",
+            self.path.display()
+        )
+    }
+
+    fn tracing_span(&self) -> Span {
+        span!(
+            tracing::Level::DEBUG,
+            "revert_foreign_shell_profile",
+            path = tracing::field::display(self.path.display()),
+        )
+    }
+
+    fn resources(&self) -> Vec<ActionResource> {
+        vec![ActionResource::Path(self.path.clone())]
+    }
+
+    fn execute_description(&self) -> Vec<ActionDescription> {
+        vec![]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn execute(&mut self) -> Result<(), ActionError> {
+        // noop
+
+        Ok(())
+    }
+
+    fn revert_description(&self) -> Vec<ActionDescription> {
+        vec![ActionDescription::new(
+            self.tracing_synopsis(),
+            [format!(
+                "The Nix install scripts added the lines between `{BLOCK_BEGIN}` and `{BLOCK_END}`"
+            )]
+            .into_iter()
+            .chain(self.backup.as_ref().map(|backup| {
+                format!(
+                    "The backup `{}` the scripts made before is removed if `{}` is the same as it again, otherwise it's kept",
+                    backup.display(),
+                    self.path.display()
+                )
+            }))
+            .collect(),
+        )]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn revert(&mut self) -> Result<(), ActionError> {
+        let Self { path, backup } = self;
+
+        // The profile may have been cleaned up by hand since
+        let content = match tokio::fs::read_to_string(&path).await {
+            Ok(content) => Some(content),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(Self::error(ActionErrorKind::Read(path.clone(), e))),
+        };
+        let remaining = match content {
+            Some(content) => match remove_block(&content) {
+                Some(remaining) if remaining.trim().is_empty() => {
+                    tokio::fs::remove_file(&path)
+                        .await
+                        .map_err(|e| ActionErrorKind::Remove(path.clone(), e))
+                        .map_err(Self::error)?;
+                    None
+                },
+                Some(remaining) => {
+                    tokio::fs::write(&path, &remaining)
+                        .await
+                        .map_err(|e| ActionErrorKind::Write(path.clone(), e))
+                        .map_err(Self::error)?;
+                    Some(remaining)
+                },
+                None => Some(content),
+            },
+            None => None,
+        };
+
+        let Some(backup) = backup else {
+            return Ok(());
+        };
+        let backed_up = match tokio::fs::read_to_string(&backup).await {
+            Ok(backed_up) => backed_up,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(Self::error(ActionErrorKind::Read(backup.clone(), e))),
+        };
+        if unchanged_since_backup(remaining.as_deref(), &backed_up) {
+            tokio::fs::remove_file(&backup)
+                .await
+                .map_err(|e| ActionErrorKind::Remove(backup.clone(), e))
+                .map_err(Self::error)
+        } else {
+            tracing::warn!(
+                "Kept `{}`, as `{}` was edited since the Nix install scripts backed it up, compare them and remove the backup",
+                backup.display(),
+                path.display()
+            );
+            Ok(())
+        }
+    }
+}
+
+/// If a profile without the block, or `None` if it was removed, is what the upstream scripts backed up
+fn unchanged_since_backup(remaining: Option<&str>, backed_up: &str) -> bool {
+    remaining.unwrap_or_default().trim_end() == backed_up.trim_end()
+}
+
+/// `content` without the block the upstream scripts add, or `None` if it has none
+fn remove_block(content: &str) -> Option<String> {
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let begin = lines.iter().position(|v| v.trim_end() == BLOCK_BEGIN)?;
+    let end = begin
+        + lines[begin..]
+            .iter()
+            .position(|v| v.trim_end() == BLOCK_END)?;
+
+    // The scripts put an empty line before the block
+    let begin = match begin.checked_sub(1) {
+        Some(previous) if lines[previous].trim().is_empty() => previous,
+        _ => begin,
+    };
+    Some(
+        lines[..begin]
+            .iter()
+            .chain(&lines[end + 1..])
+            .copied()
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn removes_block_and_unchanged_backup() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = temp_dir.path().join("bashrc");
+        let backup = temp_dir.path().join(format!("bashrc{BACKUP_SUFFIX}"));
+        tokio::fs::write(&backup, "export EDITOR=vi\n").await?;
+        tokio::fs::write(&path, "export EDITOR=vi\n\n# Nix\n. nix.sh\n# End Nix\n").await?;
+
+        let mut action = RevertForeignShellProfile::plan(&path)
+            .await?
+            .expect("the backup exists");
+        action.try_revert().await?;
+        assert_eq!(
+            tokio::fs::read_to_string(&path).await?,
+            "export EDITOR=vi\n"
+        );
+        assert!(!backup.exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn keeps_edits_made_after_backup() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = temp_dir.path().join("bashrc");
+        let backup = temp_dir.path().join(format!("bashrc{BACKUP_SUFFIX}"));
+        tokio::fs::write(&backup, "export EDITOR=vi\n").await?;
+        tokio::fs::write(
+            &path,
+            "export EDITOR=vi\n\n# Nix\n. nix.sh\n# End Nix\nalias ll='ls -l'\n",
+        )
+        .await?;
+
+        let mut action = RevertForeignShellProfile::plan(&path)
+            .await?
+            .expect("the backup exists");
+        action.try_revert().await?;
+        assert_eq!(
+            tokio::fs::read_to_string(&path).await?,
+            "export EDITOR=vi\nalias ll='ls -l'\n"
+        );
+        assert_eq!(
+            tokio::fs::read_to_string(&backup).await?,
+            "export EDITOR=vi\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn removes_upstream_block() {
+        let content = "\
+            export EDITOR=vi\n\
+            \n\
+            # Nix\n\
+            if [ -e '/nix/var/nix/profiles/default/etc/profile.d/nix-daemon.sh' ]; then\n  \
+              . '/nix/var/nix/profiles/default/etc/profile.d/nix-daemon.sh'\n\
+            fi\n\
+            # End Nix\n\
+            \n\
+            alias ll='ls -l'\n";
+        assert_eq!(
+            remove_block(content).as_deref(),
+            Some("export EDITOR=vi\n\nalias ll='ls -l'\n")
+        );
+        assert_eq!(remove_block("export EDITOR=vi\n# Nix\n"), None);
+        assert_eq!(remove_block("export EDITOR=vi\n"), None);
+    }
+}
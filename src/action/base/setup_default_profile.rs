@@ -1,8 +1,9 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use crate::{
     action::{ActionError, ActionErrorKind, ActionTag, StatefulAction},
     execute_command, set_env,
+    settings::default_root,
 };
 
 use glob::glob;
@@ -14,16 +15,57 @@ use crate::action::{Action, ActionDescription};
 
 /**
 Setup the default Nix profile with `nss-cacert` and `nix` itself.
+
+When `root` is not `/` the Nix binaries are run in a `chroot` of it, as they can only find their
+libraries under its `/nix/store`.
  */
 #[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
 pub struct SetupDefaultProfile {
     unpacked_path: PathBuf,
+    #[serde(default = "default_root")]
+    root: PathBuf,
 }
 
 impl SetupDefaultProfile {
     #[tracing::instrument(level = "debug", skip_all)]
-    pub async fn plan(unpacked_path: PathBuf) -> Result<StatefulAction<Self>, ActionError> {
-        Ok(Self { unpacked_path }.into())
+    pub async fn plan(
+        unpacked_path: PathBuf,
+        root: PathBuf,
+    ) -> Result<StatefulAction<Self>, ActionError> {
+        Ok(Self {
+            unpacked_path,
+            root,
+        }
+        .into())
+    }
+
+    fn in_root(&self) -> bool {
+        self.root != Path::new("/")
+    }
+
+    /// A command running `program` (found under `root`) inside of `root`
+    fn command(&self, program: &Path) -> Command {
+        if self.in_root() {
+            let mut command = Command::new("chroot");
+            command.arg(&self.root).arg(self.inside_root(program));
+            command
+        } else {
+            Command::new(program)
+        }
+    }
+
+    /// The `HOME` for the commands, which must exist inside of `root`
+    fn home(&self) -> Result<PathBuf, ActionError> {
+        if self.in_root() {
+            Ok(PathBuf::from("/root"))
+        } else {
+            dirs::home_dir().ok_or_else(|| Self::error(SetupDefaultProfileError::NoRootHome))
+        }
+    }
+
+    /// `path` (found under `root`) as seen from inside of `root`
+    fn inside_root(&self, path: &Path) -> PathBuf {
+        Path::new("/").join(path.strip_prefix(&self.root).unwrap_or(path))
     }
 }
 
@@ -42,6 +84,7 @@ impl Action for SetupDefaultProfile {
             tracing::Level::DEBUG,
             "setup_default_profile",
             unpacked_path = %self.unpacked_path.display(),
+            root = %self.root.display(),
         )
     }
 
@@ -121,16 +164,13 @@ impl Action for SetupDefaultProfile {
             .await
             .map_err(|e| ActionErrorKind::Read(reginfo_path.to_path_buf(), e))
             .map_err(Self::error)?;
-        let mut load_db_command = Command::new(nix_pkg.join("bin/nix-store"));
+        let mut load_db_command = self.command(&nix_pkg.join("bin/nix-store"));
         load_db_command.process_group(0);
         load_db_command.arg("--load-db");
         load_db_command.stdin(std::process::Stdio::piped());
         load_db_command.stdout(std::process::Stdio::piped());
         load_db_command.stderr(std::process::Stdio::piped());
-        load_db_command.env(
-            "HOME",
-            dirs::home_dir().ok_or_else(|| Self::error(SetupDefaultProfileError::NoRootHome))?,
-        );
+        load_db_command.env("HOME", self.home()?);
         tracing::trace!(
             "Executing `{:?}` with stdin from `{}`",
             load_db_command.as_std(),
@@ -172,19 +212,16 @@ impl Action for SetupDefaultProfile {
 
         // Install `nix` itself into the store
         execute_command(
-            Command::new(nix_pkg.join("bin/nix-env"))
+            self.command(&nix_pkg.join("bin/nix-env"))
                 .process_group(0)
                 .arg("-i")
-                .arg(&nix_pkg)
+                .arg(self.inside_root(&nix_pkg))
                 .stdin(std::process::Stdio::null())
-                .env(
-                    "HOME",
-                    dirs::home_dir()
-                        .ok_or_else(|| Self::error(SetupDefaultProfileError::NoRootHome))?,
-                )
+                .env("HOME", self.home()?)
                 .env(
                     "NIX_SSL_CERT_FILE",
-                    nss_ca_cert_pkg.join("etc/ssl/certs/ca-bundle.crt"),
+                    self.inside_root(&nss_ca_cert_pkg)
+                        .join("etc/ssl/certs/ca-bundle.crt"),
                 ), /* This is apparently load bearing... */
         )
         .await
@@ -192,28 +229,28 @@ impl Action for SetupDefaultProfile {
 
         // Install `nix` itself into the store
         execute_command(
-            Command::new(nix_pkg.join("bin/nix-env"))
+            self.command(&nix_pkg.join("bin/nix-env"))
                 .process_group(0)
                 .arg("-i")
-                .arg(&nss_ca_cert_pkg)
+                .arg(self.inside_root(&nss_ca_cert_pkg))
                 .stdin(std::process::Stdio::null())
-                .env(
-                    "HOME",
-                    dirs::home_dir()
-                        .ok_or_else(|| Self::error(SetupDefaultProfileError::NoRootHome))?,
-                )
+                .env("HOME", self.home()?)
                 .env(
                     "NIX_SSL_CERT_FILE",
-                    nss_ca_cert_pkg.join("etc/ssl/certs/ca-bundle.crt"),
+                    self.inside_root(&nss_ca_cert_pkg)
+                        .join("etc/ssl/certs/ca-bundle.crt"),
                 ), /* This is apparently load bearing... */
         )
         .await
         .map_err(Self::error)?;
 
-        set_env(
-            "NIX_SSL_CERT_FILE",
-            "/nix/var/nix/profiles/default/etc/ssl/certs/ca-bundle.crt",
-        );
+        // Nix inside of another root can't be used by this process
+        if !self.in_root() {
+            set_env(
+                "NIX_SSL_CERT_FILE",
+                "/nix/var/nix/profiles/default/etc/ssl/certs/ca-bundle.crt",
+            );
+        }
 
         Ok(())
     }
@@ -1,4 +1,6 @@
 #[cfg(target_os = "linux")]
+use std::os::unix::fs::PermissionsExt;
+#[cfg(target_os = "linux")]
 use std::path::Path;
 use std::path::PathBuf;
 use tokio::process::Command;
@@ -8,25 +10,132 @@ use crate::action::{ActionError, ActionErrorKind, ActionTag, StatefulAction};
 use crate::execute_command;
 
 use crate::action::{Action, ActionDescription};
-use crate::settings::InitSystem;
+use crate::settings::{CommonSettings, InitSettings, InitSystem};
+use url::Url;
 
 #[cfg(target_os = "linux")]
-const SERVICE_SRC: &str = "/nix/var/nix/profiles/default/lib/systemd/system/nix-daemon.service";
+pub(crate) const SERVICE_SRC: &str =
+    "/nix/var/nix/profiles/default/lib/systemd/system/nix-daemon.service";
+#[cfg(target_os = "linux")]
+pub(crate) const SERVICE_DEST: &str = "/etc/systemd/system/nix-daemon.service";
+#[cfg(target_os = "linux")]
+pub(crate) const SOCKET_SRC: &str =
+    "/nix/var/nix/profiles/default/lib/systemd/system/nix-daemon.socket";
+#[cfg(target_os = "linux")]
+pub(crate) const SOCKET_DEST: &str = "/etc/systemd/system/nix-daemon.socket";
+#[cfg(target_os = "linux")]
+pub(crate) const TMPFILES_SRC: &str =
+    "/nix/var/nix/profiles/default/lib/tmpfiles.d/nix-daemon.conf";
+#[cfg(target_os = "linux")]
+pub(crate) const TMPFILES_DEST: &str = "/etc/tmpfiles.d/nix-daemon.conf";
+#[cfg(target_os = "linux")]
+const DROP_IN_DIR: &str = "/etc/systemd/system/nix-daemon.service.d";
+#[cfg(target_os = "linux")]
+const DROP_IN_DEST: &str = "/etc/systemd/system/nix-daemon.service.d/nix-installer.conf";
+/// The first line of the drop-in, which tells it apart from one written by someone else
+const DROP_IN_HEADER: &str = "# Managed by nix-installer, removed on uninstall";
+#[cfg(target_os = "linux")]
+const OPENRC_SERVICE: &str = "nix-daemon";
+#[cfg(target_os = "linux")]
+const OPENRC_SCRIPT_DEST: &str = "/etc/init.d/nix-daemon";
+#[cfg(target_os = "linux")]
+const OPENRC_SCRIPT: &str = "\
+#!/sbin/openrc-run
+
+description=\"Nix package manager daemon\"
+command=\"/nix/var/nix/profiles/default/bin/nix-daemon\"
+command_background=true
+pidfile=\"/run/${RC_SVCNAME}.pid\"
+
+depend() {
+    need localmount
+    after net
+}
+";
+#[cfg(target_os = "linux")]
+const SUPERVISED_SERVICE: &str = "nix-daemon";
+#[cfg(target_os = "linux")]
+const RUNIT_SERVICE_DIR: &str = "/etc/sv/nix-daemon";
+#[cfg(target_os = "linux")]
+const RUNIT_SCAN_DIRS: &[&str] = &["/var/service", "/etc/service", "/service"];
 #[cfg(target_os = "linux")]
-const SERVICE_DEST: &str = "/etc/systemd/system/nix-daemon.service";
+const S6_SERVICE_DIR: &str = "/etc/s6/sv/nix-daemon";
 #[cfg(target_os = "linux")]
-const SOCKET_SRC: &str = "/nix/var/nix/profiles/default/lib/systemd/system/nix-daemon.socket";
+const S6_SCAN_DIRS: &[&str] = &["/run/service", "/service", "/etc/s6/service"];
 #[cfg(target_os = "linux")]
-const SOCKET_DEST: &str = "/etc/systemd/system/nix-daemon.socket";
+const SUPERVISED_RUN_SCRIPT: &str = "\
+#!/bin/sh
+exec 2>&1
+exec /nix/var/nix/profiles/default/bin/nix-daemon
+";
 #[cfg(target_os = "linux")]
-const TMPFILES_SRC: &str = "/nix/var/nix/profiles/default/lib/tmpfiles.d/nix-daemon.conf";
+const RUNIT_LOG_SCRIPT: &str = "\
+#!/bin/sh
+mkdir -p /var/log/nix-daemon
+exec svlogd -tt /var/log/nix-daemon
+";
 #[cfg(target_os = "linux")]
-const TMPFILES_DEST: &str = "/etc/tmpfiles.d/nix-daemon.conf";
+const S6_LOG_SCRIPT: &str = "\
+#!/bin/sh
+mkdir -p /var/log/nix-daemon
+exec s6-log -b T /var/log/nix-daemon
+";
 #[cfg(target_os = "macos")]
 const DARWIN_NIX_DAEMON_DEST: &str = "/Library/LaunchDaemons/org.nixos.nix-daemon.plist";
 #[cfg(target_os = "macos")]
 const DARWIN_NIX_DAEMON_SOURCE: &str =
     "/nix/var/nix/profiles/default/Library/LaunchDaemons/org.nixos.nix-daemon.plist";
+/// The environment and resource limits of the Nix daemon, applied with a systemd drop-in
+#[derive(Debug, Default, serde::Deserialize, serde::Serialize, Clone, PartialEq, Eq)]
+pub struct DaemonOverrides {
+    /// Set as `http_proxy` and `https_proxy`
+    pub proxy: Option<Url>,
+    /// Set as `NIX_SSL_CERT_FILE`
+    pub ssl_cert_file: Option<PathBuf>,
+    pub limit_nofile: Option<u64>,
+    pub cpu_quota: Option<String>,
+}
+
+impl DaemonOverrides {
+    pub fn new(settings: &CommonSettings, init: Option<&InitSettings>) -> Self {
+        Self {
+            proxy: settings.proxy.clone(),
+            ssl_cert_file: settings.ssl_cert_file.clone(),
+            limit_nofile: init.and_then(|init| init.daemon_limit_nofile),
+            cpu_quota: init.and_then(|init| init.daemon_cpu_quota.clone()),
+        }
+    }
+
+    /// The content of the drop-in, if there is anything to override
+    pub fn drop_in(&self) -> Option<String> {
+        let mut lines = vec![];
+        if let Some(proxy) = &self.proxy {
+            lines.push(format!("Environment=\"http_proxy={proxy}\""));
+            lines.push(format!("Environment=\"https_proxy={proxy}\""));
+        }
+        if let Some(ssl_cert_file) = &self.ssl_cert_file {
+            lines.push(format!(
+                "Environment=\"NIX_SSL_CERT_FILE={}\"",
+                ssl_cert_file.display()
+            ));
+        }
+        if let Some(limit_nofile) = self.limit_nofile {
+            lines.push(format!("LimitNOFILE={limit_nofile}"));
+        }
+        if let Some(cpu_quota) = &self.cpu_quota {
+            lines.push(format!("CPUQuota={cpu_quota}"));
+        }
+
+        if lines.is_empty() {
+            return None;
+        }
+        Some(format!(
+            "{DROP_IN_HEADER}\n[Service]\n{}\n",
+            lines.join("\n")
+        ))
+    }
+}
+
 /**
 Configure the init to run the Nix daemon
 */
@@ -34,6 +143,12 @@ Configure the init to run the Nix daemon
 pub struct ConfigureInitService {
     init: InitSystem,
     start_daemon: bool,
+    /// Written to a drop-in for `nix-daemon.service` with systemd
+    #[serde(default)]
+    overrides: DaemonOverrides,
+    /// The directory runit or s6 scans for services, where the Nix daemon service is linked
+    #[serde(default)]
+    scan_dir: Option<PathBuf>,
 }
 
 impl ConfigureInitService {
@@ -57,11 +172,10 @@ impl ConfigureInitService {
                 return Err(ActionErrorKind::FileExists(unit_dest));
             }
         }
-        // NOTE: ...and if there are any overrides in the most well-known places for systemd
-        if Path::new(&format!("{dest}.d")).exists() {
-            return Err(ActionErrorKind::DirExists(PathBuf::from(format!(
-                "{dest}.d"
-            ))));
+        // NOTE: ...and if there are any overrides in the most well-known places for systemd, other than our own
+        let drop_in_dir = PathBuf::from(format!("{dest}.d"));
+        if drop_in_dir.exists() && !only_contains_our_drop_in(&drop_in_dir).await? {
+            return Err(ActionErrorKind::DirExists(drop_in_dir));
         }
 
         Ok(())
@@ -71,7 +185,12 @@ impl ConfigureInitService {
     pub async fn plan(
         init: InitSystem,
         start_daemon: bool,
+        overrides: DaemonOverrides,
     ) -> Result<StatefulAction<Self>, ActionError> {
+        #[cfg_attr(not(target_os = "linux"), allow(unused_mut))]
+        let mut scan_dir = None;
+        #[cfg_attr(not(target_os = "linux"), allow(unused_mut))]
+        let mut completed = false;
         match init {
             #[cfg(target_os = "macos")]
             InitSystem::Launchd => {
@@ -95,6 +214,82 @@ impl ConfigureInitService {
                 Self::check_if_systemd_unit_exists(SOCKET_SRC, SOCKET_DEST)
                     .await
                     .map_err(Self::error)?;
+                check_drop_in_is_ours(Path::new(DROP_IN_DEST))
+                    .await
+                    .map_err(Self::error)?;
+
+                // Both units existing means they link to the store, as checked above
+                if Path::new(SERVICE_DEST).exists()
+                    && Path::new(SOCKET_DEST).exists()
+                    && Path::new(TMPFILES_DEST).exists()
+                    && tokio::fs::read_to_string(DROP_IN_DEST).await.ok() == overrides.drop_in()
+                    && is_enabled("nix-daemon.socket").await.map_err(Self::error)?
+                    && (!start_daemon
+                        || is_active("nix-daemon.socket").await.map_err(Self::error)?)
+                {
+                    tracing::debug!("Configuring the Nix daemon with systemd already complete");
+                    completed = true;
+                }
+            },
+            #[cfg(target_os = "linux")]
+            InitSystem::OpenRC => {
+                if which::which("rc-update").is_err() || which::which("rc-service").is_err() {
+                    return Err(Self::error(ActionErrorKind::OpenRcMissing));
+                }
+
+                let script_dest = Path::new(OPENRC_SCRIPT_DEST);
+                if script_dest.exists() {
+                    let existing = tokio::fs::read_to_string(script_dest)
+                        .await
+                        .map_err(|e| ActionErrorKind::Read(script_dest.to_path_buf(), e))
+                        .map_err(Self::error)?;
+                    if existing != OPENRC_SCRIPT {
+                        return Err(Self::error(ActionErrorKind::DifferentContent(
+                            script_dest.to_path_buf(),
+                        )));
+                    }
+                }
+            },
+            #[cfg(target_os = "linux")]
+            InitSystem::Runit | InitSystem::S6 => {
+                let missing_tool = match init {
+                    InitSystem::S6 => ["s6-svc", "s6-svstat", "s6-svscanctl"]
+                        .into_iter()
+                        .any(|tool| which::which(tool).is_err()),
+                    _ => which::which("sv").is_err(),
+                };
+                let found_scan_dir = supervisor_scan_dir(init);
+                if missing_tool || found_scan_dir.is_none() {
+                    return Err(Self::error(supervisor_missing(init)));
+                }
+                scan_dir = found_scan_dir;
+
+                let service_dir = Path::new(supervised_service_dir(init));
+                if service_dir.exists() {
+                    let run = service_dir.join("run");
+                    let existing = tokio::fs::read_to_string(&run).await.unwrap_or_default();
+                    if existing != SUPERVISED_RUN_SCRIPT {
+                        return Err(Self::error(ActionErrorKind::DirExists(
+                            service_dir.to_path_buf(),
+                        )));
+                    }
+                }
+
+                let link = scan_dir
+                    .as_ref()
+                    .expect("Scan directory was just found")
+                    .join(SUPERVISED_SERVICE);
+                if link.is_symlink() {
+                    let link_dest = tokio::fs::read_link(&link)
+                        .await
+                        .map_err(|e| ActionErrorKind::ReadSymlink(link.clone(), e))
+                        .map_err(Self::error)?;
+                    if link_dest != service_dir {
+                        return Err(Self::error(ActionErrorKind::SymlinkExists(link)));
+                    }
+                } else if link.exists() {
+                    return Err(Self::error(ActionErrorKind::DirExists(link)));
+                }
             },
             #[cfg(target_os = "linux")]
             InitSystem::None => {
@@ -102,7 +297,16 @@ impl ConfigureInitService {
             },
         };
 
-        Ok(Self { init, start_daemon }.into())
+        let this = Self {
+            init,
+            start_daemon,
+            scan_dir,
+            overrides,
+        };
+        if completed {
+            return Ok(StatefulAction::completed(this));
+        }
+        Ok(this.into())
     }
 }
 
@@ -116,6 +320,12 @@ impl Action for ConfigureInitService {
         match self.init {
             #[cfg(target_os = "linux")]
             InitSystem::Systemd => "Configure Nix daemon related settings with systemd".to_string(),
+            #[cfg(target_os = "linux")]
+            InitSystem::OpenRC => "Configure the Nix daemon service with OpenRC".to_string(),
+            #[cfg(target_os = "linux")]
+            InitSystem::Runit => "Configure the Nix daemon service with runit".to_string(),
+            #[cfg(target_os = "linux")]
+            InitSystem::S6 => "Configure the Nix daemon service with s6".to_string(),
             #[cfg(target_os = "macos")]
             InitSystem::Launchd => {
                 "Configure Nix daemon related settings with launchctl".to_string()
@@ -138,13 +348,45 @@ impl Action for ConfigureInitService {
                     "Run `systemd-tempfiles --create --prefix=/nix/var/nix`".to_string(),
                     format!("Symlink `{SERVICE_SRC}` to `{SERVICE_DEST}`"),
                     format!("Symlink `{SOCKET_SRC}` to `{SOCKET_DEST}`"),
-                    "Run `systemctl daemon-reload`".to_string(),
                 ];
+                if self.overrides.drop_in().is_some() {
+                    explanation.push(format!(
+                        "Create `{DROP_IN_DEST}` with the environment and limits of the daemon"
+                    ));
+                }
+                explanation.push("Run `systemctl daemon-reload`".to_string());
                 if self.start_daemon {
                     explanation.push(format!("Run `systemctl enable --now {SOCKET_SRC}`"));
                 }
                 vec.push(ActionDescription::new(self.tracing_synopsis(), explanation))
             },
+            #[cfg(target_os = "linux")]
+            InitSystem::OpenRC => {
+                let mut explanation = vec![
+                    format!("Create `{OPENRC_SCRIPT_DEST}`"),
+                    format!("Run `rc-update add {OPENRC_SERVICE} default`"),
+                ];
+                if self.start_daemon {
+                    explanation.push(format!("Run `rc-service {OPENRC_SERVICE} start`"));
+                }
+                vec.push(ActionDescription::new(self.tracing_synopsis(), explanation))
+            },
+            #[cfg(target_os = "linux")]
+            InitSystem::Runit | InitSystem::S6 => {
+                let service_dir = supervised_service_dir(self.init);
+                let mut explanation = vec![
+                    format!("Create `{service_dir}` with a `run` script and a `log` subservice"),
+                    format!(
+                        "Symlink `{service_dir}` into `{}`, the supervisor starts the daemon once it notices",
+                        self.scan_dir.as_deref().unwrap_or(Path::new("the scan directory")).display()
+                    ),
+                ];
+                if self.start_daemon {
+                    explanation
+                        .push("Wait for the supervisor to report the daemon as up".to_string());
+                }
+                vec.push(ActionDescription::new(self.tracing_synopsis(), explanation))
+            },
             #[cfg(target_os = "macos")]
             InitSystem::Launchd => {
                 let mut explanation = vec![format!(
@@ -163,7 +405,12 @@ impl Action for ConfigureInitService {
 
     #[tracing::instrument(level = "debug", skip_all)]
     async fn execute(&mut self) -> Result<(), ActionError> {
-        let Self { init, start_daemon } = self;
+        let Self {
+            init,
+            start_daemon,
+            scan_dir,
+            overrides,
+        } = self;
 
         match init {
             #[cfg(target_os = "macos")]
@@ -328,6 +575,20 @@ impl Action for ConfigureInitService {
                     })
                     .map_err(Self::error)?;
 
+                if let Some(drop_in) = overrides.drop_in() {
+                    tokio::fs::create_dir_all(DROP_IN_DIR)
+                        .await
+                        .map_err(|e| {
+                            ActionErrorKind::CreateDirectory(PathBuf::from(DROP_IN_DIR), e)
+                        })
+                        .map_err(Self::error)?;
+                    tracing::trace!(path = DROP_IN_DEST, "Writing");
+                    tokio::fs::write(DROP_IN_DEST, drop_in)
+                        .await
+                        .map_err(|e| ActionErrorKind::Write(PathBuf::from(DROP_IN_DEST), e))
+                        .map_err(Self::error)?;
+                }
+
                 if *start_daemon {
                     execute_command(
                         Command::new("systemctl")
@@ -345,6 +606,52 @@ impl Action for ConfigureInitService {
                     enable(SOCKET_SRC, false).await.map_err(Self::error)?;
                 }
             },
+            #[cfg(target_os = "linux")]
+            InitSystem::OpenRC => {
+                write_openrc_script(Path::new(OPENRC_SCRIPT_DEST))
+                    .await
+                    .map_err(Self::error)?;
+                openrc_add(None, *start_daemon).await.map_err(Self::error)?;
+            },
+            #[cfg(target_os = "linux")]
+            InitSystem::Runit | InitSystem::S6 => {
+                let scan_dir = scan_dir
+                    .clone()
+                    .or_else(|| supervisor_scan_dir(*init))
+                    .ok_or_else(|| Self::error(supervisor_missing(*init)))?;
+                let service_dir = PathBuf::from(supervised_service_dir(*init));
+                write_supervised_service(&service_dir, supervised_log_script(*init))
+                    .await
+                    .map_err(Self::error)?;
+
+                let link = scan_dir.join(SUPERVISED_SERVICE);
+                if !link.is_symlink() {
+                    tracing::trace!(src = %service_dir.display(), dest = %link.display(), "Symlinking");
+                    tokio::fs::symlink(&service_dir, &link)
+                        .await
+                        .map_err(|e| ActionErrorKind::Symlink(service_dir.clone(), link.clone(), e))
+                        .map_err(Self::error)?;
+                }
+
+                // `s6-svscan` only notices new services when told to rescan, `runsvdir` checks every few seconds
+                if *init == InitSystem::S6 {
+                    execute_command(
+                        Command::new("s6-svscanctl")
+                            .process_group(0)
+                            .arg("-a")
+                            .arg(&scan_dir)
+                            .stdin(std::process::Stdio::null()),
+                    )
+                    .await
+                    .map_err(Self::error)?;
+                }
+
+                if *start_daemon {
+                    wait_for_supervised_up(*init, &link)
+                        .await
+                        .map_err(Self::error)?;
+                }
+            },
             #[cfg(not(target_os = "macos"))]
             InitSystem::None => {
                 // Nothing here, no init system
@@ -364,10 +671,38 @@ impl Action for ConfigureInitService {
                         format!("Run `systemctl disable {SOCKET_SRC}`"),
                         format!("Run `systemctl disable {SERVICE_SRC}`"),
                         "Run `systemd-tempfiles --remove --prefix=/nix/var/nix`".to_string(),
+                        format!("Remove `{DROP_IN_DEST}`, if it exists"),
                         "Run `systemctl daemon-reload`".to_string(),
                     ],
                 )]
             },
+            #[cfg(target_os = "linux")]
+            InitSystem::OpenRC => {
+                vec![ActionDescription::new(
+                    "Unconfigure the Nix daemon service with OpenRC".to_string(),
+                    vec![
+                        format!("Run `rc-service {OPENRC_SERVICE} stop`"),
+                        format!("Run `rc-update del {OPENRC_SERVICE} default`"),
+                        format!("Remove `{OPENRC_SCRIPT_DEST}`"),
+                    ],
+                )]
+            },
+            #[cfg(target_os = "linux")]
+            InitSystem::Runit | InitSystem::S6 => {
+                let service_dir = supervised_service_dir(self.init);
+                let down = match self.init {
+                    InitSystem::S6 => "s6-svc -d",
+                    _ => "sv down",
+                };
+                vec![ActionDescription::new(
+                    format!("Unconfigure the Nix daemon service with {}", self.init),
+                    vec![
+                        format!("Run `{down} {SUPERVISED_SERVICE}`"),
+                        format!("Remove the link to `{service_dir}` from the scan directory"),
+                        format!("Remove `{service_dir}`"),
+                    ],
+                )]
+            },
             #[cfg(target_os = "macos")]
             InitSystem::Launchd => {
                 vec![ActionDescription::new(
@@ -482,6 +817,10 @@ impl Action for ConfigureInitService {
                     errors.push(err);
                 }
 
+                if let Err(err) = remove_drop_in().await {
+                    errors.push(err);
+                }
+
                 if let Err(err) = execute_command(
                     Command::new("systemctl")
                         .process_group(0)
@@ -493,6 +832,92 @@ impl Action for ConfigureInitService {
                     errors.push(err);
                 }
             },
+            #[cfg(target_os = "linux")]
+            InitSystem::OpenRC => {
+                let script_dest = PathBuf::from(OPENRC_SCRIPT_DEST);
+                // Without the script, the service can be neither running nor registered
+                if script_dest.exists() {
+                    errors.extend(openrc_del(None).await);
+
+                    if let Err(err) = tokio::fs::remove_file(&script_dest)
+                        .await
+                        .map_err(|e| ActionErrorKind::Remove(script_dest.clone(), e))
+                    {
+                        errors.push(err);
+                    }
+                }
+            },
+            #[cfg(target_os = "linux")]
+            InitSystem::Runit | InitSystem::S6 => {
+                let service_dir = PathBuf::from(supervised_service_dir(self.init));
+                let scan_dir = self
+                    .scan_dir
+                    .clone()
+                    .or_else(|| supervisor_scan_dir(self.init));
+                if let Some(scan_dir) = scan_dir {
+                    let link = scan_dir.join(SUPERVISED_SERVICE);
+                    // Only take down a service which is still ours
+                    let is_ours = tokio::fs::read_link(&link)
+                        .await
+                        .map(|dest| dest == service_dir)
+                        .unwrap_or(false);
+                    if is_ours {
+                        let mut command = match self.init {
+                            InitSystem::S6 => {
+                                let mut command = Command::new("s6-svc");
+                                command.arg("-d");
+                                command
+                            },
+                            _ => {
+                                let mut command = Command::new("sv");
+                                command.arg("down");
+                                command
+                            },
+                        };
+                        if let Err(err) = execute_command(
+                            command
+                                .process_group(0)
+                                .arg(&link)
+                                .stdin(std::process::Stdio::null()),
+                        )
+                        .await
+                        {
+                            errors.push(err);
+                        }
+
+                        if let Err(err) = tokio::fs::remove_file(&link)
+                            .await
+                            .map_err(|e| ActionErrorKind::Remove(link.clone(), e))
+                        {
+                            errors.push(err);
+                        }
+
+                        // Have `s6-svscan` stop supervising services which no longer exist
+                        if self.init == InitSystem::S6 {
+                            if let Err(err) = execute_command(
+                                Command::new("s6-svscanctl")
+                                    .process_group(0)
+                                    .arg("-an")
+                                    .arg(&scan_dir)
+                                    .stdin(std::process::Stdio::null()),
+                            )
+                            .await
+                            {
+                                errors.push(err);
+                            }
+                        }
+                    }
+                }
+
+                if service_dir.exists() {
+                    if let Err(err) = tokio::fs::remove_dir_all(&service_dir)
+                        .await
+                        .map_err(|e| ActionErrorKind::Remove(service_dir.clone(), e))
+                    {
+                        errors.push(err);
+                    }
+                }
+            },
             #[cfg(not(target_os = "macos"))]
             InitSystem::None => {
                 // Nothing here, no init
@@ -521,6 +946,264 @@ pub enum ConfigureNixDaemonServiceError {
     InitNotSupported,
 }
 
+/// Write the OpenRC script of the Nix daemon to `dest`
+#[cfg(target_os = "linux")]
+async fn write_openrc_script(dest: &Path) -> Result<(), ActionErrorKind> {
+    tracing::trace!(path = %dest.display(), "Writing");
+    tokio::fs::write(dest, OPENRC_SCRIPT)
+        .await
+        .map_err(|e| ActionErrorKind::Write(dest.to_path_buf(), e))?;
+    tokio::fs::set_permissions(dest, PermissionsExt::from_mode(0o0755))
+        .await
+        .map_err(|e| ActionErrorKind::SetPermissions(0o0755, dest.to_path_buf(), e))
+}
+
+/// `tool` of OpenRC, from `bin_dir` if given or else found in `$PATH`
+#[cfg(target_os = "linux")]
+fn openrc_tool(bin_dir: Option<&Path>, tool: &str) -> Command {
+    match bin_dir {
+        Some(bin_dir) => Command::new(bin_dir.join(tool)),
+        None => Command::new(tool),
+    }
+}
+
+/// Add the Nix daemon to the default runlevel, and start it if `start_daemon`
+#[cfg(target_os = "linux")]
+async fn openrc_add(bin_dir: Option<&Path>, start_daemon: bool) -> Result<(), ActionErrorKind> {
+    execute_command(
+        openrc_tool(bin_dir, "rc-update")
+            .process_group(0)
+            .args(["add", OPENRC_SERVICE, "default"])
+            .stdin(std::process::Stdio::null()),
+    )
+    .await?;
+
+    if start_daemon {
+        execute_command(
+            openrc_tool(bin_dir, "rc-service")
+                .process_group(0)
+                .args([OPENRC_SERVICE, "start"])
+                .stdin(std::process::Stdio::null()),
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Stop the Nix daemon if it is started, and remove it from the default runlevel
+#[cfg(target_os = "linux")]
+async fn openrc_del(bin_dir: Option<&Path>) -> Vec<ActionErrorKind> {
+    let mut errors = vec![];
+
+    let is_started = openrc_tool(bin_dir, "rc-service")
+        .args([OPENRC_SERVICE, "status"])
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .await
+        .map(|status| status.success())
+        .unwrap_or(false);
+    if is_started {
+        if let Err(err) = execute_command(
+            openrc_tool(bin_dir, "rc-service")
+                .process_group(0)
+                .args([OPENRC_SERVICE, "stop"])
+                .stdin(std::process::Stdio::null()),
+        )
+        .await
+        {
+            errors.push(err);
+        }
+    }
+
+    if let Err(err) = execute_command(
+        openrc_tool(bin_dir, "rc-update")
+            .process_group(0)
+            .args(["del", OPENRC_SERVICE, "default"])
+            .stdin(std::process::Stdio::null()),
+    )
+    .await
+    {
+        errors.push(err);
+    }
+
+    errors
+}
+
+/// If the drop-in directory `dir` holds nothing but the drop-in we write
+#[cfg(target_os = "linux")]
+async fn only_contains_our_drop_in(dir: &Path) -> Result<bool, ActionErrorKind> {
+    let mut entries = tokio::fs::read_dir(dir)
+        .await
+        .map_err(|e| ActionErrorKind::Read(dir.to_path_buf(), e))?;
+    while let Some(entry) = entries
+        .next_entry()
+        .await
+        .map_err(|e| ActionErrorKind::Read(dir.to_path_buf(), e))?
+    {
+        if entry.path() != Path::new(DROP_IN_DEST) {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Refuse a drop-in at `path` which wasn't written by `nix-installer`, as it would be overwritten and then removed
+#[cfg(target_os = "linux")]
+async fn check_drop_in_is_ours(path: &Path) -> Result<(), ActionErrorKind> {
+    match tokio::fs::read_to_string(path).await {
+        Ok(content) if content.lines().next() == Some(DROP_IN_HEADER) => Ok(()),
+        Ok(_) => Err(ActionErrorKind::DifferentContent(path.to_path_buf())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(ActionErrorKind::Read(path.to_path_buf(), e)),
+    }
+}
+
+/// Remove our drop-in, and the drop-in directory if nothing else is in it
+#[cfg(target_os = "linux")]
+async fn remove_drop_in() -> Result<(), ActionErrorKind> {
+    let drop_in = Path::new(DROP_IN_DEST);
+    // One written by someone else since the install is left alone
+    if drop_in.exists() && check_drop_in_is_ours(drop_in).await.is_ok() {
+        tracing::trace!(path = %drop_in.display(), "Removing");
+        tokio::fs::remove_file(drop_in)
+            .await
+            .map_err(|e| ActionErrorKind::Remove(drop_in.to_path_buf(), e))?;
+    }
+    let drop_in_dir = Path::new(DROP_IN_DIR);
+    if drop_in_dir.exists() && only_contains_our_drop_in(drop_in_dir).await? {
+        tokio::fs::remove_dir(drop_in_dir)
+            .await
+            .map_err(|e| ActionErrorKind::Remove(drop_in_dir.to_path_buf(), e))?;
+    }
+    Ok(())
+}
+
+/// The first of the usual scan directories of `init` which exists
+#[cfg(target_os = "linux")]
+fn supervisor_scan_dir(init: InitSystem) -> Option<PathBuf> {
+    match init {
+        InitSystem::Runit => RUNIT_SCAN_DIRS
+            .iter()
+            .map(PathBuf::from)
+            .find(|scan_dir| scan_dir.is_dir()),
+        InitSystem::S6 => s6_scan_dir(),
+        _ => None,
+    }
+}
+
+/// The first of the usual scan directories which an `s6-svscan` is running in
+#[cfg(target_os = "linux")]
+pub(crate) fn s6_scan_dir() -> Option<PathBuf> {
+    S6_SCAN_DIRS
+        .iter()
+        .map(PathBuf::from)
+        .find(|scan_dir| scan_dir.join(".s6-svscan").is_dir())
+}
+
+#[cfg(target_os = "linux")]
+fn supervisor_missing(init: InitSystem) -> ActionErrorKind {
+    match init {
+        InitSystem::S6 => ActionErrorKind::S6Missing,
+        _ => ActionErrorKind::RunitMissing,
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn supervised_service_dir(init: InitSystem) -> &'static str {
+    match init {
+        InitSystem::S6 => S6_SERVICE_DIR,
+        _ => RUNIT_SERVICE_DIR,
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn supervised_log_script(init: InitSystem) -> &'static str {
+    match init {
+        InitSystem::S6 => S6_LOG_SCRIPT,
+        _ => RUNIT_LOG_SCRIPT,
+    }
+}
+
+/// Write the `run` script of the service, and the `log/run` script of its logging subservice
+#[cfg(target_os = "linux")]
+async fn write_supervised_service(
+    service_dir: &Path,
+    log_script: &str,
+) -> Result<(), ActionErrorKind> {
+    let log_dir = service_dir.join("log");
+    tokio::fs::create_dir_all(&log_dir)
+        .await
+        .map_err(|e| ActionErrorKind::CreateDirectory(log_dir.clone(), e))?;
+
+    for (path, script) in [
+        (service_dir.join("run"), SUPERVISED_RUN_SCRIPT),
+        (log_dir.join("run"), log_script),
+    ] {
+        tracing::trace!(path = %path.display(), "Writing");
+        tokio::fs::write(&path, script)
+            .await
+            .map_err(|e| ActionErrorKind::Write(path.clone(), e))?;
+        tokio::fs::set_permissions(&path, PermissionsExt::from_mode(0o0755))
+            .await
+            .map_err(|e| ActionErrorKind::SetPermissions(0o0755, path.clone(), e))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+async fn supervised_is_up(init: InitSystem, service: &Path) -> Result<bool, ActionErrorKind> {
+    let mut command = match init {
+        InitSystem::S6 => {
+            let mut command = Command::new("s6-svstat");
+            command.args(["-o", "up"]);
+            command
+        },
+        _ => {
+            let mut command = Command::new("sv");
+            command.arg("status");
+            command
+        },
+    };
+    command.arg(service);
+    command.stdin(std::process::Stdio::null());
+    let output = command
+        .output()
+        .await
+        .map_err(|e| ActionErrorKind::command(&command, e))?;
+    // Until the supervisor picks up the service, there is no status to read
+    if !output.status.success() {
+        return Ok(false);
+    }
+    let stdout = String::from_utf8(output.stdout)?;
+    Ok(match init {
+        InitSystem::S6 => stdout.trim() == "true",
+        _ => stdout.starts_with("run:"),
+    })
+}
+
+#[cfg(target_os = "linux")]
+async fn wait_for_supervised_up(init: InitSystem, service: &Path) -> Result<(), ActionErrorKind> {
+    // `runsvdir` only rescans every five seconds, so give it a few chances
+    let mut retry_tokens: usize = 60;
+    loop {
+        if supervised_is_up(init, service).await? {
+            tracing::trace!(service = %service.display(), "Is up");
+            return Ok(());
+        } else if retry_tokens == 0 {
+            return Err(ActionErrorKind::SupervisedServiceNotUp(
+                service.to_path_buf(),
+            ));
+        } else {
+            retry_tokens = retry_tokens.saturating_sub(1);
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+    }
+}
+
 #[cfg(target_os = "linux")]
 async fn stop(unit: &str) -> Result<(), ActionErrorKind> {
     let mut command = Command::new("systemctl");
@@ -617,3 +1300,133 @@ async fn is_enabled(unit: &str) -> Result<bool, ActionErrorKind> {
         Ok(false)
     }
 }
+
+#[cfg(all(test, target_os = "linux"))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn drop_in_only_when_overriding() -> eyre::Result<()> {
+        assert_eq!(DaemonOverrides::default().drop_in(), None);
+
+        let overrides = DaemonOverrides {
+            proxy: Some("http://proxy.example:3128".parse()?),
+            ssl_cert_file: Some(PathBuf::from("/etc/ssl/certs/corp.pem")),
+            limit_nofile: Some(1048576),
+            cpu_quota: Some("200%".to_string()),
+        };
+        assert_eq!(
+            overrides.drop_in().as_deref(),
+            Some(
+                "\
+# Managed by nix-installer, removed on uninstall
+[Service]
+Environment=\"http_proxy=http://proxy.example:3128/\"
+Environment=\"https_proxy=http://proxy.example:3128/\"
+Environment=\"NIX_SSL_CERT_FILE=/etc/ssl/certs/corp.pem\"
+LimitNOFILE=1048576
+CPUQuota=200%
+"
+            )
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn writes_supervised_service_with_log() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let service_dir = temp_dir.path().join("nix-daemon");
+
+        write_supervised_service(&service_dir, RUNIT_LOG_SCRIPT).await?;
+
+        let run = service_dir.join("run");
+        assert_eq!(
+            tokio::fs::read_to_string(&run).await?,
+            SUPERVISED_RUN_SCRIPT
+        );
+        assert_eq!(
+            tokio::fs::metadata(&run).await?.permissions().mode() & 0o777,
+            0o0755
+        );
+        assert_eq!(
+            tokio::fs::read_to_string(service_dir.join("log/run")).await?,
+            RUNIT_LOG_SCRIPT
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn writes_openrc_script() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let script = temp_dir.path().join("nix-daemon");
+
+        write_openrc_script(&script).await?;
+
+        assert_eq!(tokio::fs::read_to_string(&script).await?, OPENRC_SCRIPT);
+        assert_eq!(
+            tokio::fs::metadata(&script).await?.permissions().mode() & 0o777,
+            0o0755
+        );
+        // `openrc-run` sources the script with `sh`
+        let parsed = Command::new("sh").arg("-n").arg(&script).status().await?;
+        assert!(parsed.success());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn adds_and_deletes_openrc_service() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let log = temp_dir.path().join("log");
+        // Stand-ins logging how they are called, with the service always started
+        for tool in ["rc-update", "rc-service"] {
+            let path = temp_dir.path().join(tool);
+            tokio::fs::write(
+                &path,
+                format!("#!/bin/sh\necho \"{tool} $*\" >> {}\n", log.display()),
+            )
+            .await?;
+            tokio::fs::set_permissions(&path, PermissionsExt::from_mode(0o0755)).await?;
+        }
+
+        openrc_add(Some(temp_dir.path()), true).await?;
+        assert!(openrc_del(Some(temp_dir.path())).await.is_empty());
+        assert_eq!(
+            tokio::fs::read_to_string(&log).await?,
+            "\
+rc-update add nix-daemon default
+rc-service nix-daemon start
+rc-service nix-daemon status
+rc-service nix-daemon stop
+rc-update del nix-daemon default
+"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn refuses_foreign_drop_in() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let drop_in = temp_dir.path().join("nix-installer.conf");
+
+        check_drop_in_is_ours(&drop_in).await?;
+
+        let overrides = DaemonOverrides {
+            limit_nofile: Some(1048576),
+            ..Default::default()
+        };
+        tokio::fs::write(&drop_in, overrides.drop_in().unwrap()).await?;
+        check_drop_in_is_ours(&drop_in).await?;
+
+        tokio::fs::write(&drop_in, "[Service]\nEnvironment=\"TMPDIR=/var/tmp\"\n").await?;
+        match check_drop_in_is_ours(&drop_in).await {
+            Err(ActionErrorKind::DifferentContent(path)) => assert_eq!(path, drop_in),
+            _ => unreachable!(),
+        }
+
+        Ok(())
+    }
+}
@@ -1,13 +1,17 @@
-use std::path::PathBuf;
+use std::path::Path;
 
 use crate::{
     action::{
         base::SetupDefaultProfile,
-        common::{ConfigureShellProfile, PlaceNixConfiguration},
-        Action, ActionDescription, ActionError, ActionErrorKind, ActionTag, StatefulAction,
+        common::{
+            configure_shell_profile::ProfileScripts, place_nix_configuration::NIX_CONF,
+            ConfigureShellProfile, PlaceNixConfiguration,
+        },
+        Action, ActionDescription, ActionError, ActionErrorKind, ActionResource, ActionTag,
+        StatefulAction, SubAction,
     },
     planner::ShellProfileLocations,
-    settings::{CommonSettings, SCRATCH_DIR},
+    settings::{rebase, CommonSettings, SCRATCH_DIR},
 };
 
 use tracing::{span, Instrument, Span};
@@ -27,26 +31,39 @@ impl ConfigureNix {
     pub async fn plan(
         shell_profile_locations: ShellProfileLocations,
         settings: &CommonSettings,
+        root: &Path,
     ) -> Result<StatefulAction<Self>, ActionError> {
-        let setup_default_profile = SetupDefaultProfile::plan(PathBuf::from(SCRATCH_DIR))
-            .await
-            .map_err(Self::error)?;
-
-        let configure_shell_profile = if settings.modify_profile {
-            Some(
-                ConfigureShellProfile::plan(shell_profile_locations)
+        let setup_default_profile =
+            SetupDefaultProfile::plan(rebase(root, SCRATCH_DIR), root.to_path_buf())
+                .await
+                .map_err(Self::error)?;
+
+        let configure_shell_profile =
+            if settings.modify_profile && settings.profile_users.is_empty() {
+                Some(
+                    ConfigureShellProfile::plan(shell_profile_locations, ProfileScripts::default())
+                        .await
+                        .map_err(Self::error)?,
+                )
+            } else if settings.modify_profile {
+                Some(
+                    ConfigureShellProfile::plan_for_users(
+                        settings.profile_users.clone(),
+                        ProfileScripts::default(),
+                    )
                     .await
                     .map_err(Self::error)?,
-            )
-        } else {
-            None
-        };
+                )
+            } else {
+                None
+            };
         let place_nix_configuration = PlaceNixConfiguration::plan(
             settings.nix_build_group_name.clone(),
             settings.proxy.clone(),
             settings.ssl_cert_file.clone(),
             settings.extra_conf.clone(),
             settings.force,
+            rebase(root, NIX_CONF),
         )
         .await
         .map_err(Self::error)?;
@@ -74,6 +91,43 @@ impl Action for ConfigureNix {
         span!(tracing::Level::DEBUG, "configure_nix",)
     }
 
+    fn resources(&self) -> Vec<ActionResource> {
+        let mut resources = vec![
+            self.setup_default_profile.resources(),
+            self.place_nix_configuration.resources(),
+        ];
+        if let Some(configure_shell_profile) = &self.configure_shell_profile {
+            resources.push(configure_shell_profile.resources());
+        }
+        ActionResource::union(resources)
+    }
+
+    fn children(&self) -> Vec<&dyn SubAction> {
+        let mut children: Vec<&dyn SubAction> = vec![
+            &self.setup_default_profile as &dyn SubAction,
+            &self.place_nix_configuration,
+        ];
+        children.extend(
+            self.configure_shell_profile
+                .iter()
+                .map(|v| v as &dyn SubAction),
+        );
+        children
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut dyn SubAction> {
+        let mut children: Vec<&mut dyn SubAction> = vec![
+            &mut self.setup_default_profile as &mut dyn SubAction,
+            &mut self.place_nix_configuration,
+        ];
+        children.extend(
+            self.configure_shell_profile
+                .iter_mut()
+                .map(|v| v as &mut dyn SubAction),
+        );
+        children
+    }
+
     fn execute_description(&self) -> Vec<ActionDescription> {
         let Self {
             setup_default_profile,
@@ -1,10 +1,14 @@
-use crate::action::base::{create_or_insert_into_file, CreateDirectory, CreateOrInsertIntoFile};
+use crate::action::base::{
+    create_or_insert_into_file, CreateDirectory, CreateOrInsertIntoFile, CreateOrUpdateManagedBlock,
+};
 use crate::action::{
-    Action, ActionDescription, ActionError, ActionErrorKind, ActionTag, StatefulAction,
+    Action, ActionDescription, ActionError, ActionErrorKind, ActionResource, ActionTag,
+    StatefulAction, SubAction,
 };
 use crate::planner::ShellProfileLocations;
 
-use nix::unistd::User;
+use nix::unistd::{Group, Uid, User};
+use std::os::unix::fs::MetadataExt;
 use std::path::{Path, PathBuf};
 use tokio::task::JoinSet;
 use tracing::{span, Instrument, Span};
@@ -12,13 +16,60 @@ use tracing::{span, Instrument, Span};
 const PROFILE_NIX_FILE_SHELL: &str = "/nix/var/nix/profiles/default/etc/profile.d/nix-daemon.sh";
 const PROFILE_NIX_FILE_FISH: &str = "/nix/var/nix/profiles/default/etc/profile.d/nix-daemon.fish";
 
+/// The markers around the block sourcing Nix in a shell profile
+pub(crate) const PROFILE_BLOCK_BEGIN: &str = "# Nix";
+pub(crate) const PROFILE_BLOCK_END: &str = "# End Nix";
+
+/// Where `nix-daemon.sh` looks for a CA bundle, for the shells which can't source it
+const SSL_CERT_FILES: &[&str] = &[
+    "/etc/ssl/certs/ca-certificates.crt",
+    "/etc/ssl/ca-bundle.pem",
+    "/etc/ssl/certs/ca-bundle.crt",
+    "/etc/pki/tls/certs/ca-bundle.crt",
+    "/nix/var/nix/profiles/default/etc/ssl/certs/ca-bundle.crt",
+];
+
+/// The scripts which set up the environment for Nix, sourced by the shell profiles
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfileScripts {
+    pub shell: PathBuf,
+    pub fish: PathBuf,
+}
+
+impl Default for ProfileScripts {
+    fn default() -> Self {
+        Self {
+            shell: PROFILE_NIX_FILE_SHELL.into(),
+            fish: PROFILE_NIX_FILE_FISH.into(),
+        }
+    }
+}
+
+impl ProfileScripts {
+    /// The scripts of a single user install, found in the profile of the user with `home`
+    pub fn single_user(home: &Path) -> Self {
+        let profile_d = home.join(".nix-profile/etc/profile.d");
+        Self {
+            shell: profile_d.join("nix.sh"),
+            fish: profile_d.join("nix.fish"),
+        }
+    }
+}
+
 /**
 Configure any detected shell profiles to include Nix support
  */
 #[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
 pub struct ConfigureShellProfile {
-    locations: ShellProfileLocations,
+    /// The system wide profiles, unless the profiles of `users` are configured instead
+    locations: Option<ShellProfileLocations>,
+    #[serde(default)]
+    users: Vec<String>,
     create_directories: Vec<StatefulAction<CreateDirectory>>,
+    /// The blocks sourcing Nix in the shell profiles, receipts from before these were managed by their markers have
+    /// them in `create_or_insert_into_files`
+    #[serde(default)]
+    create_or_update_managed_blocks: Vec<StatefulAction<CreateOrUpdateManagedBlock>>,
     create_or_insert_into_files: Vec<StatefulAction<CreateOrInsertIntoFile>>,
 }
 
@@ -26,158 +77,418 @@ impl ConfigureShellProfile {
     #[tracing::instrument(level = "debug", skip_all)]
     pub async fn plan(
         locations: ShellProfileLocations,
+        scripts: ProfileScripts,
     ) -> Result<StatefulAction<Self>, ActionError> {
         let mut create_or_insert_files = Vec::default();
-        let mut create_directories = Vec::default();
+        let mut profiles = ProfilesPlan::default();
+        profiles
+            .add(&locations, &scripts, None, None)
+            .await
+            .map_err(Self::error)?;
+
+        // If the `$GITHUB_PATH` environment exists, we're almost certainly running on Github
+        // Actions, and almost certainly wants the relevant `$PATH` additions added.
+        if let Ok(github_path) = std::env::var("GITHUB_PATH") {
+            let mut buf = "/nix/var/nix/profiles/default/bin\n".to_string();
+            // Actions runners operate as `runner` user by default
+            if let Ok(Some(runner)) = User::from_name("runner") {
+                #[cfg(target_os = "linux")]
+                let path = format!("/home/{}/.nix-profile/bin\n", runner.name);
+                #[cfg(target_os = "macos")]
+                let path = format!("/Users/{}/.nix-profile/bin\n", runner.name);
+                buf += &path;
+            }
+            create_or_insert_files.push(
+                CreateOrInsertIntoFile::plan(
+                    &github_path,
+                    None,
+                    None,
+                    // We want the `nix-installer-action` to not error if it writes here.
+                    // Prior to `v5` this was done in this crate, in `v5` and later, this is done in the action.
+                    0o777,
+                    buf,
+                    create_or_insert_into_file::Position::End,
+                )
+                .await?,
+            );
+        }
+
+        Ok(Self {
+            locations: Some(locations),
+            users: vec![],
+            create_directories: profiles.create_directories,
+            create_or_update_managed_blocks: profiles.create_or_update_managed_blocks,
+            create_or_insert_into_files: create_or_insert_files,
+        }
+        .into())
+    }
 
+    /// Configure the own shell profiles of each of `users`, like `~/.bashrc`, instead of the system wide ones
+    ///
+    /// For hosts where the system wide profiles are managed by something else which would overwrite them.
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub async fn plan_for_users(
+        users: Vec<String>,
+        scripts: ProfileScripts,
+    ) -> Result<StatefulAction<Self>, ActionError> {
+        let mut profiles = ProfilesPlan::default();
+        for name in &users {
+            let user = User::from_name(name)
+                .map_err(|e| ActionErrorKind::GettingUserId(name.clone(), e))
+                .map_err(Self::error)?
+                .ok_or_else(|| ActionErrorKind::NoUser(name.clone()))
+                .map_err(Self::error)?;
+            let group = Group::from_gid(user.gid)
+                .map_err(|e| ActionErrorKind::GettingGroupId(user.gid.to_string(), e))
+                .map_err(Self::error)?
+                .map(|group| group.name);
+            let locations = ShellProfileLocations::for_user(&user.dir, &user.dir.join(".config"));
+
+            profiles.home = Some((user.dir.clone(), user.uid));
+            profiles
+                .add(&locations, &scripts, Some(name), group.as_deref())
+                .await
+                .map_err(Self::error)?;
+        }
+
+        Ok(Self {
+            locations: None,
+            users,
+            create_directories: profiles.create_directories,
+            create_or_update_managed_blocks: profiles.create_or_update_managed_blocks,
+            create_or_insert_into_files: vec![],
+        }
+        .into())
+    }
+
+    /// Check every path about to be written in the home directories of `users` with [`check_home_path`], as they
+    /// may have changed since planning
+    fn check_home_paths(&self) -> Result<(), ActionError> {
+        let mut homes = vec![];
+        for name in &self.users {
+            let user = User::from_name(name)
+                .map_err(|e| ActionErrorKind::GettingUserId(name.clone(), e))
+                .map_err(Self::error)?
+                .ok_or_else(|| ActionErrorKind::NoUser(name.clone()))
+                .map_err(Self::error)?;
+            homes.push((user.dir, user.uid));
+        }
+
+        let paths = self
+            .create_directories
+            .iter()
+            .map(|v| v.resources())
+            .chain(
+                self.create_or_update_managed_blocks
+                    .iter()
+                    .map(|v| v.resources()),
+            )
+            .flatten()
+            .filter_map(|v| match v {
+                ActionResource::Path(path) => Some(path),
+                _ => None,
+            });
+        for path in paths {
+            for (home, uid) in &homes {
+                if path.starts_with(home) {
+                    check_home_path(home, *uid, &path).map_err(Self::error)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The directories and blocks for a set of [`ShellProfileLocations`], created by `user` and `group` if set
+#[derive(Default)]
+struct ProfilesPlan {
+    /// The home directory and uid of the user whose profiles are added, see [`check_home_path`]
+    home: Option<(PathBuf, Uid)>,
+    create_directories: Vec<StatefulAction<CreateDirectory>>,
+    create_or_update_managed_blocks: Vec<StatefulAction<CreateOrUpdateManagedBlock>>,
+}
+
+impl ProfilesPlan {
+    async fn add(
+        &mut self,
+        locations: &ShellProfileLocations,
+        scripts: &ProfileScripts,
+        user: Option<&str>,
+        group: Option<&str>,
+    ) -> Result<(), ActionError> {
         let shell_buf = format!(
-            "\n\
-            # Nix\n\
-            if [ -e '{PROFILE_NIX_FILE_SHELL}' ]; then\n\
-            {inde}. '{PROFILE_NIX_FILE_SHELL}'\n\
+            "\
+            if [ -e '{script}' ]; then\n\
+            {inde}. '{script}'\n\
             fi\n\
-            # End Nix\n
-        \n",
+            ",
+            script = scripts.shell.display(),
             inde = "    ", // indent
         );
 
         for profile_target in locations.bash.iter().chain(locations.zsh.iter()) {
-            let profile_target_path = Path::new(profile_target);
-            if let Some(parent) = profile_target_path.parent() {
-                // Some tools (eg `nix-darwin`) create symlinks to these files, don't write to them if that's the case.
-                if !profile_target_path.is_symlink() {
-                    if !parent.exists() {
-                        create_directories.push(
-                            CreateDirectory::plan(parent, None, None, 0o0755, false)
-                                .await
-                                .map_err(Self::error)?,
-                        );
-                    }
-
-                    create_or_insert_files.push(
-                        CreateOrInsertIntoFile::plan(
-                            profile_target_path,
-                            None,
-                            None,
-                            0o644,
-                            shell_buf.to_string(),
-                            create_or_insert_into_file::Position::Beginning,
-                        )
-                        .await
-                        .map_err(Self::error)?,
-                    );
-                }
+            // Some tools (eg `nix-darwin`) create symlinks to these files, don't write to them if that's the case.
+            if !profile_target.is_symlink() {
+                self.add_block(profile_target, &shell_buf, user, group)
+                    .await?;
             }
         }
 
         let fish_buf = format!(
-            "\n\
-            # Nix\n\
-            if test -e '{PROFILE_NIX_FILE_FISH}'\n\
-            {inde}. '{PROFILE_NIX_FILE_FISH}'\n\
+            "\
+            if test -e '{script}'\n\
+            {inde}. '{script}'\n\
             end\n\
-            # End Nix\n\
-        \n",
+            ",
+            script = scripts.fish.display(),
             inde = "    ", // indent
         );
 
         for fish_prefix in &locations.fish.confd_prefixes {
-            let fish_prefix_path = PathBuf::from(fish_prefix);
-
-            if !fish_prefix_path.exists() {
+            if !fish_prefix.exists() {
                 // If the prefix doesn't exist, don't create the `conf.d/nix.fish`
                 continue;
             }
 
-            let mut profile_target = fish_prefix_path;
-            profile_target.push(locations.fish.confd_suffix.clone());
+            let profile_target = fish_prefix.join(&locations.fish.confd_suffix);
 
             // Some tools (eg `nix-darwin`) create symlinks to these files, don't write to them if that's the case.
             if !profile_target.is_symlink() {
-                if let Some(conf_d) = profile_target.parent() {
-                    create_directories.push(
-                        CreateDirectory::plan(conf_d.to_path_buf(), None, None, 0o755, false)
-                            .await?,
-                    );
-                }
-
-                create_or_insert_files.push(
-                    CreateOrInsertIntoFile::plan(
-                        profile_target,
-                        None,
-                        None,
-                        0o644,
-                        fish_buf.to_string(),
-                        create_or_insert_into_file::Position::Beginning,
-                    )
-                    .await?,
-                );
+                self.add_block(&profile_target, &fish_buf, user, group)
+                    .await?;
             }
         }
         for fish_prefix in &locations.fish.vendor_confd_prefixes {
-            let fish_prefix_path = PathBuf::from(fish_prefix);
-
-            if !fish_prefix_path.exists() {
+            if !fish_prefix.exists() {
                 // If the prefix doesn't exist, don't create the `conf.d/nix.fish`
                 continue;
             }
 
-            let mut profile_target = fish_prefix_path;
-            profile_target.push(locations.fish.vendor_confd_suffix.clone());
+            let profile_target = fish_prefix.join(&locations.fish.vendor_confd_suffix);
+            self.add_block(&profile_target, &fish_buf, user, group)
+                .await?;
+        }
 
-            if let Some(conf_d) = profile_target.parent() {
-                create_directories.push(
-                    CreateDirectory::plan(conf_d.to_path_buf(), None, None, 0o755, false).await?,
-                );
+        // Nix ships no scripts for these, so their snippets set up `PATH` and `NIX_PROFILES` like `nix-daemon.sh`,
+        // once that script exists
+        let other_shells = [
+            (&locations.nushell, &["nu"][..], nushell_buf(&scripts.shell)),
+            (
+                &locations.tcsh,
+                &["tcsh", "csh"][..],
+                tcsh_buf(&scripts.shell),
+            ),
+            (&locations.xonsh, &["xonsh"][..], xonsh_buf(&scripts.shell)),
+            (
+                &locations.elvish,
+                &["elvish"][..],
+                elvish_buf(&scripts.shell),
+            ),
+        ];
+        for (profile_targets, executables, buf) in other_shells {
+            for profile_target in other_shell_targets(profile_targets, executables) {
+                self.add_block(&profile_target, &buf, user, group).await?;
             }
+        }
 
-            create_or_insert_files.push(
-                CreateOrInsertIntoFile::plan(
-                    profile_target,
-                    None,
-                    None,
-                    0o644,
-                    fish_buf.to_string(),
-                    create_or_insert_into_file::Position::Beginning,
-                )
-                .await?,
-            );
+        Ok(())
+    }
+
+    /// Add the block to `profile_target`, and its missing parent directories from the outermost one down
+    async fn add_block(
+        &mut self,
+        profile_target: &Path,
+        buf: &str,
+        user: Option<&str>,
+        group: Option<&str>,
+    ) -> Result<(), ActionError> {
+        if let Some((home, uid)) = &self.home {
+            if let Err(err) = check_home_path(home, *uid, profile_target) {
+                tracing::warn!("Not configuring `{}`: {err}", profile_target.display());
+                return Ok(());
+            }
         }
 
-        // If the `$GITHUB_PATH` environment exists, we're almost certainly running on Github
-        // Actions, and almost certainly wants the relevant `$PATH` additions added.
-        if let Ok(github_path) = std::env::var("GITHUB_PATH") {
-            let mut buf = "/nix/var/nix/profiles/default/bin\n".to_string();
-            // Actions runners operate as `runner` user by default
-            if let Ok(Some(runner)) = User::from_name("runner") {
-                #[cfg(target_os = "linux")]
-                let path = format!("/home/{}/.nix-profile/bin\n", runner.name);
-                #[cfg(target_os = "macos")]
-                let path = format!("/Users/{}/.nix-profile/bin\n", runner.name);
-                buf += &path;
+        if let Some(parent) = profile_target.parent() {
+            let mut missing: Vec<&Path> = parent.ancestors().take_while(|v| !v.exists()).collect();
+            missing.reverse();
+            for directory in missing {
+                if self.create_directories.iter().any(|v| {
+                    v.resources()
+                        .contains(&ActionResource::Path(directory.to_path_buf()))
+                }) {
+                    continue;
+                }
+                self.create_directories.push(
+                    CreateDirectory::plan(
+                        directory,
+                        user.map(ToString::to_string),
+                        group.map(ToString::to_string),
+                        0o0755,
+                        false,
+                    )
+                    .await?,
+                );
             }
-            create_or_insert_files.push(
-                CreateOrInsertIntoFile::plan(
-                    &github_path,
-                    None,
-                    None,
-                    // We want the `nix-installer-action` to not error if it writes here.
-                    // Prior to `v5` this was done in this crate, in `v5` and later, this is done in the action.
-                    0o777,
-                    buf,
-                    create_or_insert_into_file::Position::End,
-                )
-                .await?,
-            );
         }
 
-        Ok(Self {
-            locations,
-            create_directories,
-            create_or_insert_into_files: create_or_insert_files,
+        self.create_or_update_managed_blocks.push(
+            CreateOrUpdateManagedBlock::plan(
+                profile_target,
+                user.map(ToString::to_string),
+                group.map(ToString::to_string),
+                0o644,
+                PROFILE_BLOCK_BEGIN,
+                PROFILE_BLOCK_END,
+                buf.to_string(),
+                create_or_insert_into_file::Position::Beginning,
+            )
+            .await?,
+        );
+
+        Ok(())
+    }
+}
+
+/**
+Refuse `path` in `home` if it, or a directory between it and `home`, is a symlink or isn't owned by `uid`
+
+`root` writes the profiles of other users, so otherwise a user could point `~/.config` at `/etc` and have `root` write
+and hand them files there. What doesn't exist yet is created by `root`.
+*/
+fn check_home_path(home: &Path, uid: Uid, path: &Path) -> Result<(), ActionErrorKind> {
+    let Ok(relative) = path.strip_prefix(home) else {
+        return Err(ActionErrorKind::UnsafeHomePath(
+            path.to_path_buf(),
+            uid.as_raw(),
+        ));
+    };
+    let mut current = home.to_path_buf();
+    for component in relative.components() {
+        current.push(component);
+        match std::fs::symlink_metadata(&current) {
+            Ok(metadata) if metadata.file_type().is_symlink() || metadata.uid() != uid.as_raw() => {
+                return Err(ActionErrorKind::UnsafeHomePath(current, uid.as_raw()));
+            },
+            Ok(_) => (),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => break,
+            Err(e) => return Err(ActionErrorKind::GettingMetadata(current, e)),
         }
-        .into())
     }
+    Ok(())
+}
+
+/**
+The profiles to write for a shell other than bash, zsh or fish: the ones which already exist, otherwise the first one
+if the shell is installed
+
+Unlike `/etc/bashrc`, these would be created on most systems for a shell nobody uses.
+*/
+fn other_shell_targets(profile_targets: &[PathBuf], executables: &[&str]) -> Vec<PathBuf> {
+    // Some tools (eg `nix-darwin`) create symlinks to these files, don't write to them if that's the case.
+    let mut targets: Vec<_> = profile_targets
+        .iter()
+        .filter(|v| v.exists() && !v.is_symlink())
+        .cloned()
+        .collect();
+    if targets.is_empty() && executables.iter().any(|v| which::which(v).is_ok()) {
+        targets.extend(profile_targets.first().filter(|v| !v.is_symlink()).cloned());
+    }
+    targets
+}
+
+fn nushell_buf(script: &Path) -> String {
+    let certs = SSL_CERT_FILES
+        .iter()
+        .map(|v| format!("'{v}'"))
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!(
+        "\
+        if ('{script}' | path exists) and ('__ETC_PROFILE_NIX_SOURCED' not-in $env) {{\n\
+        {inde}$env.__ETC_PROFILE_NIX_SOURCED = '1'\n\
+        {inde}$env.NIX_PROFILES = $\"/nix/var/nix/profiles/default ($env.HOME)/.nix-profile\"\n\
+        {inde}if 'NIX_SSL_CERT_FILE' not-in $env {{\n\
+        {inde}{inde}let certs = [{certs}] | where {{|cert| $cert | path exists }}\n\
+        {inde}{inde}if ($certs | is-not-empty) {{\n\
+        {inde}{inde}{inde}$env.NIX_SSL_CERT_FILE = ($certs | first)\n\
+        {inde}{inde}}}\n\
+        {inde}}}\n\
+        {inde}$env.PATH = ($env.PATH | split row (char esep) | prepend [$\"($env.HOME)/.nix-profile/bin\" '/nix/var/nix/profiles/default/bin'])\n\
+        }}\n\
+        ",
+        script = script.display(),
+        inde = "    ", // indent
+    )
+}
+
+fn tcsh_buf(script: &Path) -> String {
+    format!(
+        "\
+        if ( -e '{script}' && ! $?__ETC_PROFILE_NIX_SOURCED ) then\n\
+        {inde}setenv __ETC_PROFILE_NIX_SOURCED 1\n\
+        {inde}setenv NIX_PROFILES \"/nix/var/nix/profiles/default $HOME/.nix-profile\"\n\
+        {inde}if ( ! $?NIX_SSL_CERT_FILE ) then\n\
+        {inde}{inde}foreach nix_cert ( {certs} )\n\
+        {inde}{inde}{inde}if ( -e $nix_cert && ! $?NIX_SSL_CERT_FILE ) setenv NIX_SSL_CERT_FILE $nix_cert\n\
+        {inde}{inde}end\n\
+        {inde}{inde}unset nix_cert\n\
+        {inde}endif\n\
+        {inde}setenv PATH \"$HOME/.nix-profile/bin:/nix/var/nix/profiles/default/bin:$PATH\"\n\
+        endif\n\
+        ",
+        script = script.display(),
+        certs = SSL_CERT_FILES.join(" "),
+        inde = "    ", // indent
+    )
+}
+
+fn xonsh_buf(script: &Path) -> String {
+    let certs = SSL_CERT_FILES
+        .iter()
+        .map(|v| format!("'{v}'"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!(
+        "\
+        import os.path as __nix_path\n\
+        if __nix_path.exists('{script}') and '__ETC_PROFILE_NIX_SOURCED' not in ${{...}}:\n\
+        {inde}$__ETC_PROFILE_NIX_SOURCED = '1'\n\
+        {inde}$NIX_PROFILES = '/nix/var/nix/profiles/default ' + $HOME + '/.nix-profile'\n\
+        {inde}if 'NIX_SSL_CERT_FILE' not in ${{...}}:\n\
+        {inde}{inde}for __nix_cert in [{certs}]:\n\
+        {inde}{inde}{inde}if __nix_path.exists(__nix_cert):\n\
+        {inde}{inde}{inde}{inde}$NIX_SSL_CERT_FILE = __nix_cert\n\
+        {inde}{inde}{inde}{inde}break\n\
+        {inde}$PATH.insert(0, '/nix/var/nix/profiles/default/bin')\n\
+        {inde}$PATH.insert(0, $HOME + '/.nix-profile/bin')\n\
+        ",
+        script = script.display(),
+        inde = "    ", // indent
+    )
+}
+
+fn elvish_buf(script: &Path) -> String {
+    format!(
+        "\
+        use os\n\
+        if (and (os:exists '{script}') (not (has-env __ETC_PROFILE_NIX_SOURCED))) {{\n\
+        {inde}set-env __ETC_PROFILE_NIX_SOURCED 1\n\
+        {inde}set-env NIX_PROFILES '/nix/var/nix/profiles/default '$E:HOME'/.nix-profile'\n\
+        {inde}if (not (has-env NIX_SSL_CERT_FILE)) {{\n\
+        {inde}{inde}for cert [{certs}] {{\n\
+        {inde}{inde}{inde}if (and (not (has-env NIX_SSL_CERT_FILE)) (os:exists $cert)) {{\n\
+        {inde}{inde}{inde}{inde}set-env NIX_SSL_CERT_FILE $cert\n\
+        {inde}{inde}{inde}}}\n\
+        {inde}{inde}}}\n\
+        {inde}}}\n\
+        {inde}set paths = [$E:HOME/.nix-profile/bin /nix/var/nix/profiles/default/bin $@paths]\n\
+        }}\n\
+        ",
+        script = script.display(),
+        certs = SSL_CERT_FILES.join(" "),
+        inde = "    ", // indent
+    )
 }
 
 #[async_trait::async_trait]
@@ -187,13 +498,78 @@ impl Action for ConfigureShellProfile {
         ActionTag("configure_shell_profile")
     }
     fn tracing_synopsis(&self) -> String {
-        "Configure the shell profiles".to_string()
+        if self.users.is_empty() {
+            "Configure the shell profiles".to_string()
+        } else {
+            format!(
+                "Configure the shell profiles of {}",
+                self.users
+                    .iter()
+                    .map(|v| format!("`{v}`"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        }
     }
 
     fn tracing_span(&self) -> Span {
         span!(tracing::Level::DEBUG, "configure_shell_profile",)
     }
 
+    fn resources(&self) -> Vec<ActionResource> {
+        ActionResource::union(
+            self.create_directories
+                .iter()
+                .map(|v| v.resources())
+                .chain(
+                    self.create_or_update_managed_blocks
+                        .iter()
+                        .map(|v| v.resources()),
+                )
+                .chain(
+                    self.create_or_insert_into_files
+                        .iter()
+                        .map(|v| v.resources()),
+                ),
+        )
+    }
+
+    fn children(&self) -> Vec<&dyn SubAction> {
+        let mut children: Vec<&dyn SubAction> = vec![];
+        children.extend(self.create_directories.iter().map(|v| v as &dyn SubAction));
+        children.extend(
+            self.create_or_update_managed_blocks
+                .iter()
+                .map(|v| v as &dyn SubAction),
+        );
+        children.extend(
+            self.create_or_insert_into_files
+                .iter()
+                .map(|v| v as &dyn SubAction),
+        );
+        children
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut dyn SubAction> {
+        let mut children: Vec<&mut dyn SubAction> = vec![];
+        children.extend(
+            self.create_directories
+                .iter_mut()
+                .map(|v| v as &mut dyn SubAction),
+        );
+        children.extend(
+            self.create_or_update_managed_blocks
+                .iter_mut()
+                .map(|v| v as &mut dyn SubAction),
+        );
+        children.extend(
+            self.create_or_insert_into_files
+                .iter_mut()
+                .map(|v| v as &mut dyn SubAction),
+        );
+        children
+    }
+
     fn execute_description(&self) -> Vec<ActionDescription> {
         vec![ActionDescription::new(
             self.tracing_synopsis(),
@@ -203,13 +579,42 @@ impl Action for ConfigureShellProfile {
 
     #[tracing::instrument(level = "debug", skip_all)]
     async fn execute(&mut self) -> Result<(), ActionError> {
+        self.check_home_paths()?;
         for create_directory in &mut self.create_directories {
             create_directory.try_execute().await?;
         }
+        // The directories exist now, so every path up to the profiles can be checked
+        self.check_home_paths()?;
 
-        let mut set = JoinSet::new();
         let mut errors = vec![];
 
+        let mut set = JoinSet::new();
+        for (idx, create_or_update_managed_block) in
+            self.create_or_update_managed_blocks.iter().enumerate()
+        {
+            let span = tracing::Span::current().clone();
+            let mut create_or_update_managed_block_clone = create_or_update_managed_block.clone();
+            let _abort_handle = set.spawn(async move {
+                create_or_update_managed_block_clone
+                    .try_execute()
+                    .instrument(span)
+                    .await
+                    .map_err(Self::error)?;
+                Result::<_, ActionError>::Ok((idx, create_or_update_managed_block_clone))
+            });
+        }
+
+        while let Some(result) = set.join_next().await {
+            match result {
+                Ok(Ok((idx, create_or_update_managed_block))) => {
+                    self.create_or_update_managed_blocks[idx] = create_or_update_managed_block
+                },
+                Ok(Err(e)) => errors.push(e),
+                Err(e) => return Err(Self::error(e))?,
+            };
+        }
+
+        let mut set = JoinSet::new();
         for (idx, create_or_insert_into_file) in
             self.create_or_insert_into_files.iter_mut().enumerate()
         {
@@ -257,9 +662,31 @@ impl Action for ConfigureShellProfile {
 
     #[tracing::instrument(level = "debug", skip_all)]
     async fn revert(&mut self) -> Result<(), ActionError> {
-        let mut set = JoinSet::new();
         let mut errors = vec![];
 
+        let mut set = JoinSet::new();
+        for (idx, create_or_update_managed_block) in
+            self.create_or_update_managed_blocks.iter().enumerate()
+        {
+            let mut create_or_update_managed_block_clone = create_or_update_managed_block.clone();
+            let _abort_handle = set.spawn(async move {
+                create_or_update_managed_block_clone.try_revert().await?;
+                Result::<_, _>::Ok((idx, create_or_update_managed_block_clone))
+            });
+        }
+
+        while let Some(result) = set.join_next().await {
+            match result {
+                Ok(Ok((idx, create_or_update_managed_block))) => {
+                    self.create_or_update_managed_blocks[idx] = create_or_update_managed_block
+                },
+                Ok(Err(e)) => errors.push(e),
+                // This is quite rare and generally a very bad sign.
+                Err(e) => return Err(e).map_err(|e| Self::error(ActionErrorKind::from(e)))?,
+            };
+        }
+
+        let mut set = JoinSet::new();
         for (idx, create_or_insert_into_file) in
             self.create_or_insert_into_files.iter_mut().enumerate()
         {
@@ -281,7 +708,8 @@ impl Action for ConfigureShellProfile {
             };
         }
 
-        for create_directory in self.create_directories.iter_mut() {
+        // Nested directories are planned outermost first
+        for create_directory in self.create_directories.iter_mut().rev() {
             if let Err(err) = create_directory.try_revert().await {
                 errors.push(err);
             }
@@ -299,3 +727,219 @@ impl Action for ConfigureShellProfile {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SCRIPT: &str = "/nix/var/nix/profiles/default/etc/profile.d/nix-daemon.sh";
+
+    #[test]
+    fn other_shell_snippets() {
+        let script = Path::new(SCRIPT);
+        assert_eq!(
+            nushell_buf(script),
+            "\
+if ('/nix/var/nix/profiles/default/etc/profile.d/nix-daemon.sh' | path exists) and ('__ETC_PROFILE_NIX_SOURCED' not-in $env) {
+    $env.__ETC_PROFILE_NIX_SOURCED = '1'
+    $env.NIX_PROFILES = $\"/nix/var/nix/profiles/default ($env.HOME)/.nix-profile\"
+    if 'NIX_SSL_CERT_FILE' not-in $env {
+        let certs = ['/etc/ssl/certs/ca-certificates.crt' '/etc/ssl/ca-bundle.pem' '/etc/ssl/certs/ca-bundle.crt' '/etc/pki/tls/certs/ca-bundle.crt' '/nix/var/nix/profiles/default/etc/ssl/certs/ca-bundle.crt'] | where {|cert| $cert | path exists }
+        if ($certs | is-not-empty) {
+            $env.NIX_SSL_CERT_FILE = ($certs | first)
+        }
+    }
+    $env.PATH = ($env.PATH | split row (char esep) | prepend [$\"($env.HOME)/.nix-profile/bin\" '/nix/var/nix/profiles/default/bin'])
+}
+"
+        );
+        assert_eq!(
+            tcsh_buf(script),
+            "\
+if ( -e '/nix/var/nix/profiles/default/etc/profile.d/nix-daemon.sh' && ! $?__ETC_PROFILE_NIX_SOURCED ) then
+    setenv __ETC_PROFILE_NIX_SOURCED 1
+    setenv NIX_PROFILES \"/nix/var/nix/profiles/default $HOME/.nix-profile\"
+    if ( ! $?NIX_SSL_CERT_FILE ) then
+        foreach nix_cert ( /etc/ssl/certs/ca-certificates.crt /etc/ssl/ca-bundle.pem /etc/ssl/certs/ca-bundle.crt /etc/pki/tls/certs/ca-bundle.crt /nix/var/nix/profiles/default/etc/ssl/certs/ca-bundle.crt )
+            if ( -e $nix_cert && ! $?NIX_SSL_CERT_FILE ) setenv NIX_SSL_CERT_FILE $nix_cert
+        end
+        unset nix_cert
+    endif
+    setenv PATH \"$HOME/.nix-profile/bin:/nix/var/nix/profiles/default/bin:$PATH\"
+endif
+"
+        );
+        assert_eq!(
+            xonsh_buf(script),
+            "\
+import os.path as __nix_path
+if __nix_path.exists('/nix/var/nix/profiles/default/etc/profile.d/nix-daemon.sh') and '__ETC_PROFILE_NIX_SOURCED' not in ${...}:
+    $__ETC_PROFILE_NIX_SOURCED = '1'
+    $NIX_PROFILES = '/nix/var/nix/profiles/default ' + $HOME + '/.nix-profile'
+    if 'NIX_SSL_CERT_FILE' not in ${...}:
+        for __nix_cert in ['/etc/ssl/certs/ca-certificates.crt', '/etc/ssl/ca-bundle.pem', '/etc/ssl/certs/ca-bundle.crt', '/etc/pki/tls/certs/ca-bundle.crt', '/nix/var/nix/profiles/default/etc/ssl/certs/ca-bundle.crt']:
+            if __nix_path.exists(__nix_cert):
+                $NIX_SSL_CERT_FILE = __nix_cert
+                break
+    $PATH.insert(0, '/nix/var/nix/profiles/default/bin')
+    $PATH.insert(0, $HOME + '/.nix-profile/bin')
+"
+        );
+        assert_eq!(
+            elvish_buf(script),
+            "\
+use os
+if (and (os:exists '/nix/var/nix/profiles/default/etc/profile.d/nix-daemon.sh') (not (has-env __ETC_PROFILE_NIX_SOURCED))) {
+    set-env __ETC_PROFILE_NIX_SOURCED 1
+    set-env NIX_PROFILES '/nix/var/nix/profiles/default '$E:HOME'/.nix-profile'
+    if (not (has-env NIX_SSL_CERT_FILE)) {
+        for cert [/etc/ssl/certs/ca-certificates.crt /etc/ssl/ca-bundle.pem /etc/ssl/certs/ca-bundle.crt /etc/pki/tls/certs/ca-bundle.crt /nix/var/nix/profiles/default/etc/ssl/certs/ca-bundle.crt] {
+            if (and (not (has-env NIX_SSL_CERT_FILE)) (os:exists $cert)) {
+                set-env NIX_SSL_CERT_FILE $cert
+            }
+        }
+    }
+    set paths = [$E:HOME/.nix-profile/bin /nix/var/nix/profiles/default/bin $@paths]
+}
+"
+        );
+    }
+
+    /// Run each snippet with the shell it's for, if that is installed, checking it puts Nix on `PATH`
+    #[tokio::test]
+    async fn other_shell_snippets_run() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        // The snippets only act once the script they stand in for exists, any path will do
+        let script = temp_dir.path();
+        let shells = [
+            ("nu", &["--no-config-file"][..], nushell_buf(script)),
+            ("tcsh", &["-f"][..], tcsh_buf(script)),
+            ("xonsh", &["--no-rc"][..], xonsh_buf(script)),
+            ("elvish", &["-norc"][..], elvish_buf(script)),
+        ];
+        for (shell, args, buf) in shells {
+            if which::which(shell).is_err() {
+                continue;
+            }
+            let profile = temp_dir.path().join(shell);
+            tokio::fs::write(&profile, format!("{buf}printenv PATH\n")).await?;
+
+            let output = tokio::process::Command::new(shell)
+                .args(args)
+                .arg(&profile)
+                .env("HOME", temp_dir.path())
+                .env_remove("__ETC_PROFILE_NIX_SOURCED")
+                .stdin(std::process::Stdio::null())
+                .output()
+                .await?;
+            assert!(output.status.success(), "{shell}: {output:?}");
+            assert!(
+                String::from_utf8_lossy(&output.stdout).starts_with(&format!(
+                    "{}/.nix-profile/bin:/nix/var/nix/profiles/default/bin:",
+                    temp_dir.path().display()
+                )),
+                "{shell}: {output:?}"
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn refuses_symlinks_and_foreign_paths_in_home() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let home = temp_dir.path();
+        let uid = Uid::effective();
+
+        std::fs::create_dir(home.join(".config"))?;
+        check_home_path(home, uid, &home.join(".config/fish/conf.d/nix.fish"))?;
+
+        std::os::unix::fs::symlink("/etc", home.join(".elsewhere"))?;
+        assert!(matches!(
+            check_home_path(home, uid, &home.join(".elsewhere/fish/conf.d/nix.fish")),
+            Err(ActionErrorKind::UnsafeHomePath(path, _)) if path == home.join(".elsewhere")
+        ));
+
+        // Owned by someone else
+        let other = Uid::from_raw(uid.as_raw() + 1);
+        assert!(matches!(
+            check_home_path(home, other, &home.join(".config/nix/nix.conf")),
+            Err(ActionErrorKind::UnsafeHomePath(path, _)) if path == home.join(".config")
+        ));
+        assert!(check_home_path(home, uid, Path::new("/etc/bashrc")).is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn skips_profiles_behind_symlinks_in_home() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let home = temp_dir.path().join("home");
+        let elsewhere = temp_dir.path().join("elsewhere");
+        std::fs::create_dir_all(elsewhere.join("fish"))?;
+        std::fs::create_dir(&home)?;
+        std::os::unix::fs::symlink(&elsewhere, home.join(".config"))?;
+
+        let mut profiles = ProfilesPlan {
+            home: Some((home.clone(), Uid::effective())),
+            ..Default::default()
+        };
+        profiles
+            .add(
+                &ShellProfileLocations::for_user(&home, &home.join(".config")),
+                &ProfileScripts::default(),
+                None,
+                None,
+            )
+            .await?;
+
+        let paths: Vec<PathBuf> = profiles
+            .create_or_update_managed_blocks
+            .iter()
+            .flat_map(|v| v.resources())
+            .chain(
+                profiles
+                    .create_directories
+                    .iter()
+                    .flat_map(|v| v.resources()),
+            )
+            .filter_map(|v| match v {
+                ActionResource::Path(path) => Some(path),
+                _ => None,
+            })
+            .collect();
+        assert!(paths.contains(&home.join(".bashrc")));
+        assert!(paths.iter().all(|v| !v.starts_with(home.join(".config"))));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn plans_profiles_of_users() -> eyre::Result<()> {
+        let root = User::from_uid(Uid::from_raw(0))?.expect("Every host has `root`");
+
+        let action = ConfigureShellProfile::plan_for_users(
+            vec![root.name.clone()],
+            ProfileScripts::default(),
+        )
+        .await?;
+        let blocks = &action.inner().create_or_update_managed_blocks;
+        assert!(!blocks.is_empty());
+        for block in blocks {
+            for resource in block.resources() {
+                if let ActionResource::Path(path) = resource {
+                    assert!(path.starts_with(&root.dir), "{}", path.display());
+                }
+            }
+        }
+
+        assert!(ConfigureShellProfile::plan_for_users(
+            vec!["nix-installer-no-such-user".to_string()],
+            ProfileScripts::default()
+        )
+        .await
+        .is_err());
+
+        Ok(())
+    }
+}
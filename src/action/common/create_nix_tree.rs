@@ -1,9 +1,13 @@
+use std::path::Path;
+
 use tracing::{span, Span};
 
 use crate::action::base::CreateDirectory;
 use crate::action::{
-    Action, ActionDescription, ActionError, ActionErrorKind, ActionTag, StatefulAction,
+    Action, ActionDescription, ActionError, ActionErrorKind, ActionResource, ActionTag,
+    StatefulAction, SubAction,
 };
+use crate::settings::rebase;
 
 const PATHS: &[&str] = &[
     "/nix/var",
@@ -31,12 +35,15 @@ pub struct CreateNixTree {
 
 impl CreateNixTree {
     #[tracing::instrument(level = "debug", skip_all)]
-    pub async fn plan() -> Result<StatefulAction<Self>, ActionError> {
+    pub async fn plan(
+        root: &Path,
+        owner: Option<String>,
+    ) -> Result<StatefulAction<Self>, ActionError> {
         let mut create_directories = Vec::default();
         for path in PATHS {
             // We use `create_dir` over `create_dir_all` to ensure we always set permissions right
             create_directories.push(
-                CreateDirectory::plan(path, String::from("root"), None, 0o0755, true)
+                CreateDirectory::plan(rebase(root, path), owner.clone(), None, 0o0755, true)
                     .await
                     .map_err(Self::error)?,
             )
@@ -60,6 +67,24 @@ impl Action for CreateNixTree {
         span!(tracing::Level::DEBUG, "create_nix_tree",)
     }
 
+    fn resources(&self) -> Vec<ActionResource> {
+        ActionResource::union(self.create_directories.iter().map(|v| v.resources()))
+    }
+
+    fn children(&self) -> Vec<&dyn SubAction> {
+        self.create_directories
+            .iter()
+            .map(|v| v as &dyn SubAction)
+            .collect()
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut dyn SubAction> {
+        self.create_directories
+            .iter_mut()
+            .map(|v| v as &mut dyn SubAction)
+            .collect()
+    }
+
     fn execute_description(&self) -> Vec<ActionDescription> {
         let Self { create_directories } = &self;
 
@@ -1,10 +1,12 @@
 use crate::{
     action::{
         base::{AddUserToGroup, CreateGroup, CreateUser},
-        Action, ActionDescription, ActionError, ActionErrorKind, ActionTag, StatefulAction,
+        Action, ActionDescription, ActionError, ActionErrorKind, ActionResource, ActionTag,
+        StatefulAction, SubAction,
     },
     settings::CommonSettings,
 };
+use std::path::Path;
 use tracing::{span, Span};
 
 #[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
@@ -21,11 +23,16 @@ pub struct CreateUsersAndGroups {
 
 impl CreateUsersAndGroups {
     #[tracing::instrument(level = "debug", skip_all)]
-    pub async fn plan(settings: CommonSettings) -> Result<StatefulAction<Self>, ActionError> {
+    pub async fn plan(
+        settings: CommonSettings,
+        root: &Path,
+    ) -> Result<StatefulAction<Self>, ActionError> {
         let create_group = CreateGroup::plan(
             settings.nix_build_group_name.clone(),
             settings.nix_build_group_id,
-        )?;
+            root.to_path_buf(),
+        )
+        .await?;
         let mut create_users = Vec::with_capacity(settings.nix_build_user_count as usize);
         let mut add_users_to_groups = Vec::with_capacity(settings.nix_build_user_count as usize);
         for index in 1..=settings.nix_build_user_count {
@@ -36,6 +43,7 @@ impl CreateUsersAndGroups {
                     settings.nix_build_group_name.clone(),
                     settings.nix_build_group_id,
                     format!("Nix build user {index}"),
+                    root.to_path_buf(),
                 )
                 .await
                 .map_err(Self::error)?,
@@ -46,6 +54,7 @@ impl CreateUsersAndGroups {
                     settings.nix_build_user_id_base + index,
                     settings.nix_build_group_name.clone(),
                     settings.nix_build_group_id,
+                    root.to_path_buf(),
                 )
                 .await
                 .map_err(Self::error)?,
@@ -96,6 +105,37 @@ impl Action for CreateUsersAndGroups {
         )
     }
 
+    fn resources(&self) -> Vec<ActionResource> {
+        ActionResource::union(
+            std::iter::once(self.create_group.resources())
+                .chain(self.create_users.iter().map(|v| v.resources()))
+                .chain(self.add_users_to_groups.iter().map(|v| v.resources())),
+        )
+    }
+
+    fn children(&self) -> Vec<&dyn SubAction> {
+        let mut children: Vec<&dyn SubAction> = vec![&self.create_group as &dyn SubAction];
+        children.extend(self.create_users.iter().map(|v| v as &dyn SubAction));
+        children.extend(self.add_users_to_groups.iter().map(|v| v as &dyn SubAction));
+        children
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut dyn SubAction> {
+        let mut children: Vec<&mut dyn SubAction> =
+            vec![&mut self.create_group as &mut dyn SubAction];
+        children.extend(
+            self.create_users
+                .iter_mut()
+                .map(|v| v as &mut dyn SubAction),
+        );
+        children.extend(
+            self.add_users_to_groups
+                .iter_mut()
+                .map(|v| v as &mut dyn SubAction),
+        );
+        children
+    }
+
     fn execute_description(&self) -> Vec<ActionDescription> {
         let Self {
             nix_build_user_count: _,
@@ -150,57 +190,15 @@ impl Action for CreateUsersAndGroups {
         // Create group
         create_group.try_execute().await?;
 
-        // Mac is apparently not threadsafe here...
-        use target_lexicon::OperatingSystem;
-        match OperatingSystem::host() {
-            OperatingSystem::MacOSX {
-                major: _,
-                minor: _,
-                patch: _,
-            }
-            | OperatingSystem::Darwin => {
-                for create_user in create_users.iter_mut() {
-                    create_user.try_execute().await.map_err(Self::error)?;
-                }
-            },
-            _ => {
-                for create_user in create_users.iter_mut() {
-                    create_user.try_execute().await.map_err(Self::error)?;
-                }
-                // While we may be tempted to do something like this, it can break on many older OSes like Ubuntu 18.04:
-                // ```
-                // useradd: cannot lock /etc/passwd; try again later.
-                // ```
-                // So, instead, we keep this here in hopes one day we can enable it for some detected OS:
-                //
-                // let mut set = JoinSet::new();
-                // let mut errors: Vec<Box<ActionError>> = Vec::new();
-                // for (idx, create_user) in create_users.iter_mut().enumerate() {
-                //     let span = tracing::Span::current().clone();
-                //     let mut create_user_clone = create_user.clone();
-                //     let _abort_handle = set.spawn(async move {
-                //         create_user_clone.try_execute().instrument(span).await?;
-                //         Result::<_, _>::Ok((idx, create_user_clone))
-                //     });
-                // }
-
-                // while let Some(result) = set.join_next().await {
-                //     match result {
-                //         Ok(Ok((idx, success))) => create_users[idx] = success,
-                //         Ok(Err(e)) => errors.push(Box::new(e)),
-                //         Err(e) => return Err(ActionErrorKind::Join(e))?,
-                //     };
-                // }
-
-                // if !errors.is_empty() {
-                //     if errors.len() == 1 {
-                //         return Err(errors.into_iter().next().unwrap().into());
-                //     } else {
-                //         return Err(ActionErrorKind::Children(errors));
-                //     }
-                // }
-            },
-        };
+        // Users are created one after another, not concurrently: every one of them edits the same account database
+        // (see `CreateUser::resources`), and the user tools of many older OSes like Ubuntu 18.04 fail rather than wait
+        // when another holds its lock:
+        // ```
+        // useradd: cannot lock /etc/passwd; try again later.
+        // ```
+        for create_user in create_users.iter_mut() {
+            create_user.try_execute().await.map_err(Self::error)?;
+        }
 
         for add_user_to_group in add_users_to_groups.iter_mut() {
             add_user_to_group.try_execute().await.map_err(Self::error)?;
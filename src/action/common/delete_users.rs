@@ -1,6 +1,6 @@
 use crate::action::{
-    base::DeleteUser, Action, ActionDescription, ActionError, ActionErrorKind, ActionTag,
-    StatefulAction,
+    base::DeleteUser, Action, ActionDescription, ActionError, ActionErrorKind, ActionResource,
+    ActionTag, StatefulAction, SubAction,
 };
 use tracing::{span, Span};
 
@@ -55,6 +55,24 @@ impl Action for DeleteUsersInGroup {
         )
     }
 
+    fn resources(&self) -> Vec<ActionResource> {
+        ActionResource::union(self.delete_users.iter().map(|v| v.resources()))
+    }
+
+    fn children(&self) -> Vec<&dyn SubAction> {
+        self.delete_users
+            .iter()
+            .map(|v| v as &dyn SubAction)
+            .collect()
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut dyn SubAction> {
+        self.delete_users
+            .iter_mut()
+            .map(|v| v as &mut dyn SubAction)
+            .collect()
+    }
+
     fn execute_description(&self) -> Vec<ActionDescription> {
         let mut delete_users_descriptions = Vec::new();
         for delete_user in self.delete_users.iter() {
@@ -8,12 +8,16 @@ pub(crate) mod create_users_and_groups;
 pub(crate) mod delete_users;
 pub(crate) mod place_nix_configuration;
 pub(crate) mod provision_nix;
+pub(crate) mod wait_for_nix_daemon;
 
-pub use configure_init_service::{ConfigureInitService, ConfigureNixDaemonServiceError};
+pub use configure_init_service::{
+    ConfigureInitService, ConfigureNixDaemonServiceError, DaemonOverrides,
+};
 pub use configure_nix::ConfigureNix;
-pub use configure_shell_profile::ConfigureShellProfile;
+pub use configure_shell_profile::{ConfigureShellProfile, ProfileScripts};
 pub use create_nix_tree::CreateNixTree;
 pub use create_users_and_groups::CreateUsersAndGroups;
 pub use delete_users::DeleteUsersInGroup;
 pub use place_nix_configuration::PlaceNixConfiguration;
 pub use provision_nix::ProvisionNix;
+pub use wait_for_nix_daemon::{DaemonHealth, WaitForNixDaemon};
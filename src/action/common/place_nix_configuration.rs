@@ -4,25 +4,31 @@ use url::Url;
 use crate::action::base::create_or_merge_nix_config::CreateOrMergeNixConfigError;
 use crate::action::base::{CreateDirectory, CreateOrMergeNixConfig};
 use crate::action::{
-    Action, ActionDescription, ActionError, ActionErrorKind, ActionTag, StatefulAction,
+    Action, ActionDescription, ActionError, ActionErrorKind, ActionResource, ActionTag,
+    StatefulAction, SubAction,
 };
 use crate::parse_ssl_cert;
 use crate::settings::UrlOrPathOrString;
 use indexmap::map::Entry;
 use std::path::PathBuf;
 
-const NIX_CONF_FOLDER: &str = "/etc/nix";
-const NIX_CONF: &str = "/etc/nix/nix.conf";
+pub(crate) const NIX_CONF: &str = "/etc/nix/nix.conf";
 
 /**
-Place the `/etc/nix.conf` file
+Place the `/etc/nix.conf` file, or the `nix.conf` at another path such as the one of a single user
  */
 #[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
 pub struct PlaceNixConfiguration {
+    #[serde(default = "default_nix_conf")]
+    nix_conf: PathBuf,
     create_directory: StatefulAction<CreateDirectory>,
     create_or_merge_nix_config: StatefulAction<CreateOrMergeNixConfig>,
 }
 
+fn default_nix_conf() -> PathBuf {
+    PathBuf::from(NIX_CONF)
+}
+
 impl PlaceNixConfiguration {
     #[tracing::instrument(level = "debug", skip_all)]
     pub async fn plan(
@@ -31,6 +37,7 @@ impl PlaceNixConfiguration {
         ssl_cert_file: Option<PathBuf>,
         extra_conf: Vec<UrlOrPathOrString>,
         force: bool,
+        nix_conf: PathBuf,
     ) -> Result<StatefulAction<Self>, ActionError> {
         let mut extra_conf_text = vec![];
         for extra in extra_conf {
@@ -134,13 +141,18 @@ impl PlaceNixConfiguration {
             "https://install.determinate.systems/nix-upgrade/stable/universal".to_string(),
         );
 
-        let create_directory = CreateDirectory::plan(NIX_CONF_FOLDER, None, None, 0o0755, force)
+        let nix_conf_folder = nix_conf
+            .parent()
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from("/"));
+        let create_directory = CreateDirectory::plan(nix_conf_folder, None, None, 0o0755, force)
             .await
             .map_err(Self::error)?;
-        let create_or_merge_nix_config = CreateOrMergeNixConfig::plan(NIX_CONF, nix_config)
+        let create_or_merge_nix_config = CreateOrMergeNixConfig::plan(&nix_conf, nix_config)
             .await
             .map_err(Self::error)?;
         Ok(Self {
+            nix_conf,
             create_directory,
             create_or_merge_nix_config,
         }
@@ -155,15 +167,40 @@ impl Action for PlaceNixConfiguration {
         ActionTag("place_nix_configuration")
     }
     fn tracing_synopsis(&self) -> String {
-        format!("Place the Nix configuration in `{NIX_CONF}`")
+        format!(
+            "Place the Nix configuration in `{}`",
+            self.nix_conf.display()
+        )
     }
 
     fn tracing_span(&self) -> Span {
         span!(tracing::Level::DEBUG, "place_nix_configuration",)
     }
 
+    fn resources(&self) -> Vec<ActionResource> {
+        ActionResource::union([
+            self.create_directory.resources(),
+            self.create_or_merge_nix_config.resources(),
+        ])
+    }
+
+    fn children(&self) -> Vec<&dyn SubAction> {
+        vec![
+            &self.create_directory as &dyn SubAction,
+            &self.create_or_merge_nix_config,
+        ]
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut dyn SubAction> {
+        vec![
+            &mut self.create_directory as &mut dyn SubAction,
+            &mut self.create_or_merge_nix_config,
+        ]
+    }
+
     fn execute_description(&self) -> Vec<ActionDescription> {
         let Self {
+            nix_conf: _,
             create_or_merge_nix_config,
             create_directory,
         } = self;
@@ -199,7 +236,10 @@ impl Action for PlaceNixConfiguration {
 
     fn revert_description(&self) -> Vec<ActionDescription> {
         vec![ActionDescription::new(
-            format!("Remove the Nix configuration in `{NIX_CONF}`"),
+            format!(
+                "Remove the Nix configuration in `{}`",
+                self.nix_conf.display()
+            ),
             vec![
                 "This file is read by the Nix daemon to set its configuration options at runtime."
                     .to_string(),
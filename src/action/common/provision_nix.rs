@@ -4,11 +4,12 @@ use super::CreateNixTree;
 use crate::{
     action::{
         base::{FetchAndUnpackNix, MoveUnpackedNix},
-        Action, ActionDescription, ActionError, ActionErrorKind, ActionTag, StatefulAction,
+        Action, ActionDescription, ActionError, ActionErrorKind, ActionResource, ActionTag,
+        StatefulAction, SubAction,
     },
-    settings::{CommonSettings, SCRATCH_DIR},
+    settings::{rebase, CommonSettings, NIX_DIR, SCRATCH_DIR},
 };
-use std::path::PathBuf;
+use std::path::Path;
 
 /**
 Place Nix and it's requirements onto the target
@@ -22,19 +23,25 @@ pub struct ProvisionNix {
 
 impl ProvisionNix {
     #[tracing::instrument(level = "debug", skip_all)]
-    pub async fn plan(settings: &CommonSettings) -> Result<StatefulAction<Self>, ActionError> {
+    pub async fn plan(
+        settings: &CommonSettings,
+        root: &Path,
+    ) -> Result<StatefulAction<Self>, ActionError> {
         let fetch_nix = FetchAndUnpackNix::plan(
             settings.nix_package_url.clone(),
-            PathBuf::from(SCRATCH_DIR),
+            rebase(root, SCRATCH_DIR),
             settings.proxy.clone(),
             settings.ssl_cert_file.clone(),
         )
         .await?;
 
-        let create_nix_tree = CreateNixTree::plan().await.map_err(Self::error)?;
-        let move_unpacked_nix = MoveUnpackedNix::plan(PathBuf::from(SCRATCH_DIR))
+        let create_nix_tree = CreateNixTree::plan(root, Some(String::from("root")))
             .await
             .map_err(Self::error)?;
+        let move_unpacked_nix =
+            MoveUnpackedNix::plan(rebase(root, SCRATCH_DIR), rebase(root, NIX_DIR))
+                .await
+                .map_err(Self::error)?;
         Ok(Self {
             fetch_nix,
             create_nix_tree,
@@ -58,6 +65,30 @@ impl Action for ProvisionNix {
         span!(tracing::Level::DEBUG, "provision_nix",)
     }
 
+    fn resources(&self) -> Vec<ActionResource> {
+        ActionResource::union([
+            self.fetch_nix.resources(),
+            self.create_nix_tree.resources(),
+            self.move_unpacked_nix.resources(),
+        ])
+    }
+
+    fn children(&self) -> Vec<&dyn SubAction> {
+        vec![
+            &self.fetch_nix as &dyn SubAction,
+            &self.create_nix_tree,
+            &self.move_unpacked_nix,
+        ]
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut dyn SubAction> {
+        vec![
+            &mut self.fetch_nix as &mut dyn SubAction,
+            &mut self.create_nix_tree,
+            &mut self.move_unpacked_nix,
+        ]
+    }
+
     fn execute_description(&self) -> Vec<ActionDescription> {
         let Self {
             fetch_nix,
@@ -0,0 +1,277 @@
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use tokio::process::Command;
+use tracing::{span, Span};
+
+use crate::action::{
+    Action, ActionDescription, ActionError, ActionErrorKind, ActionTag, StatefulAction,
+};
+use crate::settings::InitSystem;
+
+pub(crate) const DAEMON_SOCKET: &str = "/nix/var/nix/daemon-socket/socket";
+
+const WORKER_MAGIC_1: u64 = 0x6e697863;
+const WORKER_MAGIC_2: u64 = 0x6478696f;
+const STDERR_LAST: u64 = 0x616c7473;
+/// The worker protocol version we speak, 1.35, the first to report if the client is trusted
+const CLIENT_VERSION: u64 = 1 << 8 | 35;
+
+/// What the Nix daemon reported about itself during the handshake
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, PartialEq, Eq)]
+pub struct DaemonHealth {
+    /// The worker protocol version of the daemon, as `(major, minor)`
+    pub protocol_version: (u8, u8),
+    /// The version of Nix the daemon runs, reported since protocol 1.33
+    pub nix_version: Option<String>,
+    /// If the daemon trusts us, reported since protocol 1.35
+    pub trusted: Option<bool>,
+}
+
+/**
+Wait for the Nix daemon to accept connections on its socket, and shake hands with it
+
+Failures to start the daemon otherwise only show up later, when the shell self tests fail.
+*/
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
+pub struct WaitForNixDaemon {
+    socket: PathBuf,
+    init: InitSystem,
+    /// What the daemon reported, once it responded
+    #[serde(default)]
+    health: Option<DaemonHealth>,
+}
+
+impl WaitForNixDaemon {
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub async fn plan(init: InitSystem) -> Result<StatefulAction<Self>, ActionError> {
+        Ok(Self {
+            socket: PathBuf::from(DAEMON_SOCKET),
+            init,
+            health: None,
+        }
+        .into())
+    }
+}
+
+#[async_trait::async_trait]
+#[typetag::serde(name = "wait_for_nix_daemon")]
+impl Action for WaitForNixDaemon {
+    fn action_tag() -> ActionTag {
+        ActionTag("wait_for_nix_daemon")
+    }
+    fn tracing_synopsis(&self) -> String {
+        "Wait for the Nix daemon to respond".to_string()
+    }
+
+    fn tracing_span(&self) -> Span {
+        span!(
+            tracing::Level::DEBUG,
+            "wait_for_nix_daemon",
+            socket = tracing::field::display(self.socket.display()),
+        )
+    }
+
+    fn execute_description(&self) -> Vec<ActionDescription> {
+        vec![ActionDescription::new(
+            self.tracing_synopsis(),
+            vec![format!(
+                "Connect to `{}` and complete the worker protocol handshake",
+                self.socket.display()
+            )],
+        )]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn execute(&mut self) -> Result<(), ActionError> {
+        let mut retry_tokens: usize = 60;
+        loop {
+            let socket = self.socket.clone();
+            let result = tokio::task::spawn_blocking(move || handshake(&socket))
+                .await
+                .map_err(|e| Self::error(ActionErrorKind::Join(e)))?;
+            match result {
+                Ok(health) => {
+                    tracing::info!(
+                        protocol_version = %format!("{}.{}", health.protocol_version.0, health.protocol_version.1),
+                        nix_version = ?health.nix_version,
+                        trusted = ?health.trusted,
+                        "Nix daemon is ready"
+                    );
+                    self.health = Some(health);
+                    return Ok(());
+                },
+                Err(err) if retry_tokens == 0 => {
+                    let logs = collect_logs(self.init).await;
+                    return Err(Self::error(ActionErrorKind::DaemonNotReady(
+                        self.socket.clone(),
+                        err,
+                        logs,
+                    )));
+                },
+                Err(err) => {
+                    tracing::trace!(%retry_tokens, %err, "Nix daemon not ready yet");
+                    retry_tokens = retry_tokens.saturating_sub(1);
+                },
+            }
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+    }
+
+    fn revert_description(&self) -> Vec<ActionDescription> {
+        vec![]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn revert(&mut self) -> Result<(), ActionError> {
+        Ok(())
+    }
+}
+
+/// The recent logs of the daemon, where the init system keeps them somewhere we know how to read
+async fn collect_logs(init: InitSystem) -> String {
+    let mut command = match init {
+        #[cfg(target_os = "linux")]
+        InitSystem::Systemd => {
+            let mut command = Command::new("journalctl");
+            command.args([
+                "--no-pager",
+                "--lines=50",
+                "--unit=nix-daemon.service",
+                "--unit=nix-daemon.socket",
+            ]);
+            command
+        },
+        _ => return String::new(),
+    };
+    command.stdin(std::process::Stdio::null());
+    match command.output().await {
+        Ok(output) => String::from_utf8_lossy(&output.stdout).into_owned(),
+        Err(err) => format!("Could not run `journalctl`: {err}"),
+    }
+}
+
+/// Connect to the daemon on `socket` and complete the handshake of the worker protocol
+///
+/// The handshake is completed, rather than abandoned halfway, so the daemon doesn't log an error about it.
+pub(crate) fn handshake(socket: &Path) -> std::io::Result<DaemonHealth> {
+    let mut stream = UnixStream::connect(socket)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    stream.set_write_timeout(Some(Duration::from_secs(5)))?;
+
+    write_u64(&mut stream, WORKER_MAGIC_1)?;
+    let magic = read_u64(&mut stream)?;
+    if magic != WORKER_MAGIC_2 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("unexpected magic {magic:#x} from the daemon"),
+        ));
+    }
+    let daemon_version = read_u64(&mut stream)?;
+    let protocol_version = ((daemon_version >> 8) as u8, daemon_version as u8);
+    // Both sides speak the older of the two versions
+    let minor = protocol_version.1.min(CLIENT_VERSION as u8);
+
+    write_u64(&mut stream, CLIENT_VERSION)?;
+    // No CPU affinity
+    write_u64(&mut stream, 0)?;
+    // Don't reserve space
+    write_u64(&mut stream, 0)?;
+
+    let nix_version = if minor >= 33 {
+        Some(read_string(&mut stream)?)
+    } else {
+        None
+    };
+    let trusted = if minor >= 35 {
+        match read_u64(&mut stream)? {
+            1 => Some(true),
+            2 => Some(false),
+            _ => None,
+        }
+    } else {
+        None
+    };
+
+    // The daemon finishes its side of the handshake like any other operation
+    let last = read_u64(&mut stream)?;
+    if last != STDERR_LAST {
+        tracing::trace!("Daemon sent {last:#x} instead of finishing the handshake");
+    }
+
+    Ok(DaemonHealth {
+        protocol_version,
+        nix_version,
+        trusted,
+    })
+}
+
+fn write_u64(stream: &mut impl Write, value: u64) -> std::io::Result<()> {
+    stream.write_all(&value.to_le_bytes())
+}
+
+fn read_u64(stream: &mut impl Read) -> std::io::Result<u64> {
+    let mut buf = [0; 8];
+    stream.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+/// Strings are a length, followed by the bytes padded to a multiple of 8
+fn read_string(stream: &mut impl Read) -> std::io::Result<String> {
+    let len = read_u64(stream)? as usize;
+    if len > 1024 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("string of {len} bytes from the daemon is implausibly long"),
+        ));
+    }
+    let mut buf = vec![0; len.next_multiple_of(8)];
+    stream.read_exact(&mut buf)?;
+    buf.truncate(len);
+    String::from_utf8(buf).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::os::unix::net::UnixListener;
+
+    #[test]
+    fn handshakes_with_daemon() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let socket = temp_dir.path().join("socket");
+        let listener = UnixListener::bind(&socket)?;
+
+        // Play the part of a Nix 2.19 daemon
+        let daemon = std::thread::spawn(move || -> std::io::Result<Vec<u64>> {
+            let (mut stream, _) = listener.accept()?;
+            let mut received = vec![read_u64(&mut stream)?];
+            write_u64(&mut stream, WORKER_MAGIC_2)?;
+            write_u64(&mut stream, 1 << 8 | 35)?;
+            for _ in 0..3 {
+                received.push(read_u64(&mut stream)?);
+            }
+            write_u64(&mut stream, 6)?;
+            stream.write_all(b"2.19.3\0\0")?;
+            write_u64(&mut stream, 1)?;
+            write_u64(&mut stream, STDERR_LAST)?;
+            Ok(received)
+        });
+
+        let health = handshake(&socket)?;
+        assert_eq!(
+            health,
+            DaemonHealth {
+                protocol_version: (1, 35),
+                nix_version: Some("2.19.3".to_string()),
+                trusted: Some(true),
+            }
+        );
+        let received = daemon.join().expect("Daemon thread panicked")?;
+        assert_eq!(received, vec![WORKER_MAGIC_1, CLIENT_VERSION, 0, 0]);
+
+        Ok(())
+    }
+}
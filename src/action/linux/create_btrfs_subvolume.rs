@@ -0,0 +1,137 @@
+use std::path::{Path, PathBuf};
+
+use tokio::process::Command;
+use tracing::{span, Span};
+
+use crate::action::{
+    Action, ActionDescription, ActionError, ActionErrorKind, ActionResource, ActionTag,
+    StatefulAction,
+};
+use crate::execute_command;
+
+/**
+Create a btrfs subvolume
+
+Snapshots of the subvolume containing `path` do not include a nested subvolume, so what is stored in it survives
+rollbacks. Reverting deletes the subvolume, and everything in it.
+*/
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
+pub struct CreateBtrfsSubvolume {
+    path: PathBuf,
+}
+
+impl CreateBtrfsSubvolume {
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub async fn plan(path: impl AsRef<Path>) -> Result<StatefulAction<Self>, ActionError> {
+        let path = path.as_ref().to_path_buf();
+
+        if path.exists() {
+            if is_subvolume(&path).await {
+                tracing::debug!(
+                    "Creating btrfs subvolume `{}` already complete",
+                    path.display()
+                );
+                return Ok(StatefulAction::completed(Self { path }));
+            }
+            return Err(Self::error(ActionErrorKind::DirExists(path)));
+        }
+
+        Ok(StatefulAction::uncompleted(Self { path }))
+    }
+}
+
+#[async_trait::async_trait]
+#[typetag::serde(name = "create_btrfs_subvolume")]
+impl Action for CreateBtrfsSubvolume {
+    fn action_tag() -> ActionTag {
+        ActionTag("create_btrfs_subvolume")
+    }
+    fn tracing_synopsis(&self) -> String {
+        format!("Create the btrfs subvolume `{}`", self.path.display())
+    }
+
+    fn tracing_span(&self) -> Span {
+        span!(
+            tracing::Level::DEBUG,
+            "create_btrfs_subvolume",
+            path = tracing::field::display(self.path.display()),
+        )
+    }
+
+    fn resources(&self) -> Vec<ActionResource> {
+        vec![ActionResource::Path(self.path.clone())]
+    }
+
+    fn execute_description(&self) -> Vec<ActionDescription> {
+        vec![ActionDescription::new(
+            self.tracing_synopsis(),
+            vec![format!(
+                "Run `btrfs subvolume create {}`, which keeps it out of snapshots of its parent",
+                self.path.display()
+            )],
+        )]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn execute(&mut self) -> Result<(), ActionError> {
+        if let Some(parent) = self.path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|e| ActionErrorKind::CreateDirectory(parent.to_path_buf(), e))
+                .map_err(Self::error)?;
+        }
+
+        execute_command(
+            Command::new("btrfs")
+                .process_group(0)
+                .args(["subvolume", "create"])
+                .arg(&self.path)
+                .stdin(std::process::Stdio::null()),
+        )
+        .await
+        .map_err(Self::error)?;
+
+        Ok(())
+    }
+
+    fn revert_description(&self) -> Vec<ActionDescription> {
+        vec![ActionDescription::new(
+            format!("Delete the btrfs subvolume `{}`", self.path.display()),
+            vec![format!(
+                "Run `btrfs subvolume delete {}`",
+                self.path.display()
+            )],
+        )]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn revert(&mut self) -> Result<(), ActionError> {
+        if !self.path.exists() {
+            return Ok(());
+        }
+
+        execute_command(
+            Command::new("btrfs")
+                .process_group(0)
+                .args(["subvolume", "delete"])
+                .arg(&self.path)
+                .stdin(std::process::Stdio::null()),
+        )
+        .await
+        .map_err(Self::error)?;
+
+        Ok(())
+    }
+}
+
+async fn is_subvolume(path: &Path) -> bool {
+    Command::new("btrfs")
+        .args(["subvolume", "show"])
+        .arg(path)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .await
+        .is_ok_and(|status| status.success())
+}
@@ -0,0 +1,140 @@
+use std::path::{Path, PathBuf};
+
+use tokio::process::Command;
+use tracing::{span, Span};
+
+use crate::action::{
+    Action, ActionDescription, ActionError, ActionErrorKind, ActionResource, ActionTag,
+    StatefulAction,
+};
+use crate::execute_command;
+use crate::settings::LoopbackFilesystem;
+
+/**
+Create a sparse image file of `size` bytes and format it with `filesystem`, to be mounted through a loop device
+
+Reverting removes the image, and everything in it.
+*/
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
+pub struct CreateLoopbackImage {
+    path: PathBuf,
+    size: u64,
+    filesystem: LoopbackFilesystem,
+}
+
+impl CreateLoopbackImage {
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub async fn plan(
+        path: impl AsRef<Path>,
+        size: u64,
+        filesystem: LoopbackFilesystem,
+    ) -> Result<StatefulAction<Self>, ActionError> {
+        let path = path.as_ref().to_path_buf();
+
+        // An existing image may hold anything, so it's never reused or overwritten
+        if path.exists() {
+            return Err(Self::error(ActionErrorKind::FileExists(path)));
+        }
+
+        Ok(StatefulAction::uncompleted(Self {
+            path,
+            size,
+            filesystem,
+        }))
+    }
+}
+
+#[async_trait::async_trait]
+#[typetag::serde(name = "create_loopback_image")]
+impl Action for CreateLoopbackImage {
+    fn action_tag() -> ActionTag {
+        ActionTag("create_loopback_image")
+    }
+    fn tracing_synopsis(&self) -> String {
+        format!(
+            "Create a {size} byte {filesystem} image `{path}`",
+            size = self.size,
+            filesystem = self.filesystem,
+            path = self.path.display(),
+        )
+    }
+
+    fn tracing_span(&self) -> Span {
+        span!(
+            tracing::Level::DEBUG,
+            "create_loopback_image",
+            path = tracing::field::display(self.path.display()),
+            size = self.size,
+            filesystem = tracing::field::display(self.filesystem),
+        )
+    }
+
+    fn resources(&self) -> Vec<ActionResource> {
+        vec![ActionResource::Path(self.path.clone())]
+    }
+
+    fn execute_description(&self) -> Vec<ActionDescription> {
+        vec![ActionDescription::new(
+            self.tracing_synopsis(),
+            vec![
+                "The image is sparse, so it only takes up the space Nix uses".to_string(),
+                format!("Format it with `{}`", self.filesystem.mkfs()),
+            ],
+        )]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn execute(&mut self) -> Result<(), ActionError> {
+        if let Some(parent) = self.path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|e| ActionErrorKind::CreateDirectory(parent.to_path_buf(), e))
+                .map_err(Self::error)?;
+        }
+
+        let image = tokio::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&self.path)
+            .await
+            .map_err(|e| ActionErrorKind::Open(self.path.clone(), e))
+            .map_err(Self::error)?;
+        image
+            .set_len(self.size)
+            .await
+            .map_err(|e| ActionErrorKind::Write(self.path.clone(), e))
+            .map_err(Self::error)?;
+        drop(image);
+
+        let mut command = Command::new(self.filesystem.mkfs());
+        command.process_group(0);
+        match self.filesystem {
+            LoopbackFilesystem::Ext4 => command.args(["-q", "-F", "-L", "nix"]),
+            LoopbackFilesystem::Btrfs => command.args(["-q", "-L", "nix"]),
+        };
+        command.arg(&self.path).stdin(std::process::Stdio::null());
+        execute_command(&mut command).await.map_err(Self::error)?;
+
+        Ok(())
+    }
+
+    fn revert_description(&self) -> Vec<ActionDescription> {
+        vec![ActionDescription::new(
+            format!("Remove the image `{}`", self.path.display()),
+            vec![],
+        )]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn revert(&mut self) -> Result<(), ActionError> {
+        if self.path.exists() {
+            tokio::fs::remove_file(&self.path)
+                .await
+                .map_err(|e| ActionErrorKind::Remove(self.path.clone(), e))
+                .map_err(Self::error)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,166 @@
+use std::path::{Path, PathBuf};
+
+use tracing::{span, Span};
+
+use crate::action::{
+    base::CreateDirectory,
+    linux::{CreateLoopbackImage, NixMountStrategy, PersistentNixMount},
+    Action, ActionDescription, ActionError, ActionErrorKind, ActionResource, ActionTag,
+    StatefulAction, SubAction,
+};
+use crate::settings::{LoopbackFilesystem, NIX_DIR};
+
+/**
+Store Nix in a loopback image file which systemd mounts on `/nix`, for hosts where `/nix` can't get a partition
+
+Like [`CreateNixVolume`](crate::action::macos::CreateNixVolume) on macOS, this creates and formats the image, then
+mounts it with a [`PersistentNixMount`] using [`NixMountStrategy::Loopback`]. The root is writable, so `/nix` is
+created here rather than by a `nix-directory.service` unit.
+*/
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
+pub struct CreateNixLoopbackStore {
+    image: PathBuf,
+    filesystem: LoopbackFilesystem,
+    create_image: StatefulAction<CreateLoopbackImage>,
+    create_directory: StatefulAction<CreateDirectory>,
+    mount: StatefulAction<PersistentNixMount>,
+}
+
+impl CreateNixLoopbackStore {
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub async fn plan(
+        image: impl AsRef<Path>,
+        size: u64,
+        filesystem: LoopbackFilesystem,
+    ) -> Result<StatefulAction<Self>, ActionError> {
+        let image = image.as_ref().to_path_buf();
+
+        let create_image = CreateLoopbackImage::plan(&image, size, filesystem)
+            .await
+            .map_err(Self::error)?;
+        let create_directory = CreateDirectory::plan(NIX_DIR, None, None, 0o0755, false)
+            .await
+            .map_err(Self::error)?;
+        let mount = PersistentNixMount::plan(&image, NixMountStrategy::Loopback, None)
+            .await
+            .map_err(Self::error)?;
+
+        Ok(Self {
+            image,
+            filesystem,
+            create_image,
+            create_directory,
+            mount,
+        }
+        .into())
+    }
+}
+
+#[async_trait::async_trait]
+#[typetag::serde(name = "create_nix_loopback_store")]
+impl Action for CreateNixLoopbackStore {
+    fn action_tag() -> ActionTag {
+        ActionTag("create_nix_loopback_store")
+    }
+    fn tracing_synopsis(&self) -> String {
+        format!(
+            "Create the {} image `{}` for Nix and mount it on `/nix`",
+            self.filesystem,
+            self.image.display()
+        )
+    }
+
+    fn tracing_span(&self) -> Span {
+        span!(
+            tracing::Level::DEBUG,
+            "create_nix_loopback_store",
+            image = tracing::field::display(self.image.display()),
+        )
+    }
+
+    fn resources(&self) -> Vec<ActionResource> {
+        ActionResource::union(vec![
+            self.create_image.resources(),
+            self.create_directory.resources(),
+            self.mount.resources(),
+        ])
+    }
+
+    fn children(&self) -> Vec<&dyn SubAction> {
+        vec![
+            &self.create_image as &dyn SubAction,
+            &self.create_directory,
+            &self.mount,
+        ]
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut dyn SubAction> {
+        vec![
+            &mut self.create_image as &mut dyn SubAction,
+            &mut self.create_directory,
+            &mut self.mount,
+        ]
+    }
+
+    fn execute_description(&self) -> Vec<ActionDescription> {
+        vec![ActionDescription::new(
+            self.tracing_synopsis(),
+            vec![
+                self.create_image.tracing_synopsis(),
+                self.create_directory.tracing_synopsis(),
+                self.mount.tracing_synopsis(),
+            ],
+        )]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn execute(&mut self) -> Result<(), ActionError> {
+        self.create_image.try_execute().await.map_err(Self::error)?;
+        self.create_directory
+            .try_execute()
+            .await
+            .map_err(Self::error)?;
+        self.mount.try_execute().await.map_err(Self::error)?;
+
+        Ok(())
+    }
+
+    fn revert_description(&self) -> Vec<ActionDescription> {
+        vec![ActionDescription::new(
+            format!(
+                "Unmount `/nix` and remove the image `{}`",
+                self.image.display()
+            ),
+            vec![
+                self.mount.tracing_synopsis(),
+                self.create_directory.tracing_synopsis(),
+                self.create_image.tracing_synopsis(),
+            ],
+        )]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn revert(&mut self) -> Result<(), ActionError> {
+        let mut errors = vec![];
+        if let Err(err) = self.mount.try_revert().await {
+            errors.push(err);
+        }
+        if let Err(err) = self.create_directory.try_revert().await {
+            errors.push(err);
+        }
+        if let Err(err) = self.create_image.try_revert().await {
+            errors.push(err);
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else if errors.len() == 1 {
+            Err(errors
+                .into_iter()
+                .next()
+                .expect("Expected 1 len Vec to have at least 1 item"))
+        } else {
+            Err(Self::error(ActionErrorKind::MultipleChildren(errors)))
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use std::path::PathBuf;
+
+use tracing::{span, Span};
+
+use crate::action::{
+    Action, ActionDescription, ActionError, ActionErrorKind, ActionResource, ActionTag,
+    StatefulAction,
+};
+
+pub(crate) const WSL_CONF: &str = "/etc/wsl.conf";
+
+/**
+Have WSL2 boot the distribution with systemd, by setting `systemd=true` in the `[boot]` section of `/etc/wsl.conf`
+
+This only takes effect once WSL is restarted with `wsl.exe --shutdown`. Reverting restores the file as it was found.
+*/
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
+pub struct EnableWslSystemd {
+    path: PathBuf,
+    /// The content of `path` before it was changed, `None` if it did not exist
+    previous: Option<String>,
+}
+
+impl EnableWslSystemd {
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub async fn plan(path: impl Into<PathBuf>) -> Result<StatefulAction<Self>, ActionError> {
+        let path = path.into();
+        let previous = if path.exists() {
+            Some(
+                tokio::fs::read_to_string(&path)
+                    .await
+                    .map_err(|e| ActionErrorKind::Read(path.clone(), e))
+                    .map_err(Self::error)?,
+            )
+        } else {
+            None
+        };
+
+        if let Some(previous) = &previous {
+            if enable_systemd(previous) == *previous {
+                // Someone else enabled it, so it stays enabled on uninstall
+                tracing::debug!("Enabling systemd in `{}` already complete", path.display());
+                return Ok(StatefulAction::skipped(Self {
+                    path,
+                    previous: None,
+                }));
+            }
+        }
+
+        Ok(StatefulAction::uncompleted(Self { path, previous }))
+    }
+}
+
+#[async_trait::async_trait]
+#[typetag::serde(name = "enable_wsl_systemd")]
+impl Action for EnableWslSystemd {
+    fn action_tag() -> ActionTag {
+        ActionTag("enable_wsl_systemd")
+    }
+    fn tracing_synopsis(&self) -> String {
+        format!("Enable systemd for WSL2 in `{}`", self.path.display())
+    }
+
+    fn tracing_span(&self) -> Span {
+        span!(
+            tracing::Level::DEBUG,
+            "enable_wsl_systemd",
+            path = tracing::field::display(self.path.display()),
+        )
+    }
+
+    fn resources(&self) -> Vec<ActionResource> {
+        vec![ActionResource::Path(self.path.clone())]
+    }
+
+    fn execute_description(&self) -> Vec<ActionDescription> {
+        vec![ActionDescription::new(
+            self.tracing_synopsis(),
+            vec![
+                "Set `systemd=true` in the `[boot]` section".to_string(),
+                "WSL must be restarted with `wsl.exe --shutdown` from Windows before systemd starts the Nix daemon".to_string(),
+            ],
+        )]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn execute(&mut self) -> Result<(), ActionError> {
+        let content = enable_systemd(self.previous.as_deref().unwrap_or_default());
+        tracing::trace!(path = %self.path.display(), "Writing");
+        tokio::fs::write(&self.path, content)
+            .await
+            .map_err(|e| ActionErrorKind::Write(self.path.clone(), e))
+            .map_err(Self::error)?;
+
+        Ok(())
+    }
+
+    fn revert_description(&self) -> Vec<ActionDescription> {
+        let explanation = match self.previous {
+            Some(_) => format!("Restore the previous content of `{}`", self.path.display()),
+            None => format!("Remove `{}`", self.path.display()),
+        };
+        vec![ActionDescription::new(
+            format!(
+                "Stop enabling systemd for WSL2 in `{}`",
+                self.path.display()
+            ),
+            vec![explanation],
+        )]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn revert(&mut self) -> Result<(), ActionError> {
+        match &self.previous {
+            Some(previous) => tokio::fs::write(&self.path, previous)
+                .await
+                .map_err(|e| ActionErrorKind::Write(self.path.clone(), e))
+                .map_err(Self::error)?,
+            None if self.path.exists() => tokio::fs::remove_file(&self.path)
+                .await
+                .map_err(|e| ActionErrorKind::Remove(self.path.clone(), e))
+                .map_err(Self::error)?,
+            None => (),
+        }
+
+        Ok(())
+    }
+}
+
+/// Set `systemd=true` in the `[boot]` section of the `wsl.conf` in `content`, leaving everything else as it is
+fn enable_systemd(content: &str) -> String {
+    let mut lines: Vec<String> = content.lines().map(ToString::to_string).collect();
+
+    let boot = lines.iter().position(|line| line.trim() == "[boot]");
+    match boot {
+        Some(boot) => {
+            let section_end = lines[boot + 1..]
+                .iter()
+                .position(|line| line.trim_start().starts_with('['))
+                .map(|offset| boot + 1 + offset)
+                .unwrap_or(lines.len());
+            let existing = lines[boot + 1..section_end].iter().position(|line| {
+                line.split_once('=')
+                    .is_some_and(|(key, _)| key.trim() == "systemd")
+            });
+            match existing {
+                Some(offset) => lines[boot + 1 + offset] = "systemd=true".to_string(),
+                None => lines.insert(boot + 1, "systemd=true".to_string()),
+            }
+        },
+        None => {
+            if lines.last().is_some_and(|line| !line.trim().is_empty()) {
+                lines.push(String::new());
+            }
+            lines.push("[boot]".to_string());
+            lines.push("systemd=true".to_string());
+        },
+    }
+
+    let mut content = lines.join("\n");
+    content.push('\n');
+    content
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn enables_systemd_in_wsl_conf() {
+        assert_eq!(enable_systemd(""), "[boot]\nsystemd=true\n");
+        assert_eq!(
+            enable_systemd("[network]\nhostname=nix\n"),
+            "[network]\nhostname=nix\n\n[boot]\nsystemd=true\n"
+        );
+        assert_eq!(
+            enable_systemd("[boot]\ncommand=echo\n[user]\ndefault=nix\n"),
+            "[boot]\nsystemd=true\ncommand=echo\n[user]\ndefault=nix\n"
+        );
+        assert_eq!(
+            enable_systemd("[boot]\nsystemd = false\n"),
+            "[boot]\nsystemd=true\n"
+        );
+        // Already enabled is left alone, so planning skips it
+        assert_eq!(
+            enable_systemd("[boot]\nsystemd=true\n"),
+            "[boot]\nsystemd=true\n"
+        );
+    }
+}
@@ -0,0 +1,371 @@
+use std::path::{Path, PathBuf};
+
+use tokio::process::Command;
+use tracing::{span, Span};
+
+use crate::action::base::{create_or_insert_into_file, CreateDirectory, CreateOrInsertIntoFile};
+use crate::action::{
+    Action, ActionDescription, ActionError, ActionErrorKind, ActionResource, ActionState,
+    ActionTag, StatefulAction, SubAction,
+};
+use crate::execute_command;
+use crate::settings::{rebase, StoreLink, NIX_DIR};
+
+const FSTAB: &str = "/etc/fstab";
+
+/**
+Make a Nix store location other than `/nix` (such as `/opt/nix`) available at `/nix`
+
+With [`StoreLink::BindMount`] an entry is added to `/etc/fstab` and the bind mount is made right away,
+with [`StoreLink::Symlink`] `/nix` becomes a relative symlink, so it resolves the same way inside and
+outside of `root`.
+*/
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
+pub struct LinkNixDirectory {
+    store_location: PathBuf,
+    link: StoreLink,
+    root: PathBuf,
+    create_directory: Option<StatefulAction<CreateDirectory>>,
+    create_fstab_entry: Option<StatefulAction<CreateOrInsertIntoFile>>,
+}
+
+impl LinkNixDirectory {
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub async fn plan(
+        store_location: PathBuf,
+        link: StoreLink,
+        root: PathBuf,
+    ) -> Result<StatefulAction<Self>, ActionError> {
+        let nix_dir = rebase(&root, NIX_DIR);
+
+        let (create_directory, create_fstab_entry) = match link {
+            StoreLink::BindMount => {
+                let create_directory = CreateDirectory::plan(&nix_dir, None, None, 0o0755, false)
+                    .await
+                    .map_err(Self::error)?;
+                let create_fstab_entry = CreateOrInsertIntoFile::plan(
+                    rebase(&root, FSTAB),
+                    None,
+                    None,
+                    0o0644,
+                    format!("{} {NIX_DIR} none bind 0 0\n", store_location.display()),
+                    create_or_insert_into_file::Position::End,
+                )
+                .await
+                .map_err(Self::error)?;
+                (Some(create_directory), Some(create_fstab_entry))
+            },
+            StoreLink::Symlink => (None, None),
+        };
+
+        let this = Self {
+            store_location,
+            link,
+            root,
+            create_directory,
+            create_fstab_entry,
+        };
+
+        match this.link {
+            StoreLink::BindMount => {
+                let children_completed = this
+                    .create_directory
+                    .iter()
+                    .all(|v| v.state == ActionState::Completed)
+                    && this
+                        .create_fstab_entry
+                        .iter()
+                        .all(|v| v.state == ActionState::Completed);
+                if children_completed && is_mounted(&nix_dir).await.map_err(Self::error)? {
+                    tracing::debug!("Linking `{}` already complete", nix_dir.display());
+                    return Ok(StatefulAction::completed(this));
+                }
+            },
+            StoreLink::Symlink => {
+                if nix_dir.is_symlink() {
+                    let target = tokio::fs::read_link(&nix_dir)
+                        .await
+                        .map_err(|e| ActionErrorKind::ReadSymlink(nix_dir.clone(), e))
+                        .map_err(Self::error)?;
+                    if target != this.symlink_target() {
+                        return Err(Self::error(ActionErrorKind::SymlinkExists(nix_dir)));
+                    }
+                    tracing::debug!("Linking `{}` already complete", nix_dir.display());
+                    return Ok(StatefulAction::completed(this));
+                } else if nix_dir.exists() {
+                    return Err(Self::error(ActionErrorKind::DirExists(nix_dir)));
+                }
+            },
+        }
+
+        Ok(StatefulAction::uncompleted(this))
+    }
+
+    fn nix_dir(&self) -> PathBuf {
+        rebase(&self.root, NIX_DIR)
+    }
+
+    /// `/nix` is in `/`, so a target relative to `/` resolves the same way under any root
+    fn symlink_target(&self) -> PathBuf {
+        self.store_location
+            .strip_prefix("/")
+            .unwrap_or(&self.store_location)
+            .to_path_buf()
+    }
+}
+
+#[async_trait::async_trait]
+#[typetag::serde(name = "link_nix_directory")]
+impl Action for LinkNixDirectory {
+    fn action_tag() -> ActionTag {
+        ActionTag("link_nix_directory")
+    }
+    fn tracing_synopsis(&self) -> String {
+        match self.link {
+            StoreLink::BindMount => format!(
+                "Bind mount `{}` on `{NIX_DIR}`",
+                self.store_location.display()
+            ),
+            StoreLink::Symlink => {
+                format!("Symlink `{NIX_DIR}` to `{}`", self.store_location.display())
+            },
+        }
+    }
+
+    fn tracing_span(&self) -> Span {
+        span!(
+            tracing::Level::DEBUG,
+            "link_nix_directory",
+            store_location = tracing::field::display(self.store_location.display()),
+            link = tracing::field::display(self.link),
+            root = tracing::field::display(self.root.display()),
+        )
+    }
+
+    fn resources(&self) -> Vec<ActionResource> {
+        vec![
+            ActionResource::Path(self.nix_dir()),
+            ActionResource::Path(rebase(&self.root, &self.store_location)),
+            ActionResource::Path(rebase(&self.root, FSTAB)),
+        ]
+    }
+
+    fn children(&self) -> Vec<&dyn SubAction> {
+        let mut children: Vec<&dyn SubAction> = vec![];
+        children.extend(self.create_directory.iter().map(|v| v as &dyn SubAction));
+        children.extend(self.create_fstab_entry.iter().map(|v| v as &dyn SubAction));
+        children
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut dyn SubAction> {
+        let mut children: Vec<&mut dyn SubAction> = vec![];
+        children.extend(
+            self.create_directory
+                .iter_mut()
+                .map(|v| v as &mut dyn SubAction),
+        );
+        children.extend(
+            self.create_fstab_entry
+                .iter_mut()
+                .map(|v| v as &mut dyn SubAction),
+        );
+        children
+    }
+
+    fn execute_description(&self) -> Vec<ActionDescription> {
+        let mut explanation = vec![format!(
+            "Nix always refers to its store as `{NIX_DIR}/store`, so `{}` must be reachable through `{NIX_DIR}`",
+            self.store_location.display()
+        )];
+        if let Some(create_fstab_entry) = &self.create_fstab_entry {
+            for val in create_fstab_entry.describe_execute() {
+                explanation.push(val.description)
+            }
+        }
+        vec![ActionDescription::new(self.tracing_synopsis(), explanation)]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn execute(&mut self) -> Result<(), ActionError> {
+        let nix_dir = self.nix_dir();
+        match self.link {
+            StoreLink::BindMount => {
+                if let Some(create_directory) = &mut self.create_directory {
+                    create_directory.try_execute().await.map_err(Self::error)?;
+                }
+                if let Some(create_fstab_entry) = &mut self.create_fstab_entry {
+                    create_fstab_entry
+                        .try_execute()
+                        .await
+                        .map_err(Self::error)?;
+                }
+                if !is_mounted(&nix_dir).await.map_err(Self::error)? {
+                    execute_command(
+                        Command::new("mount")
+                            .process_group(0)
+                            .arg("--bind")
+                            .arg(rebase(&self.root, &self.store_location))
+                            .arg(&nix_dir)
+                            .stdin(std::process::Stdio::null()),
+                    )
+                    .await
+                    .map_err(Self::error)?;
+                }
+            },
+            StoreLink::Symlink => {
+                let target = self.symlink_target();
+                tokio::fs::symlink(&target, &nix_dir)
+                    .await
+                    .map_err(|e| ActionErrorKind::Symlink(target, nix_dir, e))
+                    .map_err(Self::error)?;
+            },
+        }
+
+        Ok(())
+    }
+
+    fn revert_description(&self) -> Vec<ActionDescription> {
+        let synopsis = match self.link {
+            StoreLink::BindMount => {
+                format!("Unmount `{NIX_DIR}` and remove its entry from `{FSTAB}`")
+            },
+            StoreLink::Symlink => format!("Remove the `{NIX_DIR}` symlink"),
+        };
+        vec![ActionDescription::new(synopsis, vec![])]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn revert(&mut self) -> Result<(), ActionError> {
+        let nix_dir = self.nix_dir();
+        let mut errors = vec![];
+        match self.link {
+            StoreLink::BindMount => {
+                match is_mounted(&nix_dir).await {
+                    Ok(true) => {
+                        if let Err(err) = execute_command(
+                            Command::new("umount")
+                                .process_group(0)
+                                .arg(&nix_dir)
+                                .stdin(std::process::Stdio::null()),
+                        )
+                        .await
+                        {
+                            errors.push(Self::error(err));
+                        }
+                    },
+                    Ok(false) => (),
+                    Err(err) => errors.push(Self::error(err)),
+                }
+                if let Some(create_fstab_entry) = &mut self.create_fstab_entry {
+                    if let Err(err) = create_fstab_entry.try_revert().await {
+                        errors.push(err);
+                    }
+                }
+                // The directory can only be removed once nothing is mounted on it
+                if errors.is_empty() {
+                    if let Some(create_directory) = &mut self.create_directory {
+                        if let Err(err) = create_directory.try_revert().await {
+                            errors.push(err);
+                        }
+                    }
+                }
+            },
+            StoreLink::Symlink => {
+                if nix_dir.is_symlink() {
+                    if let Err(err) = tokio::fs::remove_file(&nix_dir)
+                        .await
+                        .map_err(|e| ActionErrorKind::Remove(nix_dir.clone(), e))
+                    {
+                        errors.push(Self::error(err));
+                    }
+                }
+            },
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else if errors.len() == 1 {
+            Err(errors
+                .into_iter()
+                .next()
+                .expect("Expected 1 len Vec to have at least 1 item"))
+        } else {
+            Err(Self::error(ActionErrorKind::MultipleChildren(errors)))
+        }
+    }
+}
+
+/// If something is mounted on `path`, according to `/proc/self/mountinfo`
+async fn is_mounted(path: &Path) -> Result<bool, ActionErrorKind> {
+    let mountinfo_path = PathBuf::from("/proc/self/mountinfo");
+    let mountinfo = tokio::fs::read_to_string(&mountinfo_path)
+        .await
+        .map_err(|e| ActionErrorKind::Read(mountinfo_path, e))?;
+    let path = path.to_string_lossy();
+    // Spaces and other special characters in the mount point are octal escaped
+    let escaped = path
+        .replace('\\', "\\134")
+        .replace(' ', "\\040")
+        .replace('\t', "\\011")
+        .replace('\n', "\\012");
+    Ok(mountinfo
+        .lines()
+        .any(|line| line.split(' ').nth(4) == Some(escaped.as_str())))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn symlinks_and_unlinks_nix_in_root() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let root = temp_dir.path().to_path_buf();
+        let store_location = PathBuf::from("/opt/nix");
+
+        let mut action =
+            LinkNixDirectory::plan(store_location.clone(), StoreLink::Symlink, root.clone())
+                .await?;
+        action.try_execute().await?;
+
+        let nix_dir = rebase(&root, NIX_DIR);
+        assert_eq!(
+            tokio::fs::read_link(&nix_dir).await?,
+            PathBuf::from("opt/nix")
+        );
+
+        // Planning again sees the link is already in place
+        let replanned =
+            LinkNixDirectory::plan(store_location, StoreLink::Symlink, root.clone()).await?;
+        assert_eq!(replanned.state, ActionState::Completed);
+
+        action.try_revert().await?;
+        assert!(!nix_dir.is_symlink());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn refuses_existing_nix() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let root = temp_dir.path().to_path_buf();
+        let nix_dir = rebase(&root, NIX_DIR);
+
+        tokio::fs::create_dir_all(&nix_dir).await?;
+        assert!(
+            LinkNixDirectory::plan("/opt/nix".into(), StoreLink::Symlink, root.clone())
+                .await
+                .is_err()
+        );
+
+        tokio::fs::remove_dir(&nix_dir).await?;
+        tokio::fs::symlink("srv/nix", &nix_dir).await?;
+        assert!(
+            LinkNixDirectory::plan("/opt/nix".into(), StoreLink::Symlink, root)
+                .await
+                .is_err()
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,232 @@
+use std::path::{Path, PathBuf};
+
+use tracing::{span, Span};
+
+use crate::action::common::configure_init_service::{
+    SERVICE_DEST, SERVICE_SRC, SOCKET_DEST, SOCKET_SRC, TMPFILES_DEST, TMPFILES_SRC,
+};
+use crate::action::{
+    Action, ActionDescription, ActionError, ActionErrorKind, ActionResource, ActionTag,
+    StatefulAction,
+};
+use crate::settings::rebase;
+
+const SOCKET_WANTS: &str = "/etc/systemd/system/sockets.target.wants/nix-daemon.socket";
+
+/**
+Enable the Nix daemon units in a `root` which is not running, such as a mounted disk image
+
+This creates the same symlinks `systemctl enable` and `systemd-tmpfiles` would, without calling either,
+so the socket is activated the first time `root` boots.
+*/
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
+pub struct LinkSystemdUnits {
+    root: PathBuf,
+}
+
+impl LinkSystemdUnits {
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub async fn plan(root: PathBuf) -> Result<StatefulAction<Self>, ActionError> {
+        let this = Self { root };
+
+        let mut all_linked = true;
+        for (link, target) in this.links() {
+            if link.is_symlink() {
+                let existing_target = tokio::fs::read_link(&link)
+                    .await
+                    .map_err(|e| ActionErrorKind::ReadSymlink(link.clone(), e))
+                    .map_err(Self::error)?;
+                if existing_target != target {
+                    return Err(Self::error(ActionErrorKind::SymlinkExists(link)));
+                }
+            } else if link.exists() {
+                return Err(Self::error(ActionErrorKind::FileExists(link)));
+            } else {
+                all_linked = false;
+            }
+        }
+
+        if all_linked {
+            tracing::debug!("Linking systemd units already complete");
+            return Ok(StatefulAction::completed(this));
+        }
+
+        Ok(StatefulAction::uncompleted(this))
+    }
+
+    /// Each link under `root`, and the target it points to as seen from inside of `root`
+    fn links(&self) -> Vec<(PathBuf, PathBuf)> {
+        [
+            (SERVICE_DEST, SERVICE_SRC),
+            (SOCKET_DEST, SOCKET_SRC),
+            (SOCKET_WANTS, SOCKET_DEST),
+            (TMPFILES_DEST, TMPFILES_SRC),
+        ]
+        .into_iter()
+        .map(|(link, target)| (rebase(&self.root, link), PathBuf::from(target)))
+        .collect()
+    }
+}
+
+#[async_trait::async_trait]
+#[typetag::serde(name = "link_systemd_units")]
+impl Action for LinkSystemdUnits {
+    fn action_tag() -> ActionTag {
+        ActionTag("link_systemd_units")
+    }
+    fn tracing_synopsis(&self) -> String {
+        format!(
+            "Enable the Nix daemon units in `{}` with symlinks",
+            self.root.display()
+        )
+    }
+
+    fn tracing_span(&self) -> Span {
+        span!(
+            tracing::Level::DEBUG,
+            "link_systemd_units",
+            root = tracing::field::display(self.root.display()),
+        )
+    }
+
+    fn resources(&self) -> Vec<ActionResource> {
+        self.links()
+            .into_iter()
+            .map(|(link, _)| ActionResource::Path(link))
+            .collect()
+    }
+
+    fn execute_description(&self) -> Vec<ActionDescription> {
+        let mut explanation = vec![
+            "Nothing in the root is running, so the symlinks `systemctl enable` would make are created directly".to_string(),
+        ];
+        for (link, target) in self.links() {
+            explanation.push(format!(
+                "Symlink `{}` to `{}`",
+                link.display(),
+                target.display()
+            ));
+        }
+        vec![ActionDescription::new(self.tracing_synopsis(), explanation)]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn execute(&mut self) -> Result<(), ActionError> {
+        for (link, target) in self.links() {
+            if link.is_symlink() {
+                continue;
+            }
+            if let Some(parent) = link.parent() {
+                tokio::fs::create_dir_all(parent)
+                    .await
+                    .map_err(|e| ActionErrorKind::CreateDirectory(parent.to_path_buf(), e))
+                    .map_err(Self::error)?;
+            }
+            tracing::trace!(src = %target.display(), dest = %link.display(), "Symlinking");
+            tokio::fs::symlink(&target, &link)
+                .await
+                .map_err(|e| ActionErrorKind::Symlink(target, link, e))
+                .map_err(Self::error)?;
+        }
+
+        Ok(())
+    }
+
+    fn revert_description(&self) -> Vec<ActionDescription> {
+        vec![ActionDescription::new(
+            format!("Disable the Nix daemon units in `{}`", self.root.display()),
+            self.links()
+                .into_iter()
+                .map(|(link, _)| format!("Remove `{}`", link.display()))
+                .collect(),
+        )]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn revert(&mut self) -> Result<(), ActionError> {
+        let mut errors = vec![];
+        // Only remove links which still point where we left them
+        for (link, target) in self.links().into_iter().rev() {
+            if let Err(err) = remove_link(&link, &target).await {
+                errors.push(err);
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else if errors.len() == 1 {
+            Err(Self::error(
+                errors
+                    .into_iter()
+                    .next()
+                    .expect("Expected 1 len Vec to have at least 1 item"),
+            ))
+        } else {
+            Err(Self::error(ActionErrorKind::Multiple(errors)))
+        }
+    }
+}
+
+async fn remove_link(link: &Path, target: &Path) -> Result<(), ActionErrorKind> {
+    if !link.is_symlink() {
+        return Ok(());
+    }
+    let existing_target = tokio::fs::read_link(link)
+        .await
+        .map_err(|e| ActionErrorKind::ReadSymlink(link.to_path_buf(), e))?;
+    if existing_target != target {
+        tracing::debug!(path = %link.display(), "Symlink points elsewhere, leaving it");
+        return Ok(());
+    }
+    tracing::trace!(path = %link.display(), "Removing");
+    tokio::fs::remove_file(link)
+        .await
+        .map_err(|e| ActionErrorKind::Remove(link.to_path_buf(), e))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn links_and_unlinks_units_in_root() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let root = temp_dir.path().to_path_buf();
+
+        let mut action = LinkSystemdUnits::plan(root.clone()).await?;
+        action.try_execute().await?;
+
+        let wants = rebase(&root, SOCKET_WANTS);
+        assert_eq!(
+            tokio::fs::read_link(&wants).await?,
+            PathBuf::from(SOCKET_DEST)
+        );
+        assert_eq!(
+            tokio::fs::read_link(rebase(&root, SERVICE_DEST)).await?,
+            PathBuf::from(SERVICE_SRC)
+        );
+
+        // Planning again sees the links are already in place
+        let replanned = LinkSystemdUnits::plan(root.clone()).await?;
+        assert_eq!(replanned.state, crate::action::ActionState::Completed);
+
+        action.try_revert().await?;
+        assert!(!wants.is_symlink());
+        assert!(!rebase(&root, TMPFILES_DEST).is_symlink());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn refuses_foreign_unit() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let root = temp_dir.path().to_path_buf();
+        let service = rebase(&root, SERVICE_DEST);
+        tokio::fs::create_dir_all(service.parent().unwrap()).await?;
+        tokio::fs::write(&service, "[Unit]\n").await?;
+
+        assert!(LinkSystemdUnits::plan(root).await.is_err());
+
+        Ok(())
+    }
+}
@@ -1,11 +1,25 @@
+pub(crate) mod create_btrfs_subvolume;
+pub(crate) mod create_loopback_image;
+pub(crate) mod create_nix_loopback_store;
+pub(crate) mod enable_wsl_systemd;
 pub(crate) mod ensure_steamos_nix_directory;
+pub(crate) mod link_nix_directory;
+pub(crate) mod link_systemd_units;
+pub(crate) mod persistent_nix_mount;
 pub(crate) mod provision_selinux;
 pub(crate) mod revert_clean_steamos_nix_offload;
 pub(crate) mod start_systemd_unit;
 pub(crate) mod systemctl_daemon_reload;
 
+pub use create_btrfs_subvolume::CreateBtrfsSubvolume;
+pub use create_loopback_image::CreateLoopbackImage;
+pub use create_nix_loopback_store::CreateNixLoopbackStore;
+pub use enable_wsl_systemd::EnableWslSystemd;
 pub use ensure_steamos_nix_directory::EnsureSteamosNixDirectory;
-pub use provision_selinux::ProvisionSelinux;
+pub use link_nix_directory::LinkNixDirectory;
+pub use link_systemd_units::LinkSystemdUnits;
+pub use persistent_nix_mount::{nix_directory_unit, NixMountStrategy, PersistentNixMount};
+pub use provision_selinux::{ProvisionSelinux, SelinuxPolicySource};
 pub use revert_clean_steamos_nix_offload::RevertCleanSteamosNixOffload;
 pub use start_systemd_unit::{StartSystemdUnit, StartSystemdUnitError};
 pub use systemctl_daemon_reload::SystemctlDaemonReload;
@@ -0,0 +1,434 @@
+use std::path::{Path, PathBuf};
+
+use tracing::{span, Span};
+
+use crate::action::{
+    base::{CreateDirectory, CreateFile},
+    linux::{CreateBtrfsSubvolume, StartSystemdUnit},
+    Action, ActionDescription, ActionError, ActionErrorKind, ActionResource, ActionTag,
+    StatefulAction, SubAction,
+};
+
+const NIX_DIRECTORY_UNIT: &str = "/etc/systemd/system/nix-directory.service";
+const NIX_MOUNT_UNIT: &str = "/etc/systemd/system/nix.mount";
+pub(crate) const ENSURE_SYMLINKED_UNITS_RESOLVE_UNIT: &str =
+    "/etc/systemd/system/ensure-symlinked-units-resolve.service";
+pub(crate) const ENSURE_SYMLINKED_UNITS_RESOLVE_BUF: &str = "\
+    [Unit]\n\
+    Description=Ensure Nix related units which are symlinked resolve\n\
+    After=nix.mount\n\
+    Requires=nix.mount\n\
+    DefaultDependencies=no\n\
+    \n\
+    [Service]\n\
+    Type=oneshot\n\
+    RemainAfterExit=yes\n\
+    ExecStart=/usr/bin/systemctl daemon-reload\n\
+    ExecStart=/usr/bin/systemctl restart --no-block nix-daemon.socket\n\
+    \n\
+    [Install]\n\
+    WantedBy=sysinit.target\n\
+";
+
+/// How the persistent storage behind `/nix` is provided and mounted by a [`PersistentNixMount`]
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NixMountStrategy {
+    /// Bind mount a directory
+    Bind,
+    /// Bind mount a btrfs subvolume, which snapshots of its parent leave alone
+    BtrfsSubvolume,
+    /// Mount a filesystem image file through a loop device, the image is created beforehand by
+    /// [`CreateLoopbackImage`](crate::action::linux::CreateLoopbackImage)
+    Loopback,
+}
+
+impl std::fmt::Display for NixMountStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NixMountStrategy::Bind => write!(f, "bind mount"),
+            NixMountStrategy::BtrfsSubvolume => write!(f, "btrfs subvolume"),
+            NixMountStrategy::Loopback => write!(f, "loopback image"),
+        }
+    }
+}
+
+/**
+Mount persistent storage on `/nix` on systems with a read-only root, such as those using ostree
+
+This sets up the storage at `persistence`, a `nix-directory.service` unit creating the `/nix` mount point, a
+`nix.mount` unit mounting the storage on it, and an `ensure-symlinked-units-resolve.service` unit which reloads
+systemd once the Nix daemon units symlinked into the store can be resolved at boot, then starts `nix.mount`.
+
+Making a mount point in a read-only root differs between distributions, so the `nix-directory.service` unit is given
+by the planner, see [`nix_directory_unit`] for the common case. With a writable root there is none, and `/nix` is
+created beforehand.
+*/
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
+pub struct PersistentNixMount {
+    persistence: PathBuf,
+    strategy: NixMountStrategy,
+    create_persistence: Option<StatefulAction<Box<dyn Action>>>,
+    create_nix_directory_unit: Option<StatefulAction<CreateFile>>,
+    create_mount_unit: StatefulAction<CreateFile>,
+    create_ensure_symlinked_units_resolve_unit: StatefulAction<CreateFile>,
+    start_mount: StatefulAction<StartSystemdUnit>,
+}
+
+impl PersistentNixMount {
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub async fn plan(
+        persistence: impl AsRef<Path>,
+        strategy: NixMountStrategy,
+        nix_directory_unit: Option<String>,
+    ) -> Result<StatefulAction<Self>, ActionError> {
+        let persistence = persistence.as_ref().to_path_buf();
+
+        let create_persistence = match strategy {
+            NixMountStrategy::Bind => Some(
+                CreateDirectory::plan(&persistence, None, None, 0o0755, true)
+                    .await
+                    .map_err(Self::error)?
+                    .boxed(),
+            ),
+            NixMountStrategy::BtrfsSubvolume => Some(
+                CreateBtrfsSubvolume::plan(&persistence)
+                    .await
+                    .map_err(Self::error)?
+                    .boxed(),
+            ),
+            NixMountStrategy::Loopback => {
+                if persistence.exists() && !persistence.is_file() {
+                    return Err(Self::error(ActionErrorKind::PathWasNotFile(persistence)));
+                }
+                None
+            },
+        };
+
+        let has_nix_directory_unit = nix_directory_unit.is_some();
+        let create_nix_directory_unit = match nix_directory_unit {
+            Some(nix_directory_unit) => Some(
+                CreateFile::plan(
+                    NIX_DIRECTORY_UNIT,
+                    None,
+                    None,
+                    0o0644,
+                    nix_directory_unit,
+                    false,
+                )
+                .await
+                .map_err(Self::error)?,
+            ),
+            None => None,
+        };
+        let create_mount_unit = CreateFile::plan(
+            NIX_MOUNT_UNIT,
+            None,
+            None,
+            0o0644,
+            mount_unit(&persistence, strategy, has_nix_directory_unit),
+            false,
+        )
+        .await
+        .map_err(Self::error)?;
+        let create_ensure_symlinked_units_resolve_unit = CreateFile::plan(
+            ENSURE_SYMLINKED_UNITS_RESOLVE_UNIT,
+            None,
+            None,
+            0o0644,
+            ENSURE_SYMLINKED_UNITS_RESOLVE_BUF.to_string(),
+            false,
+        )
+        .await
+        .map_err(Self::error)?;
+        let start_mount = StartSystemdUnit::plan("nix.mount".to_string(), false)
+            .await
+            .map_err(Self::error)?;
+
+        Ok(Self {
+            persistence,
+            strategy,
+            create_persistence,
+            create_nix_directory_unit,
+            create_mount_unit,
+            create_ensure_symlinked_units_resolve_unit,
+            start_mount,
+        }
+        .into())
+    }
+}
+
+#[async_trait::async_trait]
+#[typetag::serde(name = "persistent_nix_mount")]
+impl Action for PersistentNixMount {
+    fn action_tag() -> ActionTag {
+        ActionTag("persistent_nix_mount")
+    }
+    fn tracing_synopsis(&self) -> String {
+        format!(
+            "Mount `{}` on `/nix` ({})",
+            self.persistence.display(),
+            self.strategy
+        )
+    }
+
+    fn tracing_span(&self) -> Span {
+        span!(
+            tracing::Level::DEBUG,
+            "persistent_nix_mount",
+            persistence = tracing::field::display(self.persistence.display()),
+            strategy = tracing::field::display(self.strategy),
+        )
+    }
+
+    fn resources(&self) -> Vec<ActionResource> {
+        let mut resources = vec![];
+        if let Some(create_persistence) = &self.create_persistence {
+            resources.push(create_persistence.resources());
+        }
+        if let Some(create_nix_directory_unit) = &self.create_nix_directory_unit {
+            resources.push(create_nix_directory_unit.resources());
+        }
+        resources.push(self.create_mount_unit.resources());
+        resources.push(self.create_ensure_symlinked_units_resolve_unit.resources());
+        resources.push(self.start_mount.resources());
+        ActionResource::union(resources)
+    }
+
+    fn children(&self) -> Vec<&dyn SubAction> {
+        let mut children: Vec<&dyn SubAction> = vec![];
+        children.extend(self.create_persistence.iter().map(|v| v as &dyn SubAction));
+        children.extend(
+            self.create_nix_directory_unit
+                .iter()
+                .map(|v| v as &dyn SubAction),
+        );
+        children.push(&self.create_mount_unit);
+        children.push(&self.create_ensure_symlinked_units_resolve_unit);
+        children.push(&self.start_mount);
+        children
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut dyn SubAction> {
+        let mut children: Vec<&mut dyn SubAction> = vec![];
+        children.extend(
+            self.create_persistence
+                .iter_mut()
+                .map(|v| v as &mut dyn SubAction),
+        );
+        children.extend(
+            self.create_nix_directory_unit
+                .iter_mut()
+                .map(|v| v as &mut dyn SubAction),
+        );
+        children.push(&mut self.create_mount_unit);
+        children.push(&mut self.create_ensure_symlinked_units_resolve_unit);
+        children.push(&mut self.start_mount);
+        children
+    }
+
+    fn execute_description(&self) -> Vec<ActionDescription> {
+        let mut explanation = vec![];
+        if let Some(create_persistence) = &self.create_persistence {
+            explanation.push(create_persistence.tracing_synopsis());
+        }
+        if let Some(create_nix_directory_unit) = &self.create_nix_directory_unit {
+            explanation.push(create_nix_directory_unit.tracing_synopsis());
+        }
+        explanation.push(self.create_mount_unit.tracing_synopsis());
+        explanation.push(
+            self.create_ensure_symlinked_units_resolve_unit
+                .tracing_synopsis(),
+        );
+        explanation.push(self.start_mount.tracing_synopsis());
+
+        vec![ActionDescription::new(self.tracing_synopsis(), explanation)]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn execute(&mut self) -> Result<(), ActionError> {
+        if let Some(create_persistence) = &mut self.create_persistence {
+            create_persistence
+                .try_execute()
+                .await
+                .map_err(Self::error)?;
+        }
+        if let Some(create_nix_directory_unit) = &mut self.create_nix_directory_unit {
+            create_nix_directory_unit
+                .try_execute()
+                .await
+                .map_err(Self::error)?;
+        }
+        self.create_mount_unit
+            .try_execute()
+            .await
+            .map_err(Self::error)?;
+        self.create_ensure_symlinked_units_resolve_unit
+            .try_execute()
+            .await
+            .map_err(Self::error)?;
+        self.start_mount.try_execute().await.map_err(Self::error)?;
+
+        Ok(())
+    }
+
+    fn revert_description(&self) -> Vec<ActionDescription> {
+        let mut explanation = vec![self.start_mount.tracing_synopsis()];
+        explanation.push(
+            self.create_ensure_symlinked_units_resolve_unit
+                .tracing_synopsis(),
+        );
+        explanation.push(self.create_mount_unit.tracing_synopsis());
+        if let Some(create_nix_directory_unit) = &self.create_nix_directory_unit {
+            explanation.push(create_nix_directory_unit.tracing_synopsis());
+        }
+        if let Some(create_persistence) = &self.create_persistence {
+            explanation.push(create_persistence.tracing_synopsis());
+        }
+
+        vec![ActionDescription::new(
+            format!(
+                "Unmount `{}` from `/nix` and remove the units mounting it",
+                self.persistence.display()
+            ),
+            explanation,
+        )]
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn revert(&mut self) -> Result<(), ActionError> {
+        let mut errors = vec![];
+        if let Err(err) = self.start_mount.try_revert().await {
+            errors.push(err);
+        }
+        if let Err(err) = self
+            .create_ensure_symlinked_units_resolve_unit
+            .try_revert()
+            .await
+        {
+            errors.push(err);
+        }
+        if let Err(err) = self.create_mount_unit.try_revert().await {
+            errors.push(err);
+        }
+        if let Some(create_nix_directory_unit) = &mut self.create_nix_directory_unit {
+            if let Err(err) = create_nix_directory_unit.try_revert().await {
+                errors.push(err);
+            }
+        }
+        if let Some(create_persistence) = &mut self.create_persistence {
+            if let Err(err) = create_persistence.try_revert().await {
+                errors.push(err);
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else if errors.len() == 1 {
+            Err(errors
+                .into_iter()
+                .next()
+                .expect("Expected 1 len Vec to have at least 1 item"))
+        } else {
+            Err(Self::error(ActionErrorKind::MultipleChildren(errors)))
+        }
+    }
+}
+
+/// A `nix-directory.service` unit which runs `unlock`, creates `/nix`, then runs `lock`
+///
+/// This suits distributions where the root is made writable for a moment, like `chattr -i /` with ostree.
+pub fn nix_directory_unit(description: &str, unlock: &str, lock: &str) -> String {
+    format!(
+        "\
+        [Unit]\n\
+        Description={description}\n\
+        ConditionPathExists=!/nix\n\
+        DefaultDependencies=no\n\
+        Requires=local-fs-pre.target\n\
+        After=local-fs-pre.target\n\
+        [Service]\n\
+        Type=oneshot\n\
+        ExecStartPre={unlock}\n\
+        ExecStart=mkdir -p /nix\n\
+        ExecStopPost={lock}\n\
+        "
+    )
+}
+
+/// The `nix.mount` unit mounting `persistence` on `/nix`, after `nix-directory.service` if `has_nix_directory_unit`
+fn mount_unit(
+    persistence: &Path,
+    strategy: NixMountStrategy,
+    has_nix_directory_unit: bool,
+) -> String {
+    let (type_, options) = match strategy {
+        NixMountStrategy::Bind | NixMountStrategy::BtrfsSubvolume => ("Type=none\n", "bind"),
+        // `mount` sets up the loop device, and detects the filesystem of the image
+        NixMountStrategy::Loopback => ("", "loop"),
+    };
+    let nix_directory = if has_nix_directory_unit {
+        "\
+        PropagatesStopTo=nix-directory.service\n\
+        After=nix-directory.service\n\
+        Requires=nix-directory.service\n\
+        "
+    } else {
+        ""
+    };
+    format!(
+        "\
+        [Unit]\n\
+        Description=Mount `{persistence}` on `/nix`\n\
+        PropagatesStopTo=nix-daemon.service\n\
+        {nix_directory}\
+        RequiresMountsFor={persistence}\n\
+        ConditionPathIsDirectory=/nix\n\
+        DefaultDependencies=no\n\
+        \n\
+        [Mount]\n\
+        What={persistence}\n\
+        Where=/nix\n\
+        {type_}\
+        DirectoryMode=0755\n\
+        Options={options}\n\
+        \n\
+        [Install]\n\
+        RequiredBy=nix-daemon.service\n\
+        RequiredBy=nix-daemon.socket\n\
+        ",
+        persistence = persistence.display(),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn mount_unit_follows_strategy() {
+        let bind = mount_unit(Path::new("/var/home/nix"), NixMountStrategy::Bind, true);
+        assert!(bind.contains("What=/var/home/nix\nWhere=/nix\nType=none\n"));
+        assert!(bind.contains("Options=bind\n"));
+        assert_eq!(
+            bind,
+            mount_unit(
+                Path::new("/var/home/nix"),
+                NixMountStrategy::BtrfsSubvolume,
+                true
+            )
+        );
+
+        assert!(bind.contains("Requires=nix-directory.service\n"));
+
+        let loopback = mount_unit(
+            Path::new("/var/lib/nix.img"),
+            NixMountStrategy::Loopback,
+            false,
+        );
+        assert!(!loopback.contains("nix-directory.service"));
+        assert!(loopback.contains("What=/var/lib/nix.img\nWhere=/nix\nDirectoryMode=0755\n"));
+        assert!(loopback.contains("Options=loop\n"));
+        assert!(loopback.contains("RequiresMountsFor=/var/lib/nix.img\n"));
+    }
+}
@@ -4,25 +4,62 @@ use tokio::fs::{create_dir_all, remove_file};
 use tokio::process::Command;
 use tracing::{span, Span};
 
-use crate::action::{ActionError, ActionErrorKind, ActionTag};
+use crate::action::{ActionError, ActionErrorKind, ActionResource, ActionTag};
 use crate::execute_command;
 
 use crate::action::{Action, ActionDescription, StatefulAction};
 
 const SE_LINUX_POLICY_PP_CONTENT: &[u8] = include_bytes!("selinux/nix.pp");
+const SE_LINUX_POLICY_TE_CONTENT: &str = include_str!("selinux/nix.te");
+const SE_LINUX_POLICY_FC_CONTENT: &str = include_str!("selinux/nix.fc");
+
+/// Where the policy package installed by [`ProvisionSelinux`] came from
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum SelinuxPolicySource {
+    /// Compiled on the host from `selinux/nix.te` and `selinux/nix.fc`, so it matches the policy version of the host
+    Compiled,
+    /// The `selinux/nix.pp` built with `selinux/build.sh`
+    Prebuilt,
+}
+
+impl SelinuxPolicySource {
+    fn detect() -> Self {
+        if which::which("checkmodule").is_ok() && which::which("semodule_package").is_ok() {
+            Self::Compiled
+        } else {
+            Self::Prebuilt
+        }
+    }
+}
 
 /**
 Provision the selinux/nix.pp for SELinux compatibility
+
+The policy is compiled from source with `checkmodule` and `semodule_package` where they are available, otherwise
+the prebuilt package is used.
 */
 #[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
 pub struct ProvisionSelinux {
     policy_path: PathBuf,
+    #[serde(default = "default_source")]
+    source: SelinuxPolicySource,
+    /// The version in the `module` statement of the policy
+    #[serde(default)]
+    module_version: Option<String>,
+}
+
+fn default_source() -> SelinuxPolicySource {
+    SelinuxPolicySource::Prebuilt
 }
 
 impl ProvisionSelinux {
     #[tracing::instrument(level = "debug", skip_all)]
     pub async fn plan(policy_path: PathBuf) -> Result<StatefulAction<Self>, ActionError> {
-        let this = Self { policy_path };
+        let this = Self {
+            policy_path,
+            source: SelinuxPolicySource::detect(),
+            module_version: module_version(SE_LINUX_POLICY_TE_CONTENT),
+        };
 
         // Note: `restorecon` requires us to not just skip this, even if everything is in place.
 
@@ -48,12 +85,36 @@ impl Action for ProvisionSelinux {
         )
     }
 
+    fn resources(&self) -> Vec<ActionResource> {
+        vec![
+            ActionResource::Path(self.policy_path.clone()),
+            // `restorecon` relabels everything in `/nix`
+            ActionResource::path("/nix"),
+        ]
+    }
+
     fn execute_description(&self) -> Vec<ActionDescription> {
+        let source = match self.source {
+            SelinuxPolicySource::Compiled => {
+                "Compile the policy from source with `checkmodule` and `semodule_package`, falling back to the prebuilt policy if that fails or `semodule` rejects it"
+            },
+            SelinuxPolicySource::Prebuilt => {
+                "Use the prebuilt policy, since `checkmodule` or `semodule_package` is not installed"
+            },
+        };
         vec![ActionDescription::new(
             self.tracing_synopsis(),
-            vec![format!(
-                "On SELinux systems (such as Fedora) a policy for Nix needs to be configured for correct operation."
-            )],
+            vec![
+                format!(
+                    "On SELinux systems (such as Fedora) a policy for Nix needs to be configured for correct operation."
+                ),
+                source.to_string(),
+                format!(
+                    "Install the `nix` module (version {}) with `semodule --install {}`",
+                    self.module_version.as_deref().unwrap_or("unknown"),
+                    self.policy_path.display()
+                ),
+            ],
         )]
     }
 
@@ -73,18 +134,26 @@ impl Action for ProvisionSelinux {
                 .map_err(Self::error)?;
         }
 
-        tokio::fs::write(&self.policy_path, SE_LINUX_POLICY_PP_CONTENT)
-            .await
-            .map_err(|e| ActionErrorKind::Write(self.policy_path.clone(), e))
-            .map_err(Self::error)?;
-
-        execute_command(
-            Command::new("semodule")
-                .arg("--install")
-                .arg(&self.policy_path),
-        )
-        .await
-        .map_err(Self::error)?;
+        tracing::debug!(source = ?self.source, module_version = ?self.module_version, "Installing SELinux policy");
+        if self.source == SelinuxPolicySource::Compiled {
+            let installed = match compile_policy(&self.policy_path).await {
+                Ok(()) => install_policy(&self.policy_path).await,
+                Err(err) => Err(err),
+            };
+            if let Err(err) = installed {
+                tracing::warn!(%err, "Compiling or installing the compiled SELinux policy failed, using the prebuilt policy instead");
+                self.source = SelinuxPolicySource::Prebuilt;
+            }
+        }
+        if self.source == SelinuxPolicySource::Prebuilt {
+            tokio::fs::write(&self.policy_path, SE_LINUX_POLICY_PP_CONTENT)
+                .await
+                .map_err(|e| ActionErrorKind::Write(self.policy_path.clone(), e))
+                .map_err(Self::error)?;
+            install_policy(&self.policy_path)
+                .await
+                .map_err(Self::error)?;
+        }
 
         execute_command(Command::new("restorecon").args(["-FR", "/nix"]))
             .await
@@ -112,6 +181,81 @@ impl Action for ProvisionSelinux {
     }
 }
 
+/// Install the policy package at `policy_path` with `semodule`
+async fn install_policy(policy_path: &Path) -> Result<(), ActionErrorKind> {
+    execute_command(Command::new("semodule").arg("--install").arg(policy_path))
+        .await
+        .map(|_| ())
+}
+
+/// The version from the `module nix 1.0;` statement of a policy source
+fn module_version(te: &str) -> Option<String> {
+    te.lines().find_map(|line| {
+        let mut words = line.trim().trim_end_matches(';').split_whitespace();
+        match (words.next(), words.next(), words.next()) {
+            (Some("module"), Some(_name), Some(version)) => Some(version.to_string()),
+            _ => None,
+        }
+    })
+}
+
+/// Compile the policy sources into a package at `policy_path`, for the policy version of the host
+async fn compile_policy(policy_path: &Path) -> Result<(), ActionErrorKind> {
+    // A fresh directory only we can write to, as a predictable one in the shared temporary directory could be
+    // planted beforehand
+    let build_dir = tempfile::Builder::new()
+        .prefix("nix-installer-selinux-")
+        .tempdir()
+        .map_err(|e| ActionErrorKind::CreateDirectory(std::env::temp_dir(), e))?;
+
+    let result = compile_policy_in(build_dir.path(), policy_path).await;
+
+    let path = build_dir.path().to_path_buf();
+    if let Err(err) = build_dir.close() {
+        tracing::debug!(%err, path = %path.display(), "Could not remove SELinux build directory");
+    }
+    result
+}
+
+async fn compile_policy_in(build_dir: &Path, policy_path: &Path) -> Result<(), ActionErrorKind> {
+    let te = build_dir.join("nix.te");
+    let fc = build_dir.join("nix.fc");
+    let module = build_dir.join("nix.mod");
+    for (path, content) in [
+        (&te, SE_LINUX_POLICY_TE_CONTENT),
+        (&fc, SE_LINUX_POLICY_FC_CONTENT),
+    ] {
+        tokio::fs::write(path, content)
+            .await
+            .map_err(|e| ActionErrorKind::Write(path.clone(), e))?;
+    }
+
+    // Without `-c`, `checkmodule` targets the module policy version the host supports
+    execute_command(
+        Command::new("checkmodule")
+            .process_group(0)
+            .args(["-M", "-m", "-o"])
+            .arg(&module)
+            .arg(&te)
+            .stdin(std::process::Stdio::null()),
+    )
+    .await?;
+    execute_command(
+        Command::new("semodule_package")
+            .process_group(0)
+            .arg("-o")
+            .arg(policy_path)
+            .arg("-m")
+            .arg(&module)
+            .arg("-f")
+            .arg(&fc)
+            .stdin(std::process::Stdio::null()),
+    )
+    .await?;
+
+    Ok(())
+}
+
 async fn remove_existing_policy(policy_path: &Path) -> Result<(), ActionErrorKind> {
     execute_command(Command::new("semodule").arg("--remove").arg("nix")).await?;
 
@@ -123,3 +267,17 @@ async fn remove_existing_policy(policy_path: &Path) -> Result<(), ActionErrorKin
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reads_module_version() {
+        assert_eq!(
+            module_version(SE_LINUX_POLICY_TE_CONTENT).as_deref(),
+            Some("1.0")
+        );
+        assert_eq!(module_version("require {\n}\n"), None);
+    }
+}
@@ -1,5 +1,5 @@
 use crate::action::base::{create_or_insert_into_file, CreateOrInsertIntoFile};
-use crate::action::{Action, ActionDescription, ActionError, ActionTag, StatefulAction};
+use crate::action::{Action, ActionDescription, ActionError, ActionTag, StatefulAction, SubAction};
 
 use std::path::Path;
 use tracing::{span, Instrument, Span};
@@ -69,6 +69,20 @@ impl Action for ConfigureRemoteBuilding {
         span!(tracing::Level::DEBUG, "configure_remote_building",)
     }
 
+    fn children(&self) -> Vec<&dyn SubAction> {
+        self.create_or_insert_into_file
+            .iter()
+            .map(|v| v as &dyn SubAction)
+            .collect()
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut dyn SubAction> {
+        self.create_or_insert_into_file
+            .iter_mut()
+            .map(|v| v as &mut dyn SubAction)
+            .collect()
+    }
+
     fn execute_description(&self) -> Vec<ActionDescription> {
         vec![ActionDescription::new(
             if self.create_or_insert_into_file.is_none() {
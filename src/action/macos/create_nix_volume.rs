@@ -4,7 +4,7 @@ use crate::action::{
         BootstrapLaunchctlService, CreateApfsVolume, CreateSyntheticObjects, EnableOwnership,
         EncryptApfsVolume, UnmountApfsVolume,
     },
-    Action, ActionDescription, ActionError, ActionErrorKind, ActionTag, StatefulAction,
+    Action, ActionDescription, ActionError, ActionErrorKind, ActionTag, StatefulAction, SubAction,
 };
 use std::{
     path::{Path, PathBuf},
@@ -143,6 +143,42 @@ impl Action for CreateNixVolume {
         )
     }
 
+    fn children(&self) -> Vec<&dyn SubAction> {
+        let mut children: Vec<&dyn SubAction> = vec![
+            &self.create_or_append_synthetic_conf as &dyn SubAction,
+            &self.create_synthetic_objects,
+            &self.unmount_volume,
+            &self.create_volume,
+            &self.create_fstab_entry,
+        ];
+        children.extend(self.encrypt_volume.iter().map(|v| v as &dyn SubAction));
+        children.push(&self.setup_volume_daemon);
+        children.push(&self.bootstrap_volume);
+        children.push(&self.kickstart_launchctl_service);
+        children.push(&self.enable_ownership);
+        children
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut dyn SubAction> {
+        let mut children: Vec<&mut dyn SubAction> = vec![
+            &mut self.create_or_append_synthetic_conf as &mut dyn SubAction,
+            &mut self.create_synthetic_objects,
+            &mut self.unmount_volume,
+            &mut self.create_volume,
+            &mut self.create_fstab_entry,
+        ];
+        children.extend(
+            self.encrypt_volume
+                .iter_mut()
+                .map(|v| v as &mut dyn SubAction),
+        );
+        children.push(&mut self.setup_volume_daemon);
+        children.push(&mut self.bootstrap_volume);
+        children.push(&mut self.kickstart_launchctl_service);
+        children.push(&mut self.enable_ownership);
+        children
+    }
+
     fn execute_description(&self) -> Vec<ActionDescription> {
         let mut explanation = vec![
             self.create_or_append_synthetic_conf.tracing_synopsis(),
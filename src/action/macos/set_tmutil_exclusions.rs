@@ -3,7 +3,7 @@ use std::path::PathBuf;
 use tracing::{span, Span};
 
 use crate::action::{
-    Action, ActionDescription, ActionError, ActionErrorKind, ActionTag, StatefulAction,
+    Action, ActionDescription, ActionError, ActionErrorKind, ActionTag, StatefulAction, SubAction,
 };
 
 use super::SetTmutilExclusion;
@@ -71,6 +71,20 @@ impl Action for SetTmutilExclusions {
         span!(tracing::Level::DEBUG, "set_tmutil_exclusions",)
     }
 
+    fn children(&self) -> Vec<&dyn SubAction> {
+        self.set_tmutil_exclusions
+            .iter()
+            .map(|v| v as &dyn SubAction)
+            .collect()
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut dyn SubAction> {
+        self.set_tmutil_exclusions
+            .iter_mut()
+            .map(|v| v as &mut dyn SubAction)
+            .collect()
+    }
+
     fn execute_description(&self) -> Vec<ActionDescription> {
         let Self {
             set_tmutil_exclusions,
@@ -192,9 +192,12 @@ pub mod base;
 pub mod common;
 pub mod linux;
 pub mod macos;
+mod resource;
 mod stateful;
 
-pub use stateful::{ActionState, StatefulAction};
+pub(crate) use resource::dependencies;
+pub use resource::ActionResource;
+pub use stateful::{ActionState, StatefulAction, SubAction};
 use std::{error::Error, process::Output};
 use tokio::task::JoinError;
 use tracing::Span;
@@ -246,6 +249,26 @@ pub trait Action: Send + Sync + std::fmt::Debug + dyn_clone::DynClone {
     ///
     /// This is called by [`InstallPlan::uninstall`](crate::InstallPlan::uninstall) through [`StatefulAction::try_revert`] which handles tracing as well as if the action needs to revert based on its `action_state`.
     async fn revert(&mut self) -> Result<(), ActionError>;
+    /// The resources this action touches during execution or revert
+    ///
+    /// [`InstallPlan`](crate::InstallPlan) runs actions which touch unrelated resources concurrently. The default
+    /// of no resources means the action may touch anything, so it will never run alongside another action.
+    ///
+    /// If this action calls sub-[`Action`]s, it should typically return the [`ActionResource::union`] of theirs.
+    fn resources(&self) -> Vec<ActionResource> {
+        Vec::new()
+    }
+    /// The sub-[`Action`]s this action calls, in the order it executes them
+    ///
+    /// A plan walks down these to [`adopt`](crate::InstallPlan::adopt) an install, and to leave some sub-actions out of
+    /// an [`uninstall`](crate::InstallPlan::uninstall). Meta-actions should return all of theirs.
+    fn children(&self) -> Vec<&dyn SubAction> {
+        Vec::new()
+    }
+    /// The sub-[`Action`]s this action calls, like [`children`](Action::children)
+    fn children_mut(&mut self) -> Vec<&mut dyn SubAction> {
+        Vec::new()
+    }
 
     fn stateful(self) -> StatefulAction<Self>
     where
@@ -402,6 +425,9 @@ pub enum ActionErrorKind {
     SymlinkExists(std::path::PathBuf),
     #[error("`{0}` exists with a different uid ({1}) than planned ({2}), consider updating it with `chown {2} {0}`")]
     PathUserMismatch(std::path::PathBuf, u32, u32),
+    /// A path in a home directory which `root` must not write through, as its user could point it anywhere
+    #[error("`{0}` is a symlink or isn't owned by the user (uid {1}) whose home directory it is in, refusing to write through it")]
+    UnsafeHomePath(std::path::PathBuf, u32),
     #[error("`{0}` exists with a different gid ({1}) than planned ({2}), consider updating it with `chgrp {2} {0}`")]
     PathGroupMismatch(std::path::PathBuf, u32, u32),
     #[error("`{0}` exists with a different mode ({existing_mode:o}) than planned ({planned_mode:o}), consider updating it with `chmod {planned_mode:o} {0}`",
@@ -474,6 +500,14 @@ pub enum ActionErrorKind {
     GroupGidMismatch(String, u32, u32),
     #[error("Getting group `{0}`")]
     NoGroup(String),
+    #[error("Locking `{0}`")]
+    Lock(std::path::PathBuf, #[source] std::io::Error),
+    #[error("`{0}` already contains an entry `{1}`")]
+    UserDatabaseEntryExists(std::path::PathBuf, String),
+    #[error("`{0}` already contains an entry with ID {1}")]
+    UserDatabaseIdExists(std::path::PathBuf, u32),
+    #[error("Malformed entry on line {1} of `{0}`")]
+    MalformedUserDatabase(std::path::PathBuf, usize),
     #[error("Chowning path `{0}`")]
     Chown(std::path::PathBuf, #[source] nix::errno::Errno),
     #[error("Glob globbing error")]
@@ -558,6 +592,22 @@ pub enum ActionErrorKind {
         See https://github.com/DeterminateSystems/nix-installer#without-systemd-linux-only for documentation on usage and drawbacks.\
         ")]
     SystemdMissing,
+    #[error("\
+        Could not detect OpenRC, `rc-update` and `rc-service` are required; you may be able to get up and running without an init system with `nix-installer install linux --init none`.\
+        ")]
+    OpenRcMissing,
+    #[error("\
+        Could not detect runit, `sv` and a service directory such as `/var/service` are required; you may be able to get up and running without an init system with `nix-installer install linux --init none`.\
+        ")]
+    RunitMissing,
+    #[error("\
+        Could not detect s6, `s6-svc`, `s6-svstat`, `s6-svscanctl` and a scan directory supervised by `s6-svscan` are required; you may be able to get up and running without an init system with `nix-installer install linux --init none`.\
+        ")]
+    S6Missing,
+    #[error("The supervisor did not report the service `{0}` as up in time")]
+    SupervisedServiceNotUp(std::path::PathBuf),
+    #[error("The Nix daemon did not respond on `{0}` in time: {1}{}", if .2.is_empty() { String::new() } else { format!("\n\nRecent logs of the daemon:\n{}", .2) })]
+    DaemonNotReady(std::path::PathBuf, #[source] std::io::Error, String),
     #[error("`{command}` failed, message: {message}")]
     DiskUtilInfoError { command: String, message: String },
     #[error(transparent)]
@@ -595,9 +645,13 @@ impl HasExpectedErrors for ActionErrorKind {
     fn expected<'a>(&'a self) -> Option<Box<dyn std::error::Error + 'a>> {
         match self {
             Self::PathUserMismatch(_, _, _)
+            | Self::UnsafeHomePath(_, _)
             | Self::PathGroupMismatch(_, _, _)
             | Self::PathModeMismatch(_, _, _) => Some(Box::new(self)),
             Self::SystemdMissing => Some(Box::new(self)),
+            Self::OpenRcMissing => Some(Box::new(self)),
+            Self::RunitMissing => Some(Box::new(self)),
+            Self::S6Missing => Some(Box::new(self)),
             _ => None,
         }
     }
@@ -0,0 +1,124 @@
+use std::path::PathBuf;
+
+/**
+Something on the host an [`Action`](crate::action::Action) reads or modifies
+
+[`InstallPlan`](crate::InstallPlan) uses these to build a dependency graph of its actions, so actions
+which touch unrelated resources can run concurrently. An action which declares no resources is
+assumed to touch everything, and never runs alongside another action.
+*/
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Deserialize, serde::Serialize)]
+pub enum ActionResource {
+    /// A file or directory, which includes everything beneath it
+    Path(PathBuf),
+    /// An operating system level user
+    User(String),
+    /// An operating system level group
+    Group(String),
+    /// An init system unit or service, such as `nix-daemon.socket`
+    Unit(String),
+}
+
+impl ActionResource {
+    pub fn path(path: impl Into<PathBuf>) -> Self {
+        Self::Path(path.into())
+    }
+
+    /// If two actions touching these resources must not run at the same time
+    pub fn conflicts_with(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Path(this), Self::Path(other)) => {
+                this.starts_with(other) || other.starts_with(this)
+            },
+            (Self::User(this), Self::User(other))
+            | (Self::Group(this), Self::Group(other))
+            | (Self::Unit(this), Self::Unit(other)) => this == other,
+            _ => false,
+        }
+    }
+
+    /// Combine the resources of several sub-[`Action`](crate::action::Action)s
+    ///
+    /// If any of them declares no resources the combination is empty, as that sub-action may touch anything.
+    pub fn union(resources: impl IntoIterator<Item = Vec<ActionResource>>) -> Vec<ActionResource> {
+        let mut union = Vec::new();
+        for resources in resources {
+            if resources.is_empty() {
+                return Vec::new();
+            }
+            for resource in resources {
+                if !union.contains(&resource) {
+                    union.push(resource);
+                }
+            }
+        }
+        union
+    }
+}
+
+/// For each entry of `resources`, the indices of earlier entries which must complete before it starts
+pub(crate) fn dependencies(resources: &[Vec<ActionResource>]) -> Vec<Vec<usize>> {
+    resources
+        .iter()
+        .enumerate()
+        .map(|(index, these)| {
+            resources[..index]
+                .iter()
+                .enumerate()
+                .filter(|(_, earlier)| {
+                    these.is_empty()
+                        || earlier.is_empty()
+                        || these
+                            .iter()
+                            .any(|this| earlier.iter().any(|other| this.conflicts_with(other)))
+                })
+                .map(|(earlier_index, _)| earlier_index)
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn paths_conflict_with_ancestors() {
+        let nix = ActionResource::path("/nix");
+        let store = ActionResource::path("/nix/store");
+        let etc = ActionResource::path("/etc/nix");
+        let nix_like = ActionResource::path("/nixos");
+
+        assert!(nix.conflicts_with(&store));
+        assert!(store.conflicts_with(&nix));
+        assert!(!store.conflicts_with(&etc));
+        assert!(!nix.conflicts_with(&nix_like));
+        assert!(!ActionResource::User("nixbld".into())
+            .conflicts_with(&ActionResource::Group("nixbld".into())));
+    }
+
+    #[test]
+    fn union_of_undeclared_is_empty() {
+        let declared = vec![ActionResource::path("/nix")];
+        assert_eq!(
+            ActionResource::union([declared.clone(), declared.clone()]),
+            declared
+        );
+        assert!(ActionResource::union([declared, vec![]]).is_empty());
+    }
+
+    #[test]
+    fn builds_dependencies() {
+        let resources = vec![
+            vec![ActionResource::path("/nix")],
+            vec![ActionResource::path("/nix/store")],
+            vec![ActionResource::Group("nixbld".into())],
+            vec![],
+            vec![ActionResource::path("/etc/tmpfiles.d")],
+        ];
+        assert_eq!(
+            dependencies(&resources),
+            vec![vec![], vec![0], vec![], vec![0, 1, 2], vec![3]]
+        );
+    }
+}
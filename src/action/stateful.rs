@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 use tracing::{Instrument, Span};
 
-use super::{Action, ActionDescription, ActionError, ActionTag};
+use super::{Action, ActionDescription, ActionError, ActionResource, ActionTag};
 
 /// A wrapper around an [`Action`](crate::action::Action) which tracks the [`ActionState`] and
 /// handles some tracing output
@@ -34,6 +34,10 @@ impl StatefulAction<Box<dyn Action>> {
     pub fn tracing_span(&self) -> Span {
         self.action.tracing_span()
     }
+    /// The resources the action touches
+    pub fn resources(&self) -> Vec<ActionResource> {
+        self.action.resources()
+    }
     /// A description of what this action would do during execution
     pub fn describe_execute(&self) -> Vec<ActionDescription> {
         match self.state {
@@ -127,6 +131,11 @@ where
         self.action.tracing_span()
     }
 
+    /// The resources the action touches
+    pub fn resources(&self) -> Vec<ActionResource> {
+        self.action.resources()
+    }
+
     pub fn inner(&self) -> &A {
         &self.action
     }
@@ -149,7 +158,7 @@ where
     }
     /// A description of what this action would do during revert
     pub fn describe_revert(&self) -> Vec<ActionDescription> {
-        if self.state == ActionState::Uncompleted {
+        if matches!(self.state, ActionState::Uncompleted | ActionState::Skipped) {
             return vec![];
         }
         self.action.revert_description()
@@ -249,6 +258,123 @@ where
     }
 }
 
+/**
+A [`StatefulAction`] of any [`Action`], so a plan can walk down the sub-actions of meta-actions
+
+Meta-actions list theirs with [`Action::children`] and [`Action::children_mut`].
+*/
+pub trait SubAction: Send + Sync {
+    fn state(&self) -> ActionState;
+    fn set_state(&mut self, state: ActionState);
+    /// The name the action is serialized with, which is also its [`ActionTag`]
+    fn typetag_name(&self) -> &'static str;
+    fn tracing_synopsis(&self) -> String;
+    fn children(&self) -> Vec<&dyn SubAction>;
+    fn children_mut(&mut self) -> Vec<&mut dyn SubAction>;
+
+    /// Derive the state of a meta-action from its sub-actions, unless it is done itself, returning its state
+    ///
+    /// It is completed when all its sub-actions are, and in progress when only some are. This lets
+    /// [`InstallPlan::adopt`](crate::InstallPlan::adopt) record a meta-action whose own `plan` can't tell if it was
+    /// done by other means.
+    fn adopt_state(&mut self) -> ActionState {
+        let state = self.state();
+        if state != ActionState::Uncompleted {
+            return state;
+        }
+        let states: Vec<ActionState> = self
+            .children_mut()
+            .into_iter()
+            .map(|child| child.adopt_state())
+            .collect();
+        if states.is_empty() {
+            return state;
+        }
+
+        let state = if states
+            .iter()
+            .all(|v| matches!(v, ActionState::Completed | ActionState::Skipped))
+        {
+            ActionState::Completed
+        } else if states
+            .iter()
+            .any(|v| matches!(v, ActionState::Completed | ActionState::Progress))
+        {
+            ActionState::Progress
+        } else {
+            ActionState::Uncompleted
+        };
+        self.set_state(state);
+        state
+    }
+
+    /// The synopses of the actions under this one which aren't done, the sub-actions of one which is only partially
+    /// done, or this one if none of it is
+    fn unadopted(&self) -> Vec<String> {
+        match self.state() {
+            ActionState::Completed | ActionState::Skipped => vec![],
+            ActionState::Uncompleted => vec![self.tracing_synopsis()],
+            ActionState::Progress => {
+                let unadopted: Vec<String> = self
+                    .children()
+                    .into_iter()
+                    .flat_map(|child| child.unadopted())
+                    .collect();
+                if unadopted.is_empty() {
+                    vec![self.tracing_synopsis()]
+                } else {
+                    unadopted
+                }
+            },
+        }
+    }
+}
+
+impl SubAction for StatefulAction<Box<dyn Action>> {
+    fn state(&self) -> ActionState {
+        self.state
+    }
+    fn set_state(&mut self, state: ActionState) {
+        self.state = state;
+    }
+    fn typetag_name(&self) -> &'static str {
+        self.action.typetag_name()
+    }
+    fn tracing_synopsis(&self) -> String {
+        self.action.tracing_synopsis()
+    }
+    fn children(&self) -> Vec<&dyn SubAction> {
+        self.action.children()
+    }
+    fn children_mut(&mut self) -> Vec<&mut dyn SubAction> {
+        self.action.children_mut()
+    }
+}
+
+impl<A> SubAction for StatefulAction<A>
+where
+    A: Action,
+{
+    fn state(&self) -> ActionState {
+        self.state
+    }
+    fn set_state(&mut self, state: ActionState) {
+        self.state = state;
+    }
+    fn typetag_name(&self) -> &'static str {
+        self.action.typetag_name()
+    }
+    fn tracing_synopsis(&self) -> String {
+        self.action.tracing_synopsis()
+    }
+    fn children(&self) -> Vec<&dyn SubAction> {
+        self.action.children()
+    }
+    fn children_mut(&mut self) -> Vec<&mut dyn SubAction> {
+        self.action.children_mut()
+    }
+}
+
 /** The state of an [`Action`](crate::action::Action)
 */
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Copy)]
@@ -46,6 +46,10 @@ impl CommandExecute for NixInstallerCli {
         match subcommand {
             NixInstallerSubcommand::Plan(plan) => plan.execute().await,
             NixInstallerSubcommand::SelfTest(self_test) => self_test.execute().await,
+            NixInstallerSubcommand::Doctor(doctor) => doctor.execute().await,
+            #[cfg(target_os = "linux")]
+            NixInstallerSubcommand::Takeover(takeover) => takeover.execute().await,
+            NixInstallerSubcommand::Adopt(adopt) => adopt.execute().await,
             NixInstallerSubcommand::Install(install) => install.execute().await,
             NixInstallerSubcommand::Repair(restore_shell) => restore_shell.execute().await,
             NixInstallerSubcommand::Uninstall(revert) => revert.execute().await,
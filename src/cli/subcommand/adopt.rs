@@ -0,0 +1,164 @@
+use std::process::ExitCode;
+
+use clap::{ArgAction, Parser};
+use color_eyre::eyre::eyre;
+use owo_colors::OwoColorize;
+
+use crate::{
+    cli::{
+        ensure_root,
+        interaction::{self, PromptChoice},
+        CommandExecute,
+    },
+    error::HasExpectedErrors,
+    settings::CommonSettings,
+    BuiltinPlanner,
+};
+
+/**
+Record an existing Nix install, made by other means, in a receipt, so `uninstall` can remove it later
+
+The planner is picked and configured like with `install`, then each of its actions checks whether what it would do is
+already done. Only those are recorded as done, the rest is reported and left alone by `uninstall`.
+*/
+#[derive(Debug, Parser)]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct Adopt {
+    #[clap(
+        long,
+        env = "NIX_INSTALLER_NO_CONFIRM",
+        action(ArgAction::SetTrue),
+        default_value = "false",
+        global = true
+    )]
+    pub no_confirm: bool,
+
+    #[clap(
+        long,
+        env = "NIX_INSTALLER_EXPLAIN",
+        action(ArgAction::SetTrue),
+        default_value = "false",
+        global = true
+    )]
+    pub explain: bool,
+
+    #[clap(flatten)]
+    pub settings: CommonSettings,
+
+    #[clap(subcommand)]
+    pub planner: Option<BuiltinPlanner>,
+}
+
+#[async_trait::async_trait]
+impl CommandExecute for Adopt {
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn execute(self) -> eyre::Result<ExitCode> {
+        let Self {
+            no_confirm,
+            explain,
+            settings,
+            planner,
+        } = self;
+
+        let planner = match planner {
+            Some(planner) => planner,
+            None => BuiltinPlanner::from_common_settings(settings).await?,
+        };
+        if planner.clone().boxed().requires_root() {
+            ensure_root()?;
+        }
+
+        let receipt = planner.clone().boxed().receipt_location();
+        if receipt.exists() {
+            eprintln!(
+                "{}",
+                format!(
+                    "Found a receipt in `{}`, this install is already recorded",
+                    receipt.display()
+                )
+                .red()
+            );
+            return Ok(ExitCode::FAILURE);
+        }
+
+        let plan = match planner.adopt().await {
+            Ok(plan) => plan,
+            Err(err) => {
+                if let Some(expected) = err.expected() {
+                    eprintln!("{}", expected.red());
+                    return Ok(ExitCode::FAILURE);
+                }
+                return Err(err)?;
+            },
+        };
+
+        let unadopted = plan.unadopted();
+        let report = if unadopted.is_empty() {
+            "Found everything the planner would install.\n".to_string()
+        } else {
+            let lines = unadopted
+                .iter()
+                .map(|(synopsis, sub_actions)| {
+                    if sub_actions.is_empty() {
+                        format!("* {synopsis}")
+                    } else {
+                        let sub_actions = sub_actions
+                            .iter()
+                            .map(|v| format!("\n  * {v}"))
+                            .collect::<String>();
+                        format!("* {synopsis} (partially found), missing:{sub_actions}")
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            format!(
+                "\
+                Could not find these parts of the install, `uninstall` will leave them alone:\n\
+                {lines}\n\
+                "
+            )
+        };
+
+        if no_confirm {
+            print!("{report}");
+        } else {
+            let mut currently_explaining = explain;
+            loop {
+                let question = format!(
+                    "\
+                    {report}\n\
+                    A receipt will be written to `{}`, `uninstall` would then:\n\
+                    \n\
+                    {}\
+                    ",
+                    receipt.display(),
+                    plan.describe_uninstall(currently_explaining)
+                        .await
+                        .map_err(|e| eyre!(e))?,
+                );
+                match interaction::prompt(question, PromptChoice::Yes, currently_explaining).await?
+                {
+                    PromptChoice::Yes => break,
+                    PromptChoice::Explain => currently_explaining = true,
+                    PromptChoice::No => {
+                        interaction::clean_exit_with_message("Okay, didn't do anything! Bye!").await
+                    },
+                }
+            }
+        }
+
+        plan.write_receipt().await?;
+
+        println!(
+            "{}",
+            format!(
+                "The existing Nix install was recorded in `{}`",
+                receipt.display()
+            )
+            .green()
+            .bold()
+        );
+
+        Ok(ExitCode::SUCCESS)
+    }
+}
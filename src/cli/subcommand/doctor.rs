@@ -0,0 +1,64 @@
+use std::process::ExitCode;
+
+use clap::{ArgAction, Parser};
+use owo_colors::OwoColorize;
+
+use crate::{
+    cli::CommandExecute,
+    doctor::{doctor, FindingStatus},
+    settings::CommonSettings,
+};
+
+/// Check whether Nix can be installed on this host, reporting every problem found with how to fix it
+#[derive(Debug, Parser)]
+pub struct Doctor {
+    #[clap(flatten)]
+    pub settings: CommonSettings,
+
+    /// Print the findings as JSON, with the status, message and fixes of each check
+    #[clap(long, action(ArgAction::SetTrue), default_value = "false")]
+    pub json: bool,
+}
+
+#[async_trait::async_trait]
+impl CommandExecute for Doctor {
+    #[tracing::instrument(level = "debug", skip_all, fields())]
+    async fn execute(self) -> eyre::Result<ExitCode> {
+        let Self { settings, json } = self;
+
+        let findings = doctor(&settings).await;
+        let problems = findings
+            .iter()
+            .filter(|finding| finding.status == FindingStatus::Problem)
+            .count();
+
+        if json {
+            println!("{}", serde_json::to_string_pretty(&findings)?);
+        } else {
+            for finding in &findings {
+                let status = match finding.status {
+                    FindingStatus::Ok => "ok".green().to_string(),
+                    FindingStatus::Warning => "warning".yellow().to_string(),
+                    FindingStatus::Problem => "problem".red().to_string(),
+                    FindingStatus::Skipped => "skipped".dimmed().to_string(),
+                };
+                println!("{} [{status}] {}", finding.check.bold(), finding.message);
+                for fix in &finding.fixes {
+                    println!("    * {fix}");
+                }
+            }
+            if problems != 0 {
+                eprintln!(
+                    "{}",
+                    format!("Found {problems} problem(s) which would stop Nix installing").red()
+                );
+            }
+        }
+
+        Ok(if problems == 0 {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        })
+    }
+}
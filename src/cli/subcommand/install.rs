@@ -12,7 +12,7 @@ use crate::{
         signal_channel, CommandExecute,
     },
     error::HasExpectedErrors,
-    plan::RECEIPT_LOCATION,
+    plan::{DEFAULT_MAX_CONCURRENCY, RECEIPT_LOCATION},
     planner::Planner,
     settings::CommonSettings,
     BuiltinPlanner, InstallPlan, NixInstallerError,
@@ -61,6 +61,25 @@ pub struct Install {
     )]
     pub explain: bool,
 
+    /// Run every action one after another, instead of running independent actions concurrently
+    #[clap(
+        long,
+        env = "NIX_INSTALLER_SEQUENTIAL",
+        action(ArgAction::SetTrue),
+        default_value = "false",
+        global = true
+    )]
+    pub sequential: bool,
+
+    /// The maximum number of independent actions to run at once
+    #[clap(
+        long,
+        env = "NIX_INSTALLER_MAX_CONCURRENCY",
+        default_value_t = DEFAULT_MAX_CONCURRENCY,
+        global = true
+    )]
+    pub max_concurrency: usize,
+
     #[clap(env = "NIX_INSTALLER_PLAN")]
     pub plan: Option<PathBuf>,
 
@@ -78,19 +97,45 @@ impl CommandExecute for Install {
             planner,
             settings,
             explain,
+            sequential,
+            max_concurrency,
         } = self;
 
-        ensure_root()?;
+        let plan = match plan {
+            Some(plan_path) => {
+                let install_plan_string = tokio::fs::read_to_string(&plan_path)
+                    .await
+                    .wrap_err("Reading plan")?;
+                Some(serde_json::from_str::<InstallPlan>(&install_plan_string)?)
+            },
+            None => None,
+        };
+
+        let requires_root = match (&planner, &plan) {
+            (Some(planner), _) => planner.clone().boxed().requires_root(),
+            (None, Some(plan)) => plan.planner.requires_root(),
+            (None, None) => true,
+        };
+        if requires_root {
+            ensure_root()?;
+        }
 
-        let existing_receipt: Option<InstallPlan> = match Path::new(RECEIPT_LOCATION).exists() {
+        // Planners installing somewhere other than this machine keep their receipt there too
+        let receipt_path = match (&planner, &plan) {
+            (Some(planner), _) => planner.clone().boxed().receipt_location(),
+            (None, Some(plan)) => plan.receipt_location(),
+            (None, None) => PathBuf::from(RECEIPT_LOCATION),
+        };
+        let receipt_location = receipt_path.display();
+        let existing_receipt: Option<InstallPlan> = match receipt_path.exists() {
             true => {
                 tracing::trace!("Reading existing receipt");
-                let install_plan_string = tokio::fs::read_to_string(&RECEIPT_LOCATION)
+                let install_plan_string = tokio::fs::read_to_string(&receipt_path)
                     .await
                     .wrap_err("Reading plan")?;
                 Some(
                     serde_json::from_str(&install_plan_string).wrap_err_with(|| {
-                        format!("Unable to parse existing receipt `{RECEIPT_LOCATION}`, it may be from an incompatible version of `nix-installer`. Try running `/nix/nix-installer uninstall`, then installing again.")
+                        format!("Unable to parse existing receipt `{receipt_location}`, it may be from an incompatible version of `nix-installer`. Try running `/nix/nix-installer uninstall`, then installing again.")
                     })?,
                 )
             },
@@ -114,21 +159,21 @@ impl CommandExecute for Install {
                                 format!("\
                                     {e}\n\
                                     \n\
-                                    Found existing plan in `{RECEIPT_LOCATION}` which was created by a version incompatible `nix-installer`.\n\
+                                    Found existing plan in `{receipt_location}` which was created by a version incompatible `nix-installer`.\n\
                                     {EXISTING_INCOMPATIBLE_PLAN_GUIDANCE}\n\
                                 ").red()
                             );
                             return Ok(ExitCode::FAILURE)
                         }
                         if existing_receipt.planner.typetag_name() != chosen_planner.typetag_name() {
-                            eprintln!("{}", format!("Found existing plan in `{RECEIPT_LOCATION}` which used a different planner, try uninstalling the existing install with `{uninstall_command}`").red());
+                            eprintln!("{}", format!("Found existing plan in `{receipt_location}` which used a different planner, try uninstalling the existing install with `{uninstall_command}`").red());
                             return Ok(ExitCode::FAILURE)
                         }
                         if existing_receipt.planner.settings().map_err(|e| eyre!(e))? != chosen_planner.settings().map_err(|e| eyre!(e))? {
-                            eprintln!("{}", format!("Found existing plan in `{RECEIPT_LOCATION}` which used different planner settings, try uninstalling the existing install with `{uninstall_command}`").red());
+                            eprintln!("{}", format!("Found existing plan in `{receipt_location}` which used different planner settings, try uninstalling the existing install with `{uninstall_command}`").red());
                             return Ok(ExitCode::FAILURE)
                         }
-                        eprintln!("{}", format!("Found existing plan in `{RECEIPT_LOCATION}`, with the same settings, already completed. Try uninstalling (`{uninstall_command}`) and reinstalling if Nix isn't working").red());
+                        eprintln!("{}", format!("Found existing plan in `{receipt_location}`, with the same settings, already completed. Try uninstalling (`{uninstall_command}`) and reinstalling if Nix isn't working").red());
                         return Ok(ExitCode::SUCCESS)
                     },
                     None => {
@@ -146,12 +191,7 @@ impl CommandExecute for Install {
                     },
                 }
             },
-            (None, Some(plan_path)) => {
-                let install_plan_string = tokio::fs::read_to_string(&plan_path)
-                .await
-                .wrap_err("Reading plan")?;
-                serde_json::from_str(&install_plan_string)?
-            },
+            (None, Some(plan)) => plan,
             (None, None) => {
                 let builtin_planner = BuiltinPlanner::from_common_settings(settings.clone())
                     .await
@@ -165,22 +205,22 @@ impl CommandExecute for Install {
                                 format!("\
                                     {e}\n\
                                     \n\
-                                    Found existing plan in `{RECEIPT_LOCATION}` which was created by a version incompatible `nix-installer`.\n\
+                                    Found existing plan in `{receipt_location}` which was created by a version incompatible `nix-installer`.\n\
                                     {EXISTING_INCOMPATIBLE_PLAN_GUIDANCE}\n\
                                 ").red()
                             );
                             return Ok(ExitCode::FAILURE)
                         }
                         if existing_receipt.planner.typetag_name() != builtin_planner.typetag_name() {
-                            eprintln!("{}", format!("Found existing plan in `{RECEIPT_LOCATION}` which used a different planner, try uninstalling the existing install with `{uninstall_command}`").red());
+                            eprintln!("{}", format!("Found existing plan in `{receipt_location}` which used a different planner, try uninstalling the existing install with `{uninstall_command}`").red());
                             return Ok(ExitCode::FAILURE)
                         }
                         if existing_receipt.planner.settings().map_err(|e| eyre!(e))? != builtin_planner.settings().map_err(|e| eyre!(e))? {
-                            eprintln!("{}", format!("Found existing plan in `{RECEIPT_LOCATION}` which used different planner settings, try uninstalling the existing install with `{uninstall_command}`").red());
+                            eprintln!("{}", format!("Found existing plan in `{receipt_location}` which used different planner settings, try uninstalling the existing install with `{uninstall_command}`").red());
                             return Ok(ExitCode::FAILURE)
                         }
                         if existing_receipt.actions.iter().all(|v| v.state == ActionState::Completed) {
-                            eprintln!("{}", format!("Found existing plan in `{RECEIPT_LOCATION}`, with the same settings, already completed. Try uninstalling (`{uninstall_command}`) and reinstalling if Nix isn't working").yellow());
+                            eprintln!("{}", format!("Found existing plan in `{receipt_location}`, with the same settings, already completed. Try uninstalling (`{uninstall_command}`) and reinstalling if Nix isn't working").yellow());
                             return Ok(ExitCode::SUCCESS)
                         }
                         existing_receipt
@@ -203,6 +243,8 @@ impl CommandExecute for Install {
             (Some(_), Some(_)) => return Err(eyre!("`--plan` conflicts with passing a planner, a planner creates plans, so passing an existing plan doesn't make sense")),
         };
 
+        install_plan.max_concurrency(if sequential { 1 } else { max_concurrency });
+
         if let Err(err) = install_plan.pre_install_check().await {
             if let Some(expected) = err.expected() {
                 eprintln!("{}", expected.red());
@@ -238,7 +280,7 @@ impl CommandExecute for Install {
         match install_plan.install(rx1).await {
             Err(err) => {
                 // Attempt to copy self to the store if possible, but since the install failed, this might not work, that's ok.
-                copy_self_to_nix_dir().await.ok();
+                copy_self_to_nix_dir(&install_plan.nix_dir()).await.ok();
 
                 if !no_confirm {
                     let mut was_expected = false;
@@ -313,7 +355,7 @@ impl CommandExecute for Install {
                 }
             },
             Ok(_) => {
-                copy_self_to_nix_dir()
+                copy_self_to_nix_dir(&install_plan.nix_dir())
                     .await
                     .wrap_err("Copying `nix-installer` to `/nix/nix-installer`")?;
                 println!(
@@ -329,6 +371,9 @@ impl CommandExecute for Install {
                             ". /nix/var/nix/profiles/default/etc/profile.d/nix-daemon.sh".bold(),
                     },
                 );
+                if let Some(message) = install_plan.planner.post_install_message() {
+                    println!("{}\n", message.yellow());
+                }
             },
         }
 
@@ -337,9 +382,10 @@ impl CommandExecute for Install {
 }
 
 #[tracing::instrument(level = "debug")]
-async fn copy_self_to_nix_dir() -> Result<(), std::io::Error> {
+async fn copy_self_to_nix_dir(nix_dir: &Path) -> Result<(), std::io::Error> {
     let path = std::env::current_exe()?;
-    tokio::fs::copy(path, "/nix/nix-installer").await?;
-    tokio::fs::set_permissions("/nix/nix-installer", PermissionsExt::from_mode(0o0755)).await?;
+    let dest = nix_dir.join("nix-installer");
+    tokio::fs::copy(path, &dest).await?;
+    tokio::fs::set_permissions(&dest, PermissionsExt::from_mode(0o0755)).await?;
     Ok(())
 }
@@ -8,6 +8,14 @@ mod uninstall;
 use uninstall::Uninstall;
 mod self_test;
 use self_test::SelfTest;
+mod doctor;
+use doctor::Doctor;
+#[cfg(target_os = "linux")]
+mod takeover;
+#[cfg(target_os = "linux")]
+use takeover::Takeover;
+mod adopt;
+use adopt::Adopt;
 
 #[allow(clippy::large_enum_variant)]
 #[derive(Debug, clap::Subcommand)]
@@ -16,5 +24,9 @@ pub enum NixInstallerSubcommand {
     Repair(Repair),
     Uninstall(Uninstall),
     SelfTest(SelfTest),
+    Doctor(Doctor),
+    #[cfg(target_os = "linux")]
+    Takeover(Takeover),
+    Adopt(Adopt),
     Plan(Plan),
 }
@@ -1,7 +1,7 @@
 use std::process::ExitCode;
 
 use crate::{
-    action::common::ConfigureShellProfile,
+    action::common::{ConfigureShellProfile, ProfileScripts},
     cli::{ensure_root, CommandExecute},
     planner::{PlannerError, ShellProfileLocations},
 };
@@ -31,10 +31,13 @@ impl CommandExecute for Repair {
 
         ensure_root()?;
 
-        let mut reconfigure = ConfigureShellProfile::plan(ShellProfileLocations::default())
-            .await
-            .map_err(PlannerError::Action)?
-            .boxed();
+        let mut reconfigure = ConfigureShellProfile::plan(
+            ShellProfileLocations::default(),
+            ProfileScripts::default(),
+        )
+        .await
+        .map_err(PlannerError::Action)?
+        .boxed();
 
         if let Err(err) = reconfigure.try_execute().await {
             println!("{:#?}", err);
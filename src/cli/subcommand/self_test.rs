@@ -1,28 +1,117 @@
-use std::process::ExitCode;
+use std::{path::PathBuf, process::ExitCode};
 
-use clap::Parser;
+use clap::{ArgAction, Parser};
 
-use crate::{cli::CommandExecute, NixInstallerError};
+use crate::{
+    cli::{ensure_root, CommandExecute},
+    self_test::{run_checks, tarball_nix_version, Check, CheckOutcome, SelfTestOptions, TestUser},
+    InstallPlan, NixInstallerError,
+};
 
 /// Run a self test of Nix to ensure that an install is working
 #[derive(Debug, Parser)]
-pub struct SelfTest {}
+pub struct SelfTest {
+    /// Only run these checks, defaults to all of them
+    #[clap(long = "check", value_enum, value_delimiter = ',')]
+    pub checks: Vec<Check>,
+
+    /// Don't run these checks
+    #[clap(long = "skip", value_enum, value_delimiter = ',')]
+    pub skip: Vec<Check>,
+
+    /// Print the results as JSON, with the status, message and duration of each check
+    #[clap(long, action(ArgAction::SetTrue), default_value = "false")]
+    pub json: bool,
+
+    /// Run the checks as this user, through the daemon socket and profiles like they would, instead of as whoever runs `nix-installer`
+    #[clap(long, env = "NIX_INSTALLER_SELF_TEST_AS_USER")]
+    pub as_user: Option<String>,
+
+    /// The receipt of the install, to compare the version of `nix` with, defaults to `/nix/receipt.json`, or the receipt of a single user install by the current user
+    #[clap(long)]
+    pub receipt: Option<PathBuf>,
+}
 
 #[async_trait::async_trait]
 impl CommandExecute for SelfTest {
     #[tracing::instrument(level = "debug", skip_all, fields())]
     async fn execute(self) -> eyre::Result<ExitCode> {
-        crate::self_test::self_test()
+        let Self {
+            checks,
+            skip,
+            json,
+            as_user,
+            receipt,
+        } = self;
+
+        let user = match as_user {
+            Some(name) => {
+                // Only `root` can switch to another user
+                ensure_root()?;
+                Some(TestUser::from_name(&name).map_err(|e| NixInstallerError::SelfTest(vec![e]))?)
+            },
+            None => None,
+        };
+
+        let checks: Vec<Check> = if checks.is_empty() {
+            Check::all().to_vec()
+        } else {
+            checks
+        }
+        .into_iter()
+        .filter(|check| !skip.contains(check))
+        .collect();
+
+        // The receipt of a multi-user install may only be readable by `root`, the version check is skipped then
+        let receipt = receipt.unwrap_or_else(super::uninstall::default_receipt);
+        let expected_nix_version = tokio::fs::read_to_string(&receipt)
             .await
-            .map_err(NixInstallerError::SelfTest)?;
+            .ok()
+            .and_then(|receipt| serde_json::from_str::<InstallPlan>(&receipt).ok())
+            .and_then(|plan| tarball_nix_version(&plan));
+
+        let options = SelfTestOptions {
+            expected_nix_version,
+            user,
+        };
+        let reports = run_checks(&checks, &options).await;
 
-        tracing::info!(
-            shells = ?crate::self_test::Shell::discover()
+        if json {
+            println!("{}", serde_json::to_string_pretty(&reports)?);
+            let failed = reports
                 .iter()
-                .map(|v| v.executable())
-                .collect::<Vec<_>>(),
-            "Successfully tested Nix install in all discovered shells."
-        );
+                .any(|report| matches!(report.outcome, CheckOutcome::Failed(_)));
+            return Ok(if failed {
+                ExitCode::FAILURE
+            } else {
+                ExitCode::SUCCESS
+            });
+        }
+
+        let mut failures = vec![];
+        for report in reports {
+            let duration_ms = report.duration.as_millis();
+            match report.outcome {
+                CheckOutcome::Passed(message) => tracing::info!(
+                    duration_ms,
+                    "Check `{}` passed{}",
+                    report.check,
+                    message.map(|v| format!(": {v}")).unwrap_or_default()
+                ),
+                CheckOutcome::Skipped(reason) => {
+                    tracing::info!("Check `{}` skipped: {reason}", report.check)
+                },
+                CheckOutcome::Failed(errors) => {
+                    tracing::warn!(duration_ms, "Check `{}` failed", report.check);
+                    failures.extend(errors);
+                },
+            }
+        }
+        if !failures.is_empty() {
+            return Err(NixInstallerError::SelfTest(failures).into());
+        }
+
+        tracing::info!("Successfully tested Nix install with all selected checks.");
         Ok(ExitCode::SUCCESS)
     }
 }
@@ -0,0 +1,116 @@
+use std::{path::Path, process::ExitCode};
+
+use clap::{ArgAction, Parser};
+use color_eyre::eyre::eyre;
+use owo_colors::OwoColorize;
+
+use crate::{
+    cli::{ensure_root, interaction, interaction::PromptChoice, signal_channel, CommandExecute},
+    error::HasExpectedErrors,
+    plan::RECEIPT_LOCATION,
+    settings::CommonSettings,
+    takeover, NixInstallerError,
+};
+
+/// Remove a Nix install made by another installer, like the upstream install scripts, restoring the shell profiles they backed up
+#[derive(Debug, Parser)]
+pub struct Takeover {
+    #[clap(
+        long,
+        env = "NIX_INSTALLER_NO_CONFIRM",
+        action(ArgAction::SetTrue),
+        default_value = "false",
+        global = true
+    )]
+    pub no_confirm: bool,
+
+    #[clap(
+        long,
+        env = "NIX_INSTALLER_EXPLAIN",
+        action(ArgAction::SetTrue),
+        default_value = "false",
+        global = true
+    )]
+    pub explain: bool,
+
+    #[clap(flatten)]
+    pub settings: CommonSettings,
+}
+
+#[async_trait::async_trait]
+impl CommandExecute for Takeover {
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn execute(self) -> eyre::Result<ExitCode> {
+        let Self {
+            no_confirm,
+            explain,
+            settings,
+        } = self;
+
+        ensure_root()?;
+
+        if Path::new(RECEIPT_LOCATION).exists() {
+            eprintln!(
+                "{}",
+                format!("Found a receipt in `{RECEIPT_LOCATION}`, this install was made by `nix-installer`, uninstall it with `/nix/nix-installer uninstall`").red()
+            );
+            return Ok(ExitCode::FAILURE);
+        }
+
+        let mut plan = takeover::plan(settings).await?;
+        if plan.actions.is_empty() {
+            println!("Found nothing left by another Nix installer");
+            return Ok(ExitCode::SUCCESS);
+        }
+
+        if !no_confirm {
+            let mut currently_explaining = explain;
+            loop {
+                match interaction::prompt(
+                    plan.describe_uninstall(currently_explaining)
+                        .await
+                        .map_err(|e| eyre!(e))?,
+                    PromptChoice::Yes,
+                    currently_explaining,
+                )
+                .await?
+                {
+                    PromptChoice::Yes => break,
+                    PromptChoice::Explain => currently_explaining = true,
+                    PromptChoice::No => {
+                        interaction::clean_exit_with_message("Okay, didn't do anything! Bye!").await
+                    },
+                }
+            }
+        }
+
+        let (_tx, rx) = signal_channel().await?;
+
+        match plan.uninstall(rx).await {
+            Err(err @ NixInstallerError::ActionRevert(_)) => {
+                tracing::error!("Removal complete, some errors encountered");
+                return Err(err)?;
+            },
+            Err(err) => {
+                if let Some(expected) = err.expected() {
+                    eprintln!("{}", expected.red());
+                    return Ok(ExitCode::FAILURE);
+                }
+                return Err(err)?;
+            },
+            Ok(()) => (),
+        }
+
+        println!(
+            "\
+            {success}\n\
+            Install Nix again with `nix-installer install`\n\
+            ",
+            success = "The other Nix install was removed successfully!"
+                .green()
+                .bold(),
+        );
+
+        Ok(ExitCode::SUCCESS)
+    }
+}
@@ -5,10 +5,10 @@ use std::{
 };
 
 use crate::{
-    cli::{ensure_root, interaction::PromptChoice, signal_channel},
+    cli::{ensure_root, interaction::PromptChoice, is_root, signal_channel},
     error::HasExpectedErrors,
-    plan::{current_version, RECEIPT_LOCATION},
-    InstallPlan, NixInstallerError,
+    plan::{current_version, DEFAULT_MAX_CONCURRENCY, RECEIPT_LOCATION},
+    InstallPlan, NixInstallerError, UninstallSelection,
 };
 use clap::{ArgAction, Parser};
 use color_eyre::eyre::{eyre, WrapErr};
@@ -38,8 +38,44 @@ pub struct Uninstall {
     )]
     pub explain: bool,
 
-    #[clap(default_value = RECEIPT_LOCATION)]
-    pub receipt: PathBuf,
+    /// Run every action one after another, instead of running independent actions concurrently
+    #[clap(
+        long,
+        env = "NIX_INSTALLER_SEQUENTIAL",
+        action(ArgAction::SetTrue),
+        default_value = "false",
+        global = true
+    )]
+    pub sequential: bool,
+
+    /// The maximum number of independent actions to run at once
+    #[clap(
+        long,
+        env = "NIX_INSTALLER_MAX_CONCURRENCY",
+        default_value_t = DEFAULT_MAX_CONCURRENCY,
+        global = true
+    )]
+    pub max_concurrency: usize,
+
+    /// Leave the actions with this tag installed, the receipt keeps them for a later uninstall
+    #[clap(long, value_delimiter = ',', action(ArgAction::Append))]
+    pub keep: Vec<String>,
+
+    /// Only uninstall the actions with this tag, the receipt keeps the others for a later uninstall
+    #[clap(
+        long,
+        value_delimiter = ',',
+        action(ArgAction::Append),
+        conflicts_with_all = ["keep", "keep_store"]
+    )]
+    pub only: Vec<String>,
+
+    /// Leave `/nix` and the store in place to install over, like `--keep` with the actions placing them
+    #[clap(long, action(ArgAction::SetTrue), default_value = "false")]
+    pub keep_store: bool,
+
+    /// The receipt of the install, defaults to `/nix/receipt.json`, or the receipt of a single user install by the current user
+    pub receipt: Option<PathBuf>,
 }
 
 #[async_trait::async_trait]
@@ -50,9 +86,14 @@ impl CommandExecute for Uninstall {
             no_confirm,
             receipt,
             explain,
+            sequential,
+            max_concurrency,
+            keep,
+            only,
+            keep_store,
         } = self;
 
-        ensure_root()?;
+        let receipt = receipt.unwrap_or_else(default_receipt);
 
         if let Ok(current_dir) = std::env::current_dir() {
             let mut components = current_dir.components();
@@ -108,9 +149,17 @@ impl CommandExecute for Uninstall {
             }
         }
 
-        let install_receipt_string = tokio::fs::read_to_string(receipt)
-            .await
-            .wrap_err("Reading receipt")?;
+        let install_receipt_string = match tokio::fs::read_to_string(&receipt).await {
+            Ok(install_receipt_string) => install_receipt_string,
+            // The receipt of a multi-user install may only be readable by `root`
+            Err(_) if !is_root() => {
+                ensure_root()?;
+                tokio::fs::read_to_string(&receipt)
+                    .await
+                    .wrap_err("Reading receipt")?
+            },
+            Err(e) => return Err(e).wrap_err("Reading receipt"),
+        };
 
         let mut plan: InstallPlan = match serde_json::from_str(&install_receipt_string) {
             Ok(plan) => plan,
@@ -138,6 +187,10 @@ impl CommandExecute for Uninstall {
             },
         };
 
+        if plan.planner.requires_root() {
+            ensure_root()?;
+        }
+
         if let Err(e) = plan.check_compatible() {
             let version = plan.version;
             eprintln!(
@@ -154,6 +207,48 @@ impl CommandExecute for Uninstall {
             return Ok(ExitCode::FAILURE);
         }
 
+        plan.max_concurrency(if sequential { 1 } else { max_concurrency });
+
+        let tags = plan.action_tags();
+        let unknown: Vec<&String> = keep
+            .iter()
+            .chain(&only)
+            .filter(|v| !tags.contains(&v.as_str()))
+            .collect();
+        if !unknown.is_empty() {
+            eprintln!(
+                "{}",
+                format!(
+                    "\
+                    The receipt has no actions tagged {}\n\
+                    It has: {}\
+                    ",
+                    unknown
+                        .iter()
+                        .map(|v| format!("`{v}`"))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    tags.iter()
+                        .map(|v| format!("`{v}`"))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                )
+                .red()
+            );
+            return Ok(ExitCode::FAILURE);
+        }
+        plan.uninstall_selection(if !only.is_empty() {
+            UninstallSelection::Only(only)
+        } else if keep_store {
+            let mut keep = keep;
+            keep.extend(crate::plan::store_action_tags());
+            UninstallSelection::Keep(keep)
+        } else if !keep.is_empty() {
+            UninstallSelection::Keep(keep)
+        } else {
+            UninstallSelection::All
+        });
+
         if let Err(err) = plan.pre_uninstall_check().await {
             if let Some(expected) = err.expected() {
                 eprintln!("{}", expected.red());
@@ -211,3 +306,15 @@ impl CommandExecute for Uninstall {
         Ok(ExitCode::SUCCESS)
     }
 }
+
+pub(super) fn default_receipt() -> PathBuf {
+    let receipt = PathBuf::from(RECEIPT_LOCATION);
+    #[cfg(target_os = "linux")]
+    if !receipt.exists() {
+        let single_user_receipt = crate::planner::single_user::receipt_location();
+        if single_user_receipt.exists() {
+            return single_user_receipt;
+        }
+    }
+    receipt
+}
@@ -0,0 +1,778 @@
+/*! Diagnose a host before installing Nix on it
+
+The planners stop at the first pre-flight check which fails, [`doctor`] runs every check and reports all of them, each
+problem with the ways it could be fixed.
+*/
+
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use nix::{
+    sys::statvfs::{statvfs, FsFlags},
+    unistd::{Gid, Group, Uid, User},
+};
+use url::Url;
+
+use crate::{
+    action::base::revert_foreign_shell_profile::BACKUP_SUFFIX, plan::RECEIPT_LOCATION,
+    settings::CommonSettings, settings::NIX_DIR,
+};
+
+/// Below this much free space where `/nix` would be, installing is likely to fail
+const MIN_FREE_SPACE: u64 = 1024 * 1024 * 1024;
+/// Below this much free space where `/nix` would be, there won't be room for much more than Nix itself
+const LOW_FREE_SPACE: u64 = 8 * 1024 * 1024 * 1024;
+/// How long to wait for the proxy to accept a connection
+const PROXY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A check of [`doctor`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DoctorCheck {
+    Nixos,
+    Wsl1,
+    Init,
+    Selinux,
+    ExistingNix,
+    DiskSpace,
+    Mounts,
+    Ids,
+    Leftovers,
+    Proxy,
+}
+
+impl std::fmt::Display for DoctorCheck {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            DoctorCheck::Nixos => "nixos",
+            DoctorCheck::Wsl1 => "wsl1",
+            DoctorCheck::Init => "init",
+            DoctorCheck::Selinux => "selinux",
+            DoctorCheck::ExistingNix => "existing-nix",
+            DoctorCheck::DiskSpace => "disk-space",
+            DoctorCheck::Mounts => "mounts",
+            DoctorCheck::Ids => "ids",
+            DoctorCheck::Leftovers => "leftovers",
+            DoctorCheck::Proxy => "proxy",
+        };
+        write!(f, "{name}")
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FindingStatus {
+    Ok,
+    /// The install can go ahead, but may not work as expected
+    Warning,
+    /// The install will fail, or shouldn't be done
+    Problem,
+    /// The check doesn't apply to this host
+    Skipped,
+}
+
+/// What a [`DoctorCheck`] found
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct Finding {
+    pub check: DoctorCheck,
+    pub status: FindingStatus,
+    pub message: String,
+    /// Ways to fix a warning or problem
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fixes: Vec<String>,
+}
+
+impl Finding {
+    fn ok(check: DoctorCheck, message: impl Into<String>) -> Self {
+        Self {
+            check,
+            status: FindingStatus::Ok,
+            message: message.into(),
+            fixes: vec![],
+        }
+    }
+    fn skipped(check: DoctorCheck, message: impl Into<String>) -> Self {
+        Self {
+            check,
+            status: FindingStatus::Skipped,
+            message: message.into(),
+            fixes: vec![],
+        }
+    }
+    fn warning(check: DoctorCheck, message: impl Into<String>, fixes: Vec<String>) -> Self {
+        Self {
+            check,
+            status: FindingStatus::Warning,
+            message: message.into(),
+            fixes,
+        }
+    }
+    fn problem(check: DoctorCheck, message: impl Into<String>, fixes: Vec<String>) -> Self {
+        Self {
+            check,
+            status: FindingStatus::Problem,
+            message: message.into(),
+            fixes,
+        }
+    }
+}
+
+/// Run every check against the host, for an install with `settings`
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn doctor(settings: &CommonSettings) -> Vec<Finding> {
+    vec![
+        nixos(),
+        wsl1(),
+        init(),
+        selinux().await,
+        existing_nix().await,
+        disk_space(),
+        mounts(),
+        ids(settings, &HostAccounts),
+        leftovers(settings, Path::new("/"), &HostAccounts),
+        proxy(settings).await,
+    ]
+}
+
+fn nixos() -> Finding {
+    let check = DoctorCheck::Nixos;
+    #[cfg(target_os = "linux")]
+    if let Err(err) = crate::planner::linux::check_not_nixos() {
+        return Finding::problem(
+            check,
+            err.to_string(),
+            vec![
+                "Configure Nix with the `nix` options of the NixOS configuration instead"
+                    .to_string(),
+            ],
+        );
+    }
+    Finding::ok(check, "This is not NixOS")
+}
+
+fn wsl1() -> Finding {
+    let check = DoctorCheck::Wsl1;
+    #[cfg(target_os = "linux")]
+    if let Err(err) = crate::planner::linux::check_not_wsl1() {
+        return Finding::problem(
+            check,
+            err.to_string(),
+            vec![
+                "Convert the distribution to WSL2 with `wsl.exe --set-version <distribution> 2`"
+                    .to_string(),
+            ],
+        );
+    }
+    Finding::ok(check, "This is not WSL1")
+}
+
+fn init() -> Finding {
+    let check = DoctorCheck::Init;
+    #[cfg(target_os = "linux")]
+    {
+        match crate::settings::linux_detect_init() {
+            Some(init) => Finding::ok(
+                check,
+                format!("{init} is running, the Nix daemon will be configured with it"),
+            ),
+            None => {
+                let mut fixes = vec![];
+                if std::env::var("WSL_DISTRO_NAME").is_ok() {
+                    fixes.push(
+                        "Let the `wsl2` planner enable systemd, with `nix-installer install wsl2`"
+                            .to_string(),
+                    );
+                }
+                fixes.push(
+                    "If the init system will be started later, pass `--init` with it and `--no-start-daemon`"
+                        .to_string(),
+                );
+                fixes.push(
+                    "For a `root`-only install without a daemon, pass `--init none`".to_string(),
+                );
+                Finding::warning(
+                    check,
+                    "None of systemd, OpenRC, runit, or s6 is running",
+                    fixes,
+                )
+            },
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    Finding::skipped(check, "The Nix daemon is started by launchd")
+}
+
+async fn selinux() -> Finding {
+    let check = DoctorCheck::Selinux;
+    #[cfg(target_os = "linux")]
+    {
+        match crate::planner::linux::detect_selinux().await {
+            Ok(false) => Finding::ok(check, "SELinux is not enabled"),
+            Ok(true) => {
+                let mode = match std::fs::read_to_string("/sys/fs/selinux/enforce") {
+                    Ok(enforce) if enforce.trim() == "1" => "enforcing",
+                    Ok(_) => "permissive",
+                    Err(_) => "in an unknown mode",
+                };
+                Finding::ok(
+                    check,
+                    format!("SELinux is {mode}, a policy module for Nix will be installed"),
+                )
+            },
+            Err(err) => Finding::problem(
+                check,
+                err.to_string(),
+                vec![
+                    "Install the SELinux tools, like `policycoreutils` on Fedora or RHEL"
+                        .to_string(),
+                ],
+            ),
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    Finding::skipped(check, "SELinux is only on Linux")
+}
+
+async fn existing_nix() -> Finding {
+    let check = DoctorCheck::ExistingNix;
+    if Path::new(RECEIPT_LOCATION).exists() {
+        return Finding::problem(
+            check,
+            format!("Nix was already installed by `nix-installer`, it left `{RECEIPT_LOCATION}`"),
+            vec![
+                "Fix the install with `nix-installer repair`".to_string(),
+                "Remove it with `/nix/nix-installer uninstall`".to_string(),
+            ],
+        );
+    }
+    #[cfg(target_os = "linux")]
+    if let Err(err) = crate::planner::linux::check_nix_not_already_installed().await {
+        return Finding::problem(
+            check,
+            err.to_string(),
+            vec!["Uninstall it the way it was installed, see https://nixos.org/manual/nix/stable/installation/uninstall".to_string()],
+        );
+    }
+    Finding::ok(check, "Nix is not installed")
+}
+
+/// On Mac, the volume of user data, in the APFS container the volume for `/nix` will be created in
+const MACOS_DATA_VOLUME: &str = "/System/Volumes/Data";
+
+/// The existing directory `/nix` is or would be on, `None` if it will be a volume of its own
+fn nix_filesystem() -> Option<PathBuf> {
+    let nix_dir = Path::new(NIX_DIR);
+    // On Mac, `/` is the sealed, read-only system volume, and `/nix` becomes a new APFS volume instead
+    if cfg!(target_os = "macos") && !nix_dir.exists() {
+        return None;
+    }
+    Some(
+        nix_dir
+            .ancestors()
+            .find(|v| v.exists())
+            .unwrap_or(Path::new("/"))
+            .to_path_buf(),
+    )
+}
+
+fn disk_space() -> Finding {
+    let check = DoctorCheck::DiskSpace;
+    // The volumes of an APFS container share its free space
+    let path = nix_filesystem().unwrap_or_else(|| PathBuf::from(MACOS_DATA_VOLUME));
+    let stat = match statvfs(&path) {
+        Ok(stat) => stat,
+        Err(err) => {
+            return Finding::warning(
+                check,
+                format!(
+                    "Could not get the free space on `{}`: {err}",
+                    path.display()
+                ),
+                vec![],
+            )
+        },
+    };
+    #[allow(clippy::unnecessary_cast)] // The types differ between platforms
+    let free = stat.blocks_available() as u64 * stat.fragment_size() as u64;
+    let message = format!("{} free on `{}`", human_bytes(free), path.display());
+    let fixes = vec![
+        format!("Free up space on `{}`", path.display()),
+        #[cfg(target_os = "linux")]
+        "Keep the store on a larger filesystem with `--store-location` or `--store-image`"
+            .to_string(),
+    ];
+    if free < MIN_FREE_SPACE {
+        Finding::problem(check, message, fixes)
+    } else if free < LOW_FREE_SPACE {
+        Finding::warning(check, message, fixes)
+    } else {
+        Finding::ok(check, message)
+    }
+}
+
+fn human_bytes(bytes: u64) -> String {
+    const GIB: u64 = 1024 * 1024 * 1024;
+    const MIB: u64 = 1024 * 1024;
+    if bytes >= GIB {
+        format!("{:.1} GiB", bytes as f64 / GIB as f64)
+    } else {
+        format!("{} MiB", bytes / MIB)
+    }
+}
+
+fn mounts() -> Finding {
+    let check = DoctorCheck::Mounts;
+    let Some(path) = nix_filesystem() else {
+        return Finding::skipped(
+            check,
+            "`/nix` will be a new APFS volume, mounted by `nix-installer`",
+        );
+    };
+    let flags = match statvfs(&path) {
+        Ok(stat) => stat.flags(),
+        Err(err) => {
+            return Finding::warning(
+                check,
+                format!(
+                    "Could not get the mount options of `{}`: {err}",
+                    path.display()
+                ),
+                vec![],
+            )
+        },
+    };
+
+    if flags.contains(FsFlags::ST_NOEXEC) {
+        return Finding::problem(
+            check,
+            format!(
+                "`{}` is mounted `noexec`, nothing in the store could run",
+                path.display()
+            ),
+            vec![
+                format!("Remount `{}` without `noexec`", path.display()),
+                #[cfg(target_os = "linux")]
+                "Keep the store on another filesystem with `--store-location` or `--store-image`"
+                    .to_string(),
+            ],
+        );
+    }
+    if flags.contains(FsFlags::ST_RDONLY) {
+        return Finding::warning(
+            check,
+            format!("`{}` is mounted read-only", path.display()),
+            vec![
+                "On an immutable distribution, let `nix-installer install` pick its planner, which mounts `/nix` from a writable location".to_string(),
+            ],
+        );
+    }
+    if flags.contains(FsFlags::ST_NOSUID) {
+        return Finding::warning(
+            check,
+            format!(
+                "`{}` is mounted `nosuid`, setuid programs from the store won't work",
+                path.display()
+            ),
+            vec![format!("Remount `{}` without `nosuid`", path.display())],
+        );
+    }
+    Finding::ok(
+        check,
+        format!("`{}` allows running programs", path.display()),
+    )
+}
+
+/// The users and groups of the host, so the checks of them can be tried with made up ones
+trait Accounts {
+    fn group_name(&self, gid: Gid) -> Option<String>;
+    fn group_id(&self, name: &str) -> Option<Gid>;
+    fn user_name(&self, uid: Uid) -> Option<String>;
+    fn user_id(&self, name: &str) -> Option<Uid>;
+}
+
+/// The users and groups in the account database of the host
+struct HostAccounts;
+
+impl Accounts for HostAccounts {
+    fn group_name(&self, gid: Gid) -> Option<String> {
+        Group::from_gid(gid).ok().flatten().map(|group| group.name)
+    }
+    fn group_id(&self, name: &str) -> Option<Gid> {
+        Group::from_name(name).ok().flatten().map(|group| group.gid)
+    }
+    fn user_name(&self, uid: Uid) -> Option<String> {
+        User::from_uid(uid).ok().flatten().map(|user| user.name)
+    }
+    fn user_id(&self, name: &str) -> Option<Uid> {
+        User::from_name(name).ok().flatten().map(|user| user.uid)
+    }
+}
+
+/// The build users and group `settings` would create, and the users and groups already using their names or IDs
+fn ids(settings: &CommonSettings, accounts: &impl Accounts) -> Finding {
+    let check = DoctorCheck::Ids;
+    let mut collisions = vec![];
+
+    let group_name = &settings.nix_build_group_name;
+    let gid = Gid::from_raw(settings.nix_build_group_id);
+    if let Some(name) = accounts.group_name(gid) {
+        if &name != group_name {
+            collisions.push(format!("GID {gid} is taken by the group `{name}`"));
+        }
+    }
+    if let Some(existing_gid) = accounts.group_id(group_name) {
+        if existing_gid != gid {
+            collisions.push(format!(
+                "The group `{group_name}` already has GID {existing_gid}"
+            ));
+        }
+    }
+
+    for index in 1..=settings.nix_build_user_count {
+        let name = format!("{}{index}", settings.nix_build_user_prefix);
+        let uid = Uid::from_raw(settings.nix_build_user_id_base + index);
+        if let Some(existing_name) = accounts.user_name(uid) {
+            if existing_name != name {
+                collisions.push(format!("UID {uid} is taken by the user `{existing_name}`"));
+            }
+        }
+        if let Some(existing_uid) = accounts.user_id(&name) {
+            if existing_uid != uid {
+                collisions.push(format!("The user `{name}` already has UID {existing_uid}"));
+            }
+        }
+    }
+
+    if collisions.is_empty() {
+        Finding::ok(
+            check,
+            format!(
+                "GID {gid} and UIDs {} to {} are free",
+                settings.nix_build_user_id_base + 1,
+                settings.nix_build_user_id_base + settings.nix_build_user_count
+            ),
+        )
+    } else {
+        Finding::problem(
+            check,
+            collisions.join("\n"),
+            vec![
+                "Pick a free GID with `--nix-build-group-id`".to_string(),
+                "Pick a free range of UIDs with `--nix-build-user-id-base`".to_string(),
+            ],
+        )
+    }
+}
+
+/// Files under `root` and build users left behind by a previous Nix install which wasn't cleanly removed
+fn leftovers(settings: &CommonSettings, root: &Path, accounts: &impl Accounts) -> Finding {
+    let check = DoctorCheck::Leftovers;
+    let mut found = vec![];
+    let mut fixes = vec![];
+
+    // The backups the Nix install scripts make of the shell profiles they edit
+    for dir in ["etc", "etc/zsh"] {
+        let Ok(entries) = std::fs::read_dir(root.join(dir)) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Some(original) = path.to_str().and_then(|v| v.strip_suffix(BACKUP_SUFFIX)) else {
+                continue;
+            };
+            found.push(format!("`{}`", path.display()));
+            fixes.push(format!(
+                "Check `{original}` no longer mentions Nix, then restore it with `sudo mv {} {original}`",
+                path.display()
+            ));
+        }
+    }
+
+    // Without `/nix`, anything else of Nix is stale
+    if !root.join(NIX_DIR.trim_start_matches('/')).exists() {
+        for path in [
+            "/etc/nix/nix.conf",
+            "/etc/profile.d/nix.sh",
+            "/etc/systemd/system/nix-daemon.service",
+            "/etc/systemd/system/nix-daemon.socket",
+            "/Library/LaunchDaemons/org.nixos.nix-daemon.plist",
+        ] {
+            if root.join(path.trim_start_matches('/')).exists() {
+                found.push(format!("`{path}`"));
+                fixes.push(format!("Remove `{path}`"));
+            }
+        }
+
+        let group_name = &settings.nix_build_group_name;
+        if accounts.group_id(group_name).is_some() {
+            found.push(format!("the group `{group_name}`"));
+            #[cfg(target_os = "linux")]
+            fixes.push(format!(
+                "Remove the group with `sudo groupdel {group_name}`"
+            ));
+            #[cfg(target_os = "macos")]
+            fixes.push(format!(
+                "Remove the group with `sudo dscl . -delete /Groups/{group_name}`"
+            ));
+        }
+        let users: Vec<_> = (1..=settings.nix_build_user_count)
+            .map(|index| format!("{}{index}", settings.nix_build_user_prefix))
+            .filter(|name| accounts.user_id(name).is_some())
+            .collect();
+        if !users.is_empty() {
+            found.push(format!("{} build users like `{}`", users.len(), users[0]));
+            #[cfg(target_os = "linux")]
+            fixes.push(format!(
+                "Remove the build users with `for user in {}; do sudo userdel $user; done`",
+                users.join(" ")
+            ));
+            #[cfg(target_os = "macos")]
+            fixes.push(format!(
+                "Remove the build users with `for user in {}; do sudo dscl . -delete /Users/$user; done`",
+                users.join(" ")
+            ));
+        }
+    }
+
+    if found.is_empty() {
+        Finding::ok(check, "Nothing was left by a previous install")
+    } else {
+        // Everything at once, through a plan which can be reviewed first
+        #[cfg(target_os = "linux")]
+        fixes.insert(
+            0,
+            "Remove all of it, and the Nix setup in the shell profiles, with `nix-installer takeover`"
+                .to_string(),
+        );
+        Finding::warning(
+            check,
+            format!("A previous install left {}", found.join(", ")),
+            fixes,
+        )
+    }
+}
+
+/// Connect to the proxy `settings` or the environment set, without sending anything through it
+async fn proxy(settings: &CommonSettings) -> Finding {
+    let check = DoctorCheck::Proxy;
+    let proxy = match &settings.proxy {
+        Some(proxy) => proxy.clone(),
+        None => {
+            let from_env = ["https_proxy", "HTTPS_PROXY", "all_proxy", "ALL_PROXY"]
+                .iter()
+                .find_map(|v| std::env::var(v).ok())
+                .filter(|v| !v.is_empty());
+            match from_env.map(|v| Url::parse(&v)) {
+                Some(Ok(proxy)) => proxy,
+                Some(Err(err)) => {
+                    return Finding::problem(
+                        check,
+                        format!("The proxy in the environment is not a valid URL: {err}"),
+                        vec![
+                            "Set `https_proxy` to a URL like `http://proxy.example.com:3128`"
+                                .to_string(),
+                        ],
+                    )
+                },
+                None => return Finding::skipped(check, "No proxy is set"),
+            }
+        },
+    };
+
+    let (Some(host), Some(port)) = (proxy.host_str(), proxy.port_or_known_default()) else {
+        return Finding::problem(
+            check,
+            format!("The proxy `{proxy}` has no host or port"),
+            vec!["Pass a proxy like `--proxy http://proxy.example.com:3128`".to_string()],
+        );
+    };
+    let address = format!("{host}:{port}");
+    let fixes = vec![
+        format!("Check `{address}` is the right proxy, and reachable from this host"),
+        "If no proxy is needed, unset `https_proxy` and `all_proxy`".to_string(),
+    ];
+    let addresses: Vec<_> = match tokio::net::lookup_host(&address).await {
+        Ok(addresses) => addresses.collect(),
+        Err(err) => {
+            return Finding::problem(
+                check,
+                format!("Could not resolve the proxy `{address}`: {err}"),
+                fixes,
+            )
+        },
+    };
+    match tokio::time::timeout(
+        PROXY_TIMEOUT,
+        tokio::net::TcpStream::connect(&addresses[..]),
+    )
+    .await
+    {
+        Ok(Ok(_)) => Finding::ok(check, format!("The proxy `{address}` accepts connections")),
+        Ok(Err(err)) => Finding::problem(
+            check,
+            format!("Could not connect to the proxy `{address}`: {err}"),
+            fixes,
+        ),
+        Err(_) => Finding::problem(
+            check,
+            format!(
+                "The proxy `{address}` did not accept a connection within {} seconds",
+                PROXY_TIMEOUT.as_secs()
+            ),
+            fixes,
+        ),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn findings_as_json() -> eyre::Result<()> {
+        let finding = Finding::problem(
+            DoctorCheck::DiskSpace,
+            "12 MiB free on `/`",
+            vec!["Free up space on `/`".to_string()],
+        );
+        assert_eq!(
+            serde_json::to_value(&finding)?,
+            serde_json::json!({
+                "check": "disk-space",
+                "status": "problem",
+                "message": "12 MiB free on `/`",
+                "fixes": ["Free up space on `/`"],
+            })
+        );
+        assert_eq!(
+            serde_json::to_value(Finding::ok(DoctorCheck::Nixos, "This is not NixOS"))?,
+            serde_json::json!({
+                "check": "nixos",
+                "status": "ok",
+                "message": "This is not NixOS",
+            })
+        );
+        Ok(())
+    }
+
+    /// Made up users and groups, as `(name, id)`
+    #[derive(Default)]
+    struct FakeAccounts {
+        users: Vec<(&'static str, u32)>,
+        groups: Vec<(&'static str, u32)>,
+    }
+
+    impl Accounts for FakeAccounts {
+        fn group_name(&self, gid: Gid) -> Option<String> {
+            self.groups
+                .iter()
+                .find(|(_, id)| *id == gid.as_raw())
+                .map(|(name, _)| name.to_string())
+        }
+        fn group_id(&self, name: &str) -> Option<Gid> {
+            self.groups
+                .iter()
+                .find(|(v, _)| *v == name)
+                .map(|(_, id)| Gid::from_raw(*id))
+        }
+        fn user_name(&self, uid: Uid) -> Option<String> {
+            self.users
+                .iter()
+                .find(|(_, id)| *id == uid.as_raw())
+                .map(|(name, _)| name.to_string())
+        }
+        fn user_id(&self, name: &str) -> Option<Uid> {
+            self.users
+                .iter()
+                .find(|(v, _)| *v == name)
+                .map(|(_, id)| Uid::from_raw(*id))
+        }
+    }
+
+    async fn settings() -> eyre::Result<CommonSettings> {
+        let mut settings = CommonSettings::default().await?;
+        settings.nix_build_group_name = "nixbld".to_string();
+        settings.nix_build_group_id = 30_000;
+        settings.nix_build_user_prefix = "nixbld".to_string();
+        settings.nix_build_user_id_base = 30_000;
+        settings.nix_build_user_count = 2;
+        Ok(settings)
+    }
+
+    #[tokio::test]
+    async fn finds_taken_ids() -> eyre::Result<()> {
+        let settings = settings().await?;
+
+        let free = ids(&settings, &FakeAccounts::default());
+        assert_eq!(free.status, FindingStatus::Ok);
+        assert_eq!(free.message, "GID 30000 and UIDs 30001 to 30002 are free");
+
+        // The users and group of a previous install are no collision
+        let previous = FakeAccounts {
+            users: vec![("nixbld1", 30_001), ("nixbld2", 30_002)],
+            groups: vec![("nixbld", 30_000)],
+        };
+        assert_eq!(ids(&settings, &previous).status, FindingStatus::Ok);
+
+        let taken = FakeAccounts {
+            users: vec![("alice", 30_001), ("nixbld2", 1000)],
+            groups: vec![("docker", 30_000)],
+        };
+        let finding = ids(&settings, &taken);
+        assert_eq!(finding.status, FindingStatus::Problem);
+        assert_eq!(
+            finding.message,
+            [
+                "GID 30000 is taken by the group `docker`",
+                "UID 30001 is taken by the user `alice`",
+                "The user `nixbld2` already has UID 1000",
+            ]
+            .join("\n")
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn finds_leftovers() -> eyre::Result<()> {
+        let settings = settings().await?;
+        let root = tempfile::tempdir()?;
+
+        let clean = leftovers(&settings, root.path(), &FakeAccounts::default());
+        assert_eq!(clean.status, FindingStatus::Ok);
+
+        std::fs::create_dir_all(root.path().join("etc/nix"))?;
+        std::fs::write(root.path().join("etc/nix/nix.conf"), "")?;
+        std::fs::write(root.path().join(format!("etc/bashrc{BACKUP_SUFFIX}")), "")?;
+        let accounts = FakeAccounts {
+            users: vec![("nixbld1", 30_001), ("nixbld2", 30_002)],
+            groups: vec![("nixbld", 30_000)],
+        };
+        let finding = leftovers(&settings, root.path(), &accounts);
+        assert_eq!(finding.status, FindingStatus::Warning);
+        assert_eq!(
+            finding.message,
+            format!(
+                "A previous install left `{}`, `/etc/nix/nix.conf`, the group `nixbld`, 2 build users like `nixbld1`",
+                root.path().join(format!("etc/bashrc{BACKUP_SUFFIX}")).display()
+            )
+        );
+        #[cfg(target_os = "linux")]
+        assert!(finding.fixes[0].contains("nix-installer takeover"));
+
+        // With `/nix` still there, only the backups are leftovers
+        std::fs::create_dir(root.path().join("nix"))?;
+        let finding = leftovers(&settings, root.path(), &accounts);
+        assert_eq!(
+            finding.message,
+            format!(
+                "A previous install left `{}`",
+                root.path()
+                    .join(format!("etc/bashrc{BACKUP_SUFFIX}"))
+                    .display()
+            )
+        );
+        Ok(())
+    }
+}
@@ -74,17 +74,20 @@ pub mod action;
 pub mod cli;
 #[cfg(feature = "diagnostics")]
 pub mod diagnostics;
+pub mod doctor;
 mod error;
 mod os;
 mod plan;
 pub mod planner;
 pub mod self_test;
 pub mod settings;
+#[cfg(target_os = "linux")]
+pub mod takeover;
 
 use std::{ffi::OsStr, path::Path, process::Output};
 
 pub use error::NixInstallerError;
-pub use plan::InstallPlan;
+pub use plan::{InstallPlan, UninstallSelection};
 use planner::BuiltinPlanner;
 
 use reqwest::Certificate;
@@ -0,0 +1,661 @@
+/*! Direct editing of `/etc/passwd`, `/etc/group`, `/etc/shadow`, and `/etc/gshadow`
+
+Used when none of the usual `shadow-utils` or `busybox` commands (`useradd`, `groupadd`, `gpasswd`, ...)
+are available, such as in distroless container images.
+
+Locking and writing follows what `shadow-utils` does:
+
+* A `<file>.lock` is created for each file by hard linking a file containing our PID, so other
+  `shadow-utils` tools will wait on us.
+* An `fcntl` write lock is held on `/etc/.pwd.lock`, the same as `lckpwdf(3)`.
+* Each file is written to `<file>+`, the original is kept as `<file>-`, then `<file>+` is
+  renamed over the original.
+*/
+
+use std::{
+    os::{
+        fd::AsRawFd,
+        unix::fs::{MetadataExt, OpenOptionsExt, PermissionsExt},
+    },
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use tokio::io::AsyncWriteExt;
+
+use crate::action::ActionErrorKind;
+
+const PASSWD: &str = "passwd";
+const GROUP: &str = "group";
+const SHADOW: &str = "shadow";
+const GSHADOW: &str = "gshadow";
+const PWD_LOCK: &str = ".pwd.lock";
+
+/// How many times to attempt taking a lock before giving up, waiting [`LOCK_RETRY_DELAY`] in between
+const LOCK_ATTEMPTS: usize = 15;
+const LOCK_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// The user and group database files under some root (usually `/`)
+#[derive(Debug, Clone)]
+pub struct UserDatabase {
+    root: PathBuf,
+}
+
+impl UserDatabase {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// The user database of the running host
+    pub fn host() -> Self {
+        Self::new("/")
+    }
+
+    /// If this is the user database of the running host, which the system tools (like `useradd`) edit
+    pub fn is_host(&self) -> bool {
+        self.root == Path::new("/")
+    }
+
+    fn path(&self, file: &str) -> PathBuf {
+        self.root.join("etc").join(file)
+    }
+
+    /// Lock every database file that exists, the lock is released when the returned guard is dropped
+    #[tracing::instrument(level = "debug", skip_all, fields(root = %self.root.display()))]
+    pub async fn lock(&self) -> Result<UserDatabaseLock, ActionErrorKind> {
+        let pwd_lock_path = self.path(PWD_LOCK);
+        let pwd_lock = std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .mode(0o600)
+            .open(&pwd_lock_path)
+            .map_err(|e| ActionErrorKind::Open(pwd_lock_path.clone(), e))?;
+        let mut attempt = 0;
+        loop {
+            let lock = nix::libc::flock {
+                l_type: nix::libc::F_WRLCK as _,
+                l_whence: nix::libc::SEEK_SET as _,
+                l_start: 0,
+                l_len: 0,
+                l_pid: 0,
+            };
+            match nix::fcntl::fcntl(pwd_lock.as_raw_fd(), nix::fcntl::F_SETLK(&lock)) {
+                Ok(_) => break,
+                Err(nix::errno::Errno::EAGAIN | nix::errno::Errno::EACCES)
+                    if attempt < LOCK_ATTEMPTS =>
+                {
+                    attempt += 1;
+                    tracing::debug!(path = %pwd_lock_path.display(), "Waiting for lock");
+                    tokio::time::sleep(LOCK_RETRY_DELAY).await;
+                },
+                Err(e) => return Err(ActionErrorKind::Lock(pwd_lock_path, e.into())),
+            }
+        }
+
+        let mut guard = UserDatabaseLock {
+            _pwd_lock: pwd_lock,
+            lock_files: Vec::new(),
+        };
+        for file in [PASSWD, SHADOW, GROUP, GSHADOW] {
+            let path = self.path(file);
+            if !path.exists() {
+                continue;
+            }
+            guard.lock_files.push(lock_file(&path).await?);
+        }
+        Ok(guard)
+    }
+
+    /// Fetch the UID and GID of a user from `passwd`
+    pub async fn user(&self, name: &str) -> Result<Option<(u32, u32)>, ActionErrorKind> {
+        let path = self.path(PASSWD);
+        let lines = read_lines(&path).await?;
+        for (index, line) in lines.iter().enumerate() {
+            let fields = line.split(':').collect::<Vec<_>>();
+            if fields.first() != Some(&name) {
+                continue;
+            }
+            let (Some(uid), Some(gid)) = (
+                fields.get(2).and_then(|v| v.parse().ok()),
+                fields.get(3).and_then(|v| v.parse().ok()),
+            ) else {
+                return Err(ActionErrorKind::MalformedUserDatabase(path, index + 1));
+            };
+            return Ok(Some((uid, gid)));
+        }
+        Ok(None)
+    }
+
+    /// Fetch the GID of a group from `group`
+    pub async fn group(&self, name: &str) -> Result<Option<u32>, ActionErrorKind> {
+        let path = self.path(GROUP);
+        let lines = read_lines(&path).await?;
+        for (index, line) in lines.iter().enumerate() {
+            let fields = line.split(':').collect::<Vec<_>>();
+            if fields.first() != Some(&name) {
+                continue;
+            }
+            let Some(gid) = fields.get(2).and_then(|v| v.parse().ok()) else {
+                return Err(ActionErrorKind::MalformedUserDatabase(path, index + 1));
+            };
+            return Ok(Some(gid));
+        }
+        Ok(None)
+    }
+
+    /// If `user` is listed as a member of `group` in `group`
+    pub async fn user_in_group(&self, user: &str, group: &str) -> Result<bool, ActionErrorKind> {
+        let lines = read_lines(&self.path(GROUP)).await?;
+        Ok(lines.iter().any(|line| {
+            let fields = line.split(':').collect::<Vec<_>>();
+            fields.first() == Some(&group)
+                && fields
+                    .get(3)
+                    .is_some_and(|members| members.split(',').any(|member| member == user))
+        }))
+    }
+
+    /// Add a system group, like `groupadd --system`
+    #[tracing::instrument(level = "debug", skip_all, fields(name = %name, gid))]
+    pub async fn add_group(&self, name: &str, gid: u32) -> Result<(), ActionErrorKind> {
+        let _lock = self.lock().await?;
+
+        let group_path = self.path(GROUP);
+        let mut group = read_lines(&group_path).await?;
+        if let Some(line) = find_entry(&group, name) {
+            return Err(ActionErrorKind::UserDatabaseEntryExists(
+                group_path,
+                line.to_string(),
+            ));
+        }
+        if group
+            .iter()
+            .any(|line| line.split(':').nth(2) == Some(gid.to_string().as_str()))
+        {
+            return Err(ActionErrorKind::UserDatabaseIdExists(group_path, gid));
+        }
+        group.push(format!("{name}:x:{gid}:"));
+
+        let gshadow_path = self.path(GSHADOW);
+        let gshadow = if gshadow_path.exists() {
+            let mut gshadow = read_lines(&gshadow_path).await?;
+            remove_entry(&mut gshadow, name);
+            gshadow.push(format!("{name}:!::"));
+            Some(gshadow)
+        } else {
+            None
+        };
+
+        write_lines(&group_path, &group).await?;
+        if let Some(gshadow) = gshadow {
+            write_lines(&gshadow_path, &gshadow).await?;
+        }
+        Ok(())
+    }
+
+    /// Remove a group, like `groupdel`
+    #[tracing::instrument(level = "debug", skip_all, fields(name = %name))]
+    pub async fn delete_group(&self, name: &str) -> Result<(), ActionErrorKind> {
+        let _lock = self.lock().await?;
+
+        for file in [GROUP, GSHADOW] {
+            let path = self.path(file);
+            if !path.exists() {
+                continue;
+            }
+            let mut lines = read_lines(&path).await?;
+            if remove_entry(&mut lines, name) {
+                write_lines(&path, &lines).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Add a system user with a locked password, like `useradd --system --password '!'`
+    #[tracing::instrument(level = "debug", skip_all, fields(name = %name, uid, gid))]
+    pub async fn add_user(
+        &self,
+        name: &str,
+        uid: u32,
+        gid: u32,
+        comment: &str,
+        home: &str,
+        shell: &str,
+    ) -> Result<(), ActionErrorKind> {
+        let _lock = self.lock().await?;
+
+        let passwd_path = self.path(PASSWD);
+        let mut passwd = read_lines(&passwd_path).await?;
+        if let Some(line) = find_entry(&passwd, name) {
+            return Err(ActionErrorKind::UserDatabaseEntryExists(
+                passwd_path,
+                line.to_string(),
+            ));
+        }
+        if passwd
+            .iter()
+            .any(|line| line.split(':').nth(2) == Some(uid.to_string().as_str()))
+        {
+            return Err(ActionErrorKind::UserDatabaseIdExists(passwd_path, uid));
+        }
+        passwd.push(format!("{name}:x:{uid}:{gid}:{comment}:{home}:{shell}"));
+
+        let shadow_path = self.path(SHADOW);
+        let shadow = if shadow_path.exists() {
+            let mut shadow = read_lines(&shadow_path).await?;
+            remove_entry(&mut shadow, name);
+            let last_change = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs() / (60 * 60 * 24))
+                .unwrap_or_default();
+            shadow.push(format!("{name}:!:{last_change}::::::"));
+            Some(shadow)
+        } else {
+            None
+        };
+
+        write_lines(&passwd_path, &passwd).await?;
+        if let Some(shadow) = shadow {
+            write_lines(&shadow_path, &shadow).await?;
+        }
+        Ok(())
+    }
+
+    /// Remove a user and any group memberships it has, like `userdel`
+    #[tracing::instrument(level = "debug", skip_all, fields(name = %name))]
+    pub async fn delete_user(&self, name: &str) -> Result<(), ActionErrorKind> {
+        let _lock = self.lock().await?;
+
+        for file in [PASSWD, SHADOW] {
+            let path = self.path(file);
+            if !path.exists() {
+                continue;
+            }
+            let mut lines = read_lines(&path).await?;
+            if remove_entry(&mut lines, name) {
+                write_lines(&path, &lines).await?;
+            }
+        }
+        for (file, members_field) in [(GROUP, 3), (GSHADOW, 3)] {
+            let path = self.path(file);
+            if !path.exists() {
+                continue;
+            }
+            let mut lines = read_lines(&path).await?;
+            let mut changed = false;
+            for line in lines.iter_mut() {
+                changed |= edit_members(line, members_field, |members| {
+                    members.retain(|member| member != name)
+                });
+            }
+            if changed {
+                write_lines(&path, &lines).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Add a user to the member list of a group, like `gpasswd -a`
+    #[tracing::instrument(level = "debug", skip_all, fields(user = %user, group = %group))]
+    pub async fn add_user_to_group(&self, user: &str, group: &str) -> Result<(), ActionErrorKind> {
+        self.edit_group_members(group, |members| {
+            if !members.iter().any(|member| member == user) {
+                members.push(user.to_string())
+            }
+        })
+        .await
+    }
+
+    /// Remove a user from the member list of a group, like `gpasswd -d`
+    #[tracing::instrument(level = "debug", skip_all, fields(user = %user, group = %group))]
+    pub async fn remove_user_from_group(
+        &self,
+        user: &str,
+        group: &str,
+    ) -> Result<(), ActionErrorKind> {
+        self.edit_group_members(group, |members| members.retain(|member| member != user))
+            .await
+    }
+
+    async fn edit_group_members(
+        &self,
+        group: &str,
+        edit: impl Fn(&mut Vec<String>),
+    ) -> Result<(), ActionErrorKind> {
+        let _lock = self.lock().await?;
+
+        let group_path = self.path(GROUP);
+        let mut group_lines = read_lines(&group_path).await?;
+        let Some(line) = group_lines
+            .iter_mut()
+            .find(|line| line.split(':').next() == Some(group))
+        else {
+            return Err(ActionErrorKind::NoGroup(group.to_string()));
+        };
+        edit_members(line, 3, &edit);
+
+        let gshadow_path = self.path(GSHADOW);
+        let gshadow = if gshadow_path.exists() {
+            let mut gshadow = read_lines(&gshadow_path).await?;
+            if let Some(line) = gshadow
+                .iter_mut()
+                .find(|line| line.split(':').next() == Some(group))
+            {
+                edit_members(line, 3, &edit);
+            }
+            Some(gshadow)
+        } else {
+            None
+        };
+
+        write_lines(&group_path, &group_lines).await?;
+        if let Some(gshadow) = gshadow {
+            write_lines(&gshadow_path, &gshadow).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Held locks on the user database, released on drop
+#[derive(Debug)]
+pub struct UserDatabaseLock {
+    _pwd_lock: std::fs::File,
+    lock_files: Vec<PathBuf>,
+}
+
+impl Drop for UserDatabaseLock {
+    fn drop(&mut self) {
+        for lock_file in self.lock_files.drain(..) {
+            if let Err(err) = std::fs::remove_file(&lock_file) {
+                tracing::warn!(path = %lock_file.display(), %err, "Could not remove lock file");
+            }
+        }
+    }
+}
+
+/// Lock `path` by linking a file containing our PID to `<path>.lock`, as `shadow-utils` does
+async fn lock_file(path: &Path) -> Result<PathBuf, ActionErrorKind> {
+    let pid = std::process::id();
+    let lock_path = with_suffix(path, ".lock");
+    let pid_path = with_suffix(path, &format!(".{pid}"));
+
+    tokio::fs::write(&pid_path, format!("{pid}"))
+        .await
+        .map_err(|e| ActionErrorKind::Write(pid_path.clone(), e))?;
+
+    let mut attempt = 0;
+    let result = loop {
+        match tokio::fs::hard_link(&pid_path, &lock_path).await {
+            Ok(()) => break Ok(lock_path),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                if lock_holder_is_dead(&lock_path).await {
+                    tracing::debug!(path = %lock_path.display(), "Removing stale lock file");
+                    tokio::fs::remove_file(&lock_path)
+                        .await
+                        .map_err(|e| ActionErrorKind::Remove(lock_path.clone(), e))?;
+                    continue;
+                }
+                if attempt >= LOCK_ATTEMPTS {
+                    break Err(ActionErrorKind::Lock(lock_path, e));
+                }
+                attempt += 1;
+                tracing::debug!(path = %lock_path.display(), "Waiting for lock");
+                tokio::time::sleep(LOCK_RETRY_DELAY).await;
+            },
+            Err(e) => break Err(ActionErrorKind::Lock(lock_path, e)),
+        }
+    };
+
+    tokio::fs::remove_file(&pid_path)
+        .await
+        .map_err(|e| ActionErrorKind::Remove(pid_path, e))?;
+    result
+}
+
+async fn lock_holder_is_dead(lock_path: &Path) -> bool {
+    let Ok(contents) = tokio::fs::read_to_string(lock_path).await else {
+        return false;
+    };
+    let Ok(pid) = contents.trim().parse::<i32>() else {
+        return false;
+    };
+    // Without a mounted `/proc` there is no telling, so assume the holder is alive
+    let proc = Path::new("/proc");
+    proc.join("self").exists() && !proc.join(pid.to_string()).exists()
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+async fn read_lines(path: &Path) -> Result<Vec<String>, ActionErrorKind> {
+    let buf = match tokio::fs::read_to_string(path).await {
+        Ok(buf) => buf,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(ActionErrorKind::Read(path.to_path_buf(), e)),
+    };
+    Ok(buf.lines().map(ToString::to_string).collect())
+}
+
+/// Write `lines` to `<path>+`, keep the original as `<path>-`, then rename `<path>+` over `path`
+async fn write_lines(path: &Path, lines: &[String]) -> Result<(), ActionErrorKind> {
+    let temp_path = with_suffix(path, "+");
+    let backup_path = with_suffix(path, "-");
+
+    let existing = match tokio::fs::metadata(path).await {
+        Ok(metadata) => Some(metadata),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(ActionErrorKind::GettingMetadata(path.to_path_buf(), e)),
+    };
+    let mode = existing
+        .as_ref()
+        .map(|metadata| metadata.mode() & 0o7777)
+        .unwrap_or(0o644);
+
+    let mut temp = tokio::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .mode(mode)
+        .open(&temp_path)
+        .await
+        .map_err(|e| ActionErrorKind::Open(temp_path.clone(), e))?;
+    let mut buf = lines.join("\n");
+    if !buf.is_empty() {
+        buf.push('\n');
+    }
+    temp.write_all(buf.as_bytes())
+        .await
+        .map_err(|e| ActionErrorKind::Write(temp_path.clone(), e))?;
+    temp.sync_all()
+        .await
+        .map_err(|e| ActionErrorKind::Sync(temp_path.clone(), e))?;
+    drop(temp);
+
+    if let Some(existing) = existing {
+        // `open` applies the umask, so the mode must be set explicitly
+        tokio::fs::set_permissions(&temp_path, std::fs::Permissions::from_mode(mode))
+            .await
+            .map_err(|e| ActionErrorKind::SetPermissions(mode, temp_path.clone(), e))?;
+        nix::unistd::chown(
+            &temp_path,
+            Some(nix::unistd::Uid::from_raw(existing.uid())),
+            Some(nix::unistd::Gid::from_raw(existing.gid())),
+        )
+        .map_err(|e| ActionErrorKind::Chown(temp_path.clone(), e))?;
+        tokio::fs::copy(path, &backup_path)
+            .await
+            .map_err(|e| ActionErrorKind::Copy(path.to_path_buf(), backup_path.clone(), e))?;
+    }
+
+    tokio::fs::rename(&temp_path, path)
+        .await
+        .map_err(|e| ActionErrorKind::Rename(temp_path, path.to_path_buf(), e))?;
+    Ok(())
+}
+
+fn find_entry<'a>(lines: &'a [String], name: &str) -> Option<&'a str> {
+    lines
+        .iter()
+        .find(|line| line.split(':').next() == Some(name))
+        .map(String::as_str)
+}
+
+/// Remove the entry named `name`, returning if one was removed
+fn remove_entry(lines: &mut Vec<String>, name: &str) -> bool {
+    let before = lines.len();
+    lines.retain(|line| line.split(':').next() != Some(name));
+    lines.len() != before
+}
+
+/// Edit the comma separated member list in field `index` of `line`, returning if it changed
+fn edit_members(line: &mut String, index: usize, edit: impl Fn(&mut Vec<String>)) -> bool {
+    let mut fields = line.split(':').map(ToString::to_string).collect::<Vec<_>>();
+    if line.starts_with('#') || fields.len() <= index {
+        return false;
+    }
+    let mut members = fields[index]
+        .split(',')
+        .filter(|member| !member.is_empty())
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    let before = members.clone();
+    edit(&mut members);
+    if members == before {
+        return false;
+    }
+    fields[index] = members.join(",");
+    *line = fields.join(":");
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    async fn temp_root() -> eyre::Result<(tempfile::TempDir, UserDatabase)> {
+        let temp_dir = tempfile::tempdir()?;
+        let etc = temp_dir.path().join("etc");
+        tokio::fs::create_dir(&etc).await?;
+        tokio::fs::write(
+            etc.join(PASSWD),
+            "root:x:0:0:root:/root:/bin/sh\nnobody:x:65534:65534:nobody:/:/sbin/nologin\n",
+        )
+        .await?;
+        tokio::fs::write(etc.join(GROUP), "root:x:0:\nwheel:x:10:root\n").await?;
+        tokio::fs::write(etc.join(SHADOW), "root:*:19000:0:99999:7:::\n").await?;
+        let database = UserDatabase::new(temp_dir.path());
+        Ok((temp_dir, database))
+    }
+
+    #[tokio::test]
+    async fn adds_and_deletes_group() -> eyre::Result<()> {
+        let (temp_dir, database) = temp_root().await?;
+
+        database.add_group("nixbld", 30000).await?;
+        let group = tokio::fs::read_to_string(temp_dir.path().join("etc/group")).await?;
+        assert_eq!(group, "root:x:0:\nwheel:x:10:root\nnixbld:x:30000:\n");
+        assert!(!temp_dir.path().join("etc/gshadow").exists());
+        assert!(!temp_dir.path().join("etc/group.lock").exists());
+
+        database.delete_group("nixbld").await?;
+        let group = tokio::fs::read_to_string(temp_dir.path().join("etc/group")).await?;
+        assert_eq!(group, "root:x:0:\nwheel:x:10:root\n");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn adds_and_deletes_user() -> eyre::Result<()> {
+        let (temp_dir, database) = temp_root().await?;
+
+        database.add_group("nixbld", 30000).await?;
+        database
+            .add_user(
+                "nixbld1",
+                30001,
+                30000,
+                "Nix build user 1",
+                "/var/empty",
+                "/sbin/nologin",
+            )
+            .await?;
+        database.add_user_to_group("nixbld1", "nixbld").await?;
+
+        let passwd = tokio::fs::read_to_string(temp_dir.path().join("etc/passwd")).await?;
+        assert!(
+            passwd.ends_with("nixbld1:x:30001:30000:Nix build user 1:/var/empty:/sbin/nologin\n")
+        );
+        assert!(database.user_in_group("nixbld1", "nixbld").await?);
+        let shadow = tokio::fs::read_to_string(temp_dir.path().join("etc/shadow")).await?;
+        assert!(shadow.lines().any(|line| line.starts_with("nixbld1:!:")));
+        let backup = tokio::fs::read_to_string(temp_dir.path().join("etc/passwd-")).await?;
+        assert!(!backup.contains("nixbld1"));
+
+        database.delete_user("nixbld1").await?;
+        let passwd = tokio::fs::read_to_string(temp_dir.path().join("etc/passwd")).await?;
+        assert!(!passwd.contains("nixbld1"));
+        assert!(!database.user_in_group("nixbld1", "nixbld").await?);
+        let shadow = tokio::fs::read_to_string(temp_dir.path().join("etc/shadow")).await?;
+        assert_eq!(shadow, "root:*:19000:0:99999:7:::\n");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn edits_group_members() -> eyre::Result<()> {
+        let (temp_dir, database) = temp_root().await?;
+        tokio::fs::write(temp_dir.path().join("etc/gshadow"), "wheel:!::root\n").await?;
+
+        database.add_user_to_group("nobody", "wheel").await?;
+        database.add_user_to_group("nobody", "wheel").await?;
+        let group = tokio::fs::read_to_string(temp_dir.path().join("etc/group")).await?;
+        assert_eq!(group, "root:x:0:\nwheel:x:10:root,nobody\n");
+        let gshadow = tokio::fs::read_to_string(temp_dir.path().join("etc/gshadow")).await?;
+        assert_eq!(gshadow, "wheel:!::root,nobody\n");
+
+        database.remove_user_from_group("root", "wheel").await?;
+        let group = tokio::fs::read_to_string(temp_dir.path().join("etc/group")).await?;
+        assert_eq!(group, "root:x:0:\nwheel:x:10:nobody\n");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn refuses_duplicates() -> eyre::Result<()> {
+        let (_temp_dir, database) = temp_root().await?;
+
+        match database.add_group("wheel", 30000).await {
+            Err(ActionErrorKind::UserDatabaseEntryExists(_, _)) => (),
+            _ => return Err(eyre::eyre!("Expected an existing entry error")),
+        }
+        match database.add_group("nixbld", 10).await {
+            Err(ActionErrorKind::UserDatabaseIdExists(_, 10)) => (),
+            _ => return Err(eyre::eyre!("Expected an existing ID error")),
+        }
+        match database
+            .add_user("nixbld1", 0, 0, "", "/var/empty", "/sbin/nologin")
+            .await
+        {
+            Err(ActionErrorKind::UserDatabaseIdExists(_, 0)) => (),
+            _ => return Err(eyre::eyre!("Expected an existing ID error")),
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn removes_stale_lock_files() -> eyre::Result<()> {
+        let (temp_dir, database) = temp_root().await?;
+        // PIDs are capped well below this, so no process can hold it
+        tokio::fs::write(temp_dir.path().join("etc/passwd.lock"), "2147483646").await?;
+
+        database.add_group("nixbld", 30000).await?;
+        assert!(!temp_dir.path().join("etc/passwd.lock").exists());
+
+        Ok(())
+    }
+}
@@ -1,16 +1,26 @@
-use std::{path::PathBuf, str::FromStr};
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
 use crate::{
-    action::{Action, ActionDescription, StatefulAction},
+    action::{
+        dependencies, Action, ActionDescription, ActionError, ActionState, StatefulAction,
+        SubAction,
+    },
     planner::{BuiltinPlanner, Planner},
     NixInstallerError,
 };
 use owo_colors::OwoColorize;
 use semver::{Version, VersionReq};
-use tokio::sync::broadcast::Receiver;
+use tokio::{sync::broadcast::Receiver, task::JoinSet};
+use tracing::Instrument;
 
 pub const RECEIPT_LOCATION: &str = "/nix/receipt.json";
 
+/// The default number of independent [`Action`]s an [`InstallPlan`] runs at once
+pub const DEFAULT_MAX_CONCURRENCY: usize = 4;
+
 /**
 A set of [`Action`]s, along with some metadata, which can be carried out to drive an install or
 revert
@@ -25,6 +35,99 @@ pub struct InstallPlan {
 
     #[cfg(feature = "diagnostics")]
     pub(crate) diagnostic_data: Option<crate::diagnostics::DiagnosticData>,
+
+    #[serde(skip, default = "default_max_concurrency")]
+    pub(crate) max_concurrency: usize,
+
+    #[serde(skip)]
+    pub(crate) uninstall_selection: UninstallSelection,
+}
+
+/// Which actions of an [`InstallPlan`] [`uninstall`](InstallPlan::uninstall) reverts, by their
+/// [`ActionTag`](crate::action::ActionTag)
+///
+/// The tags match sub-actions of meta-actions too, see [`Action::children`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub enum UninstallSelection {
+    #[default]
+    All,
+    /// Every action but these, and the sub-actions of these
+    Keep(Vec<String>),
+    /// Only these actions, with their sub-actions, and the meta-actions calling them
+    Only(Vec<String>),
+}
+
+impl UninstallSelection {
+    /// Every action but those placing the store and `/nix`, so Nix can be installed over it again
+    ///
+    /// As actions are selected by their tag, every directory created by a `create_directory` action is kept too.
+    pub fn keep_store() -> Self {
+        Self::Keep(store_action_tags())
+    }
+
+    /// Whether the action tagged `tag` is reverted
+    pub fn includes(&self, tag: &str) -> bool {
+        match self {
+            Self::All => true,
+            Self::Keep(tags) => !tags.iter().any(|v| v == tag),
+            Self::Only(tags) => tags.iter().any(|v| v == tag),
+        }
+    }
+
+    /// Call `keep` with each action at or under `action` which isn't reverted, but not with the sub-actions of those
+    ///
+    /// The meta-actions calling only some reverted sub-actions are reverted, their sub-actions which aren't are kept.
+    pub(crate) fn visit_kept(
+        &self,
+        action: &mut dyn SubAction,
+        keep: &mut dyn FnMut(&mut dyn SubAction),
+    ) {
+        let included = self.includes(action.typetag_name());
+        match self {
+            Self::All => (),
+            Self::Keep(_) if !included => keep(action),
+            Self::Only(_) if included => (),
+            Self::Only(_) if !self.includes_any_under(&*action) => keep(action),
+            Self::Keep(_) | Self::Only(_) => {
+                for child in action.children_mut() {
+                    self.visit_kept(child, keep);
+                }
+            },
+        }
+    }
+
+    fn includes_any_under(&self, action: &dyn SubAction) -> bool {
+        action
+            .children()
+            .into_iter()
+            .any(|child| self.includes(child.typetag_name()) || self.includes_any_under(child))
+    }
+}
+
+/// The tags of the actions which place the store and `/nix`, at the top of a plan or under [`ProvisionNix`](crate::action::common::ProvisionNix)
+pub fn store_action_tags() -> Vec<String> {
+    use crate::action::{base, common};
+
+    #[allow(unused_mut)]
+    let mut tags = vec![
+        common::ProvisionNix::action_tag(),
+        common::CreateNixTree::action_tag(),
+        base::FetchAndUnpackNix::action_tag(),
+        base::MoveUnpackedNix::action_tag(),
+        base::CreateDirectory::action_tag(),
+    ];
+    #[cfg(target_os = "linux")]
+    tags.extend([
+        crate::action::linux::CreateNixLoopbackStore::action_tag(),
+        crate::action::linux::LinkNixDirectory::action_tag(),
+        crate::action::linux::PersistentNixMount::action_tag(),
+    ]);
+    #[cfg(target_os = "macos")]
+    tags.extend([
+        crate::action::macos::CreateNixVolume::action_tag(),
+        crate::action::macos::SetTmutilExclusions::action_tag(),
+    ]);
+    tags.into_iter().map(|v| v.to_string()).collect()
 }
 
 impl InstallPlan {
@@ -43,6 +146,8 @@ impl InstallPlan {
             version: current_version()?,
             #[cfg(feature = "diagnostics")]
             diagnostic_data,
+            max_concurrency: DEFAULT_MAX_CONCURRENCY,
+            uninstall_selection: UninstallSelection::All,
         })
     }
 
@@ -63,9 +168,108 @@ impl InstallPlan {
             version: current_version()?,
             #[cfg(feature = "diagnostics")]
             diagnostic_data,
+            max_concurrency: DEFAULT_MAX_CONCURRENCY,
+            uninstall_selection: UninstallSelection::All,
         })
     }
 
+    /// Plan an install with `planner` on a host where Nix was installed by other means, to record it in a receipt
+    ///
+    /// Each action's `plan` marks what is already done as completed. Meta-actions are then marked completed when all
+    /// their sub-actions are, and in progress when only some are, so an uninstall reverts only what was found. See
+    /// [`unadopted`](Self::unadopted) for the rest.
+    pub async fn adopt<P>(planner: P) -> Result<Self, NixInstallerError>
+    where
+        P: Planner + 'static,
+    {
+        #[cfg(feature = "diagnostics")]
+        let diagnostic_data = Some(planner.diagnostic_data().await?);
+
+        // Unlike `plan`, there is no `pre_install_check`, it refuses hosts which already have Nix
+        let mut actions = planner.plan().await?;
+        for action in &mut actions {
+            action.adopt_state();
+        }
+
+        Ok(Self {
+            planner: planner.boxed(),
+            actions,
+            version: current_version()?,
+            #[cfg(feature = "diagnostics")]
+            diagnostic_data,
+            max_concurrency: DEFAULT_MAX_CONCURRENCY,
+            uninstall_selection: UninstallSelection::All,
+        })
+    }
+
+    /// The synopsis of each action which isn't completed with the synopses of its sub-actions which aren't either, for
+    /// a plan made by [`adopt`](Self::adopt) the parts of the install which weren't found
+    ///
+    /// The sub-actions are only listed for actions which were partially found.
+    pub fn unadopted(&self) -> Vec<(String, Vec<String>)> {
+        self.actions
+            .iter()
+            .filter(|v| !matches!(v.state, ActionState::Completed | ActionState::Skipped))
+            .map(|v| {
+                let sub_actions = match v.state {
+                    ActionState::Progress => v.unadopted(),
+                    _ => vec![],
+                };
+                (v.tracing_synopsis(), sub_actions)
+            })
+            .collect()
+    }
+
+    /// The tags of the actions of this plan and of their sub-actions, sorted and without duplicates
+    pub fn action_tags(&self) -> Vec<&'static str> {
+        fn tags(action: &dyn SubAction, into: &mut Vec<&'static str>) {
+            into.push(action.typetag_name());
+            for child in action.children() {
+                tags(child, into);
+            }
+        }
+        let mut action_tags = vec![];
+        for action in &self.actions {
+            tags(action, &mut action_tags);
+        }
+        action_tags.sort_unstable();
+        action_tags.dedup();
+        action_tags
+    }
+
+    /// Write the receipt of this plan to its [`receipt_location`](Self::receipt_location)
+    pub async fn write_receipt(&self) -> Result<(), NixInstallerError> {
+        write_receipt(self.clone()).await
+    }
+
+    /// How many independent actions may run at once, `1` runs every action sequentially
+    ///
+    /// Actions are independent when the [`ActionResource`](crate::action::ActionResource)s they declare don't conflict.
+    pub fn max_concurrency(&mut self, max_concurrency: usize) -> &mut Self {
+        self.max_concurrency = max_concurrency.max(1);
+        self
+    }
+
+    /// Which actions [`uninstall`](Self::uninstall) reverts, the receipt records the others as they were
+    pub fn uninstall_selection(&mut self, uninstall_selection: UninstallSelection) -> &mut Self {
+        self.uninstall_selection = uninstall_selection;
+        self
+    }
+
+    /// Where the receipt of this plan is written, see [`Planner::receipt_location`]
+    pub fn receipt_location(&self) -> PathBuf {
+        self.planner.receipt_location()
+    }
+
+    /// The directory the receipt lives in, `/nix` unless the planner installs elsewhere
+    pub fn nix_dir(&self) -> PathBuf {
+        let receipt_location = self.receipt_location();
+        receipt_location
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or(receipt_location)
+    }
+
     pub async fn pre_uninstall_check(&self) -> Result<(), NixInstallerError> {
         self.planner.pre_uninstall_check().await?;
         Ok(())
@@ -158,38 +362,40 @@ impl InstallPlan {
         self.check_compatible()?;
         self.planner.pre_install_check().await?;
 
-        let Self { actions, .. } = self;
+        let Self {
+            actions,
+            max_concurrency,
+            ..
+        } = self;
         let mut cancel_channel = cancel_channel.into();
 
-        // This is **deliberately sequential**.
-        // Actions which are parallelizable are represented by "group actions" like CreateUsers
-        // The plan itself represents the concept of the sequence of stages.
-        for action in actions {
-            if let Some(ref mut cancel_channel) = cancel_channel {
-                if cancel_channel.try_recv()
-                    != Err(tokio::sync::broadcast::error::TryRecvError::Empty)
-                {
-                    if let Err(err) = write_receipt(self.clone()).await {
-                        tracing::error!("Error saving receipt: {:?}", err);
-                    }
+        let result = if *max_concurrency > 1 {
+            execute_concurrently(actions, *max_concurrency, &mut cancel_channel).await
+        } else {
+            execute_sequentially(actions, &mut cancel_channel).await
+        };
 
-                    #[cfg(feature = "diagnostics")]
-                    if let Some(diagnostic_data) = &self.diagnostic_data {
-                        diagnostic_data
-                            .clone()
-                            .send(
-                                crate::diagnostics::DiagnosticAction::Install,
-                                crate::diagnostics::DiagnosticStatus::Cancelled,
-                            )
-                            .await?;
-                    }
+        match result {
+            Ok(()) => (),
+            Err(None) => {
+                if let Err(err) = write_receipt(self.clone()).await {
+                    tracing::error!("Error saving receipt: {:?}", err);
+                }
 
-                    return Err(NixInstallerError::Cancelled);
+                #[cfg(feature = "diagnostics")]
+                if let Some(diagnostic_data) = &self.diagnostic_data {
+                    diagnostic_data
+                        .clone()
+                        .send(
+                            crate::diagnostics::DiagnosticAction::Install,
+                            crate::diagnostics::DiagnosticStatus::Cancelled,
+                        )
+                        .await?;
                 }
-            }
 
-            tracing::info!("Step: {}", action.tracing_synopsis());
-            if let Err(err) = action.try_execute().await {
+                return Err(NixInstallerError::Cancelled);
+            },
+            Err(Some(err)) => {
                 if let Err(err) = write_receipt(self.clone()).await {
                     tracing::error!("Error saving receipt: {:?}", err);
                 }
@@ -207,12 +413,17 @@ impl InstallPlan {
                 }
 
                 return Err(err);
-            }
+            },
         }
 
         write_receipt(self.clone()).await?;
 
-        if let Err(err) = crate::self_test::self_test()
+        // Testing as whoever ran `sudo nix-installer` catches permission problems `root` can't run into
+        let self_test_options = crate::self_test::SelfTestOptions {
+            expected_nix_version: crate::self_test::tarball_nix_version(self),
+            user: crate::self_test::TestUser::from_sudo(),
+        };
+        if let Err(err) = crate::self_test::self_test(&self_test_options)
             .await
             .map_err(NixInstallerError::SelfTest)
         {
@@ -251,9 +462,17 @@ impl InstallPlan {
             version,
             planner,
             actions,
+            uninstall_selection,
             ..
         } = self;
 
+        // Actions left out are skipped, so meta-actions only describe the sub-actions which are reverted
+        let mut actions = actions.clone();
+        for action in &mut actions {
+            uninstall_selection
+                .visit_kept(action, &mut |kept| kept.set_state(ActionState::Skipped));
+        }
+
         let plan_settings = if explain {
             // List all settings when explaining
             planner.settings()?
@@ -329,43 +548,75 @@ impl InstallPlan {
         self.check_compatible()?;
         self.planner.pre_uninstall_check().await?;
 
-        let Self { actions, .. } = self;
+        let Self {
+            actions,
+            max_concurrency,
+            uninstall_selection,
+            ..
+        } = self;
         let mut cancel_channel = cancel_channel.into();
-        let mut errors = vec![];
-
-        // This is **deliberately sequential**.
-        // Actions which are parallelizable are represented by "group actions" like CreateUsers
-        // The plan itself represents the concept of the sequence of stages.
-        for action in actions.iter_mut().rev() {
-            if let Some(ref mut cancel_channel) = cancel_channel {
-                if cancel_channel.try_recv()
-                    != Err(tokio::sync::broadcast::error::TryRecvError::Empty)
-                {
-                    if let Err(err) = write_receipt(self.clone()).await {
-                        tracing::error!("Error saving receipt: {:?}", err);
-                    }
 
-                    #[cfg(feature = "diagnostics")]
-                    if let Some(diagnostic_data) = &self.diagnostic_data {
-                        diagnostic_data
-                            .clone()
-                            .send(
-                                crate::diagnostics::DiagnosticAction::Uninstall,
-                                crate::diagnostics::DiagnosticStatus::Cancelled,
-                            )
-                            .await?;
-                    }
-                    return Err(NixInstallerError::Cancelled);
+        // Actions left out are skipped while reverting, then recorded in the receipt as they were
+        let mut kept = vec![];
+        for action in actions.iter_mut() {
+            uninstall_selection.visit_kept(action, &mut |action| {
+                kept.push(action.state());
+                action.set_state(ActionState::Skipped);
+            });
+        }
+
+        let result = if *max_concurrency > 1 {
+            revert_concurrently(actions, *max_concurrency, &mut cancel_channel).await
+        } else {
+            revert_sequentially(actions, &mut cancel_channel).await
+        };
+
+        let anything_kept = kept
+            .iter()
+            .any(|state| matches!(state, ActionState::Completed | ActionState::Progress));
+        let mut kept = kept.into_iter();
+        for action in actions.iter_mut() {
+            uninstall_selection.visit_kept(action, &mut |action| {
+                if let Some(state) = kept.next() {
+                    action.set_state(state);
                 }
+            });
+            // A later uninstall only reverts what was kept under a meta-action which isn't uncompleted
+            keep_in_progress(action);
+        }
+
+        let Some(errors) = result else {
+            if let Err(err) = write_receipt(self.clone()).await {
+                tracing::error!("Error saving receipt: {:?}", err);
             }
 
-            tracing::info!("Revert: {}", action.tracing_synopsis());
-            if let Err(errs) = action.try_revert().await {
-                errors.push(errs);
+            #[cfg(feature = "diagnostics")]
+            if let Some(diagnostic_data) = &self.diagnostic_data {
+                diagnostic_data
+                    .clone()
+                    .send(
+                        crate::diagnostics::DiagnosticAction::Uninstall,
+                        crate::diagnostics::DiagnosticStatus::Cancelled,
+                    )
+                    .await?;
             }
-        }
+            return Err(NixInstallerError::Cancelled);
+        };
 
         if errors.is_empty() {
+            // So a later uninstall reverts what was kept, unless the directory of the receipt was removed, when writing
+            // it would leave a directory which blocks the next install
+            if anything_kept {
+                let receipt_location = self.receipt_location();
+                match receipt_location.parent() {
+                    Some(parent) if !parent.exists() => tracing::warn!(
+                        "Not recording what was kept in a receipt, as `{}` was removed",
+                        parent.display()
+                    ),
+                    _ => self.write_receipt().await?,
+                }
+            }
+
             #[cfg(feature = "diagnostics")]
             if let Some(diagnostic_data) = &self.diagnostic_data {
                 diagnostic_data
@@ -413,10 +664,12 @@ impl InstallPlan {
 }
 
 async fn write_receipt(plan: InstallPlan) -> Result<(), NixInstallerError> {
-    tokio::fs::create_dir_all("/nix")
-        .await
-        .map_err(|e| NixInstallerError::RecordingReceipt(PathBuf::from("/nix"), e))?;
-    let install_receipt_path = PathBuf::from(RECEIPT_LOCATION);
+    let install_receipt_path = plan.receipt_location();
+    if let Some(parent) = install_receipt_path.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .map_err(|e| NixInstallerError::RecordingReceipt(parent.to_path_buf(), e))?;
+    }
     let self_json =
         serde_json::to_string_pretty(&plan).map_err(NixInstallerError::SerializingReceipt)?;
     tokio::fs::write(&install_receipt_path, format!("{self_json}\n"))
@@ -425,6 +678,200 @@ async fn write_receipt(plan: InstallPlan) -> Result<(), NixInstallerError> {
     Result::<(), NixInstallerError>::Ok(())
 }
 
+/// Mark `action` in progress if anything under it is completed or in progress while it isn't, returning whether
+/// anything at or under it is
+fn keep_in_progress(action: &mut dyn SubAction) -> bool {
+    let mut left = false;
+    for child in action.children_mut() {
+        left |= keep_in_progress(child);
+    }
+    if left && action.state() == ActionState::Uncompleted {
+        action.set_state(ActionState::Progress);
+    }
+    left || matches!(
+        action.state(),
+        ActionState::Completed | ActionState::Progress
+    )
+}
+
+fn default_max_concurrency() -> usize {
+    DEFAULT_MAX_CONCURRENCY
+}
+
+fn cancelled(cancel_channel: &mut Option<Receiver<()>>) -> bool {
+    match cancel_channel {
+        Some(cancel_channel) => {
+            cancel_channel.try_recv() != Err(tokio::sync::broadcast::error::TryRecvError::Empty)
+        },
+        None => false,
+    }
+}
+
+/// Execute each action in order, returning `Err(None)` if cancelled
+async fn execute_sequentially(
+    actions: &mut [StatefulAction<Box<dyn Action>>],
+    cancel_channel: &mut Option<Receiver<()>>,
+) -> Result<(), Option<ActionError>> {
+    // Actions which are parallelizable are represented by "group actions" like CreateUsers
+    // The plan itself represents the concept of the sequence of stages.
+    for action in actions {
+        if cancelled(cancel_channel) {
+            return Err(None);
+        }
+
+        tracing::info!("Step: {}", action.tracing_synopsis());
+        action.try_execute().await.map_err(Some)?;
+    }
+    Ok(())
+}
+
+/// Execute actions once every earlier action they conflict with is complete, returning `Err(None)` if cancelled
+///
+/// After a failure or cancellation no new actions are started, but running ones are allowed to finish.
+async fn execute_concurrently(
+    actions: &mut [StatefulAction<Box<dyn Action>>],
+    max_concurrency: usize,
+    cancel_channel: &mut Option<Receiver<()>>,
+) -> Result<(), Option<ActionError>> {
+    let dependencies = dependencies(&actions.iter().map(|v| v.resources()).collect::<Vec<_>>());
+    let mut started = vec![false; actions.len()];
+    let mut completed = vec![false; actions.len()];
+    let mut failure = None;
+    let mut set = JoinSet::new();
+
+    loop {
+        if failure.is_none() && cancelled(cancel_channel) {
+            failure = Some(None);
+        }
+        if failure.is_none() {
+            for index in 0..actions.len() {
+                if set.len() >= max_concurrency {
+                    break;
+                }
+                if started[index] || !dependencies[index].iter().all(|&dep| completed[dep]) {
+                    continue;
+                }
+                started[index] = true;
+                let mut action = actions[index].clone();
+                tracing::info!("Step: {}", action.tracing_synopsis());
+                let span = tracing::Span::current();
+                set.spawn(async move {
+                    let result = action.try_execute().instrument(span).await;
+                    (index, action, result)
+                });
+            }
+        }
+
+        match set.join_next().await {
+            Some(Ok((index, action, result))) => {
+                actions[index] = action;
+                match result {
+                    Ok(()) => completed[index] = true,
+                    Err(err) => {
+                        if failure.is_none() {
+                            failure = Some(Some(err));
+                        } else {
+                            tracing::error!("{:?}", err);
+                        }
+                    },
+                }
+            },
+            Some(Err(err)) if err.is_panic() => std::panic::resume_unwind(err.into_panic()),
+            Some(Err(_)) => unreachable!("Action tasks are never aborted"),
+            None => break,
+        }
+    }
+
+    match failure {
+        Some(failure) => Err(failure),
+        None => Ok(()),
+    }
+}
+
+/// Revert each action in reverse order, returning `None` if cancelled
+async fn revert_sequentially(
+    actions: &mut [StatefulAction<Box<dyn Action>>],
+    cancel_channel: &mut Option<Receiver<()>>,
+) -> Option<Vec<ActionError>> {
+    let mut errors = vec![];
+    for action in actions.iter_mut().rev() {
+        if cancelled(cancel_channel) {
+            return None;
+        }
+
+        tracing::info!("Revert: {}", action.tracing_synopsis());
+        if let Err(errs) = action.try_revert().await {
+            errors.push(errs);
+        }
+    }
+    Some(errors)
+}
+
+/// Revert actions once every later action which conflicts with them is reverted, returning `None` if cancelled
+///
+/// This is the reverse topological order of [`execute_concurrently`]. A failed revert does not stop others from running.
+async fn revert_concurrently(
+    actions: &mut [StatefulAction<Box<dyn Action>>],
+    max_concurrency: usize,
+    cancel_channel: &mut Option<Receiver<()>>,
+) -> Option<Vec<ActionError>> {
+    let dependencies = dependencies(&actions.iter().map(|v| v.resources()).collect::<Vec<_>>());
+    let mut dependents = vec![Vec::new(); actions.len()];
+    for (index, dependencies) in dependencies.iter().enumerate() {
+        for &dependency in dependencies {
+            dependents[dependency].push(index);
+        }
+    }
+    let mut started = vec![false; actions.len()];
+    let mut finished = vec![false; actions.len()];
+    let mut was_cancelled = false;
+    let mut errors = vec![];
+    let mut set = JoinSet::new();
+
+    loop {
+        if !was_cancelled && cancelled(cancel_channel) {
+            was_cancelled = true;
+        }
+        if !was_cancelled {
+            for index in (0..actions.len()).rev() {
+                if set.len() >= max_concurrency {
+                    break;
+                }
+                if started[index] || !dependents[index].iter().all(|&dep| finished[dep]) {
+                    continue;
+                }
+                started[index] = true;
+                let mut action = actions[index].clone();
+                tracing::info!("Revert: {}", action.tracing_synopsis());
+                let span = tracing::Span::current();
+                set.spawn(async move {
+                    let result = action.try_revert().instrument(span).await;
+                    (index, action, result)
+                });
+            }
+        }
+
+        match set.join_next().await {
+            Some(Ok((index, action, result))) => {
+                actions[index] = action;
+                finished[index] = true;
+                if let Err(err) = result {
+                    errors.push(err);
+                }
+            },
+            Some(Err(err)) if err.is_panic() => std::panic::resume_unwind(err.into_panic()),
+            Some(Err(_)) => unreachable!("Action tasks are never aborted"),
+            None => break,
+        }
+    }
+
+    if was_cancelled {
+        None
+    } else {
+        Some(errors)
+    }
+}
+
 pub fn current_version() -> Result<Version, NixInstallerError> {
     let nix_installer_version_str = env!("CARGO_PKG_VERSION");
     Version::from_str(nix_installer_version_str).map_err(|e| {
@@ -436,7 +883,12 @@ pub fn current_version() -> Result<Version, NixInstallerError> {
 mod test {
     use semver::Version;
 
-    use crate::{planner::BuiltinPlanner, InstallPlan, NixInstallerError};
+    use super::{current_version, keep_in_progress, UninstallSelection, DEFAULT_MAX_CONCURRENCY};
+    use crate::{
+        action::{Action, ActionState, StatefulAction, SubAction},
+        planner::BuiltinPlanner,
+        InstallPlan, NixInstallerError,
+    };
 
     #[tokio::test]
     async fn ensure_version_allows_compatible() -> Result<(), NixInstallerError> {
@@ -465,4 +917,163 @@ mod test {
         assert!(maybe_plan.check_compatible().is_err());
         Ok(())
     }
+
+    /// The build group and two build users, planned against the user database of `passwd` and `group` in `root`
+    #[cfg(target_os = "linux")]
+    async fn create_users_and_groups(
+        root: &std::path::Path,
+        passwd: &str,
+        group: &str,
+    ) -> eyre::Result<StatefulAction<Box<dyn Action>>> {
+        use crate::{action::common::CreateUsersAndGroups, settings::CommonSettings};
+
+        std::fs::create_dir_all(root.join("etc"))?;
+        std::fs::write(root.join("etc/passwd"), passwd)?;
+        std::fs::write(root.join("etc/group"), group)?;
+        let mut settings = CommonSettings::default().await?;
+        settings.nix_build_group_name = "nixbld".to_string();
+        settings.nix_build_group_id = 30_000;
+        settings.nix_build_user_prefix = "nixbld".to_string();
+        settings.nix_build_user_id_base = 30_000;
+        settings.nix_build_user_count = 2;
+        Ok(CreateUsersAndGroups::plan(settings, root).await?.boxed())
+    }
+
+    #[cfg(target_os = "linux")]
+    const BUILD_USERS: &str = "\
+        nixbld1:x:30001:30000:Nix build user 1:/var/empty:/sbin/nologin\n\
+        nixbld2:x:30002:30000:Nix build user 2:/var/empty:/sbin/nologin\n";
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn adopts_state_of_sub_actions() -> eyre::Result<()> {
+        let root = tempfile::tempdir()?;
+
+        // Only the first of two build users is in the user database
+        let mut action = create_users_and_groups(
+            root.path(),
+            "nixbld1:x:30001:30000:Nix build user 1:/var/empty:/sbin/nologin\n",
+            "nixbld:x:30000:nixbld1\n",
+        )
+        .await?;
+        assert_eq!(action.state, ActionState::Uncompleted);
+        assert_eq!(action.adopt_state(), ActionState::Progress);
+        let missing = action.unadopted();
+        assert_eq!(missing.len(), 2, "{missing:?}");
+        assert!(missing.iter().all(|v| v.contains("nixbld2")), "{missing:?}");
+
+        let mut action =
+            create_users_and_groups(root.path(), BUILD_USERS, "nixbld:x:30000:nixbld1,nixbld2\n")
+                .await?;
+        assert_eq!(action.adopt_state(), ActionState::Completed);
+        assert!(action.unadopted().is_empty());
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn uninstall_selection_applies_to_sub_actions() -> eyre::Result<()> {
+        let root = tempfile::tempdir()?;
+        let mut action =
+            create_users_and_groups(root.path(), BUILD_USERS, "nixbld:x:30000:nixbld1,nixbld2\n")
+                .await?;
+        let mut kept = |selection: UninstallSelection| {
+            let mut kept = vec![];
+            selection.visit_kept(&mut action, &mut |action| kept.push(action.typetag_name()));
+            kept
+        };
+
+        assert!(kept(UninstallSelection::All).is_empty());
+        assert_eq!(
+            kept(UninstallSelection::Keep(vec!["create_user".into()])),
+            ["create_user", "create_user"]
+        );
+        assert_eq!(
+            kept(UninstallSelection::Keep(vec![
+                "create_users_and_group".into()
+            ])),
+            ["create_users_and_group"]
+        );
+        assert!(kept(UninstallSelection::keep_store()).is_empty());
+        // The meta-action is reverted for the sub-action, which is all that is
+        assert_eq!(
+            kept(UninstallSelection::Only(vec!["create_group".into()])),
+            [
+                "create_user",
+                "create_user",
+                "add_user_to_group",
+                "add_user_to_group"
+            ]
+        );
+        assert!(kept(UninstallSelection::Only(vec![
+            "create_users_and_group".into()
+        ]))
+        .is_empty());
+        assert_eq!(
+            kept(UninstallSelection::Only(vec!["provision_nix".into()])),
+            ["create_users_and_group"]
+        );
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn keeps_meta_actions_of_kept_sub_actions_in_progress() -> eyre::Result<()> {
+        let root = tempfile::tempdir()?;
+        let action =
+            create_users_and_groups(root.path(), BUILD_USERS, "nixbld:x:30000:nixbld1,nixbld2\n")
+                .await?;
+        let plan = InstallPlan {
+            version: current_version()?,
+            actions: vec![action],
+            planner: BuiltinPlanner::default().await?.boxed(),
+            #[cfg(feature = "diagnostics")]
+            diagnostic_data: None,
+            max_concurrency: DEFAULT_MAX_CONCURRENCY,
+            uninstall_selection: UninstallSelection::All,
+        };
+        assert_eq!(
+            plan.action_tags(),
+            [
+                "add_user_to_group",
+                "create_group",
+                "create_user",
+                "create_users_and_group"
+            ]
+        );
+
+        // Like an uninstall keeping the group, which reverts everything else
+        let mut action = plan.actions[0].clone();
+        for child in action.children_mut() {
+            if child.typetag_name() != "create_group" {
+                child.set_state(ActionState::Uncompleted);
+            }
+        }
+        action.set_state(ActionState::Uncompleted);
+        assert!(keep_in_progress(&mut action));
+        assert_eq!(action.state, ActionState::Progress);
+
+        for child in action.children_mut() {
+            child.set_state(ActionState::Uncompleted);
+        }
+        action.set_state(ActionState::Uncompleted);
+        assert!(!keep_in_progress(&mut action));
+        assert_eq!(action.state, ActionState::Uncompleted);
+        Ok(())
+    }
+
+    #[test]
+    fn uninstall_selection_includes() {
+        assert!(UninstallSelection::All.includes("provision_nix"));
+
+        let keep_store = UninstallSelection::keep_store();
+        assert!(!keep_store.includes("provision_nix"));
+        assert!(!keep_store.includes("create_directory"));
+        assert!(keep_store.includes("configure_shell_profile"));
+        assert!(keep_store.includes("configure_init_service"));
+
+        let only = UninstallSelection::Only(vec!["configure_shell_profile".into()]);
+        assert!(only.includes("configure_shell_profile"));
+        assert!(!only.includes("provision_nix"));
+    }
 }
@@ -0,0 +1,212 @@
+use crate::{
+    action::{
+        base::{CreateDirectory, RemoveDirectory},
+        common::{ConfigureNix, CreateUsersAndGroups, ProvisionNix},
+        StatefulAction,
+    },
+    planner::{Planner, PlannerError},
+    settings::{CommonSettings, InstallSettingsError, UrlOrPathOrString, NIX_DIR, SCRATCH_DIR},
+    Action, BuiltinPlanner,
+};
+use std::{collections::HashMap, path::Path};
+
+use super::{
+    linux::{check_nix_not_already_installed, check_not_nixos},
+    ShellProfileLocations,
+};
+
+/**
+A planner for building Nix into Docker or Podman images, which have no init system
+
+Nix is set up for `root` with build users but no daemon, as with `--init none`, and systemd and SELinux are never
+touched. When the container can't create user namespaces the sandbox is turned off, and the scratch directory and
+caches left behind by the install are removed so the image layer stays small.
+*/
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::Parser))]
+pub struct Container {
+    /// Whether builds run in the Nix sandbox, detected from whether the container can create user namespaces if unset
+    #[cfg_attr(feature = "cli", clap(long, env = "NIX_INSTALLER_SANDBOX"))]
+    pub sandbox: Option<bool>,
+    #[cfg_attr(feature = "cli", clap(flatten))]
+    pub settings: CommonSettings,
+}
+
+#[async_trait::async_trait]
+#[typetag::serde(name = "container")]
+impl Planner for Container {
+    async fn default() -> Result<Self, PlannerError> {
+        Ok(Self {
+            sandbox: None,
+            settings: CommonSettings::default().await?,
+        })
+    }
+
+    async fn plan(&self) -> Result<Vec<StatefulAction<Box<dyn Action>>>, PlannerError> {
+        let root = Path::new("/");
+
+        let mut settings = self.settings.clone();
+        let sandbox = self.sandbox.unwrap_or_else(user_namespaces_available);
+        if !sandbox {
+            tracing::debug!("Disabling the Nix sandbox, user namespaces are unavailable");
+            settings
+                .extra_conf
+                .push(UrlOrPathOrString::String("sandbox = false".to_string()));
+        }
+
+        let mut plan = vec![];
+
+        plan.push(
+            CreateDirectory::plan(NIX_DIR, None, None, 0o0755, true)
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+        plan.push(
+            ProvisionNix::plan(&settings, root)
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+        plan.push(
+            CreateUsersAndGroups::plan(settings.clone(), root)
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+        plan.push(
+            ConfigureNix::plan(ShellProfileLocations::default(), &settings, root)
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+        plan.push(
+            RemoveDirectory::plan(SCRATCH_DIR)
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+        // Setting up the default profile fills the cache of `root`, which is dead weight in an image
+        if let Some(cache_dir) = dirs::cache_dir() {
+            plan.push(
+                RemoveDirectory::plan(cache_dir.join("nix"))
+                    .await
+                    .map_err(PlannerError::Action)?
+                    .boxed(),
+            );
+        }
+
+        Ok(plan)
+    }
+
+    fn settings(&self) -> Result<HashMap<String, serde_json::Value>, InstallSettingsError> {
+        let Self { sandbox, settings } = self;
+        let mut map = HashMap::default();
+
+        map.extend(settings.settings()?);
+        map.insert("sandbox".to_string(), serde_json::to_value(sandbox)?);
+
+        Ok(map)
+    }
+
+    async fn configured_settings(
+        &self,
+    ) -> Result<HashMap<String, serde_json::Value>, PlannerError> {
+        let default = Self::default().await?.settings()?;
+        let configured = self.settings()?;
+
+        let mut settings: HashMap<String, serde_json::Value> = HashMap::new();
+        for (key, value) in configured.iter() {
+            if default.get(key) != Some(value) {
+                settings.insert(key.clone(), value.clone());
+            }
+        }
+
+        Ok(settings)
+    }
+
+    #[cfg(feature = "diagnostics")]
+    async fn diagnostic_data(&self) -> Result<crate::diagnostics::DiagnosticData, PlannerError> {
+        Ok(crate::diagnostics::DiagnosticData::new(
+            self.settings.diagnostic_attribution.clone(),
+            self.settings.diagnostic_endpoint.clone(),
+            self.typetag_name().into(),
+            self.configured_settings()
+                .await?
+                .into_keys()
+                .collect::<Vec<_>>(),
+            self.settings.ssl_cert_file.clone(),
+        )?)
+    }
+
+    async fn pre_install_check(&self) -> Result<(), PlannerError> {
+        check_not_nixos()?;
+
+        check_nix_not_already_installed().await?;
+
+        // Some builders (such as BuildKit) leave no trace, so this is only worth a warning
+        if !detect_container(Path::new("/")) {
+            tracing::warn!("Could not detect a container, the `container` planner sets up no init system and may disable the sandbox");
+        }
+
+        Ok(())
+    }
+}
+
+impl From<Container> for BuiltinPlanner {
+    fn from(val: Container) -> Self {
+        BuiltinPlanner::Container(val)
+    }
+}
+
+/// If `root` looks like the root of a Docker (`/.dockerenv`) or Podman (`/run/.containerenv`) container
+pub(crate) fn detect_container(root: &Path) -> bool {
+    root.join(".dockerenv").exists() || root.join("run/.containerenv").exists()
+}
+
+/// If a child process can create the user, mount, and PID namespaces the Nix sandbox needs
+///
+/// Container runtimes usually forbid this with a seccomp filter unless the container is privileged.
+fn user_namespaces_available() -> bool {
+    use nix::sched::{unshare, CloneFlags};
+    use std::os::unix::process::CommandExt;
+
+    let mut command = std::process::Command::new("sh");
+    command
+        .args(["-c", "true"])
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null());
+    // SAFETY: `unshare` is a single syscall, which is safe to make between `fork` and `exec`
+    unsafe {
+        command.pre_exec(|| {
+            unshare(CloneFlags::CLONE_NEWUSER | CloneFlags::CLONE_NEWNS | CloneFlags::CLONE_NEWPID)
+                .map_err(std::io::Error::from)
+        });
+    }
+    command
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn detects_docker_and_podman() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        assert!(!detect_container(temp_dir.path()));
+
+        std::fs::write(temp_dir.path().join(".dockerenv"), "")?;
+        assert!(detect_container(temp_dir.path()));
+
+        let temp_dir = tempfile::tempdir()?;
+        std::fs::create_dir(temp_dir.path().join("run"))?;
+        std::fs::write(temp_dir.path().join("run/.containerenv"), "")?;
+        assert!(detect_container(temp_dir.path()));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,246 @@
+use crate::{
+    action::{
+        base::{CreateDirectory, RemoveDirectory},
+        common::{ConfigureNix, CreateUsersAndGroups, ProvisionNix},
+        linux::LinkSystemdUnits,
+        StatefulAction,
+    },
+    error::HasExpectedErrors,
+    plan::RECEIPT_LOCATION,
+    planner::{Planner, PlannerError},
+    settings::{rebase, CommonSettings, InitSystem, InstallSettingsError, NIX_DIR, SCRATCH_DIR},
+    Action, BuiltinPlanner,
+};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use super::ShellProfileLocations;
+
+/// A planner which installs into the root filesystem of a VM or container image mounted on the host
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::Parser))]
+pub struct Image {
+    /// Where the root filesystem of the image is mounted
+    #[cfg_attr(
+        feature = "cli",
+        clap(long, default_value = "/mnt/image", env = "NIX_INSTALLER_IMAGE_ROOT")
+    )]
+    pub root: PathBuf,
+    /// Which init system the image boots with, `systemd` units are enabled with symlinks and never started
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            value_parser,
+            long,
+            default_value_t = InitSystem::Systemd,
+            env = "NIX_INSTALLER_INIT"
+        )
+    )]
+    pub init: InitSystem,
+    #[cfg_attr(feature = "cli", clap(flatten))]
+    pub settings: CommonSettings,
+}
+
+#[async_trait::async_trait]
+#[typetag::serde(name = "image")]
+impl Planner for Image {
+    async fn default() -> Result<Self, PlannerError> {
+        Ok(Self {
+            root: PathBuf::from("/mnt/image"),
+            init: InitSystem::Systemd,
+            settings: CommonSettings::default().await?,
+        })
+    }
+
+    async fn plan(&self) -> Result<Vec<StatefulAction<Box<dyn Action>>>, PlannerError> {
+        check_image_root(&self.root)?;
+        let root = self.root.as_path();
+
+        let mut plan = vec![];
+
+        plan.push(
+            CreateDirectory::plan(rebase(root, NIX_DIR), None, None, 0o0755, true)
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+        plan.push(
+            ProvisionNix::plan(&self.settings, root)
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+        // Written straight into the `/etc/passwd` and `/etc/group` of the image
+        plan.push(
+            CreateUsersAndGroups::plan(self.settings.clone(), root)
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+        plan.push(
+            ConfigureNix::plan(
+                ShellProfileLocations::default().rebase(root),
+                &self.settings,
+                root,
+            )
+            .await
+            .map_err(PlannerError::Action)?
+            .boxed(),
+        );
+
+        match self.init {
+            InitSystem::Systemd => {
+                plan.push(
+                    LinkSystemdUnits::plan(root.to_path_buf())
+                        .await
+                        .map_err(PlannerError::Action)?
+                        .boxed(),
+                );
+            },
+            InitSystem::None => (),
+            init => return Err(ImageError::UnsupportedInit(init).into()),
+        }
+
+        plan.push(
+            RemoveDirectory::plan(rebase(root, SCRATCH_DIR))
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+
+        Ok(plan)
+    }
+
+    fn settings(&self) -> Result<HashMap<String, serde_json::Value>, InstallSettingsError> {
+        let Self {
+            root,
+            init,
+            settings,
+        } = self;
+        let mut map = HashMap::default();
+
+        map.extend(settings.settings()?);
+        map.insert("root".to_string(), serde_json::to_value(root)?);
+        map.insert("init".to_string(), serde_json::to_value(init)?);
+
+        Ok(map)
+    }
+
+    async fn configured_settings(
+        &self,
+    ) -> Result<HashMap<String, serde_json::Value>, PlannerError> {
+        let default = Self::default().await?.settings()?;
+        let configured = self.settings()?;
+
+        let mut settings: HashMap<String, serde_json::Value> = HashMap::new();
+        for (key, value) in configured.iter() {
+            if default.get(key) != Some(value) {
+                settings.insert(key.clone(), value.clone());
+            }
+        }
+
+        Ok(settings)
+    }
+
+    #[cfg(feature = "diagnostics")]
+    async fn diagnostic_data(&self) -> Result<crate::diagnostics::DiagnosticData, PlannerError> {
+        Ok(crate::diagnostics::DiagnosticData::new(
+            self.settings.diagnostic_attribution.clone(),
+            self.settings.diagnostic_endpoint.clone(),
+            self.typetag_name().into(),
+            self.configured_settings()
+                .await?
+                .into_keys()
+                .collect::<Vec<_>>(),
+            self.settings.ssl_cert_file.clone(),
+        )?)
+    }
+
+    async fn pre_uninstall_check(&self) -> Result<(), PlannerError> {
+        check_image_root(&self.root)?;
+
+        Ok(())
+    }
+
+    async fn pre_install_check(&self) -> Result<(), PlannerError> {
+        check_image_root(&self.root)?;
+
+        if self.receipt_location().exists() {
+            return Err(PlannerError::NixExists);
+        }
+
+        Ok(())
+    }
+
+    fn receipt_location(&self) -> PathBuf {
+        rebase(&self.root, RECEIPT_LOCATION)
+    }
+}
+
+impl From<Image> for BuiltinPlanner {
+    fn from(val: Image) -> Self {
+        BuiltinPlanner::Image(val)
+    }
+}
+
+fn check_image_root(root: &Path) -> Result<(), PlannerError> {
+    if !root.is_absolute() || root == Path::new("/") {
+        return Err(ImageError::InvalidRoot(root.to_path_buf()).into());
+    }
+    if !root.join("etc").is_dir() {
+        return Err(ImageError::RootMissingEtc(root.to_path_buf()).into());
+    }
+    Ok(())
+}
+
+#[non_exhaustive]
+#[derive(Debug, thiserror::Error)]
+pub enum ImageError {
+    #[error(
+        "\
+        The image root must be an absolute path other than `/`, got `{0}`.\n\
+        \n\
+        To install on this machine, use the `linux` planner instead."
+    )]
+    InvalidRoot(PathBuf),
+    #[error("The image root `{0}` has no `etc` directory, is the image filesystem mounted there?")]
+    RootMissingEtc(PathBuf),
+    #[error("The `{0}` init system can't be configured in an image yet, pass `--init systemd` or `--init none`")]
+    UnsupportedInit(InitSystem),
+}
+
+impl HasExpectedErrors for ImageError {
+    fn expected<'a>(&'a self) -> Option<Box<dyn std::error::Error + 'a>> {
+        match self {
+            ImageError::InvalidRoot(_) => Some(Box::new(self)),
+            ImageError::RootMissingEtc(_) => Some(Box::new(self)),
+            ImageError::UnsupportedInit(_) => Some(Box::new(self)),
+        }
+    }
+}
+
+impl From<ImageError> for PlannerError {
+    fn from(v: ImageError) -> PlannerError {
+        PlannerError::Custom(Box::new(v))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn image_root_must_not_be_host() -> eyre::Result<()> {
+        assert!(check_image_root(Path::new("/")).is_err());
+        assert!(check_image_root(Path::new("mnt/image")).is_err());
+
+        let temp_dir = tempfile::tempdir()?;
+        assert!(check_image_root(temp_dir.path()).is_err());
+        std::fs::create_dir(temp_dir.path().join("etc"))?;
+        assert!(check_image_root(temp_dir.path()).is_ok());
+
+        Ok(())
+    }
+}
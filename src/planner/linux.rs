@@ -1,17 +1,24 @@
 use crate::{
     action::{
         base::{CreateDirectory, RemoveDirectory},
-        common::{ConfigureInitService, ConfigureNix, CreateUsersAndGroups, ProvisionNix},
-        linux::ProvisionSelinux,
+        common::{
+            ConfigureInitService, ConfigureNix, CreateUsersAndGroups, DaemonOverrides,
+            ProvisionNix, WaitForNixDaemon,
+        },
+        linux::{CreateNixLoopbackStore, LinkNixDirectory, ProvisionSelinux, StartSystemdUnit},
         StatefulAction,
     },
     error::HasExpectedErrors,
+    plan::RECEIPT_LOCATION,
     planner::{Planner, PlannerError},
-    settings::CommonSettings,
-    settings::{InitSettings, InitSystem, InstallSettingsError},
+    settings::{CommonSettings, LocationSettings, StoreLink, UrlOrPathOrString},
+    settings::{InitSettings, InitSystem, InstallSettingsError, NIX_DIR, SCRATCH_DIR},
     Action, BuiltinPlanner,
 };
-use std::{collections::HashMap, path::Path};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 use tokio::process::Command;
 use which::which;
 
@@ -25,6 +32,9 @@ pub struct Linux {
     pub settings: CommonSettings,
     #[cfg_attr(feature = "cli", clap(flatten))]
     pub init: InitSettings,
+    #[cfg_attr(feature = "cli", clap(flatten))]
+    #[serde(default)]
+    pub location: LocationSettings,
 }
 
 #[async_trait::async_trait]
@@ -34,38 +44,110 @@ impl Planner for Linux {
         Ok(Self {
             settings: CommonSettings::default().await?,
             init: InitSettings::default().await?,
+            location: LocationSettings::default(),
         })
     }
 
     async fn plan(&self) -> Result<Vec<StatefulAction<Box<dyn Action>>>, PlannerError> {
-        let has_selinux = detect_selinux().await?;
+        let root = self.location.root.as_path();
+        check_location(&self.location)?;
+        let init = self.init.init_system();
+        if root != Path::new("/") && init != InitSystem::None {
+            return Err(LinuxErrorKind::RootWithInit(root.to_path_buf()).into());
+        }
+        if self.location.store_image.is_some()
+            && (init != InitSystem::Systemd || !self.init.start_daemon)
+        {
+            return Err(LinuxErrorKind::StoreImageRequiresSystemd.into());
+        }
+
+        // The SELinux policy of the host says nothing about the one inside of `root`
+        let has_selinux = root == Path::new("/") && detect_selinux().await?;
 
         let mut plan = vec![];
 
-        plan.push(
-            CreateDirectory::plan("/nix", None, None, 0o0755, true)
-                .await
-                .map_err(PlannerError::Action)?
-                .boxed(),
-        );
+        let mut settings = self.settings.clone();
+        match &self.location.store_location {
+            Some(store_location) => {
+                plan.push(
+                    CreateDirectory::plan(
+                        self.location.rebase(store_location),
+                        None,
+                        None,
+                        0o0755,
+                        true,
+                    )
+                    .await
+                    .map_err(PlannerError::Action)?
+                    .boxed(),
+                );
+                plan.push(
+                    LinkNixDirectory::plan(
+                        store_location.clone(),
+                        self.location.store_link,
+                        root.to_path_buf(),
+                    )
+                    .await
+                    .map_err(PlannerError::Action)?
+                    .boxed(),
+                );
+                if self.location.store_link == StoreLink::Symlink {
+                    settings.extra_conf.push(UrlOrPathOrString::String(
+                        "allow-symlinked-store = true".to_string(),
+                    ));
+                }
+            },
+            None => match &self.location.store_image {
+                Some(store_image) => {
+                    plan.push(
+                        CreateNixLoopbackStore::plan(
+                            store_image,
+                            self.location.store_image_size,
+                            self.location.store_image_filesystem,
+                        )
+                        .await
+                        .map_err(PlannerError::Action)?
+                        .boxed(),
+                    );
+                },
+                None => {
+                    plan.push(
+                        CreateDirectory::plan(
+                            self.location.rebase(NIX_DIR),
+                            None,
+                            None,
+                            0o0755,
+                            true,
+                        )
+                        .await
+                        .map_err(PlannerError::Action)?
+                        .boxed(),
+                    );
+                },
+            },
+        }
 
         plan.push(
-            ProvisionNix::plan(&self.settings.clone())
+            ProvisionNix::plan(&settings, root)
                 .await
                 .map_err(PlannerError::Action)?
                 .boxed(),
         );
         plan.push(
-            CreateUsersAndGroups::plan(self.settings.clone())
+            CreateUsersAndGroups::plan(settings.clone(), root)
                 .await
                 .map_err(PlannerError::Action)?
                 .boxed(),
         );
         plan.push(
-            ConfigureNix::plan(ShellProfileLocations::default(), &self.settings)
-                .await
-                .map_err(PlannerError::Action)?
-                .boxed(),
+            ConfigureNix::plan(
+                ShellProfileLocations::default().rebase(root),
+                &settings,
+                root,
+            )
+            .await
+            .map_err(PlannerError::Action)?
+            .boxed(),
         );
 
         if has_selinux {
@@ -78,20 +160,47 @@ impl Planner for Linux {
         }
 
         plan.push(
-            CreateDirectory::plan("/etc/tmpfiles.d", None, None, 0o0755, false)
-                .await
-                .map_err(PlannerError::Action)?
-                .boxed(),
+            CreateDirectory::plan(
+                self.location.rebase("/etc/tmpfiles.d"),
+                None,
+                None,
+                0o0755,
+                false,
+            )
+            .await
+            .map_err(PlannerError::Action)?
+            .boxed(),
         );
 
         plan.push(
-            ConfigureInitService::plan(self.init.init, self.init.start_daemon)
-                .await
-                .map_err(PlannerError::Action)?
-                .boxed(),
+            ConfigureInitService::plan(
+                init,
+                self.init.start_daemon,
+                DaemonOverrides::new(&settings, Some(&self.init)),
+            )
+            .await
+            .map_err(PlannerError::Action)?
+            .boxed(),
         );
+        if self.location.store_image.is_some() {
+            // The daemon units are symlinked into the image, which is mounted after systemd loads units at boot
+            plan.push(
+                StartSystemdUnit::plan("ensure-symlinked-units-resolve.service".to_string(), true)
+                    .await
+                    .map_err(PlannerError::Action)?
+                    .boxed(),
+            );
+        }
+        if init != InitSystem::None && self.init.start_daemon {
+            plan.push(
+                WaitForNixDaemon::plan(init)
+                    .await
+                    .map_err(PlannerError::Action)?
+                    .boxed(),
+            );
+        }
         plan.push(
-            RemoveDirectory::plan(crate::settings::SCRATCH_DIR)
+            RemoveDirectory::plan(self.location.rebase(SCRATCH_DIR))
                 .await
                 .map_err(PlannerError::Action)?
                 .boxed(),
@@ -101,11 +210,16 @@ impl Planner for Linux {
     }
 
     fn settings(&self) -> Result<HashMap<String, serde_json::Value>, InstallSettingsError> {
-        let Self { settings, init } = self;
+        let Self {
+            settings,
+            init,
+            location,
+        } = self;
         let mut map = HashMap::default();
 
         map.extend(settings.settings()?);
         map.extend(init.settings()?);
+        map.extend(location.settings()?);
 
         Ok(map)
     }
@@ -142,7 +256,7 @@ impl Planner for Linux {
     async fn pre_uninstall_check(&self) -> Result<(), PlannerError> {
         check_not_wsl1()?;
 
-        if self.init.init == InitSystem::Systemd && self.init.start_daemon {
+        if self.init.init_system() == InitSystem::Systemd && self.init.start_daemon {
             check_systemd_active()?;
         }
 
@@ -150,18 +264,29 @@ impl Planner for Linux {
     }
 
     async fn pre_install_check(&self) -> Result<(), PlannerError> {
-        check_not_nixos()?;
+        check_location(&self.location)?;
 
-        check_nix_not_already_installed().await?;
+        // When installing into another root, what the host runs doesn't matter
+        if self.location.root == Path::new("/") {
+            check_not_nixos()?;
+
+            check_nix_not_already_installed().await?;
+        } else if self.location.rebase(RECEIPT_LOCATION).exists() {
+            return Err(PlannerError::NixExists);
+        }
 
         check_not_wsl1()?;
 
-        if self.init.init == InitSystem::Systemd && self.init.start_daemon {
+        if self.init.init_system() == InitSystem::Systemd && self.init.start_daemon {
             check_systemd_active()?;
         }
 
         Ok(())
     }
+
+    fn receipt_location(&self) -> PathBuf {
+        self.location.rebase(RECEIPT_LOCATION)
+    }
 }
 
 impl From<Linux> for BuiltinPlanner {
@@ -217,6 +342,29 @@ pub(crate) async fn check_nix_not_already_installed() -> Result<(), PlannerError
     Ok(())
 }
 
+pub(crate) fn check_location(location: &LocationSettings) -> Result<(), PlannerError> {
+    if !location.root.is_absolute() {
+        return Err(LinuxErrorKind::RelativeRoot(location.root.clone()).into());
+    }
+    if let Some(store_location) = &location.store_location {
+        if !store_location.is_absolute()
+            || store_location.starts_with(NIX_DIR)
+            || Path::new(NIX_DIR).starts_with(store_location)
+        {
+            return Err(LinuxErrorKind::InvalidStoreLocation(store_location.clone()).into());
+        }
+    }
+    if let Some(store_image) = &location.store_image {
+        if location.store_location.is_some() {
+            return Err(LinuxErrorKind::StoreImageWithStoreLocation.into());
+        }
+        if !store_image.is_absolute() || store_image.starts_with(NIX_DIR) {
+            return Err(LinuxErrorKind::InvalidStoreImage(store_image.clone()).into());
+        }
+    }
+    Ok(())
+}
+
 pub(crate) fn check_systemd_active() -> Result<(), PlannerError> {
     if !Path::new("/run/systemd/system").exists() {
         if std::env::var("WSL_DISTRO_NAME").is_ok() {
@@ -247,11 +395,38 @@ pub enum LinuxErrorKind {
         \n\
         On WSL2, systemd is not enabled by default. Consider enabling it by adding it to your `/etc/wsl.conf` with `echo -e '[boot]\\nsystemd=true'` then restarting WSL2 with `wsl.exe --shutdown` and re-entering the WSL shell. For more information, see https://devblogs.microsoft.com/commandline/systemd-support-is-now-available-in-wsl/.\n\
         \n\
+        The `wsl2` planner (`nix-installer install wsl2`) can do this for you.\n\
+        \n\
         If it will be started later consider, passing `--no-start-daemon`.\n\
         \n\
         To use a `root`-only Nix install, consider passing `--init none`."
     )]
     Wsl2SystemdNotActive,
+    #[error("The root to install into, `{0}`, must be an absolute path")]
+    RelativeRoot(PathBuf),
+    #[error(
+        "\
+        The store location `{0}` must be an absolute path outside of `/nix`, such as `/opt/nix`"
+    )]
+    InvalidStoreLocation(PathBuf),
+    #[error(
+        "\
+        An init system can't be configured when installing into `{0}`.\n\
+        \n\
+        Pass `--init none` to skip configuring the Nix daemon."
+    )]
+    RootWithInit(PathBuf),
+    #[error("Only one of `--store-location` and `--store-image` can be used")]
+    StoreImageWithStoreLocation,
+    #[error("The store image `{0}` must be an absolute path outside of `/nix`, such as `/var/lib/nix.img`")]
+    InvalidStoreImage(PathBuf),
+    #[error(
+        "\
+        The store image is mounted by systemd, which must be running.\n\
+        \n\
+        Pass `--init systemd`, or use `--store-location` instead."
+    )]
+    StoreImageRequiresSystemd,
 }
 
 impl HasExpectedErrors for LinuxErrorKind {
@@ -259,6 +434,12 @@ impl HasExpectedErrors for LinuxErrorKind {
         match self {
             LinuxErrorKind::SystemdNotActive => Some(Box::new(self)),
             LinuxErrorKind::Wsl2SystemdNotActive => Some(Box::new(self)),
+            LinuxErrorKind::RelativeRoot(_) => Some(Box::new(self)),
+            LinuxErrorKind::InvalidStoreLocation(_) => Some(Box::new(self)),
+            LinuxErrorKind::RootWithInit(_) => Some(Box::new(self)),
+            LinuxErrorKind::StoreImageWithStoreLocation => Some(Box::new(self)),
+            LinuxErrorKind::InvalidStoreImage(_) => Some(Box::new(self)),
+            LinuxErrorKind::StoreImageRequiresSystemd => Some(Box::new(self)),
         }
     }
 }
@@ -1,4 +1,8 @@
-use std::{collections::HashMap, io::Cursor, path::PathBuf};
+use std::{
+    collections::HashMap,
+    io::Cursor,
+    path::{Path, PathBuf},
+};
 
 #[cfg(feature = "cli")]
 use clap::ArgAction;
@@ -11,7 +15,10 @@ use crate::planner::HasExpectedErrors;
 use crate::{
     action::{
         base::RemoveDirectory,
-        common::{ConfigureInitService, ConfigureNix, CreateUsersAndGroups, ProvisionNix},
+        common::{
+            ConfigureInitService, ConfigureNix, CreateUsersAndGroups, DaemonOverrides,
+            ProvisionNix, WaitForNixDaemon,
+        },
         macos::{
             ConfigureRemoteBuilding, CreateNixHookService, CreateNixVolume, SetTmutilExclusions,
         },
@@ -143,7 +150,7 @@ impl Planner for Macos {
             .boxed(),
         );
         plan.push(
-            ProvisionNix::plan(&self.settings)
+            ProvisionNix::plan(&self.settings, Path::new("/"))
                 .await
                 .map_err(PlannerError::Action)?
                 .boxed(),
@@ -151,7 +158,7 @@ impl Planner for Macos {
         // Auto-allocate uids is broken on Mac. Tools like `whoami` don't work.
         // e.g. https://github.com/NixOS/nix/issues/8444
         plan.push(
-            CreateUsersAndGroups::plan(self.settings.clone())
+            CreateUsersAndGroups::plan(self.settings.clone(), Path::new("/"))
                 .await
                 .map_err(PlannerError::Action)?
                 .boxed(),
@@ -163,10 +170,14 @@ impl Planner for Macos {
                 .boxed(),
         );
         plan.push(
-            ConfigureNix::plan(ShellProfileLocations::default(), &self.settings)
-                .await
-                .map_err(PlannerError::Action)?
-                .boxed(),
+            ConfigureNix::plan(
+                ShellProfileLocations::default(),
+                &self.settings,
+                Path::new("/"),
+            )
+            .await
+            .map_err(PlannerError::Action)?
+            .boxed(),
         );
         plan.push(
             ConfigureRemoteBuilding::plan()
@@ -185,7 +196,13 @@ impl Planner for Macos {
         }
 
         plan.push(
-            ConfigureInitService::plan(InitSystem::Launchd, true)
+            ConfigureInitService::plan(InitSystem::Launchd, true, DaemonOverrides::default())
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+        plan.push(
+            WaitForNixDaemon::plan(InitSystem::Launchd)
                 .await
                 .map_err(PlannerError::Action)?
                 .boxed(),
@@ -0,0 +1,419 @@
+use crate::{
+    action::{
+        base::{CreateDirectory, RemoveDirectory},
+        common::{
+            ConfigureInitService, ConfigureNix, CreateUsersAndGroups, DaemonOverrides,
+            ProvisionNix, WaitForNixDaemon,
+        },
+        linux::{
+            nix_directory_unit, NixMountStrategy, PersistentNixMount, ProvisionSelinux,
+            StartSystemdUnit, SystemctlDaemonReload,
+        },
+        StatefulAction,
+    },
+    error::HasExpectedErrors,
+    planner::{Planner, PlannerError},
+    settings::{CommonSettings, InitSystem, InstallSettingsError, SCRATCH_DIR},
+    Action, BuiltinPlanner,
+};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use super::{
+    linux::{
+        check_nix_not_already_installed, check_not_nixos, check_not_wsl1, check_systemd_active,
+        detect_selinux,
+    },
+    ShellProfileLocations,
+};
+
+/// The `ID`s in `/etc/os-release` of the transactional openSUSE and SUSE distributions
+const MICROOS_IDS: &[&str] = &["opensuse-microos", "opensuse-leap-micro", "sle-micro"];
+
+/// Make `/` writable, leaving a mark in `/run` for each change so [`LOCK_ROOT`] only undoes those
+///
+/// The root is mounted `ro` through `/etc/fstab` and its snapshot has the btrfs `ro` property, either is enough to
+/// stop `mkdir /nix`.
+const UNLOCK_ROOT: &str = "/bin/sh -c '\
+    case \",$$(findmnt -n -o OPTIONS /),\" in *,ro,*) \
+        mount -o remount,rw / || exit 1; touch /run/nix-directory-remounted;; \
+    esac; \
+    if [ \"$$(btrfs property get / ro)\" = ro=true ]; then \
+        btrfs property set / ro false || exit 1; touch /run/nix-directory-unlocked; \
+    fi'";
+
+/// Undo what [`UNLOCK_ROOT`] changed, leaving a root which was writable before as it was
+const LOCK_ROOT: &str = "/bin/sh -c '\
+    if [ -e /run/nix-directory-unlocked ]; then \
+        btrfs property set / ro true; rm -f /run/nix-directory-unlocked; \
+    fi; \
+    if [ -e /run/nix-directory-remounted ]; then \
+        mount -o remount,ro /; rm -f /run/nix-directory-remounted; \
+    fi'";
+
+/**
+A planner for openSUSE MicroOS and other systems updated with `transactional-update`, which have a read-only btrfs root
+
+Like the [`Ostree`](super::ostree::Ostree) planner, the store lives in a btrfs subvolume under `/var`, which snapshots
+of the root leave alone, and is bind mounted on `/nix` by systemd units. The `/nix` mount point is created in the
+current root, which later snapshots inherit, and the changes to `/etc` are carried into new snapshots by
+`transactional-update`, so nothing is lost on rollback or update.
+*/
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::Parser))]
+pub struct Microos {
+    /// The btrfs subvolume which will be bind mounted to `/nix`
+    #[cfg_attr(feature = "cli", clap(long, default_value = "/var/lib/nix"))]
+    persistence: PathBuf,
+    #[cfg_attr(feature = "cli", clap(flatten))]
+    pub settings: CommonSettings,
+}
+
+#[async_trait::async_trait]
+#[typetag::serde(name = "microos")]
+impl Planner for Microos {
+    async fn default() -> Result<Self, PlannerError> {
+        Ok(Self {
+            persistence: PathBuf::from("/var/lib/nix"),
+            settings: CommonSettings::default().await?,
+        })
+    }
+
+    async fn plan(&self) -> Result<Vec<StatefulAction<Box<dyn Action>>>, PlannerError> {
+        let has_selinux = detect_selinux().await?;
+        let mut plan = vec![
+            // Primarily for uninstall
+            SystemctlDaemonReload::plan()
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        ];
+
+        // The root snapshot is read-only, so it is made writable just long enough to add the mount point
+        plan.push(
+            PersistentNixMount::plan(
+                &self.persistence,
+                NixMountStrategy::BtrfsSubvolume,
+                Some(nix_directory_unit(
+                    "Create the /nix mount point in the read-only root",
+                    UNLOCK_ROOT,
+                    LOCK_ROOT,
+                )),
+            )
+            .await
+            .map_err(PlannerError::Action)?
+            .boxed(),
+        );
+
+        // `/usr` is part of the read-only snapshot, so fish can only be configured from `/etc`
+        let mut shell_profile_locations = ShellProfileLocations::default();
+        shell_profile_locations
+            .fish
+            .vendor_confd_prefixes
+            .retain(|v| v != Path::new("/usr/share/fish/"));
+
+        plan.push(
+            ProvisionNix::plan(&self.settings, Path::new("/"))
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+        plan.push(
+            CreateUsersAndGroups::plan(self.settings.clone(), Path::new("/"))
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+        plan.push(
+            ConfigureNix::plan(shell_profile_locations, &self.settings, Path::new("/"))
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+
+        if has_selinux {
+            plan.push(
+                ProvisionSelinux::plan("/etc/nix-installer/selinux/packages/nix.pp".into())
+                    .await
+                    .map_err(PlannerError::Action)?
+                    .boxed(),
+            );
+        }
+
+        plan.push(
+            CreateDirectory::plan("/etc/tmpfiles.d", None, None, 0o0755, false)
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+
+        plan.push(
+            ConfigureInitService::plan(
+                InitSystem::Systemd,
+                true,
+                DaemonOverrides::new(&self.settings, None),
+            )
+            .await
+            .map_err(PlannerError::Action)?
+            .boxed(),
+        );
+        plan.push(
+            WaitForNixDaemon::plan(InitSystem::Systemd)
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+        plan.push(
+            StartSystemdUnit::plan("ensure-symlinked-units-resolve.service".to_string(), true)
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+        plan.push(
+            RemoveDirectory::plan(SCRATCH_DIR)
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+        plan.push(
+            SystemctlDaemonReload::plan()
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+
+        Ok(plan)
+    }
+
+    fn settings(&self) -> Result<HashMap<String, serde_json::Value>, InstallSettingsError> {
+        let Self {
+            persistence,
+            settings,
+        } = self;
+        let mut map = HashMap::default();
+
+        map.extend(settings.settings()?);
+        map.insert(
+            "persistence".to_string(),
+            serde_json::to_value(persistence)?,
+        );
+
+        Ok(map)
+    }
+
+    async fn configured_settings(
+        &self,
+    ) -> Result<HashMap<String, serde_json::Value>, PlannerError> {
+        let default = Self::default().await?.settings()?;
+        let configured = self.settings()?;
+
+        let mut settings: HashMap<String, serde_json::Value> = HashMap::new();
+        for (key, value) in configured.iter() {
+            if default.get(key) != Some(value) {
+                settings.insert(key.clone(), value.clone());
+            }
+        }
+
+        Ok(settings)
+    }
+
+    #[cfg(feature = "diagnostics")]
+    async fn diagnostic_data(&self) -> Result<crate::diagnostics::DiagnosticData, PlannerError> {
+        Ok(crate::diagnostics::DiagnosticData::new(
+            self.settings.diagnostic_attribution.clone(),
+            self.settings.diagnostic_endpoint.clone(),
+            self.typetag_name().into(),
+            self.configured_settings()
+                .await?
+                .into_keys()
+                .collect::<Vec<_>>(),
+            self.settings.ssl_cert_file.clone(),
+        )?)
+    }
+
+    async fn pre_uninstall_check(&self) -> Result<(), PlannerError> {
+        check_not_wsl1()?;
+
+        check_systemd_active()?;
+
+        check_no_pending_snapshot().await?;
+
+        Ok(())
+    }
+
+    async fn pre_install_check(&self) -> Result<(), PlannerError> {
+        check_not_nixos()?;
+
+        check_nix_not_already_installed().await?;
+
+        check_not_wsl1()?;
+
+        check_systemd_active()?;
+
+        if which::which("transactional-update").is_err() {
+            return Err(MicroosError::NotTransactional.into());
+        }
+
+        check_no_pending_snapshot().await?;
+
+        Ok(())
+    }
+}
+
+impl From<Microos> for BuiltinPlanner {
+    fn from(val: Microos) -> Self {
+        BuiltinPlanner::Microos(val)
+    }
+}
+
+/// If this host is MicroOS, Leap Micro, SLE Micro, or another system with a read-only root updated with
+/// `transactional-update`
+///
+/// `transactional-update` alone isn't enough, as it can be installed on a writable Tumbleweed.
+pub(crate) fn detect_microos() -> bool {
+    let by_id = os_release::OsRelease::new()
+        .is_ok_and(|os_release| MICROOS_IDS.contains(&os_release.id.as_str()));
+    if by_id {
+        return true;
+    }
+    let mountinfo = std::fs::read_to_string("/proc/self/mountinfo").unwrap_or_default();
+    root_is_read_only(&mountinfo) && which::which("transactional-update").is_ok()
+}
+
+/// If `/` is mounted `ro`, from its entry in `/proc/self/mountinfo`
+fn root_is_read_only(mountinfo: &str) -> bool {
+    mountinfo.lines().any(|line| {
+        let mut fields = line.split_whitespace();
+        let mount_point = fields.nth(4);
+        let options = fields.next().unwrap_or_default();
+        mount_point == Some("/") && options.split(',').any(|v| v == "ro")
+    })
+}
+
+/// Changes to `/etc` made after `transactional-update` created a snapshot are not in that snapshot, so they would be
+/// lost when it is booted
+async fn check_no_pending_snapshot() -> Result<(), PlannerError> {
+    let output = tokio::process::Command::new("btrfs")
+        .args(["subvolume", "get-default", "/"])
+        .stdin(std::process::Stdio::null())
+        .output()
+        .await
+        .map_err(|e| PlannerError::Command("btrfs".to_string(), e))?;
+    if !output.status.success() {
+        // Not on btrfs, so there are no snapshots to worry about
+        return Ok(());
+    }
+    let mountinfo = tokio::fs::read_to_string("/proc/self/mountinfo")
+        .await
+        .unwrap_or_default();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let default = parse_default_subvolume(&stdout);
+    let booted = booted_subvolume(&mountinfo);
+    match (default, booted) {
+        (Some(default), Some(booted)) if default != booted => {
+            Err(MicroosError::PendingSnapshot(default.to_string()).into())
+        },
+        _ => Ok(()),
+    }
+}
+
+/// The path of the default subvolume from the output of `btrfs subvolume get-default`, such as
+/// `ID 268 gen 1490 top level 267 path @/.snapshots/5/snapshot`
+fn parse_default_subvolume(output: &str) -> Option<&str> {
+    output
+        .lines()
+        .next()?
+        .split_once(" path ")
+        .map(|(_, path)| path.trim().trim_start_matches('/'))
+}
+
+/// The subvolume mounted on `/`, from the root of its entry in `/proc/self/mountinfo`
+fn booted_subvolume(mountinfo: &str) -> Option<&str> {
+    mountinfo.lines().find_map(|line| {
+        let mut fields = line.split_whitespace();
+        let root = fields.nth(3)?;
+        let mount_point = fields.next()?;
+        (mount_point == "/").then(|| root.trim_start_matches('/'))
+    })
+}
+
+#[non_exhaustive]
+#[derive(Debug, thiserror::Error)]
+pub enum MicroosError {
+    #[error(
+        "`transactional-update` was not found, this does not look like a transactional system"
+    )]
+    NotTransactional,
+    #[error(
+        "\
+        A snapshot created by `transactional-update` is waiting to be booted (`{0}`).\n\
+        \n\
+        Changes made to `/etc` now would be missing from it, reboot into it and try again."
+    )]
+    PendingSnapshot(String),
+}
+
+impl HasExpectedErrors for MicroosError {
+    fn expected<'a>(&'a self) -> Option<Box<dyn std::error::Error + 'a>> {
+        match self {
+            MicroosError::NotTransactional => Some(Box::new(self)),
+            MicroosError::PendingSnapshot(_) => Some(Box::new(self)),
+        }
+    }
+}
+
+impl From<MicroosError> for PlannerError {
+    fn from(v: MicroosError) -> PlannerError {
+        PlannerError::Custom(Box::new(v))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn detects_pending_snapshot() {
+        let mountinfo = "\
+            23 1 0:21 /@/.snapshots/4/snapshot / ro,relatime shared:1 - btrfs /dev/vda3 rw,subvolid=267\n\
+            45 23 0:21 /@/var /var rw,relatime shared:23 - btrfs /dev/vda3 rw,subvolid=259\n";
+        assert_eq!(booted_subvolume(mountinfo), Some("@/.snapshots/4/snapshot"));
+        assert!(root_is_read_only(mountinfo));
+        assert!(!root_is_read_only(
+            "23 1 0:21 /@/.snapshots/1/snapshot / rw,relatime,errors=remount-ro shared:1 - btrfs /dev/vda3 rw\n"
+        ));
+
+        assert_eq!(
+            parse_default_subvolume("ID 267 gen 1490 top level 266 path @/.snapshots/4/snapshot\n"),
+            booted_subvolume(mountinfo)
+        );
+        assert_eq!(
+            parse_default_subvolume("ID 270 gen 1502 top level 266 path @/.snapshots/5/snapshot\n"),
+            Some("@/.snapshots/5/snapshot")
+        );
+        assert_eq!(
+            parse_default_subvolume("ERROR: not a btrfs filesystem"),
+            None
+        );
+    }
+
+    #[test]
+    fn root_scripts_parse() -> eyre::Result<()> {
+        for command in [UNLOCK_ROOT, LOCK_ROOT] {
+            // As systemd runs it, with `$$` unescaped
+            let script = command
+                .strip_prefix("/bin/sh -c '")
+                .and_then(|v| v.strip_suffix('\''))
+                .expect("a quoted `sh` script")
+                .replace("$$", "$");
+            let parsed = std::process::Command::new("sh")
+                .args(["-n", "-c", &script])
+                .status()?;
+            assert!(parsed.success(), "{script}");
+        }
+        Ok(())
+    }
+}
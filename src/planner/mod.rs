@@ -104,22 +104,37 @@ match plan.install(None).await {
 
 */
 #[cfg(target_os = "linux")]
+pub mod container;
+#[cfg(target_os = "linux")]
+pub mod image;
+#[cfg(target_os = "linux")]
 pub mod linux;
 #[cfg(target_os = "macos")]
 pub mod macos;
 #[cfg(target_os = "linux")]
+pub mod microos;
+#[cfg(target_os = "linux")]
 pub mod ostree;
 #[cfg(target_os = "linux")]
+pub mod single_user;
+#[cfg(target_os = "linux")]
 pub mod steam_deck;
+#[cfg(target_os = "linux")]
+pub mod wsl2;
 
-use std::{collections::HashMap, path::PathBuf, string::FromUtf8Error};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    string::FromUtf8Error,
+};
 
 use serde::{Deserialize, Serialize};
 
 use crate::{
     action::{ActionError, StatefulAction},
     error::HasExpectedErrors,
-    settings::{CommonSettings, InstallSettingsError},
+    plan::RECEIPT_LOCATION,
+    settings::{rebase, CommonSettings, InstallSettingsError},
     Action, InstallPlan, NixInstallerError,
 };
 
@@ -155,6 +170,21 @@ pub trait Planner: std::fmt::Debug + Send + Sync + dyn_clone::DynClone {
         Ok(())
     }
 
+    /// Where the receipt of an [`InstallPlan`] from this planner is written
+    fn receipt_location(&self) -> PathBuf {
+        PathBuf::from(RECEIPT_LOCATION)
+    }
+
+    /// If installing or uninstalling needs to run as `root`
+    fn requires_root(&self) -> bool {
+        true
+    }
+
+    /// Anything the user must do after a successful install before Nix is usable, such as restarting
+    fn post_install_message(&self) -> Option<String> {
+        None
+    }
+
     #[cfg(feature = "diagnostics")]
     async fn diagnostic_data(&self) -> Result<crate::diagnostics::DiagnosticData, PlannerError>;
 }
@@ -174,6 +204,21 @@ pub enum BuiltinPlanner {
     /// A planner suitable for immutable systems using ostree, such as Fedora Silverblue
     #[cfg(target_os = "linux")]
     Ostree(ostree::Ostree),
+    /// A planner which installs into a mounted VM or container image, without starting anything
+    #[cfg(target_os = "linux")]
+    Image(image::Image),
+    /// A planner for a rootless install owned by the current user, without a daemon or build users
+    #[cfg(target_os = "linux")]
+    SingleUser(single_user::SingleUser),
+    /// A planner for Docker or Podman images, with no init system and caches cleaned up
+    #[cfg(target_os = "linux")]
+    Container(container::Container),
+    /// A planner for WSL2, which enables systemd in `/etc/wsl.conf` if it isn't running
+    #[cfg(target_os = "linux")]
+    Wsl2(wsl2::Wsl2),
+    /// A planner for openSUSE MicroOS and other systems updated with `transactional-update`
+    #[cfg(target_os = "linux")]
+    Microos(microos::Microos),
     /// A planner for MacOS (Darwin) systems
     #[cfg(target_os = "macos")]
     Macos(macos::Macos),
@@ -210,12 +255,30 @@ impl BuiltinPlanner {
 
     #[cfg(target_os = "linux")]
     async fn detect_linux_distro() -> Result<Self, PlannerError> {
+        // Containers booted with systemd are better served by the `linux` planner
+        if container::detect_container(std::path::Path::new("/"))
+            && !std::path::Path::new("/run/systemd/system").exists()
+        {
+            return Ok(Self::Container(container::Container::default().await?));
+        }
+
+        // WSL2 hosts which already boot with systemd are better served by the `linux` planner too
+        if wsl2::detect_host_wsl() == Some(wsl2::WslVersion::Wsl2)
+            && !std::path::Path::new("/run/systemd/system").exists()
+        {
+            return Ok(Self::Wsl2(wsl2::Wsl2::default().await?));
+        }
+
         let is_steam_deck =
             os_release::OsRelease::new().is_ok_and(|os_release| os_release.id == "steamos");
         if is_steam_deck {
             return Ok(Self::SteamDeck(steam_deck::SteamDeck::default().await?));
         }
 
+        if microos::detect_microos() {
+            return Ok(Self::Microos(microos::Microos::default().await?));
+        }
+
         let is_ostree = std::process::Command::new("ostree")
             .arg("remote")
             .arg("list")
@@ -237,6 +300,16 @@ impl BuiltinPlanner {
             BuiltinPlanner::SteamDeck(inner) => inner.settings = settings,
             #[cfg(target_os = "linux")]
             BuiltinPlanner::Ostree(inner) => inner.settings = settings,
+            #[cfg(target_os = "linux")]
+            BuiltinPlanner::Image(inner) => inner.settings = settings,
+            #[cfg(target_os = "linux")]
+            BuiltinPlanner::SingleUser(inner) => inner.settings = settings,
+            #[cfg(target_os = "linux")]
+            BuiltinPlanner::Container(inner) => inner.settings = settings,
+            #[cfg(target_os = "linux")]
+            BuiltinPlanner::Wsl2(inner) => inner.settings = settings,
+            #[cfg(target_os = "linux")]
+            BuiltinPlanner::Microos(inner) => inner.settings = settings,
             #[cfg(target_os = "macos")]
             BuiltinPlanner::Macos(inner) => inner.settings = settings,
         }
@@ -253,6 +326,16 @@ impl BuiltinPlanner {
             BuiltinPlanner::SteamDeck(inner) => inner.configured_settings().await,
             #[cfg(target_os = "linux")]
             BuiltinPlanner::Ostree(inner) => inner.configured_settings().await,
+            #[cfg(target_os = "linux")]
+            BuiltinPlanner::Image(inner) => inner.configured_settings().await,
+            #[cfg(target_os = "linux")]
+            BuiltinPlanner::SingleUser(inner) => inner.configured_settings().await,
+            #[cfg(target_os = "linux")]
+            BuiltinPlanner::Container(inner) => inner.configured_settings().await,
+            #[cfg(target_os = "linux")]
+            BuiltinPlanner::Wsl2(inner) => inner.configured_settings().await,
+            #[cfg(target_os = "linux")]
+            BuiltinPlanner::Microos(inner) => inner.configured_settings().await,
             #[cfg(target_os = "macos")]
             BuiltinPlanner::Macos(inner) => inner.configured_settings().await,
         }
@@ -266,10 +349,43 @@ impl BuiltinPlanner {
             BuiltinPlanner::SteamDeck(planner) => InstallPlan::plan(planner).await,
             #[cfg(target_os = "linux")]
             BuiltinPlanner::Ostree(planner) => InstallPlan::plan(planner).await,
+            #[cfg(target_os = "linux")]
+            BuiltinPlanner::Image(planner) => InstallPlan::plan(planner).await,
+            #[cfg(target_os = "linux")]
+            BuiltinPlanner::SingleUser(planner) => InstallPlan::plan(planner).await,
+            #[cfg(target_os = "linux")]
+            BuiltinPlanner::Container(planner) => InstallPlan::plan(planner).await,
+            #[cfg(target_os = "linux")]
+            BuiltinPlanner::Wsl2(planner) => InstallPlan::plan(planner).await,
+            #[cfg(target_os = "linux")]
+            BuiltinPlanner::Microos(planner) => InstallPlan::plan(planner).await,
             #[cfg(target_os = "macos")]
             BuiltinPlanner::Macos(planner) => InstallPlan::plan(planner).await,
         }
     }
+    /// Plan against a host where Nix was installed by other means, see [`InstallPlan::adopt`]
+    pub async fn adopt(self) -> Result<InstallPlan, NixInstallerError> {
+        match self {
+            #[cfg(target_os = "linux")]
+            BuiltinPlanner::Linux(planner) => InstallPlan::adopt(planner).await,
+            #[cfg(target_os = "linux")]
+            BuiltinPlanner::SteamDeck(planner) => InstallPlan::adopt(planner).await,
+            #[cfg(target_os = "linux")]
+            BuiltinPlanner::Ostree(planner) => InstallPlan::adopt(planner).await,
+            #[cfg(target_os = "linux")]
+            BuiltinPlanner::Image(planner) => InstallPlan::adopt(planner).await,
+            #[cfg(target_os = "linux")]
+            BuiltinPlanner::SingleUser(planner) => InstallPlan::adopt(planner).await,
+            #[cfg(target_os = "linux")]
+            BuiltinPlanner::Container(planner) => InstallPlan::adopt(planner).await,
+            #[cfg(target_os = "linux")]
+            BuiltinPlanner::Wsl2(planner) => InstallPlan::adopt(planner).await,
+            #[cfg(target_os = "linux")]
+            BuiltinPlanner::Microos(planner) => InstallPlan::adopt(planner).await,
+            #[cfg(target_os = "macos")]
+            BuiltinPlanner::Macos(planner) => InstallPlan::adopt(planner).await,
+        }
+    }
     pub fn boxed(self) -> Box<dyn Planner> {
         match self {
             #[cfg(target_os = "linux")]
@@ -278,6 +394,16 @@ impl BuiltinPlanner {
             BuiltinPlanner::SteamDeck(i) => i.boxed(),
             #[cfg(target_os = "linux")]
             BuiltinPlanner::Ostree(i) => i.boxed(),
+            #[cfg(target_os = "linux")]
+            BuiltinPlanner::Image(i) => i.boxed(),
+            #[cfg(target_os = "linux")]
+            BuiltinPlanner::SingleUser(i) => i.boxed(),
+            #[cfg(target_os = "linux")]
+            BuiltinPlanner::Container(i) => i.boxed(),
+            #[cfg(target_os = "linux")]
+            BuiltinPlanner::Wsl2(i) => i.boxed(),
+            #[cfg(target_os = "linux")]
+            BuiltinPlanner::Microos(i) => i.boxed(),
             #[cfg(target_os = "macos")]
             BuiltinPlanner::Macos(i) => i.boxed(),
         }
@@ -291,6 +417,16 @@ impl BuiltinPlanner {
             BuiltinPlanner::SteamDeck(i) => i.typetag_name(),
             #[cfg(target_os = "linux")]
             BuiltinPlanner::Ostree(i) => i.typetag_name(),
+            #[cfg(target_os = "linux")]
+            BuiltinPlanner::Image(i) => i.typetag_name(),
+            #[cfg(target_os = "linux")]
+            BuiltinPlanner::SingleUser(i) => i.typetag_name(),
+            #[cfg(target_os = "linux")]
+            BuiltinPlanner::Container(i) => i.typetag_name(),
+            #[cfg(target_os = "linux")]
+            BuiltinPlanner::Wsl2(i) => i.typetag_name(),
+            #[cfg(target_os = "linux")]
+            BuiltinPlanner::Microos(i) => i.typetag_name(),
             #[cfg(target_os = "macos")]
             BuiltinPlanner::Macos(i) => i.typetag_name(),
         }
@@ -304,6 +440,16 @@ impl BuiltinPlanner {
             BuiltinPlanner::SteamDeck(i) => i.settings(),
             #[cfg(target_os = "linux")]
             BuiltinPlanner::Ostree(i) => i.settings(),
+            #[cfg(target_os = "linux")]
+            BuiltinPlanner::Image(i) => i.settings(),
+            #[cfg(target_os = "linux")]
+            BuiltinPlanner::SingleUser(i) => i.settings(),
+            #[cfg(target_os = "linux")]
+            BuiltinPlanner::Container(i) => i.settings(),
+            #[cfg(target_os = "linux")]
+            BuiltinPlanner::Wsl2(i) => i.settings(),
+            #[cfg(target_os = "linux")]
+            BuiltinPlanner::Microos(i) => i.settings(),
             #[cfg(target_os = "macos")]
             BuiltinPlanner::Macos(i) => i.settings(),
         }
@@ -320,6 +466,16 @@ impl BuiltinPlanner {
             BuiltinPlanner::SteamDeck(i) => i.diagnostic_data().await,
             #[cfg(target_os = "linux")]
             BuiltinPlanner::Ostree(i) => i.diagnostic_data().await,
+            #[cfg(target_os = "linux")]
+            BuiltinPlanner::Image(i) => i.diagnostic_data().await,
+            #[cfg(target_os = "linux")]
+            BuiltinPlanner::SingleUser(i) => i.diagnostic_data().await,
+            #[cfg(target_os = "linux")]
+            BuiltinPlanner::Container(i) => i.diagnostic_data().await,
+            #[cfg(target_os = "linux")]
+            BuiltinPlanner::Wsl2(i) => i.diagnostic_data().await,
+            #[cfg(target_os = "linux")]
+            BuiltinPlanner::Microos(i) => i.diagnostic_data().await,
             #[cfg(target_os = "macos")]
             BuiltinPlanner::Macos(i) => i.diagnostic_data().await,
         }
@@ -331,6 +487,17 @@ pub struct ShellProfileLocations {
     pub fish: FishShellProfileLocations,
     pub bash: Vec<PathBuf>,
     pub zsh: Vec<PathBuf>,
+    /// Files in the directories nushell autoloads at startup
+    #[serde(default)]
+    pub nushell: Vec<PathBuf>,
+    /// Read by both tcsh and csh
+    #[serde(default)]
+    pub tcsh: Vec<PathBuf>,
+    #[serde(default)]
+    pub xonsh: Vec<PathBuf>,
+    /// Elvish has no system wide rc file, only the `rc.elv` of each user, so only per-user installs set it up
+    #[serde(default)]
+    pub elvish: Vec<PathBuf>,
 }
 
 impl Default for ShellProfileLocations {
@@ -347,6 +514,67 @@ impl Default for ShellProfileLocations {
                 "/etc/zshrc".into(),
                 "/etc/zsh/zshrc".into(),
             ],
+            // https://www.nushell.sh/book/configuration.html#configuration-overview
+            nushell: vec![
+                "/usr/share/nushell/vendor/autoload/nix.nu".into(),
+                "/usr/local/share/nushell/vendor/autoload/nix.nu".into(),
+            ],
+            tcsh: vec!["/etc/csh.cshrc".into()],
+            xonsh: vec!["/etc/xonsh/rc.d/nix.xsh".into()],
+            elvish: vec![],
+        }
+    }
+}
+
+impl ShellProfileLocations {
+    /// The shell profiles of the user with `home`, instead of the system wide ones
+    pub fn for_user(home: &Path, config_dir: &Path) -> Self {
+        Self {
+            fish: FishShellProfileLocations {
+                confd_prefixes: vec![config_dir.join("fish")],
+                vendor_confd_prefixes: vec![],
+                ..FishShellProfileLocations::default()
+            },
+            bash: vec![home.join(".profile"), home.join(".bashrc")],
+            zsh: vec![home.join(".zshenv")],
+            nushell: vec![config_dir.join("nushell/autoload/nix.nu")],
+            tcsh: vec![home.join(".tcshrc"), home.join(".cshrc")],
+            xonsh: vec![config_dir.join("xonsh/rc.d/nix.xsh")],
+            elvish: vec![config_dir.join("elvish/rc.elv")],
+        }
+    }
+
+    /// The same locations under `root`, see [`LocationSettings::root`](crate::settings::LocationSettings::root)
+    pub fn rebase(self, root: &Path) -> Self {
+        let Self {
+            fish,
+            bash,
+            zsh,
+            nushell,
+            tcsh,
+            xonsh,
+            elvish,
+        } = self;
+        Self {
+            fish: FishShellProfileLocations {
+                confd_prefixes: fish
+                    .confd_prefixes
+                    .iter()
+                    .map(|v| rebase(root, v))
+                    .collect(),
+                vendor_confd_prefixes: fish
+                    .vendor_confd_prefixes
+                    .iter()
+                    .map(|v| rebase(root, v))
+                    .collect(),
+                ..fish
+            },
+            bash: bash.iter().map(|v| rebase(root, v)).collect(),
+            zsh: zsh.iter().map(|v| rebase(root, v)).collect(),
+            nushell: nushell.iter().map(|v| rebase(root, v)).collect(),
+            tcsh: tcsh.iter().map(|v| rebase(root, v)).collect(),
+            xonsh: xonsh.iter().map(|v| rebase(root, v)).collect(),
+            elvish: elvish.iter().map(|v| rebase(root, v)).collect(),
         }
     }
 }
@@ -455,6 +683,22 @@ impl HasExpectedErrors for PlannerError {
                 if let Some(err) = _e.downcast_ref::<linux::LinuxErrorKind>() {
                     return err.expected();
                 }
+                #[cfg(target_os = "linux")]
+                if let Some(err) = _e.downcast_ref::<image::ImageError>() {
+                    return err.expected();
+                }
+                #[cfg(target_os = "linux")]
+                if let Some(err) = _e.downcast_ref::<single_user::SingleUserError>() {
+                    return err.expected();
+                }
+                #[cfg(target_os = "linux")]
+                if let Some(err) = _e.downcast_ref::<wsl2::Wsl2Error>() {
+                    return err.expected();
+                }
+                #[cfg(target_os = "linux")]
+                if let Some(err) = _e.downcast_ref::<microos::MicroosError>() {
+                    return err.expected();
+                }
                 #[cfg(target_os = "macos")]
                 if let Some(err) = _e.downcast_ref::<macos::MacosError>() {
                     return err.expected();
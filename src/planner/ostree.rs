@@ -1,8 +1,14 @@
 use crate::{
     action::{
-        base::{CreateDirectory, CreateFile, RemoveDirectory},
-        common::{ConfigureInitService, ConfigureNix, CreateUsersAndGroups, ProvisionNix},
-        linux::{ProvisionSelinux, StartSystemdUnit, SystemctlDaemonReload},
+        base::{CreateDirectory, RemoveDirectory},
+        common::{
+            ConfigureInitService, ConfigureNix, CreateUsersAndGroups, DaemonOverrides,
+            ProvisionNix, WaitForNixDaemon,
+        },
+        linux::{
+            nix_directory_unit, NixMountStrategy, PersistentNixMount, ProvisionSelinux,
+            StartSystemdUnit, SystemctlDaemonReload,
+        },
         StatefulAction,
     },
     error::HasExpectedErrors,
@@ -11,7 +17,10 @@ use crate::{
     settings::{InitSystem, InstallSettingsError},
     Action, BuiltinPlanner,
 };
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 
 use super::{
     linux::{
@@ -53,103 +62,20 @@ impl Planner for Ostree {
         ];
 
         plan.push(
-            CreateDirectory::plan(&self.persistence, None, None, 0o0755, true)
-                .await
-                .map_err(PlannerError::Action)?
-                .boxed(),
+            PersistentNixMount::plan(
+                &self.persistence,
+                NixMountStrategy::Bind,
+                Some(nix_directory_unit(
+                    "Enable mount points in / for ostree",
+                    "chattr -i /",
+                    "chattr +i /",
+                )),
+            )
+            .await
+            .map_err(PlannerError::Action)?
+            .boxed(),
         );
 
-        let nix_directory_buf = "\
-                [Unit]\n\
-                Description=Enable mount points in / for ostree\n\
-                ConditionPathExists=!/nix\n\
-                DefaultDependencies=no\n\
-                Requires=local-fs-pre.target\n\
-                After=local-fs-pre.target\n\
-                [Service]\n\
-                Type=oneshot\n\
-                ExecStartPre=chattr -i /\n\
-                ExecStart=mkdir -p /nix\n\
-                ExecStopPost=chattr +i /\n\
-            "
-        .to_string();
-        let nix_directory_unit = CreateFile::plan(
-            "/etc/systemd/system/nix-directory.service",
-            None,
-            None,
-            0o0644,
-            nix_directory_buf,
-            false,
-        )
-        .await
-        .map_err(PlannerError::Action)?;
-        plan.push(nix_directory_unit.boxed());
-
-        let create_bind_mount_buf = format!(
-            "\
-                [Unit]\n\
-                Description=Mount `{persistence}` on `/nix`\n\
-                PropagatesStopTo=nix-daemon.service\n\
-                PropagatesStopTo=nix-directory.service\n\
-                After=nix-directory.service\n\
-                Requires=nix-directory.service\n\
-                ConditionPathIsDirectory=/nix\n\
-                DefaultDependencies=no\n\
-                \n\
-                [Mount]\n\
-                What={persistence}\n\
-                Where=/nix\n\
-                Type=none\n\
-                DirectoryMode=0755\n\
-                Options=bind\n\
-                \n\
-                [Install]\n\
-                RequiredBy=nix-daemon.service\n\
-                RequiredBy=nix-daemon.socket\n
-            ",
-            persistence = self.persistence.display(),
-        );
-        let create_bind_mount_unit = CreateFile::plan(
-            "/etc/systemd/system/nix.mount",
-            None,
-            None,
-            0o0644,
-            create_bind_mount_buf,
-            false,
-        )
-        .await
-        .map_err(PlannerError::Action)?;
-        plan.push(create_bind_mount_unit.boxed());
-
-        let ensure_symlinked_units_resolve_buf = "\
-        [Unit]\n\
-        Description=Ensure Nix related units which are symlinked resolve\n\
-        After=nix.mount\n\
-        Requires=nix.mount\n\
-        DefaultDependencies=no\n\
-        \n\
-        [Service]\n\
-        Type=oneshot\n\
-        RemainAfterExit=yes\n\
-        ExecStart=/usr/bin/systemctl daemon-reload\n\
-        ExecStart=/usr/bin/systemctl restart --no-block nix-daemon.socket\n\
-        \n\
-        [Install]\n\
-        WantedBy=sysinit.target\n\
-    "
-        .to_string();
-        let ensure_symlinked_units_resolve_unit = CreateFile::plan(
-            "/etc/systemd/system/ensure-symlinked-units-resolve.service",
-            None,
-            None,
-            0o0644,
-            ensure_symlinked_units_resolve_buf,
-            false,
-        )
-        .await
-        .map_err(PlannerError::Action)?;
-        plan.push(ensure_symlinked_units_resolve_unit.boxed());
-
         // We need to remove this path since it's part of the read-only install.
         let mut shell_profile_locations = ShellProfileLocations::default();
         if let Some(index) = shell_profile_locations
@@ -165,26 +91,19 @@ impl Planner for Ostree {
         }
 
         plan.push(
-            StartSystemdUnit::plan("nix.mount".to_string(), false)
+            ProvisionNix::plan(&self.settings, Path::new("/"))
                 .await
                 .map_err(PlannerError::Action)?
                 .boxed(),
         );
-
         plan.push(
-            ProvisionNix::plan(&self.settings.clone())
+            CreateUsersAndGroups::plan(self.settings.clone(), Path::new("/"))
                 .await
                 .map_err(PlannerError::Action)?
                 .boxed(),
         );
         plan.push(
-            CreateUsersAndGroups::plan(self.settings.clone())
-                .await
-                .map_err(PlannerError::Action)?
-                .boxed(),
-        );
-        plan.push(
-            ConfigureNix::plan(shell_profile_locations, &self.settings)
+            ConfigureNix::plan(shell_profile_locations, &self.settings, Path::new("/"))
                 .await
                 .map_err(PlannerError::Action)?
                 .boxed(),
@@ -207,7 +126,17 @@ impl Planner for Ostree {
         );
 
         plan.push(
-            ConfigureInitService::plan(InitSystem::Systemd, true)
+            ConfigureInitService::plan(
+                InitSystem::Systemd,
+                true,
+                DaemonOverrides::new(&self.settings, None),
+            )
+            .await
+            .map_err(PlannerError::Action)?
+            .boxed(),
+        );
+        plan.push(
+            WaitForNixDaemon::plan(InitSystem::Systemd)
                 .await
                 .map_err(PlannerError::Action)?
                 .boxed(),
@@ -0,0 +1,316 @@
+use crate::{
+    action::{
+        base::{
+            CreateDirectory, FetchAndUnpackNix, MoveUnpackedNix, RemoveDirectory,
+            SetupDefaultProfile,
+        },
+        common::{ConfigureShellProfile, CreateNixTree, PlaceNixConfiguration, ProfileScripts},
+        StatefulAction,
+    },
+    error::HasExpectedErrors,
+    plan::RECEIPT_LOCATION,
+    planner::{Planner, PlannerError},
+    settings::{CommonSettings, InstallSettingsError, NIX_DIR, SCRATCH_DIR},
+    Action, BuiltinPlanner,
+};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use super::{
+    linux::{check_nix_not_already_installed, check_not_nixos},
+    ShellProfileLocations,
+};
+
+/**
+A planner for a rootless install, where `/nix` is owned by the user running `nix-installer`
+
+No build users or init service are set up, Nix runs builds as the user itself. Only the shell profiles of the
+user are modified, and the receipt is stored under `$XDG_STATE_HOME`.
+
+If `/nix` does not exist, someone with `root` must create it for the user first:
+
+```bash
+sudo mkdir -m 0755 /nix && sudo chown $USER /nix
+```
+
+Local stores elsewhere than `/nix`, like `--store ~/.nix`, are not supported. The Nix in the tarball refers to itself
+by its path in `/nix/store`, so it can only run from another store through a chroot or `proot`.
+*/
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::Parser))]
+pub struct SingleUser {
+    #[cfg_attr(feature = "cli", clap(flatten))]
+    pub settings: CommonSettings,
+}
+
+#[async_trait::async_trait]
+#[typetag::serde(name = "single-user")]
+impl Planner for SingleUser {
+    async fn default() -> Result<Self, PlannerError> {
+        Ok(Self {
+            settings: CommonSettings::default().await?,
+        })
+    }
+
+    async fn plan(&self) -> Result<Vec<StatefulAction<Box<dyn Action>>>, PlannerError> {
+        let home = dirs::home_dir().ok_or(SingleUserError::NoHome)?;
+        let config_dir = dirs::config_dir().unwrap_or_else(|| home.join(".config"));
+
+        let mut plan = vec![];
+
+        // Only `root` can create `/nix`, everyone else needs it created for them
+        if !Path::new(NIX_DIR).exists() {
+            plan.push(
+                CreateDirectory::plan(NIX_DIR, None, None, 0o0755, true)
+                    .await
+                    .map_err(PlannerError::Action)?
+                    .boxed(),
+            );
+        }
+
+        plan.push(
+            FetchAndUnpackNix::plan(
+                self.settings.nix_package_url.clone(),
+                PathBuf::from(SCRATCH_DIR),
+                self.settings.proxy.clone(),
+                self.settings.ssl_cert_file.clone(),
+            )
+            .await
+            .map_err(PlannerError::Action)?
+            .boxed(),
+        );
+        plan.push(
+            CreateNixTree::plan(Path::new("/"), None)
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+        plan.push(
+            MoveUnpackedNix::plan(PathBuf::from(SCRATCH_DIR), PathBuf::from(NIX_DIR))
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+        // Run as the user, this installs Nix into the profile of the user rather than the default profile
+        plan.push(
+            SetupDefaultProfile::plan(PathBuf::from(SCRATCH_DIR), PathBuf::from("/"))
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+        // Without a build group, Nix builds as the user running it
+        plan.push(
+            PlaceNixConfiguration::plan(
+                String::new(),
+                self.settings.proxy.clone(),
+                self.settings.ssl_cert_file.clone(),
+                self.settings.extra_conf.clone(),
+                self.settings.force,
+                config_dir.join("nix/nix.conf"),
+            )
+            .await
+            .map_err(PlannerError::Action)?
+            .boxed(),
+        );
+
+        if self.settings.modify_profile {
+            plan.push(
+                ConfigureShellProfile::plan(
+                    ShellProfileLocations::for_user(&home, &config_dir),
+                    ProfileScripts::single_user(&home),
+                )
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+            );
+        }
+
+        plan.push(
+            RemoveDirectory::plan(SCRATCH_DIR)
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+
+        Ok(plan)
+    }
+
+    fn settings(&self) -> Result<HashMap<String, serde_json::Value>, InstallSettingsError> {
+        let Self { settings } = self;
+        let mut map = HashMap::default();
+
+        map.extend(settings.settings()?);
+
+        Ok(map)
+    }
+
+    async fn configured_settings(
+        &self,
+    ) -> Result<HashMap<String, serde_json::Value>, PlannerError> {
+        let default = Self::default().await?.settings()?;
+        let configured = self.settings()?;
+
+        let mut settings: HashMap<String, serde_json::Value> = HashMap::new();
+        for (key, value) in configured.iter() {
+            if default.get(key) != Some(value) {
+                settings.insert(key.clone(), value.clone());
+            }
+        }
+
+        Ok(settings)
+    }
+
+    #[cfg(feature = "diagnostics")]
+    async fn diagnostic_data(&self) -> Result<crate::diagnostics::DiagnosticData, PlannerError> {
+        Ok(crate::diagnostics::DiagnosticData::new(
+            self.settings.diagnostic_attribution.clone(),
+            self.settings.diagnostic_endpoint.clone(),
+            self.typetag_name().into(),
+            self.configured_settings()
+                .await?
+                .into_keys()
+                .collect::<Vec<_>>(),
+            self.settings.ssl_cert_file.clone(),
+        )?)
+    }
+
+    async fn pre_install_check(&self) -> Result<(), PlannerError> {
+        check_not_nixos()?;
+
+        check_nix_not_already_installed().await?;
+
+        check_nix_dir_writable(Path::new(NIX_DIR))?;
+
+        Ok(())
+    }
+
+    async fn pre_uninstall_check(&self) -> Result<(), PlannerError> {
+        check_nix_dir_writable(Path::new(NIX_DIR))?;
+
+        Ok(())
+    }
+
+    fn receipt_location(&self) -> PathBuf {
+        receipt_location()
+    }
+
+    fn requires_root(&self) -> bool {
+        false
+    }
+}
+
+impl From<SingleUser> for BuiltinPlanner {
+    fn from(val: SingleUser) -> Self {
+        BuiltinPlanner::SingleUser(val)
+    }
+}
+
+/// Where the receipt of a single user install by the current user is, under `$XDG_STATE_HOME`
+pub fn receipt_location() -> PathBuf {
+    receipt_location_in(dirs::state_dir())
+}
+
+/// Where the receipt is under `state_dir`, or in the usual place without one
+fn receipt_location_in(state_dir: Option<PathBuf>) -> PathBuf {
+    match state_dir {
+        Some(state_dir) => state_dir.join("nix-installer/receipt.json"),
+        None => PathBuf::from(RECEIPT_LOCATION),
+    }
+}
+
+fn check_nix_dir_writable(nix_dir: &Path) -> Result<(), PlannerError> {
+    if !nix_dir.exists() {
+        // `root` can create it, which the plan does
+        if nix::unistd::Uid::effective().is_root() {
+            return Ok(());
+        }
+        return Err(SingleUserError::NoNixDir.into());
+    }
+    if nix::unistd::access(nix_dir, nix::unistd::AccessFlags::W_OK).is_err() {
+        return Err(SingleUserError::NixDirNotWritable.into());
+    }
+    Ok(())
+}
+
+#[non_exhaustive]
+#[derive(Debug, thiserror::Error)]
+pub enum SingleUserError {
+    #[error("Could not find the home directory of the current user")]
+    NoHome,
+    #[error(
+        "\
+        `/nix` does not exist, and only `root` can create it.\n\
+        \n\
+        Ask an administrator to create it for you with `sudo mkdir -m 0755 /nix && sudo chown $USER /nix`."
+    )]
+    NoNixDir,
+    #[error(
+        "\
+        `/nix` is not writable by the current user.\n\
+        \n\
+        Ask an administrator to give it to you with `sudo chown -R $USER /nix`, or use a multi-user install."
+    )]
+    NixDirNotWritable,
+}
+
+impl HasExpectedErrors for SingleUserError {
+    fn expected<'a>(&'a self) -> Option<Box<dyn std::error::Error + 'a>> {
+        match self {
+            SingleUserError::NoHome => Some(Box::new(self)),
+            SingleUserError::NoNixDir => Some(Box::new(self)),
+            SingleUserError::NixDirNotWritable => Some(Box::new(self)),
+        }
+    }
+}
+
+impl From<SingleUserError> for PlannerError {
+    fn from(v: SingleUserError) -> PlannerError {
+        PlannerError::Custom(Box::new(v))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+
+    #[test]
+    fn checks_nix_dir_writable() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let nix_dir = temp_dir.path().join("nix");
+        let is_root = nix::unistd::Uid::effective().is_root();
+
+        let missing = check_nix_dir_writable(&nix_dir);
+        assert_eq!(missing.is_ok(), is_root);
+        if !is_root {
+            assert!(matches!(
+                missing,
+                Err(PlannerError::Custom(e))
+                    if matches!(e.downcast_ref::<SingleUserError>(), Some(SingleUserError::NoNixDir))
+            ));
+        }
+
+        std::fs::create_dir(&nix_dir)?;
+        check_nix_dir_writable(&nix_dir)?;
+
+        // `root` may write anywhere
+        std::fs::set_permissions(&nix_dir, std::fs::Permissions::from_mode(0o0555))?;
+        assert_eq!(check_nix_dir_writable(&nix_dir).is_ok(), is_root);
+
+        Ok(())
+    }
+
+    #[test]
+    fn receipt_under_xdg_state_home() -> eyre::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let receipt = receipt_location_in(Some(temp_dir.path().to_path_buf()));
+
+        assert_eq!(receipt, temp_dir.path().join("nix-installer/receipt.json"));
+        assert_eq!(receipt_location_in(None), PathBuf::from(RECEIPT_LOCATION));
+
+        Ok(())
+    }
+}
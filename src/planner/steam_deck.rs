@@ -96,17 +96,27 @@ To test on a specific build id of the Steam Deck:
 6. Safely turn off the VM!
 
 */
-use std::{collections::HashMap, path::PathBuf, process::Output};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    process::Output,
+};
 
 use tokio::process::Command;
 
 use crate::{
     action::{
-        base::{CreateDirectory, CreateFile, RemoveDirectory},
-        common::{ConfigureInitService, ConfigureNix, CreateUsersAndGroups, ProvisionNix},
+        base::{CreateFile, RemoveDirectory},
+        common::{
+            ConfigureInitService, ConfigureNix, CreateUsersAndGroups, DaemonOverrides,
+            ProvisionNix, WaitForNixDaemon,
+        },
         linux::{
-            EnsureSteamosNixDirectory, RevertCleanSteamosNixOffload, StartSystemdUnit,
-            SystemctlDaemonReload,
+            persistent_nix_mount::{
+                ENSURE_SYMLINKED_UNITS_RESOLVE_BUF, ENSURE_SYMLINKED_UNITS_RESOLVE_UNIT,
+            },
+            EnsureSteamosNixDirectory, NixMountStrategy, PersistentNixMount,
+            RevertCleanSteamosNixOffload, StartSystemdUnit, SystemctlDaemonReload,
         },
         Action, StatefulAction,
     },
@@ -173,13 +183,6 @@ impl Planner for SteamDeck {
                     SteamDeckError::AbsolutePathRequired(self.persistence.clone()),
                 )));
             };
-            actions.push(
-                CreateDirectory::plan(&persistence, None, None, 0o0755, true)
-                    .await
-                    .map_err(PlannerError::Action)?
-                    .boxed(),
-            );
-
             let nix_directory_buf = "\
                 [Unit]\n\
                 Description=Create a `/nix` directory to be used for bind mounting\n\
@@ -203,53 +206,16 @@ impl Planner for SteamDeck {
                 RemainAfterExit=true\n\
             "
             .to_string();
-            let nix_directory_unit = CreateFile::plan(
-                "/etc/systemd/system/nix-directory.service",
-                None,
-                None,
-                0o0644,
-                nix_directory_buf,
-                false,
-            )
-            .await
-            .map_err(PlannerError::Action)?;
-            actions.push(nix_directory_unit.boxed());
-
-            let create_bind_mount_buf = format!(
-                "\
-                [Unit]\n\
-                Description=Mount `{persistence}` on `/nix`\n\
-                PropagatesStopTo=nix-daemon.service\n\
-                PropagatesStopTo=nix-directory.service\n\
-                After=nix-directory.service\n\
-                Requires=nix-directory.service\n\
-                ConditionPathIsDirectory=/nix\n\
-                DefaultDependencies=no\n\
-                \n\
-                [Mount]\n\
-                What={persistence}\n\
-                Where=/nix\n\
-                Type=none\n\
-                DirectoryMode=0755\n\
-                Options=bind\n\
-                \n\
-                [Install]\n\
-                RequiredBy=nix-daemon.service\n\
-                RequiredBy=nix-daemon.socket\n
-            ",
-                persistence = persistence.display(),
+            actions.push(
+                PersistentNixMount::plan(
+                    persistence,
+                    NixMountStrategy::Bind,
+                    Some(nix_directory_buf),
+                )
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
             );
-            let create_bind_mount_unit = CreateFile::plan(
-                "/etc/systemd/system/nix.mount",
-                None,
-                None,
-                0o0644,
-                create_bind_mount_buf,
-                false,
-            )
-            .await
-            .map_err(PlannerError::Action)?;
-            actions.push(create_bind_mount_unit.boxed());
         } else {
             let revert_clean_streamos_nix_offload = RevertCleanSteamosNixOffload::plan()
                 .await
@@ -265,36 +231,19 @@ impl Planner for SteamDeck {
                 .await
                 .map_err(PlannerError::Action)?;
             actions.push(start_nix_mount.boxed());
-        }
 
-        let ensure_symlinked_units_resolve_buf = "\
-            [Unit]\n\
-            Description=Ensure Nix related units which are symlinked resolve\n\
-            After=nix.mount\n\
-            Requires=nix.mount\n\
-            DefaultDependencies=no\n\
-            \n\
-            [Service]\n\
-            Type=oneshot\n\
-            RemainAfterExit=yes\n\
-            ExecStart=/usr/bin/systemctl daemon-reload\n\
-            ExecStart=/usr/bin/systemctl restart --no-block nix-daemon.socket\n\
-            \n\
-            [Install]\n\
-            WantedBy=sysinit.target\n\
-        "
-        .to_string();
-        let ensure_symlinked_units_resolve_unit = CreateFile::plan(
-            "/etc/systemd/system/ensure-symlinked-units-resolve.service",
-            None,
-            None,
-            0o0644,
-            ensure_symlinked_units_resolve_buf,
-            false,
-        )
-        .await
-        .map_err(PlannerError::Action)?;
-        actions.push(ensure_symlinked_units_resolve_unit.boxed());
+            let ensure_symlinked_units_resolve_unit = CreateFile::plan(
+                ENSURE_SYMLINKED_UNITS_RESOLVE_UNIT,
+                None,
+                None,
+                0o0644,
+                ENSURE_SYMLINKED_UNITS_RESOLVE_BUF.to_string(),
+                false,
+            )
+            .await
+            .map_err(PlannerError::Action)?;
+            actions.push(ensure_symlinked_units_resolve_unit.boxed());
+        }
 
         // We need to remove this path since it's part of the read-only install.
         let mut shell_profile_locations = ShellProfileLocations::default();
@@ -310,33 +259,28 @@ impl Planner for SteamDeck {
                 .remove(index);
         }
 
-        if requires_nix_bind_mount {
-            actions.push(
-                StartSystemdUnit::plan("nix.mount".to_string(), false)
-                    .await
-                    .map_err(PlannerError::Action)?
-                    .boxed(),
-            )
-        }
-
         actions.append(&mut vec![
-            ProvisionNix::plan(&self.settings.clone())
+            ProvisionNix::plan(&self.settings, Path::new("/"))
                 .await
                 .map_err(PlannerError::Action)?
                 .boxed(),
-            CreateUsersAndGroups::plan(self.settings.clone())
+            CreateUsersAndGroups::plan(self.settings.clone(), Path::new("/"))
                 .await
                 .map_err(PlannerError::Action)?
                 .boxed(),
-            ConfigureNix::plan(shell_profile_locations, &self.settings)
+            ConfigureNix::plan(shell_profile_locations, &self.settings, Path::new("/"))
                 .await
                 .map_err(PlannerError::Action)?
                 .boxed(),
             // Init is required for the steam-deck archetype to make the `/nix` mount
-            ConfigureInitService::plan(InitSystem::Systemd, true)
-                .await
-                .map_err(PlannerError::Action)?
-                .boxed(),
+            ConfigureInitService::plan(
+                InitSystem::Systemd,
+                true,
+                DaemonOverrides::new(&self.settings, None),
+            )
+            .await
+            .map_err(PlannerError::Action)?
+            .boxed(),
             StartSystemdUnit::plan("ensure-symlinked-units-resolve.service".to_string(), true)
                 .await
                 .map_err(PlannerError::Action)?
@@ -349,6 +293,10 @@ impl Planner for SteamDeck {
                 .await
                 .map_err(PlannerError::Action)?
                 .boxed(),
+            WaitForNixDaemon::plan(InitSystem::Systemd)
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
         ]);
         Ok(actions)
     }
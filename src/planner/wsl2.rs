@@ -0,0 +1,373 @@
+use crate::{
+    action::{
+        base::{CreateDirectory, CreateFile, RemoveDirectory},
+        common::{
+            ConfigureInitService, ConfigureNix, CreateUsersAndGroups, DaemonOverrides,
+            ProvisionNix, WaitForNixDaemon,
+        },
+        linux::{enable_wsl_systemd::WSL_CONF, EnableWslSystemd, LinkSystemdUnits},
+        StatefulAction,
+    },
+    error::HasExpectedErrors,
+    planner::{Planner, PlannerError},
+    settings::{CommonSettings, InitSystem, InstallSettingsError, NIX_DIR, SCRATCH_DIR},
+    Action, BuiltinPlanner,
+};
+use std::{collections::HashMap, path::Path};
+
+use super::{
+    linux::{check_nix_not_already_installed, check_not_nixos},
+    ShellProfileLocations,
+};
+
+const PROFILE_DAEMON_DEST: &str = "/etc/profile.d/nix-daemon-wsl.sh";
+const PROFILE_DAEMON_SCRIPT: &str = "\
+# Start the Nix daemon, which systemd would otherwise start, the first time a shell is opened
+if [ -x /nix/var/nix/profiles/default/bin/nix-daemon ] && ! pgrep -x nix-daemon >/dev/null 2>&1; then
+    if [ \"$(id -u)\" -eq 0 ]; then
+        ( nohup /nix/var/nix/profiles/default/bin/nix-daemon >/dev/null 2>&1 & )
+    else
+        sudo -b /nix/var/nix/profiles/default/bin/nix-daemon >/dev/null 2>&1
+    fi
+fi
+";
+
+/**
+A planner for WSL2, which only boots with systemd when `/etc/wsl.conf` asks for it
+
+Systemd is enabled in `/etc/wsl.conf` and the Nix daemon units are linked for when WSL is restarted, or with
+`--daemon profile` the daemon is started by the shell profile instead. If systemd is already running, the
+[`Linux`](super::linux::Linux) planner is picked by default instead, as it supports every init system and store location.
+*/
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::Parser))]
+pub struct Wsl2 {
+    /// How the Nix daemon is run when systemd isn't running
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            value_parser,
+            long,
+            default_value_t = Wsl2Daemon::WslConf,
+            env = "NIX_INSTALLER_WSL2_DAEMON"
+        )
+    )]
+    pub daemon: Wsl2Daemon,
+    #[cfg_attr(feature = "cli", clap(flatten))]
+    pub settings: CommonSettings,
+}
+
+/// How the [`Wsl2`] planner runs the Nix daemon when systemd isn't running
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Wsl2Daemon {
+    /// Enable systemd in `/etc/wsl.conf`, which starts the daemon after `wsl.exe --shutdown`
+    WslConf,
+    /// Start the daemon from `/etc/profile.d` the first time a shell is opened, with `sudo` if needed
+    Profile,
+}
+
+impl std::fmt::Display for Wsl2Daemon {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Wsl2Daemon::WslConf => write!(f, "wsl-conf"),
+            Wsl2Daemon::Profile => write!(f, "profile"),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+#[typetag::serde(name = "wsl2")]
+impl Planner for Wsl2 {
+    async fn default() -> Result<Self, PlannerError> {
+        Ok(Self {
+            daemon: Wsl2Daemon::WslConf,
+            settings: CommonSettings::default().await?,
+        })
+    }
+
+    async fn plan(&self) -> Result<Vec<StatefulAction<Box<dyn Action>>>, PlannerError> {
+        let root = Path::new("/");
+        let mut plan = vec![];
+
+        plan.push(
+            CreateDirectory::plan(NIX_DIR, None, None, 0o0755, true)
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+        plan.push(
+            ProvisionNix::plan(&self.settings, root)
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+        plan.push(
+            CreateUsersAndGroups::plan(self.settings.clone(), root)
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+        plan.push(
+            ConfigureNix::plan(ShellProfileLocations::default(), &self.settings, root)
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+
+        if systemd_booted() {
+            plan.push(
+                CreateDirectory::plan("/etc/tmpfiles.d", None, None, 0o0755, false)
+                    .await
+                    .map_err(PlannerError::Action)?
+                    .boxed(),
+            );
+            plan.push(
+                ConfigureInitService::plan(
+                    InitSystem::Systemd,
+                    true,
+                    DaemonOverrides::new(&self.settings, None),
+                )
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+            );
+            plan.push(
+                WaitForNixDaemon::plan(InitSystem::Systemd)
+                    .await
+                    .map_err(PlannerError::Action)?
+                    .boxed(),
+            );
+        } else {
+            match self.daemon {
+                Wsl2Daemon::WslConf => {
+                    plan.push(
+                        EnableWslSystemd::plan(WSL_CONF)
+                            .await
+                            .map_err(PlannerError::Action)?
+                            .boxed(),
+                    );
+                    // systemd isn't running to ask, so the units are enabled the way `systemctl` would
+                    plan.push(
+                        LinkSystemdUnits::plan(root.to_path_buf())
+                            .await
+                            .map_err(PlannerError::Action)?
+                            .boxed(),
+                    );
+                },
+                Wsl2Daemon::Profile => {
+                    plan.push(
+                        CreateFile::plan(
+                            PROFILE_DAEMON_DEST,
+                            None,
+                            None,
+                            0o0644,
+                            PROFILE_DAEMON_SCRIPT.to_string(),
+                            self.settings.force,
+                        )
+                        .await
+                        .map_err(PlannerError::Action)?
+                        .boxed(),
+                    );
+                },
+            }
+        }
+
+        plan.push(
+            RemoveDirectory::plan(SCRATCH_DIR)
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+
+        Ok(plan)
+    }
+
+    fn settings(&self) -> Result<HashMap<String, serde_json::Value>, InstallSettingsError> {
+        let Self { daemon, settings } = self;
+        let mut map = HashMap::default();
+
+        map.extend(settings.settings()?);
+        map.insert("daemon".to_string(), serde_json::to_value(daemon)?);
+
+        Ok(map)
+    }
+
+    async fn configured_settings(
+        &self,
+    ) -> Result<HashMap<String, serde_json::Value>, PlannerError> {
+        let default = Self::default().await?.settings()?;
+        let configured = self.settings()?;
+
+        let mut settings: HashMap<String, serde_json::Value> = HashMap::new();
+        for (key, value) in configured.iter() {
+            if default.get(key) != Some(value) {
+                settings.insert(key.clone(), value.clone());
+            }
+        }
+
+        Ok(settings)
+    }
+
+    #[cfg(feature = "diagnostics")]
+    async fn diagnostic_data(&self) -> Result<crate::diagnostics::DiagnosticData, PlannerError> {
+        Ok(crate::diagnostics::DiagnosticData::new(
+            self.settings.diagnostic_attribution.clone(),
+            self.settings.diagnostic_endpoint.clone(),
+            self.typetag_name().into(),
+            self.configured_settings()
+                .await?
+                .into_keys()
+                .collect::<Vec<_>>(),
+            self.settings.ssl_cert_file.clone(),
+        )?)
+    }
+
+    async fn pre_install_check(&self) -> Result<(), PlannerError> {
+        check_not_nixos()?;
+
+        check_nix_not_already_installed().await?;
+
+        check_wsl2()?;
+
+        Ok(())
+    }
+
+    async fn pre_uninstall_check(&self) -> Result<(), PlannerError> {
+        check_wsl2()?;
+
+        Ok(())
+    }
+
+    fn post_install_message(&self) -> Option<String> {
+        if systemd_booted() {
+            return None;
+        }
+        match self.daemon {
+            Wsl2Daemon::WslConf => Some(
+                "\
+                systemd was enabled in `/etc/wsl.conf`, but only starts once WSL is restarted.\n\
+                Run `wsl.exe --shutdown` from Windows (PowerShell or `cmd`), then reopen this distribution to use the Nix daemon."
+                    .to_string(),
+            ),
+            Wsl2Daemon::Profile => Some(format!(
+                "The Nix daemon will be started by `{PROFILE_DAEMON_DEST}` the next time a login shell is opened, which may ask for your `sudo` password."
+            )),
+        }
+    }
+}
+
+impl From<Wsl2> for BuiltinPlanner {
+    fn from(val: Wsl2) -> Self {
+        BuiltinPlanner::Wsl2(val)
+    }
+}
+
+fn systemd_booted() -> bool {
+    Path::new("/run/systemd/system").exists()
+}
+
+/// Which version of WSL is running, if any
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum WslVersion {
+    Wsl1,
+    Wsl2,
+}
+
+/// Detect WSL from the `WSL_DISTRO_NAME` and `WSL_INTEROP` environment variables, and the content of `/proc/version`
+///
+/// WSL1 reports a kernel like `4.4.0-19041-Microsoft`, WSL2 one like `5.15.90.1-microsoft-standard-WSL2`.
+pub(crate) fn detect_wsl(
+    distro_name: Option<&str>,
+    interop: Option<&str>,
+    proc_version: &str,
+) -> Option<WslVersion> {
+    if proc_version.contains("microsoft-standard") || proc_version.contains("WSL2") {
+        Some(WslVersion::Wsl2)
+    } else if proc_version.contains("-Microsoft") {
+        Some(WslVersion::Wsl1)
+    } else if distro_name.is_some() {
+        // Only WSL2 has an interop socket
+        match interop {
+            Some(_) => Some(WslVersion::Wsl2),
+            None => Some(WslVersion::Wsl1),
+        }
+    } else {
+        None
+    }
+}
+
+/// Detect WSL on this host
+pub(crate) fn detect_host_wsl() -> Option<WslVersion> {
+    let distro_name = std::env::var("WSL_DISTRO_NAME").ok();
+    let interop = std::env::var("WSL_INTEROP").ok();
+    let proc_version = std::fs::read_to_string("/proc/version").unwrap_or_default();
+    detect_wsl(distro_name.as_deref(), interop.as_deref(), &proc_version)
+}
+
+fn check_wsl2() -> Result<(), PlannerError> {
+    match detect_host_wsl() {
+        Some(WslVersion::Wsl2) => Ok(()),
+        Some(WslVersion::Wsl1) => Err(PlannerError::Wsl1),
+        None => Err(Wsl2Error::NotWsl2.into()),
+    }
+}
+
+#[non_exhaustive]
+#[derive(Debug, thiserror::Error)]
+pub enum Wsl2Error {
+    #[error("This does not look like WSL2, use the `linux` planner instead")]
+    NotWsl2,
+}
+
+impl HasExpectedErrors for Wsl2Error {
+    fn expected<'a>(&'a self) -> Option<Box<dyn std::error::Error + 'a>> {
+        match self {
+            Wsl2Error::NotWsl2 => Some(Box::new(self)),
+        }
+    }
+}
+
+impl From<Wsl2Error> for PlannerError {
+    fn from(v: Wsl2Error) -> PlannerError {
+        PlannerError::Custom(Box::new(v))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn detects_wsl_versions() {
+        assert_eq!(
+            detect_wsl(
+                None,
+                None,
+                "Linux version 5.15.90.1-microsoft-standard-WSL2 (oe-user@oe-host) (gcc (GCC) 12.2.0)"
+            ),
+            Some(WslVersion::Wsl2)
+        );
+        assert_eq!(
+            detect_wsl(
+                Some("Ubuntu"),
+                None,
+                "Linux version 4.4.0-19041-Microsoft (Microsoft@Microsoft.com) (gcc version 5.4.0)"
+            ),
+            Some(WslVersion::Wsl1)
+        );
+        // Custom WSL2 kernels may not say so, but only WSL2 has interop sockets
+        assert_eq!(
+            detect_wsl(
+                Some("Ubuntu"),
+                Some("/run/WSL/8_interop"),
+                "Linux version 6.6.0"
+            ),
+            Some(WslVersion::Wsl2)
+        );
+        assert_eq!(
+            detect_wsl(None, None, "Linux version 6.6.0-arch1-1 (linux@archlinux)"),
+            None
+        );
+    }
+}
@@ -1,12 +1,41 @@
-use std::{process::Output, time::SystemTime};
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+    process::{Output, Stdio},
+    time::{Duration, Instant, SystemTime},
+};
 
-use tokio::process::Command;
+use nix::unistd::{chown, Gid, Uid, User};
+use tokio::{io::AsyncWriteExt, process::Command};
 use which::which;
 
+use crate::{
+    action::common::wait_for_nix_daemon::{handshake, DAEMON_SOCKET},
+    settings::UrlOrPath,
+    InstallPlan,
+};
+
+/// The `PATH` checks start with, before any profile script adds to it
+const MINIMAL_PATH: &str = "/usr/bin:/bin:/usr/sbin:/sbin";
+
+/// Put in front of the `PATH` a shell prints, to tell it apart from anything else the shell prints
+const PATH_MARKER: &str = "nix-installer-self-test-PATH=";
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+const SYSTEM: &str = "x86_64-linux";
+#[cfg(all(target_os = "linux", target_arch = "x86"))]
+const SYSTEM: &str = "x86-linux";
+#[cfg(all(target_os = "linux", target_arch = "aarch64"))]
+const SYSTEM: &str = "aarch64-linux";
+#[cfg(all(target_os = "macos", target_arch = "x86_64"))]
+const SYSTEM: &str = "x86_64-darwin";
+#[cfg(all(target_os = "macos", target_arch = "aarch64"))]
+const SYSTEM: &str = "aarch64-darwin";
+
 #[non_exhaustive]
 #[derive(thiserror::Error, Debug, strum::IntoStaticStr)]
 pub enum SelfTestError {
-    #[error("Shell `{shell}` failed self-test with command `{command}`, stderr:\n{}", String::from_utf8_lossy(&output.stderr))]
+    #[error("Shell `{shell}` failed self-test with command `{command}`, stdout (with its `PATH`):\n{}\nstderr:\n{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr))]
     ShellFailed {
         shell: Shell,
         command: String,
@@ -22,6 +51,30 @@ pub enum SelfTestError {
         #[source]
         error: std::io::Error,
     },
+    #[error("Check `{check}` failed with command `{command}`, stderr:\n{}", String::from_utf8_lossy(&output.stderr))]
+    CheckFailed {
+        check: Check,
+        command: String,
+        output: Output,
+    },
+    /// Failed to execute the command of a check
+    #[error("Failed to execute command `{command}` of check `{check}`")]
+    CheckCommand {
+        check: Check,
+        command: String,
+        #[source]
+        error: std::io::Error,
+    },
+    #[error("The Nix daemon on `{DAEMON_SOCKET}` did not respond")]
+    Daemon(#[source] std::io::Error),
+    #[error("`nix` is version `{found}`, but the install was of version `{expected}`")]
+    VersionMismatch { expected: String, found: String },
+    #[error("Creating the scratch directory `{0}`")]
+    ScratchDirectory(PathBuf, #[source] std::io::Error),
+    #[error("Getting the user `{0}` to test as")]
+    GettingUser(String, #[source] nix::errno::Errno),
+    #[error("There is no user `{0}` to test as")]
+    NoUser(String),
     #[error(transparent)]
     SystemTime(#[from] std::time::SystemTimeError),
 }
@@ -33,6 +86,12 @@ impl crate::diagnostics::ErrorDiagnostic for SelfTestError {
         let context = match self {
             Self::ShellFailed { shell, .. } => vec![shell.to_string()],
             Self::Command { shell, .. } => vec![shell.to_string()],
+            Self::CheckFailed { check, .. } => vec![check.to_string()],
+            Self::CheckCommand { check, .. } => vec![check.to_string()],
+            Self::Daemon(_) => vec![],
+            Self::VersionMismatch { expected, found } => vec![expected.clone(), found.clone()],
+            Self::ScratchDirectory(_, _) => vec![],
+            Self::GettingUser(_, _) | Self::NoUser(_) => vec![],
             Self::SystemTime(_) => vec![],
         };
         format!(
@@ -53,6 +112,9 @@ pub enum Shell {
     Bash,
     Fish,
     Zsh,
+    Nushell,
+    Tcsh,
+    Xonsh,
 }
 
 impl std::fmt::Display for Shell {
@@ -62,8 +124,18 @@ impl std::fmt::Display for Shell {
 }
 
 impl Shell {
+    /// Elvish isn't here: it reads its `rc.elv` only in interactive sessions on a terminal, so like this it would never
+    /// pick up Nix
     pub fn all() -> &'static [Shell] {
-        &[Shell::Sh, Shell::Bash, Shell::Fish, Shell::Zsh]
+        &[
+            Shell::Sh,
+            Shell::Bash,
+            Shell::Fish,
+            Shell::Zsh,
+            Shell::Nushell,
+            Shell::Tcsh,
+            Shell::Xonsh,
+        ]
     }
     pub fn executable(&self) -> &'static str {
         match &self {
@@ -71,11 +143,15 @@ impl Shell {
             Shell::Bash => "bash",
             Shell::Fish => "fish",
             Shell::Zsh => "zsh",
+            Shell::Nushell => "nu",
+            Shell::Tcsh => "tcsh",
+            Shell::Xonsh => "xonsh",
         }
     }
 
     #[tracing::instrument(skip_all)]
-    pub async fn self_test(&self) -> Result<(), SelfTestError> {
+    /// Build a derivation from a login of the shell, returning the `PATH` it had
+    pub async fn self_test(&self, user: Option<&TestUser>) -> Result<String, SelfTestError> {
         let executable = self.executable();
         let mut command = match &self {
             // On Mac, `bash -ic nix` won't work, but `bash -lc nix` will.
@@ -89,26 +165,38 @@ impl Shell {
                 command.arg("-ic");
                 command
             },
+            // Nushell only reads its autoload directories along with its config, which `-c` skips without `-l`
+            Shell::Nushell => {
+                let mut command = Command::new(executable);
+                command.args(["-l", "-c"]);
+                command
+            },
+            // Every tcsh reads `/etc/csh.cshrc`
+            Shell::Tcsh => {
+                let mut command = Command::new(executable);
+                command.arg("-c");
+                command
+            },
+            Shell::Xonsh => {
+                let mut command = Command::new(executable);
+                command.args(["-i", "-c"]);
+                command
+            },
         };
 
-        #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
-        const SYSTEM: &str = "x86_64-linux";
-        #[cfg(all(target_os = "linux", target_arch = "x86"))]
-        const SYSTEM: &str = "x86-linux";
-        #[cfg(all(target_os = "linux", target_arch = "aarch64"))]
-        const SYSTEM: &str = "aarch64-linux";
-        #[cfg(all(target_os = "macos", target_arch = "x86_64"))]
-        const SYSTEM: &str = "x86_64-darwin";
-        #[cfg(all(target_os = "macos", target_arch = "aarch64"))]
-        const SYSTEM: &str = "aarch64-darwin";
-
-        let timestamp_millis = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)?
-            .as_millis();
-
-        command.arg(format!(
-            r#"nix build --no-link --expr 'derivation {{ name = "self-test-{executable}-{timestamp_millis}"; system = "{SYSTEM}"; builder = "/bin/sh"; args = ["-c" "echo hello > \$out"]; }}'"#
-        ));
+        let timestamp_millis = timestamp_millis()?;
+
+        let build = format!(
+            "nix build --no-link --expr '{}'",
+            derivation_expr(
+                &format!("self-test-{executable}-{timestamp_millis}"),
+                r"echo hello > \$out"
+            )
+        );
+        // `PATH` is exported in every one of these shells, whatever they call it themselves, so `sh` prints it the same
+        // way from each. It's printed first so it's in the output when the build fails too.
+        command.arg(format!("sh -c 'echo \"{PATH_MARKER}$PATH\"'; {build}"));
+        scrub_environment(&mut command, user);
         let command_str = format!("{:?}", command.as_std());
 
         tracing::debug!(
@@ -125,7 +213,7 @@ impl Shell {
             })?;
 
         if output.status.success() {
-            Ok(())
+            Ok(path_from_output(&String::from_utf8_lossy(&output.stdout)).unwrap_or_default())
         } else {
             Err(SelfTestError::ShellFailed {
                 shell: *self,
@@ -148,22 +236,658 @@ impl Shell {
     }
 }
 
-#[tracing::instrument(skip_all)]
-pub async fn self_test() -> Result<(), Vec<SelfTestError>> {
-    let shells = Shell::discover();
+/// A part of the self test, which can be selected or skipped on its own
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "kebab-case")]
+pub enum Check {
+    /// Build a derivation from each discovered shell, with the environment its profile sets up
+    Shells,
+    /// Shake hands with the Nix daemon on its socket
+    Daemon,
+    /// Add a file to the Nix store
+    Store,
+    /// Fetch a path back from a binary cache in a scratch directory
+    Substituter,
+    /// Build a derivation which fails if it can see outside of the sandbox
+    Sandbox,
+    /// Install a path into a scratch profile with `nix profile install`
+    Profile,
+    /// Compare the version of `nix` with the one in the tarball of the install
+    Version,
+}
 
-    let mut failures = vec![];
+impl std::fmt::Display for Check {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Check::Shells => "shells",
+            Check::Daemon => "daemon",
+            Check::Store => "store",
+            Check::Substituter => "substituter",
+            Check::Sandbox => "sandbox",
+            Check::Profile => "profile",
+            Check::Version => "version",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl Check {
+    pub fn all() -> &'static [Check] {
+        &[
+            Check::Shells,
+            Check::Daemon,
+            Check::Store,
+            Check::Substituter,
+            Check::Sandbox,
+            Check::Profile,
+            Check::Version,
+        ]
+    }
 
-    for shell in shells {
-        match shell.self_test().await {
-            Ok(()) => (),
-            Err(err) => failures.push(err),
+    /// The checks done after every install, the others are slower and left to `nix-installer self-test`
+    pub fn post_install() -> &'static [Check] {
+        &[Check::Shells, Check::Daemon]
+    }
+
+    #[tracing::instrument(skip_all, fields(check = %self))]
+    pub async fn run(&self, options: &SelfTestOptions) -> CheckReport {
+        let start = Instant::now();
+        let outcome = match self.outcome(options).await {
+            Ok(outcome) => outcome,
+            Err(errors) => CheckOutcome::Failed(errors),
+        };
+        CheckReport {
+            check: *self,
+            duration: start.elapsed(),
+            outcome,
         }
     }
 
+    async fn outcome(&self, options: &SelfTestOptions) -> Result<CheckOutcome, Vec<SelfTestError>> {
+        match self {
+            Check::Shells => {
+                let shells = Shell::discover();
+                if shells.is_empty() {
+                    return Ok(CheckOutcome::Skipped("No shells were found".to_string()));
+                }
+                let mut failures = vec![];
+                let mut paths = vec![];
+                for shell in &shells {
+                    match shell.self_test(options.user.as_ref()).await {
+                        Ok(path) => paths.push(format!("{shell}: PATH={path}")),
+                        Err(err) => failures.push(err),
+                    }
+                }
+                if failures.is_empty() {
+                    Ok(CheckOutcome::Passed(Some(paths.join("\n"))))
+                } else {
+                    Err(failures)
+                }
+            },
+            Check::Daemon => self.daemon(options).await.map_err(|e| vec![e]),
+            Check::Store => self.store(options).await.map_err(|e| vec![e]),
+            Check::Substituter => self.substituter(options).await.map_err(|e| vec![e]),
+            Check::Sandbox => self.sandbox(options).await.map_err(|e| vec![e]),
+            Check::Profile => self.profile(options).await.map_err(|e| vec![e]),
+            Check::Version => self.version(options).await.map_err(|e| vec![e]),
+        }
+    }
+
+    async fn daemon(&self, options: &SelfTestOptions) -> Result<CheckOutcome, SelfTestError> {
+        let socket = PathBuf::from(DAEMON_SOCKET);
+        if !socket.exists() {
+            return Ok(CheckOutcome::Skipped(format!(
+                "There is no daemon socket `{DAEMON_SOCKET}`, this is likely a single user install"
+            )));
+        }
+        // The socket can only be tried as another user from a process running as them
+        if options.user.is_some() {
+            self.nix(options, |command| {
+                command.args(["store", "ping", "--store", "daemon"]);
+            })
+            .await?;
+            return Ok(CheckOutcome::Passed(None));
+        }
+        let health = tokio::task::spawn_blocking(move || handshake(&socket))
+            .await
+            .map_err(|e| SelfTestError::Daemon(std::io::Error::other(e)))?
+            .map_err(SelfTestError::Daemon)?;
+
+        let (major, minor) = health.protocol_version;
+        Ok(CheckOutcome::Passed(Some(match health.nix_version {
+            Some(nix_version) => format!("Nix {nix_version}, protocol {major}.{minor}"),
+            None => format!("Protocol {major}.{minor}"),
+        })))
+    }
+
+    async fn store(&self, options: &SelfTestOptions) -> Result<CheckOutcome, SelfTestError> {
+        let scratch = ScratchDirectory::create(*self, options.user.as_ref())?;
+        let file = scratch.path().join("self-test");
+        // The test user owns the directory, so never follow anything they could have put there
+        let mut handle = tokio::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&file)
+            .await
+            .map_err(|e| SelfTestError::ScratchDirectory(file.clone(), e))?;
+        handle
+            .write_all(format!("self-test {}\n", timestamp_millis()?).as_bytes())
+            .await
+            .map_err(|e| SelfTestError::ScratchDirectory(file.clone(), e))?;
+        drop(handle);
+
+        let store_path = self
+            .nix(options, |command| {
+                command.args(["store", "add-file"]).arg(&file);
+            })
+            .await?;
+
+        Ok(CheckOutcome::Passed(Some(store_path)))
+    }
+
+    async fn substituter(&self, options: &SelfTestOptions) -> Result<CheckOutcome, SelfTestError> {
+        // Importing unsigned paths is only allowed for trusted users
+        if options.user.is_some() || !nix::unistd::Uid::effective().is_root() {
+            return Ok(CheckOutcome::Skipped(
+                "Fetching unsigned paths from a cache needs `root`".to_string(),
+            ));
+        }
+        let scratch = ScratchDirectory::create(*self, options.user.as_ref())?;
+        let cache = format!("file://{}", scratch.path().join("cache").display());
+
+        let out = self
+            .build(options, "self-test-substituter", r"echo hello > \$out")
+            .await?;
+        self.nix(options, |command| {
+            command.args(["copy", "--to", &cache, &out]);
+        })
+        .await?;
+        self.nix(options, |command| {
+            command.args(["store", "delete", &out]);
+        })
+        .await?;
+        self.nix(options, |command| {
+            command.args(["copy", "--no-check-sigs", "--from", &cache, &out]);
+        })
+        .await?;
+
+        Ok(CheckOutcome::Passed(None))
+    }
+
+    async fn sandbox(&self, options: &SelfTestOptions) -> Result<CheckOutcome, SelfTestError> {
+        if cfg!(not(target_os = "linux")) {
+            return Ok(CheckOutcome::Skipped(
+                "Sandboxing is only checked on Linux".to_string(),
+            ));
+        }
+
+        let config = self
+            .nix(options, |command| {
+                command.args(["show-config", "--json"]);
+            })
+            .await?;
+        let sandbox = serde_json::from_str::<serde_json::Value>(&config)
+            .ok()
+            .and_then(|config| config.pointer("/sandbox/value").cloned());
+        if matches!(sandbox, Some(serde_json::Value::Bool(false))) {
+            return Ok(CheckOutcome::Skipped(
+                "Sandboxing is disabled in the Nix configuration".to_string(),
+            ));
+        }
+
+        // Only the inputs of a build are in its sandbox, not the rest of `/nix`
+        self.build(
+            options,
+            "self-test-sandbox",
+            r"if [ -e /nix/var/nix ]; then echo /nix/var/nix is visible to builds >&2; exit 1; fi; echo hello > \$out",
+        )
+        .await?;
+
+        Ok(CheckOutcome::Passed(None))
+    }
+
+    async fn profile(&self, options: &SelfTestOptions) -> Result<CheckOutcome, SelfTestError> {
+        let scratch = ScratchDirectory::create(*self, options.user.as_ref())?;
+        let profile = scratch.path().join("profile");
+
+        let out = self
+            .build(options, "self-test-profile", r"echo hello > \$out")
+            .await?;
+        self.nix(options, |command| {
+            command
+                .args(["profile", "install", "--profile"])
+                .arg(&profile)
+                .arg(&out);
+        })
+        .await?;
+
+        Ok(CheckOutcome::Passed(None))
+    }
+
+    async fn version(&self, options: &SelfTestOptions) -> Result<CheckOutcome, SelfTestError> {
+        let Some(expected) = options.expected_nix_version.as_deref() else {
+            return Ok(CheckOutcome::Skipped(
+                "The version of the install is unknown without its receipt".to_string(),
+            ));
+        };
+
+        // Like `nix (Nix) 2.19.3`
+        let output = self
+            .nix(options, |command| {
+                command.arg("--version");
+            })
+            .await?;
+        let found = output.split_whitespace().last().unwrap_or_default();
+
+        if found == expected {
+            Ok(CheckOutcome::Passed(Some(found.to_string())))
+        } else {
+            Err(SelfTestError::VersionMismatch {
+                expected: expected.to_string(),
+                found: found.to_string(),
+            })
+        }
+    }
+
+    /// Build a derivation named after `name` running `script`, and return its output path
+    async fn build(
+        &self,
+        options: &SelfTestOptions,
+        name: &str,
+        script: &str,
+    ) -> Result<String, SelfTestError> {
+        let expr = derivation_expr(&format!("{name}-{}", timestamp_millis()?), script);
+        self.nix(options, |command| {
+            command.args(["build", "--no-link", "--print-out-paths", "--expr", &expr]);
+        })
+        .await
+    }
+
+    /// Run the `nix` of the install with `args`, and return what it printed
+    async fn nix(
+        &self,
+        options: &SelfTestOptions,
+        args: impl FnOnce(&mut Command),
+    ) -> Result<String, SelfTestError> {
+        let mut command = Command::new(nix_binary(options.user.as_ref()));
+        command.args(["--extra-experimental-features", "nix-command"]);
+        args(&mut command);
+        command.stdin(Stdio::null());
+        scrub_environment(&mut command, options.user.as_ref());
+        let command_str = format!("{:?}", command.as_std());
+
+        tracing::debug!(command = command_str, "Running check `{self}`");
+        let output = command
+            .output()
+            .await
+            .map_err(|error| SelfTestError::CheckCommand {
+                check: *self,
+                command: command_str.clone(),
+                error,
+            })?;
+
+        if output.status.success() {
+            Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+        } else {
+            Err(SelfTestError::CheckFailed {
+                check: *self,
+                command: command_str,
+                output,
+            })
+        }
+    }
+}
+
+/// The result of running a [`Check`]
+#[derive(Debug, serde::Serialize)]
+pub struct CheckReport {
+    pub check: Check,
+    #[serde(rename = "duration_ms", serialize_with = "serialize_millis")]
+    pub duration: Duration,
+    #[serde(flatten)]
+    pub outcome: CheckOutcome,
+}
+
+#[derive(Debug, serde::Serialize)]
+#[serde(tag = "status", content = "message", rename_all = "snake_case")]
+pub enum CheckOutcome {
+    /// Passed, with anything worth reporting about it
+    Passed(Option<String>),
+    /// Not applicable to this install, and why
+    Skipped(String),
+    #[serde(serialize_with = "serialize_errors")]
+    Failed(Vec<SelfTestError>),
+}
+
+fn serialize_millis<S: serde::Serializer>(
+    duration: &Duration,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(duration.as_millis() as u64)
+}
+
+fn serialize_errors<S: serde::Serializer>(
+    errors: &[SelfTestError],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(errors.iter().map(|err| match err.source() {
+        Some(source) => format!("{err}\n{source}"),
+        None => err.to_string(),
+    }))
+}
+
+/// A private directory under the temporary directory for a check, removed with everything in it when dropped
+struct ScratchDirectory {
+    dir: tempfile::TempDir,
+}
+
+impl ScratchDirectory {
+    fn create(check: Check, user: Option<&TestUser>) -> Result<Self, SelfTestError> {
+        // A fresh directory with a random name, so nothing planted in the temporary directory beforehand is ever followed or chowned
+        let dir = tempfile::Builder::new()
+            .prefix(&format!("nix-installer-self-test-{check}-"))
+            .tempdir()
+            .map_err(|e| SelfTestError::ScratchDirectory(std::env::temp_dir(), e))?;
+        if let Some(user) = user {
+            chown(dir.path(), Some(user.uid), Some(user.gid))
+                .map_err(|e| SelfTestError::ScratchDirectory(dir.path().to_path_buf(), e.into()))?;
+        }
+        Ok(Self { dir })
+    }
+
+    fn path(&self) -> &Path {
+        self.dir.path()
+    }
+}
+
+/// The `nix` of the install, which isn't on `PATH` until a new shell has sourced the profile
+fn nix_binary(user: Option<&TestUser>) -> PathBuf {
+    let default_profile = Path::new("/nix/var/nix/profiles/default/bin/nix");
+    if default_profile.exists() {
+        return default_profile.to_path_buf();
+    }
+    let home = match user {
+        Some(user) => Some(user.home.clone()),
+        None => dirs::home_dir(),
+    };
+    if let Some(user_profile) = home.map(|home| home.join(".nix-profile/bin/nix")) {
+        if user_profile.exists() {
+            return user_profile;
+        }
+    }
+    PathBuf::from("nix")
+}
+
+/// A derivation running `script` with `/bin/sh`, which must write `$out`
+fn derivation_expr(name: &str, script: &str) -> String {
+    format!(
+        r#"derivation {{ name = "{name}"; system = "{SYSTEM}"; builder = "/bin/sh"; args = ["-c" "{script}"]; }}"#
+    )
+}
+
+fn timestamp_millis() -> Result<u128, SelfTestError> {
+    Ok(SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)?
+        .as_millis())
+}
+
+/// The version of Nix in the tarball `plan` installed, from its file name like `nix-2.19.3-x86_64-linux.tar.xz`
+pub fn tarball_nix_version(plan: &InstallPlan) -> Option<String> {
+    let settings = plan.planner.settings().ok()?;
+    let url: UrlOrPath = serde_json::from_value(settings.get("nix_package_url")?.clone()).ok()?;
+    nix_version_of_tarball(&url.to_string())
+}
+
+fn nix_version_of_tarball(url: &str) -> Option<String> {
+    let file_name = url.trim_end_matches('/').rsplit('/').next()?;
+    let (version, _system) = file_name.strip_prefix("nix-")?.split_once('-')?;
+    Some(version.to_string())
+}
+
+/// What the checks of a self test compare against, and who they run as
+#[derive(Debug, Clone, Default)]
+pub struct SelfTestOptions {
+    /// The version `nix --version` should report, see [`tarball_nix_version`]
+    pub expected_nix_version: Option<String>,
+    /// Run the checks as this user rather than whoever runs `nix-installer`
+    pub user: Option<TestUser>,
+}
+
+/// An ordinary user to run the checks as, so they go through the daemon socket and profiles like that user would
+#[derive(Debug, Clone)]
+pub struct TestUser {
+    pub name: String,
+    pub uid: Uid,
+    pub gid: Gid,
+    pub home: PathBuf,
+}
+
+impl TestUser {
+    pub fn from_name(name: &str) -> Result<Self, SelfTestError> {
+        let user = User::from_name(name)
+            .map_err(|e| SelfTestError::GettingUser(name.to_string(), e))?
+            .ok_or_else(|| SelfTestError::NoUser(name.to_string()))?;
+        Ok(Self {
+            name: user.name,
+            uid: user.uid,
+            gid: user.gid,
+            home: user.dir,
+        })
+    }
+
+    /// The user who ran `sudo nix-installer`, unless that was `root` itself
+    pub fn from_sudo() -> Option<Self> {
+        if !Uid::effective().is_root() {
+            return None;
+        }
+        let name = std::env::var("SUDO_USER").ok()?;
+        match Self::from_name(&name) {
+            Ok(user) if !user.uid.is_root() => Some(user),
+            Ok(_) => None,
+            Err(err) => {
+                tracing::debug!("Not testing as `SUDO_USER` `{name}`: {err}");
+                None
+            },
+        }
+    }
+
+    /// Make `command` run as the user, in their home, with the supplementary groups they get when logging in
+    ///
+    /// Those groups are what gives the user access to the daemon through `trusted-users = @group` and the like.
+    fn apply(&self, command: &mut Command) {
+        command.current_dir(&self.home);
+        #[cfg(target_os = "linux")]
+        {
+            // What `initgroups` does, but the group database is read here, as that isn't safe between `fork` and `exec`
+            let groups = std::ffi::CString::new(self.name.as_str())
+                .ok()
+                .and_then(|name| nix::unistd::getgrouplist(&name, self.gid).ok())
+                .unwrap_or_else(|| vec![self.gid]);
+            let (uid, gid) = (self.uid, self.gid);
+            // SAFETY: Only system calls are made in the child, the groups are looked up beforehand
+            unsafe {
+                command.pre_exec(move || {
+                    nix::unistd::setgroups(&groups)?;
+                    nix::unistd::setgid(gid)?;
+                    nix::unistd::setuid(uid)?;
+                    Ok(())
+                });
+            }
+        }
+        // The groups of a user are looked up by `opendirectoryd` when needed on macOS
+        #[cfg(not(target_os = "linux"))]
+        command.uid(self.uid.as_raw()).gid(self.gid.as_raw());
+    }
+}
+
+/**
+Run `command` with only `HOME`, `USER`, `LOGNAME`, `TERM` and a minimal `PATH`, as `user` if set
+
+Anything else in the environment of `nix-installer`, like a `NIX_SSL_CERT_FILE` it set for itself, could make a check
+pass which fails in a fresh login. This way a check only passes if the profile scripts set everything up.
+*/
+fn scrub_environment(command: &mut Command, user: Option<&TestUser>) {
+    let (home, name) = match user {
+        Some(user) => (Some(user.home.clone()), Some(user.name.clone())),
+        None => (
+            dirs::home_dir(),
+            User::from_uid(Uid::effective())
+                .ok()
+                .flatten()
+                .map(|user| user.name),
+        ),
+    };
+
+    command.env_clear().env("PATH", MINIMAL_PATH).env(
+        "TERM",
+        std::env::var_os("TERM").unwrap_or_else(|| "dumb".into()),
+    );
+    if let Some(home) = home {
+        command.env("HOME", home);
+    }
+    if let Some(name) = name {
+        command.env("USER", &name).env("LOGNAME", name);
+    }
+    if let Some(user) = user {
+        user.apply(command);
+    }
+}
+
+/// The `PATH` a shell printed after [`PATH_MARKER`], interactive shells may print a greeting or more around it
+fn path_from_output(stdout: &str) -> Option<String> {
+    stdout
+        .lines()
+        .rev()
+        .find_map(|line| line.trim().strip_prefix(PATH_MARKER))
+        .map(ToString::to_string)
+}
+
+/// Run each of `checks`, one after the other
+#[tracing::instrument(skip_all)]
+pub async fn run_checks(checks: &[Check], options: &SelfTestOptions) -> Vec<CheckReport> {
+    let mut reports = vec![];
+    for check in checks {
+        reports.push(check.run(options).await);
+    }
+    reports
+}
+
+/// Run the checks done after an install, returning the errors of the ones which failed
+#[tracing::instrument(skip_all)]
+pub async fn self_test(options: &SelfTestOptions) -> Result<(), Vec<SelfTestError>> {
+    let failures: Vec<_> = run_checks(Check::post_install(), options)
+        .await
+        .into_iter()
+        .flat_map(|report| match report.outcome {
+            CheckOutcome::Failed(errors) => errors,
+            CheckOutcome::Passed(_) | CheckOutcome::Skipped(_) => vec![],
+        })
+        .collect();
+
     if failures.is_empty() {
         Ok(())
     } else {
         Err(failures)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn finds_version_of_tarball() {
+        assert_eq!(
+            nix_version_of_tarball(
+                "https://releases.nixos.org/nix/nix-2.19.3/nix-2.19.3-x86_64-linux.tar.xz"
+            )
+            .as_deref(),
+            Some("2.19.3")
+        );
+        assert_eq!(
+            nix_version_of_tarball("/tmp/nix-2.20.0pre20240115_1a2b3c4-aarch64-darwin.tar.xz")
+                .as_deref(),
+            Some("2.20.0pre20240115_1a2b3c4")
+        );
+        assert_eq!(nix_version_of_tarball("/tmp/my-nix.tar.xz"), None);
+    }
+
+    #[test]
+    fn scratch_directory_is_fresh_and_removed() -> eyre::Result<()> {
+        let scratch = ScratchDirectory::create(Check::Store, None)?;
+        let other = ScratchDirectory::create(Check::Store, None)?;
+        let path = scratch.path().to_path_buf();
+        assert_ne!(path, other.path());
+        assert!(path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with("nix-installer-self-test-store-")));
+        drop(scratch);
+        assert!(!path.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn finds_path_after_marker() {
+        assert_eq!(
+            path_from_output(&format!(
+                "Welcome to fish!\n{PATH_MARKER}/nix/var/nix/profiles/default/bin:/usr/bin\n\n"
+            ))
+            .as_deref(),
+            Some("/nix/var/nix/profiles/default/bin:/usr/bin")
+        );
+        assert_eq!(
+            path_from_output(&format!("{PATH_MARKER}\n")).as_deref(),
+            Some("")
+        );
+        assert_eq!(path_from_output("/usr/bin:/bin\nbye\n"), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_user_gets_login_groups() -> eyre::Result<()> {
+        // Only `root` can change user
+        let Ok(nobody) = TestUser::from_name("nobody") else {
+            return Ok(());
+        };
+        if !Uid::effective().is_root() {
+            return Ok(());
+        }
+        let temp_dir = tempfile::tempdir()?;
+        let user = TestUser {
+            home: temp_dir.path().to_path_buf(),
+            ..nobody
+        };
+
+        let mut command = Command::new("id");
+        user.apply(&mut command);
+        let output = command.output().await?;
+        assert!(output.status.success());
+        let ids = String::from_utf8(output.stdout)?;
+        assert!(ids.starts_with(&format!("uid={}(nobody)", user.uid)));
+        // Without the groups of `root`
+        assert!(!ids.contains("groups=0(root)"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn reports_as_json() -> eyre::Result<()> {
+        let report = CheckReport {
+            check: Check::Substituter,
+            duration: Duration::from_millis(1234),
+            outcome: CheckOutcome::Skipped("No".to_string()),
+        };
+        assert_eq!(
+            serde_json::to_value(&report)?,
+            serde_json::json!({
+                "check": "substituter",
+                "duration_ms": 1234,
+                "status": "skipped",
+                "message": "No",
+            })
+        );
+        Ok(())
+    }
+}
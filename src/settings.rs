@@ -1,6 +1,11 @@
 /*! Configurable knobs and their related errors
 */
-use std::{collections::HashMap, fmt::Display, path::PathBuf, str::FromStr};
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
 #[cfg(feature = "cli")]
 use clap::{
@@ -11,6 +16,9 @@ use url::Url;
 
 pub const SCRATCH_DIR: &str = "/nix/temp-install-dir";
 
+/// The directory Nix expects to find its store in, see [`LocationSettings::store_location`]
+pub const NIX_DIR: &str = "/nix";
+
 /// Default [`nix_package_url`](CommonSettings::nix_package_url) for Linux x86_64
 pub const NIX_X64_64_LINUX_URL: &str =
     "https://releases.nixos.org/nix/nix-2.19.3/nix-2.19.3-x86_64-linux.tar.xz";
@@ -34,6 +42,14 @@ pub enum InitSystem {
     None,
     #[cfg(target_os = "linux")]
     Systemd,
+    #[cfg(target_os = "linux")]
+    #[cfg_attr(feature = "cli", value(name = "openrc"))]
+    OpenRC,
+    #[cfg(target_os = "linux")]
+    Runit,
+    #[cfg(target_os = "linux")]
+    #[cfg_attr(feature = "cli", value(name = "s6"))]
+    S6,
     #[cfg(target_os = "macos")]
     Launchd,
 }
@@ -45,6 +61,12 @@ impl std::fmt::Display for InitSystem {
             InitSystem::None => write!(f, "none"),
             #[cfg(target_os = "linux")]
             InitSystem::Systemd => write!(f, "systemd"),
+            #[cfg(target_os = "linux")]
+            InitSystem::OpenRC => write!(f, "openrc"),
+            #[cfg(target_os = "linux")]
+            InitSystem::Runit => write!(f, "runit"),
+            #[cfg(target_os = "linux")]
+            InitSystem::S6 => write!(f, "s6"),
             #[cfg(target_os = "macos")]
             InitSystem::Launchd => write!(f, "launchd"),
         }
@@ -73,6 +95,20 @@ pub struct CommonSettings {
     )]
     pub modify_profile: bool,
 
+    /// Modify the shell profiles of these users, like `~/.bashrc`, instead of the system wide ones like `/etc/bashrc`
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long = "profile-user",
+            action = ArgAction::Append,
+            value_delimiter = ',',
+            env = "NIX_INSTALLER_PROFILE_USERS",
+            global = true
+        )
+    )]
+    #[serde(default)]
+    pub profile_users: Vec<String>,
+
     /// The Nix build group name
     #[cfg_attr(
         feature = "cli",
@@ -179,11 +215,11 @@ pub struct CommonSettings {
     )]
     pub nix_package_url: UrlOrPath,
 
-    /// The proxy to use (if any), valid proxy bases are `https://$URL`, `http://$URL` and `socks5://$URL`
+    /// The proxy to use (if any), also for the daemon with systemd, valid proxy bases are `https://$URL`, `http://$URL` and `socks5://$URL`
     #[cfg_attr(feature = "cli", clap(long, env = "NIX_INSTALLER_PROXY"))]
     pub proxy: Option<Url>,
 
-    /// An SSL cert to use (if any), used for fetching Nix and sets `ssl-cert-file` in `/etc/nix/nix.conf`
+    /// An SSL cert to use (if any), used for fetching Nix and sets `ssl-cert-file` in `/etc/nix/nix.conf` and `NIX_SSL_CERT_FILE` for the daemon with systemd
     #[cfg_attr(feature = "cli", clap(long, env = "NIX_INSTALLER_SSL_CERT_FILE"))]
     pub ssl_cert_file: Option<PathBuf>,
 
@@ -303,6 +339,7 @@ impl CommonSettings {
 
         Ok(Self {
             modify_profile: true,
+            profile_users: Default::default(),
             nix_build_group_name: String::from("nixbld"),
             nix_build_group_id: 30_000,
             nix_build_user_id_base,
@@ -324,6 +361,7 @@ impl CommonSettings {
     pub fn settings(&self) -> Result<HashMap<String, serde_json::Value>, InstallSettingsError> {
         let Self {
             modify_profile,
+            profile_users,
             nix_build_group_name,
             nix_build_group_id,
             nix_build_user_prefix,
@@ -345,6 +383,7 @@ impl CommonSettings {
             "modify_profile".into(),
             serde_json::to_value(modify_profile)?,
         );
+        map.insert("profile_users".into(), serde_json::to_value(profile_users)?);
         map.insert(
             "nix_build_group_name".into(),
             serde_json::to_value(nix_build_group_name)?,
@@ -384,43 +423,72 @@ impl CommonSettings {
     }
 }
 
+/// If the host was booted with systemd, which leaves `/run/systemd/system` behind, and `systemctl` works
 #[cfg(target_os = "linux")]
-async fn linux_detect_systemd_started() -> bool {
+fn linux_detect_systemd_started() -> bool {
     use std::process::Stdio;
 
-    let mut started = false;
-    if std::path::Path::new("/run/systemd/system").exists() {
-        started = tokio::process::Command::new("systemctl")
+    std::path::Path::new("/run/systemd/system").exists()
+        && std::process::Command::new("systemctl")
             .arg("status")
             .stdin(Stdio::null())
             .stdout(Stdio::null())
             .stderr(Stdio::null())
             .status()
-            .await
-            .ok()
             .map(|exit| exit.success())
             .unwrap_or(false)
-    }
+}
+
+/// If the host was booted with OpenRC, which leaves `/run/openrc` behind, or at least has a working `rc-status`
+#[cfg(target_os = "linux")]
+fn linux_detect_openrc_started() -> bool {
+    use std::process::Stdio;
+
+    std::path::Path::new("/run/openrc").exists()
+        || std::process::Command::new("rc-status")
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .map(|exit| exit.success())
+            .unwrap_or(false)
+}
 
-    // TODO: Other inits
-    started
+/// If the host was booted with runit, which leaves `/run/runit` behind
+#[cfg(target_os = "linux")]
+fn linux_detect_runit_started() -> bool {
+    std::path::Path::new("/run/runit").exists()
+}
+
+/// If an `s6-svscan` is supervising one of the usual scan directories
+#[cfg(target_os = "linux")]
+fn linux_detect_s6_started() -> bool {
+    crate::action::common::configure_init_service::s6_scan_dir().is_some()
+}
+
+/// The init system the host was booted with, preferring systemd, or `None` if none of those supported is running
+#[cfg(target_os = "linux")]
+pub(crate) fn linux_detect_init() -> Option<InitSystem> {
+    if linux_detect_systemd_started() {
+        Some(InitSystem::Systemd)
+    } else if linux_detect_openrc_started() {
+        Some(InitSystem::OpenRC)
+    } else if linux_detect_runit_started() {
+        Some(InitSystem::Runit)
+    } else if linux_detect_s6_started() {
+        Some(InitSystem::S6)
+    } else {
+        None
+    }
 }
 
 #[serde_with::serde_as]
 #[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
 #[cfg_attr(feature = "cli", derive(clap::Parser))]
 pub struct InitSettings {
-    /// Which init system to configure (if `--init none` Nix will be root-only)
+    /// Which init system to configure (if `--init none` Nix will be root-only), by default the one the host was booted with
     #[cfg_attr(feature = "cli", clap(value_parser, long, env = "NIX_INSTALLER_INIT",))]
-    #[cfg_attr(
-        all(target_os = "macos", feature = "cli"),
-        clap(default_value_t = InitSystem::Launchd)
-    )]
-    #[cfg_attr(
-        all(target_os = "linux", feature = "cli"),
-        clap(default_value_t = InitSystem::Systemd)
-    )]
-    pub init: InitSystem,
+    pub init: Option<InitSystem>,
 
     /// Start the daemon (if not `--init none`)
     #[cfg_attr(
@@ -435,31 +503,38 @@ pub struct InitSettings {
         )
     )]
     pub start_daemon: bool,
+
+    /// The `LimitNOFILE` of the daemon, set in a drop-in for `nix-daemon.service` (systemd only)
+    #[cfg_attr(feature = "cli", clap(long, env = "NIX_INSTALLER_DAEMON_LIMIT_NOFILE"))]
+    #[serde(default)]
+    pub daemon_limit_nofile: Option<u64>,
+
+    /// The `CPUQuota` of the daemon such as `200%`, set in a drop-in for `nix-daemon.service` (systemd only)
+    #[cfg_attr(
+        feature = "cli",
+        clap(long, value_parser = parse_cpu_quota, env = "NIX_INSTALLER_DAEMON_CPU_QUOTA")
+    )]
+    #[serde(default)]
+    pub daemon_cpu_quota: Option<String>,
 }
 
 impl InitSettings {
     /// The default settings for the given Architecture & Operating System
     pub async fn default() -> Result<Self, InstallSettingsError> {
         use target_lexicon::{Architecture, OperatingSystem};
-        let (init, start_daemon) = match (Architecture::host(), OperatingSystem::host()) {
+        let start_daemon = match (Architecture::host(), OperatingSystem::host()) {
             #[cfg(target_os = "linux")]
-            (Architecture::X86_64, OperatingSystem::Linux) => {
-                (InitSystem::Systemd, linux_detect_systemd_started().await)
-            },
+            (Architecture::X86_64, OperatingSystem::Linux) => linux_detect_init().is_some(),
             #[cfg(target_os = "linux")]
-            (Architecture::X86_32(_), OperatingSystem::Linux) => {
-                (InitSystem::Systemd, linux_detect_systemd_started().await)
-            },
+            (Architecture::X86_32(_), OperatingSystem::Linux) => linux_detect_init().is_some(),
             #[cfg(target_os = "linux")]
-            (Architecture::Aarch64(_), OperatingSystem::Linux) => {
-                (InitSystem::Systemd, linux_detect_systemd_started().await)
-            },
+            (Architecture::Aarch64(_), OperatingSystem::Linux) => linux_detect_init().is_some(),
             #[cfg(target_os = "macos")]
             (Architecture::X86_64, OperatingSystem::MacOSX { .. })
-            | (Architecture::X86_64, OperatingSystem::Darwin) => (InitSystem::Launchd, true),
+            | (Architecture::X86_64, OperatingSystem::Darwin) => true,
             #[cfg(target_os = "macos")]
             (Architecture::Aarch64(_), OperatingSystem::MacOSX { .. })
-            | (Architecture::Aarch64(_), OperatingSystem::Darwin) => (InitSystem::Launchd, true),
+            | (Architecture::Aarch64(_), OperatingSystem::Darwin) => true,
             _ => {
                 return Err(InstallSettingsError::UnsupportedArchitecture(
                     target_lexicon::HOST,
@@ -467,30 +542,333 @@ impl InitSettings {
             },
         };
 
-        Ok(Self { init, start_daemon })
+        Ok(Self {
+            // Left to `init_system`, so the settings only list an init if one was given
+            init: None,
+            start_daemon,
+            daemon_limit_nofile: None,
+            daemon_cpu_quota: None,
+        })
     }
 
     /// A listing of the settings, suitable for [`Planner::settings`](crate::planner::Planner::settings)
     pub fn settings(&self) -> Result<HashMap<String, serde_json::Value>, InstallSettingsError> {
-        let Self { init, start_daemon } = self;
+        let Self {
+            init,
+            start_daemon,
+            daemon_limit_nofile,
+            daemon_cpu_quota,
+        } = self;
         let mut map = HashMap::default();
 
         map.insert("init".into(), serde_json::to_value(init)?);
         map.insert("start_daemon".into(), serde_json::to_value(start_daemon)?);
+        map.insert(
+            "daemon_limit_nofile".into(),
+            serde_json::to_value(daemon_limit_nofile)?,
+        );
+        map.insert(
+            "daemon_cpu_quota".into(),
+            serde_json::to_value(daemon_cpu_quota)?,
+        );
         Ok(map)
     }
 
     /// Which init system to configure
     pub fn init(&mut self, init: InitSystem) -> &mut Self {
-        self.init = init;
+        self.init = Some(init);
         self
     }
 
+    /// The init system to configure, detecting the one the host was booted with if none was given
+    ///
+    /// Detecting may run commands, so it is left until planning rather than done for every parse of the CLI.
+    pub fn init_system(&self) -> InitSystem {
+        #[cfg(target_os = "linux")]
+        return self
+            .init
+            .unwrap_or_else(|| linux_detect_init().unwrap_or(InitSystem::Systemd));
+        #[cfg(target_os = "macos")]
+        return self.init.unwrap_or(InitSystem::Launchd);
+    }
+
     /// Start the daemon (if one is configured)
     pub fn start_daemon(&mut self, toggle: bool) -> &mut Self {
         self.start_daemon = toggle;
         self
     }
+
+    /// The `LimitNOFILE` of the daemon (systemd only)
+    pub fn daemon_limit_nofile(&mut self, limit: impl Into<Option<u64>>) -> &mut Self {
+        self.daemon_limit_nofile = limit.into();
+        self
+    }
+
+    /// The `CPUQuota` of the daemon, such as `200%` (systemd only)
+    pub fn daemon_cpu_quota(&mut self, quota: impl Into<Option<String>>) -> &mut Self {
+        self.daemon_cpu_quota = quota.into();
+        self
+    }
+}
+
+/// A systemd `CPUQuota`, a percentage such as `50%` or `200%`
+#[cfg(feature = "cli")]
+fn parse_cpu_quota(quota: &str) -> Result<String, String> {
+    match quota.strip_suffix('%').map(str::parse::<u32>) {
+        Some(Ok(percent)) if percent > 0 => Ok(quota.to_string()),
+        _ => Err(format!(
+            "`{quota}` is not a percentage above zero, such as `200%`"
+        )),
+    }
+}
+
+/// How [`LocationSettings::store_location`] is made available at `/nix`
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum StoreLink {
+    /// Bind mount the store location onto `/nix` through `/etc/fstab`
+    #[default]
+    BindMount,
+    /// Make `/nix` a symlink to the store location, this sets `allow-symlinked-store = true` in `/etc/nix/nix.conf`
+    Symlink,
+}
+
+impl std::fmt::Display for StoreLink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StoreLink::BindMount => write!(f, "bind-mount"),
+            StoreLink::Symlink => write!(f, "symlink"),
+        }
+    }
+}
+
+/// The filesystem of the image given by [`LocationSettings::store_image`]
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum LoopbackFilesystem {
+    #[default]
+    Ext4,
+    Btrfs,
+}
+
+impl LoopbackFilesystem {
+    /// The program which formats an image with this filesystem
+    pub fn mkfs(&self) -> &'static str {
+        match self {
+            LoopbackFilesystem::Ext4 => "mkfs.ext4",
+            LoopbackFilesystem::Btrfs => "mkfs.btrfs",
+        }
+    }
+}
+
+impl std::fmt::Display for LoopbackFilesystem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoopbackFilesystem::Ext4 => write!(f, "ext4"),
+            LoopbackFilesystem::Btrfs => write!(f, "btrfs"),
+        }
+    }
+}
+
+/// The default for [`LocationSettings::store_image_size`], 64 GiB, which costs nothing up front as the image is sparse
+pub const DEFAULT_STORE_IMAGE_SIZE: u64 = 64 * 1024 * 1024 * 1024;
+
+fn default_store_image_size() -> u64 {
+    DEFAULT_STORE_IMAGE_SIZE
+}
+
+/// A size in bytes, optionally with a binary suffix such as `512M` or `64G`
+#[cfg(feature = "cli")]
+fn parse_size(size: &str) -> Result<u64, String> {
+    let trimmed = size.trim().trim_end_matches(['B', 'b']);
+    let (digits, shift) = match trimmed.char_indices().last() {
+        Some((index, 'K' | 'k')) => (&trimmed[..index], 10),
+        Some((index, 'M' | 'm')) => (&trimmed[..index], 20),
+        Some((index, 'G' | 'g')) => (&trimmed[..index], 30),
+        Some((index, 'T' | 't')) => (&trimmed[..index], 40),
+        _ => (trimmed, 0),
+    };
+    digits
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .and_then(|value| value.checked_mul(1 << shift))
+        .filter(|bytes| *bytes > 0)
+        .ok_or_else(|| format!("`{size}` is not a size above zero, such as `512M` or `64G`"))
+}
+
+/** Where on the filesystem Nix is installed
+
+Nix always refers to its store as `/nix/store`, these settings only change where it is physically stored, or which
+filesystem tree is being installed into.
+*/
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::Parser))]
+pub struct LocationSettings {
+    /// Install into this root instead of `/` (such as a mounted disk image), every path is created under it
+    #[cfg_attr(
+        feature = "cli",
+        clap(long, default_value = "/", env = "NIX_INSTALLER_ROOT", global = true)
+    )]
+    pub root: PathBuf,
+
+    /// Store Nix in this directory (such as `/opt/nix`) instead of `/nix`, then make it available at `/nix`
+    #[cfg_attr(
+        feature = "cli",
+        clap(long, env = "NIX_INSTALLER_STORE_LOCATION", global = true)
+    )]
+    pub store_location: Option<PathBuf>,
+
+    /// How the `--store-location` is made available at `/nix`
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            value_parser,
+            default_value_t = StoreLink::BindMount,
+            env = "NIX_INSTALLER_STORE_LINK",
+            global = true
+        )
+    )]
+    pub store_link: StoreLink,
+
+    /// Store Nix in a loopback image file (such as `/var/lib/nix.img`) which systemd mounts on `/nix`
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            conflicts_with = "store_location",
+            env = "NIX_INSTALLER_STORE_IMAGE",
+            global = true
+        )
+    )]
+    #[serde(default)]
+    pub store_image: Option<PathBuf>,
+
+    /// The size of the `--store-image`, such as `64G`, it is sparse so only what is used takes up space
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            value_parser = parse_size,
+            default_value = "64G",
+            env = "NIX_INSTALLER_STORE_IMAGE_SIZE",
+            global = true
+        )
+    )]
+    #[serde(default = "default_store_image_size")]
+    pub store_image_size: u64,
+
+    /// The filesystem the `--store-image` is formatted with
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            value_parser,
+            default_value_t = LoopbackFilesystem::Ext4,
+            env = "NIX_INSTALLER_STORE_IMAGE_FILESYSTEM",
+            global = true
+        )
+    )]
+    #[serde(default)]
+    pub store_image_filesystem: LoopbackFilesystem,
+}
+
+impl Default for LocationSettings {
+    fn default() -> Self {
+        Self {
+            root: PathBuf::from("/"),
+            store_location: None,
+            store_link: StoreLink::default(),
+            store_image: None,
+            store_image_size: DEFAULT_STORE_IMAGE_SIZE,
+            store_image_filesystem: LoopbackFilesystem::default(),
+        }
+    }
+}
+
+impl LocationSettings {
+    /// A listing of the settings, suitable for [`Planner::settings`](crate::planner::Planner::settings)
+    pub fn settings(&self) -> Result<HashMap<String, serde_json::Value>, InstallSettingsError> {
+        let Self {
+            root,
+            store_location,
+            store_link,
+            store_image,
+            store_image_size,
+            store_image_filesystem,
+        } = self;
+        let mut map = HashMap::default();
+
+        map.insert("root".into(), serde_json::to_value(root)?);
+        map.insert(
+            "store_location".into(),
+            serde_json::to_value(store_location)?,
+        );
+        map.insert("store_link".into(), serde_json::to_value(store_link)?);
+        map.insert("store_image".into(), serde_json::to_value(store_image)?);
+        map.insert(
+            "store_image_size".into(),
+            serde_json::to_value(store_image_size)?,
+        );
+        map.insert(
+            "store_image_filesystem".into(),
+            serde_json::to_value(store_image_filesystem)?,
+        );
+        Ok(map)
+    }
+
+    /// Install into this root instead of `/`
+    pub fn root(&mut self, root: impl Into<PathBuf>) -> &mut Self {
+        self.root = root.into();
+        self
+    }
+
+    /// Store Nix in this directory instead of `/nix`
+    pub fn store_location(&mut self, store_location: Option<PathBuf>) -> &mut Self {
+        self.store_location = store_location;
+        self
+    }
+
+    /// How the store location is made available at `/nix`
+    pub fn store_link(&mut self, store_link: StoreLink) -> &mut Self {
+        self.store_link = store_link;
+        self
+    }
+
+    /// Store Nix in a loopback image file mounted on `/nix`
+    pub fn store_image(&mut self, store_image: Option<PathBuf>) -> &mut Self {
+        self.store_image = store_image;
+        self
+    }
+
+    /// The size in bytes of the store image
+    pub fn store_image_size(&mut self, size: u64) -> &mut Self {
+        self.store_image_size = size;
+        self
+    }
+
+    /// The filesystem the store image is formatted with
+    pub fn store_image_filesystem(&mut self, filesystem: LoopbackFilesystem) -> &mut Self {
+        self.store_image_filesystem = filesystem;
+        self
+    }
+
+    /// `path` as seen from outside of [`root`](Self::root)
+    pub fn rebase(&self, path: impl AsRef<Path>) -> PathBuf {
+        rebase(&self.root, path)
+    }
+}
+
+/// Place the absolute `path` under `root`, for example `/etc/nix` under `/mnt` is `/mnt/etc/nix`
+pub fn rebase(root: &Path, path: impl AsRef<Path>) -> PathBuf {
+    let path = path.as_ref();
+    root.join(path.strip_prefix("/").unwrap_or(path))
+}
+
+/// The default for [`LocationSettings::root`], used for receipts which predate it
+pub(crate) fn default_root() -> PathBuf {
+    PathBuf::from("/")
 }
 
 /// An error originating from a [`Planner::settings`](crate::planner::Planner::settings)
@@ -675,7 +1053,38 @@ impl crate::diagnostics::ErrorDiagnostic for InstallSettingsError {
 
 #[cfg(test)]
 mod tests {
-    use super::{FromStr, PathBuf, Url, UrlOrPath, UrlOrPathOrString};
+    use super::{rebase, FromStr, Path, PathBuf, Url, UrlOrPath, UrlOrPathOrString};
+
+    #[cfg(feature = "cli")]
+    #[test]
+    fn parses_sizes() {
+        use super::parse_size;
+
+        assert_eq!(parse_size("4096"), Ok(4096));
+        assert_eq!(parse_size("512M"), Ok(512 * 1024 * 1024));
+        assert_eq!(parse_size("64G"), Ok(64 * 1024 * 1024 * 1024));
+        assert_eq!(parse_size("1T"), Ok(1 << 40));
+        assert_eq!(parse_size("2gb"), Ok(2 << 30));
+        assert!(parse_size("0G").is_err());
+        assert!(parse_size("lots").is_err());
+        assert!(parse_size("99999999999T").is_err());
+    }
+
+    #[test]
+    fn rebase_places_paths_under_root() {
+        assert_eq!(
+            rebase(Path::new("/"), "/etc/nix/nix.conf"),
+            PathBuf::from("/etc/nix/nix.conf")
+        );
+        assert_eq!(
+            rebase(Path::new("/mnt/target"), "/nix/store"),
+            PathBuf::from("/mnt/target/nix/store")
+        );
+        assert_eq!(
+            rebase(Path::new("/mnt/target"), "/"),
+            PathBuf::from("/mnt/target")
+        );
+    }
 
     #[test]
     fn url_or_path_or_string_parses() -> Result<(), Box<dyn std::error::Error>> {
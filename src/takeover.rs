@@ -0,0 +1,241 @@
+/*! Clean up a Nix install made by another installer
+
+Receipts only cover installs made by `nix-installer`, so an install by the upstream Nix install scripts, or what is left
+of one after removing it by hand, blocks installing. [`plan`] detects what those scripts leave behind and describes it
+as an [`InstallPlan`] whose actions are all completed, so [`InstallPlan::uninstall`] reverts them like it would any
+other install.
+
+This is only done on Linux: on Mac the upstream scripts also create a Nix Store volume, mount it through
+`/etc/synthetic.conf` and `/etc/fstab`, and set up a daemon for it, none of which is detected here.
+*/
+
+use std::path::{Path, PathBuf};
+
+use nix::unistd::{Gid, Group, Uid, User};
+
+use crate::{
+    action::{
+        base::{
+            revert_foreign_shell_profile::BACKUP_SUFFIX, CreateDirectory, CreateGroup, CreateUser,
+            RevertForeignNixDaemon, RevertForeignShellProfile,
+        },
+        Action, StatefulAction,
+    },
+    plan::{current_version, DEFAULT_MAX_CONCURRENCY},
+    planner::{BuiltinPlanner, PlannerError},
+    settings::{CommonSettings, NIX_DIR},
+    InstallPlan, NixInstallerError,
+};
+
+/// The shell profiles the upstream Nix install scripts add to
+const PROFILES: &[&str] = &[
+    "/etc/bashrc",
+    "/etc/profile.d/nix.sh",
+    "/etc/zshrc",
+    "/etc/bash.bashrc",
+    "/etc/zsh/zshrc",
+    "/etc/fish/conf.d/nix.fish",
+    "/usr/local/etc/fish/conf.d/nix.fish",
+    "/opt/homebrew/etc/fish/conf.d/nix.fish",
+];
+/// The directories the upstream Nix install scripts leave backups of shell profiles in
+const BACKUP_DIRECTORIES: &[&str] = &["/etc", "/etc/zsh"];
+const NIX_CONF_DIR: &str = "/etc/nix";
+
+/// Plan the removal of the Nix install left by another installer, using the build users and group of `settings`
+///
+/// The plan has no actions if nothing was found.
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn plan(settings: CommonSettings) -> Result<InstallPlan, NixInstallerError> {
+    let mut actions: Vec<StatefulAction<Box<dyn Action>>> = vec![];
+
+    // Planned in the order an install would make them, as they are reverted in reverse
+    for dir in [NIX_DIR, NIX_CONF_DIR] {
+        if Path::new(dir).is_dir() {
+            actions.push(
+                CreateDirectory::plan(dir, None, None, None, true)
+                    .await
+                    .map_err(PlannerError::Action)?
+                    .boxed(),
+            );
+        }
+    }
+
+    let group_name = &settings.nix_build_group_name;
+    let group = Group::from_name(group_name).ok().flatten();
+    if let Some(group) = &group {
+        actions.push(
+            CreateGroup::plan(group.name.clone(), group.gid.as_raw(), "/".into())
+                .await
+                .map_err(PlannerError::Action)?
+                .boxed(),
+        );
+    }
+    let gid = group
+        .as_ref()
+        .map(|v| v.gid)
+        .unwrap_or_else(|| Gid::from_raw(settings.nix_build_group_id));
+    for user in build_users(&settings, group.as_ref(), gid) {
+        let primary_group = Group::from_gid(user.gid)
+            .ok()
+            .flatten()
+            .map(|v| v.name)
+            .unwrap_or_else(|| group_name.clone());
+        actions.push(
+            CreateUser::plan(
+                user.name,
+                user.uid.as_raw(),
+                primary_group,
+                user.gid.as_raw(),
+                user.gecos.to_string_lossy().into_owned(),
+                "/".into(),
+            )
+            .await
+            .map_err(PlannerError::Action)?
+            .boxed(),
+        );
+    }
+
+    if let Some(daemon) = RevertForeignNixDaemon::plan()
+        .await
+        .map_err(PlannerError::Action)?
+    {
+        actions.push(daemon.boxed());
+    }
+
+    for profile in profiles() {
+        if let Some(revert) = RevertForeignShellProfile::plan(&profile)
+            .await
+            .map_err(PlannerError::Action)?
+        {
+            actions.push(revert.boxed());
+        }
+    }
+
+    let planner = BuiltinPlanner::from_common_settings(settings).await?;
+    Ok(InstallPlan {
+        version: current_version()?,
+        actions,
+        planner: planner.boxed(),
+        #[cfg(feature = "diagnostics")]
+        diagnostic_data: None,
+        max_concurrency: DEFAULT_MAX_CONCURRENCY,
+        uninstall_selection: Default::default(),
+    })
+}
+
+/// The members of the build group and the users named like build users, which are build users by [`is_build_user`]
+fn build_users(settings: &CommonSettings, group: Option<&Group>, gid: Gid) -> Vec<User> {
+    let mut names: Vec<String> = group.map(|v| v.mem.clone()).unwrap_or_default();
+    for index in 1..=settings.nix_build_user_count {
+        let name = format!("{}{index}", settings.nix_build_user_prefix);
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+        .iter()
+        .filter_map(|name| User::from_name(name).ok().flatten())
+        .filter(|user| {
+            let build_user = is_build_user(settings, gid, &user.name, user.uid, user.gid);
+            if !build_user {
+                tracing::debug!(
+                    "Not removing `{}`, which doesn't look like a build user",
+                    user.name
+                );
+            }
+            build_user
+        })
+        .collect()
+}
+
+/// If the user is named `<prefix><index>`, has the UID `<base> + <index>` and the build group as primary group
+///
+/// People added to the build group, to run builds or by mistake, are never build users.
+fn is_build_user(settings: &CommonSettings, gid: Gid, name: &str, uid: Uid, user_gid: Gid) -> bool {
+    let Some(index) = name
+        .strip_prefix(&settings.nix_build_user_prefix)
+        .and_then(|v| v.parse::<u32>().ok())
+    else {
+        return false;
+    };
+    index > 0
+        && Some(uid.as_raw()) == settings.nix_build_user_id_base.checked_add(index)
+        && user_gid == gid
+}
+
+/// The shell profiles the upstream scripts add to, and any other profile they left a backup of
+fn profiles() -> Vec<PathBuf> {
+    let mut profiles: Vec<PathBuf> = PROFILES.iter().map(PathBuf::from).collect();
+    for dir in BACKUP_DIRECTORIES {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let Some(profile) = entry
+                .path()
+                .to_str()
+                .and_then(|v| v.strip_suffix(BACKUP_SUFFIX))
+                .map(PathBuf::from)
+            else {
+                continue;
+            };
+            if !profiles.contains(&profile) {
+                profiles.push(profile);
+            }
+        }
+    }
+    profiles
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn only_takes_build_users() -> eyre::Result<()> {
+        let mut settings = CommonSettings::default().await?;
+        settings.nix_build_user_prefix = "nixbld".to_string();
+        settings.nix_build_user_id_base = 30_000;
+        let gid = Gid::from_raw(30_000);
+
+        assert!(is_build_user(
+            &settings,
+            gid,
+            "nixbld3",
+            Uid::from_raw(30_003),
+            gid
+        ));
+        // A person added to the build group
+        assert!(!is_build_user(
+            &settings,
+            gid,
+            "alice",
+            Uid::from_raw(1000),
+            Gid::from_raw(1000)
+        ));
+        // Named like a build user, but not one
+        assert!(!is_build_user(
+            &settings,
+            gid,
+            "nixbld3",
+            Uid::from_raw(1000),
+            gid
+        ));
+        assert!(!is_build_user(
+            &settings,
+            gid,
+            "nixbld3",
+            Uid::from_raw(30_003),
+            Gid::from_raw(100)
+        ));
+        assert!(!is_build_user(
+            &settings,
+            gid,
+            "nixbldx",
+            Uid::from_raw(30_003),
+            gid
+        ));
+        Ok(())
+    }
+}